@@ -0,0 +1,408 @@
+use std::{error::Error, sync::Arc};
+
+use clap::{Parser, Subcommand};
+use rgpt_cfg::Config;
+use rgpt_db::{
+    Database,
+    api_key::ApiKey,
+    audit::{AuditEvent, AuditKind, AuditQuery, ClientInfo, NewAuditEvent},
+    chat::Chat,
+    moderation::ModerationFlag,
+    session::Session,
+    user::User,
+};
+use serde::Serialize;
+
+mod output;
+
+use output::Output;
+
+type AdminResult = Result<(), Box<dyn Error + Send + Sync>>;
+
+/// Operator tools for a RetroGPT deployment
+#[derive(Parser)]
+#[command(name = "rgpt-admin")]
+struct Cli {
+    /// Print JSON instead of text, for scripting
+    #[arg(long, global = true)]
+    json: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Inspect and manage user accounts
+    #[command(subcommand)]
+    Users(UsersCommand),
+
+    /// Inspect and revoke login sessions
+    #[command(subcommand)]
+    Sessions(SessionsCommand),
+
+    /// Inspect and restore trashed chats
+    #[command(subcommand)]
+    Chats(ChatsCommand),
+
+    /// Issue and revoke API keys for the `/v1` endpoints
+    #[command(subcommand)]
+    Keys(KeysCommand),
+
+    /// Review prompts and replies that moderation flagged or blocked
+    #[command(subcommand)]
+    Flags(FlagsCommand),
+
+    /// Read the audit log, newest first
+    Audit {
+        /// Only list events concerning this user
+        #[arg(long)]
+        user: Option<i32>,
+
+        /// Only list events of this kind, e.g. `login_failed`
+        #[arg(long)]
+        kind: Option<String>,
+
+        /// Only list events older than this one
+        #[arg(long)]
+        before: Option<i32>,
+
+        #[arg(long, default_value_t = 50)]
+        limit: usize,
+    },
+
+    /// List the personas chats can be answered in, the
+    /// built-in ones along with those in `persona_dir`
+    Personas,
+
+    /// Run any pending database migrations
+    Migrate,
+
+    /// Print the effective configuration
+    Config,
+
+    /// Print the API's OpenAPI document
+    Openapi,
+
+    /// Print TypeScript types for the API's request and
+    /// response bodies, for the frontend build
+    Typescript,
+}
+
+#[derive(Subcommand)]
+enum UsersCommand {
+    /// List every user
+    List,
+
+    /// Show a user along with their sessions and usage
+    Show { user_id: i32 },
+
+    /// Stop a user from logging in and revoke their sessions
+    Disable { user_id: i32 },
+
+    /// Allow a disabled user to log in again
+    Enable { user_id: i32 },
+
+    /// Permanently delete a user and all of their data
+    Delete {
+        user_id: i32,
+
+        /// Confirm the deletion, which cannot be undone
+        #[arg(long)]
+        yes: bool,
+    },
+
+    /// Show usage for one user, or for every user
+    Usage { user_id: Option<i32> },
+}
+
+#[derive(Subcommand)]
+enum SessionsCommand {
+    /// List a user's sessions
+    List { user_id: i32 },
+
+    /// Revoke every session of a user
+    Revoke { user_id: i32 },
+}
+
+#[derive(Subcommand)]
+enum ChatsCommand {
+    /// List a user's trashed chats
+    Trashed { user_id: i32 },
+
+    /// Move a trashed chat back into its owner's chat list
+    Restore { chat_id: i32 },
+}
+
+#[derive(Subcommand)]
+enum KeysCommand {
+    /// Create an API key for a user and print its secret,
+    /// which is not shown again
+    Create {
+        user_id: i32,
+
+        /// A label to tell the user's keys apart
+        #[arg(long, default_value = "default")]
+        name: String,
+    },
+
+    /// List a user's API keys
+    List { user_id: i32 },
+
+    /// Revoke an API key
+    Revoke { key_id: i32 },
+}
+
+#[derive(Subcommand)]
+enum FlagsCommand {
+    /// List the flags awaiting review
+    List {
+        /// Include flags that have been reviewed
+        #[arg(long)]
+        all: bool,
+    },
+
+    /// Show a flag along with the text it was raised on
+    Show { flag_id: i32 },
+
+    /// Mark a flag as reviewed
+    Review { flag_id: i32 },
+}
+
+#[tokio::main]
+async fn main() -> AdminResult {
+    let Cli { json, command } = Cli::parse();
+    let out = Output::new(json);
+
+    match command {
+        Command::Migrate => {
+            rgpt_db::ensure_migrations();
+            Ok(())
+        }
+        Command::Config => {
+            out.config(&Config::new().map_err(|err| err.to_string())?);
+            Ok(())
+        }
+        Command::Personas => {
+            let config = Config::new().map_err(|err| err.to_string())?;
+            out.personas(&config.personas.iter().collect::<Vec<_>>());
+            Ok(())
+        }
+        Command::Openapi => {
+            println!(
+                "{}",
+                rgpt_server::api::openapi::document().to_pretty_json()?
+            );
+            Ok(())
+        }
+        Command::Typescript => {
+            print!(
+                "{}",
+                rgpt_server::api::openapi::typescript(&rgpt_server::api::openapi::document())
+            );
+            Ok(())
+        }
+        Command::Users(command) => users(command, Database::establish_arc().await, out).await,
+        Command::Sessions(command) => sessions(command, Database::establish_arc().await, out).await,
+        Command::Chats(command) => chats(command, Database::establish_arc().await, out).await,
+        Command::Keys(command) => keys(command, Database::establish_arc().await, out).await,
+        Command::Flags(command) => flags(command, Database::establish_arc().await, out).await,
+        Command::Audit {
+            user,
+            kind,
+            before,
+            limit,
+        } => {
+            let kind = match kind {
+                Some(kind) => Some(AuditKind::parse(&kind).ok_or(UnknownAuditKind)?),
+                None => None,
+            };
+            let query = AuditQuery {
+                user_id: user,
+                kind,
+                before,
+                limit,
+            };
+            out.audit_events(&AuditEvent::list(Database::establish_arc().await, &query).await?);
+            Ok(())
+        }
+    }
+}
+
+async fn users(command: UsersCommand, db: Arc<Database>, out: Output) -> AdminResult {
+    match command {
+        UsersCommand::List => {
+            out.users(&User::list(db).await?);
+        }
+        UsersCommand::Show { user_id } => {
+            let user = User::get_by_id(db.clone(), user_id).await?;
+            let sessions = Session::get_all_for_user(db.clone(), user_id).await?;
+            let usage = user.usage(db).await?;
+            out.user_details(&UserDetails {
+                user,
+                sessions,
+                usage,
+            });
+        }
+        UsersCommand::Disable { user_id } => {
+            let user = User::get_by_id(db.clone(), user_id).await?;
+            let user = user.set_disabled(db.clone(), true).await?;
+            let revoked = Session::revoke_all_for_user(db.clone(), user_id).await?;
+            audit(db.clone(), AuditKind::AdminAction, user_id, "disabled user").await?;
+            let detail = format!("revoked {revoked} session(s)");
+            audit(db, AuditKind::SessionRevoked, user_id, &detail).await?;
+            out.users(&[user]);
+        }
+        UsersCommand::Enable { user_id } => {
+            let user = User::get_by_id(db.clone(), user_id).await?;
+            let user = user.set_disabled(db.clone(), false).await?;
+            audit(db, AuditKind::AdminAction, user_id, "enabled user").await?;
+            out.users(&[user]);
+        }
+        UsersCommand::Delete { user_id, yes } => {
+            if !yes {
+                Err(NotConfirmed)?;
+            }
+            let user = User::get_by_id(db.clone(), user_id).await?;
+            let config = Config::new().map_err(|err| err.to_string())?;
+            let attachments = user.hard_delete(db.clone()).await?;
+            rgpt_server::attachments::remove_files(&config.attachment_storage, &attachments).await;
+            audit(
+                db,
+                AuditKind::AccountDeleted,
+                user_id,
+                "deleted by an admin",
+            )
+            .await?;
+            out.done(&format!("deleted user {user_id}"));
+        }
+        UsersCommand::Usage { user_id } => {
+            let users = match user_id {
+                Some(user_id) => vec![User::get_by_id(db.clone(), user_id).await?],
+                None => User::list(db.clone()).await?,
+            };
+            let mut usage = Vec::with_capacity(users.len());
+            for user in users {
+                usage.push(user.usage(db.clone()).await?);
+            }
+            out.usage(&usage);
+        }
+    }
+    Ok(())
+}
+
+async fn sessions(command: SessionsCommand, db: Arc<Database>, out: Output) -> AdminResult {
+    match command {
+        SessionsCommand::List { user_id } => {
+            out.sessions(&Session::get_all_for_user(db, user_id).await?);
+        }
+        SessionsCommand::Revoke { user_id } => {
+            let revoked = Session::revoke_all_for_user(db.clone(), user_id).await?;
+            let detail = format!("revoked {revoked} session(s)");
+            audit(db, AuditKind::SessionRevoked, user_id, &detail).await?;
+            out.done(&format!("revoked {revoked} session(s) of user {user_id}"));
+        }
+    }
+    Ok(())
+}
+
+async fn chats(command: ChatsCommand, db: Arc<Database>, out: Output) -> AdminResult {
+    match command {
+        ChatsCommand::Trashed { user_id } => {
+            let user = User::get_by_id(db.clone(), user_id).await?;
+            out.chats(&user.get_deleted_chats(db).await?);
+        }
+        ChatsCommand::Restore { chat_id } => {
+            let chat = Chat::get_by_id(db.clone(), chat_id).await?;
+            let chat = chat.restore(db.clone()).await?;
+            let detail = format!("chat {chat_id}");
+            audit(db, AuditKind::ChatRestored, chat.user_id, &detail).await?;
+            out.chats(&[chat]);
+        }
+    }
+    Ok(())
+}
+
+async fn keys(command: KeysCommand, db: Arc<Database>, out: Output) -> AdminResult {
+    match command {
+        KeysCommand::Create { user_id, name } => {
+            let user = User::get_by_id(db.clone(), user_id).await?;
+            let (key, secret) = ApiKey::create(db.clone(), user.user_id, name).await?;
+            let detail = format!("created API key {}", key.id);
+            audit(db, AuditKind::AdminAction, user_id, &detail).await?;
+            out.created_key(&CreatedKey { key, secret });
+        }
+        KeysCommand::List { user_id } => {
+            out.keys(&ApiKey::get_all_for_user(db, user_id).await?);
+        }
+        KeysCommand::Revoke { key_id } => {
+            if !ApiKey::revoke(db.clone(), key_id).await? {
+                Err(rgpt_db::store::NotFound)?;
+            }
+            let event = NewAuditEvent::new(
+                AuditKind::AdminAction,
+                None,
+                format!("revoked API key {key_id}"),
+            );
+            AuditEvent::create(db, event.client(&admin_client())).await?;
+            out.done(&format!("revoked API key {key_id}"));
+        }
+    }
+    Ok(())
+}
+
+async fn flags(command: FlagsCommand, db: Arc<Database>, out: Output) -> AdminResult {
+    match command {
+        FlagsCommand::List { all } => {
+            out.flags(&ModerationFlag::list(db, all).await?);
+        }
+        FlagsCommand::Show { flag_id } => {
+            out.flag_details(&ModerationFlag::get_by_id(db, flag_id).await?);
+        }
+        FlagsCommand::Review { flag_id } => {
+            let flag = ModerationFlag::get_by_id(db.clone(), flag_id).await?;
+            let flag = flag.review(db.clone()).await?;
+            let detail = format!("reviewed moderation flag {flag_id}");
+            audit(db, AuditKind::AdminAction, flag.user_id, &detail).await?;
+            out.flags(&[flag]);
+        }
+    }
+    Ok(())
+}
+
+/// Records a change made to a user's account in the audit log
+async fn audit(db: Arc<Database>, kind: AuditKind, user_id: i32, detail: &str) -> AdminResult {
+    let event = NewAuditEvent::new(kind, Some(user_id), detail);
+    AuditEvent::create(db, event.client(&admin_client())).await?;
+    Ok(())
+}
+
+/// Changes made here are told apart in the audit
+/// log by their user agent
+fn admin_client() -> ClientInfo {
+    ClientInfo {
+        ip: None,
+        user_agent: Some("rgpt-admin".into()),
+    }
+}
+
+#[derive(Serialize)]
+struct CreatedKey {
+    key: ApiKey,
+    secret: String,
+}
+
+#[derive(Serialize)]
+struct UserDetails {
+    user: User,
+    sessions: Vec<Session>,
+    usage: rgpt_db::user::Usage,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Refusing To Delete Without --yes")]
+struct NotConfirmed;
+
+#[derive(Debug, thiserror::Error)]
+#[error("Unknown Audit Event Kind")]
+struct UnknownAuditKind;
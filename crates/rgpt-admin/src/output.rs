@@ -0,0 +1,251 @@
+use rgpt_cfg::{Config, personas::Persona};
+use rgpt_db::{
+    api_key::ApiKey, audit::AuditEvent, chat::Chat, moderation::ModerationFlag, session::Session,
+    user::Usage, user::User,
+};
+use serde::Serialize;
+
+use crate::{CreatedKey, UserDetails};
+
+/// Prints command results either as tab-separated
+/// text for people or as JSON for scripts
+#[derive(Clone, Copy)]
+pub struct Output {
+    json: bool,
+}
+
+impl Output {
+    pub fn new(json: bool) -> Self {
+        Output { json }
+    }
+
+    pub fn users(&self, users: &[User]) {
+        self.print(users, || {
+            let mut text = String::from("USER_ID\tEMAIL\tNAME\tLAST_LOGIN\tDISABLED");
+            for user in users {
+                text.push('\n');
+                text.push_str(&user_line(user));
+            }
+            text
+        })
+    }
+
+    pub fn user_details(&self, details: &UserDetails) {
+        self.print(details, || {
+            let UserDetails {
+                user,
+                sessions,
+                usage,
+            } = details;
+            format!(
+                "user_id:    {}\ngoogle_id:  {}\nemail:      {}\nname:       {}\ncreated_at: {}\nlast_login: {}\ndisabled:   {}\ndeleting:   {}\n\n{}\n\n{}",
+                user.user_id,
+                user.google_id,
+                user.email,
+                user.name,
+                user.created_at,
+                user.last_login,
+                user.disabled,
+                optional(user.delete_after),
+                sessions_text(sessions),
+                usage_text(std::slice::from_ref(usage)),
+            )
+        })
+    }
+
+    pub fn sessions(&self, sessions: &[Session]) {
+        self.print(sessions, || sessions_text(sessions))
+    }
+
+    pub fn chats(&self, chats: &[Chat]) {
+        self.print(chats, || {
+            let mut text = String::from("CHAT_ID\tUSER_ID\tUPDATED_AT\tDELETED\tNAME");
+            for chat in chats {
+                text.push_str(&format!(
+                    "\n{}\t{}\t{}\t{}\t{}",
+                    chat.id,
+                    chat.user_id,
+                    chat.updated_at,
+                    chat.deleted,
+                    chat.name.as_deref().unwrap_or("Untitled Chat"),
+                ));
+            }
+            text
+        })
+    }
+
+    pub fn keys(&self, keys: &[ApiKey]) {
+        self.print(keys, || {
+            let mut text = String::from("KEY_ID\tUSER_ID\tCREATED_AT\tNAME");
+            for key in keys {
+                text.push('\n');
+                text.push_str(&key_line(key));
+            }
+            text
+        })
+    }
+
+    pub fn created_key(&self, created: &CreatedKey) {
+        self.print(created, || {
+            format!(
+                "KEY_ID\tUSER_ID\tCREATED_AT\tNAME\n{}\n\nsecret: {}\n(store it now; it cannot be shown again)",
+                key_line(&created.key),
+                created.secret,
+            )
+        })
+    }
+
+    pub fn flags(&self, flags: &[ModerationFlag]) {
+        self.print(flags, || {
+            let mut text = String::from(
+                "FLAG_ID\tUSER_ID\tCHAT_ID\tMSG_ID\tSOURCE\tACTION\tCREATED_AT\tREVIEWED\tREASONS",
+            );
+            for flag in flags {
+                text.push_str(&format!(
+                    "\n{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                    flag.id,
+                    flag.user_id,
+                    optional(flag.chat_id),
+                    optional(flag.msg_id),
+                    flag.source,
+                    flag.action,
+                    flag.created_at,
+                    flag.reviewed_at.is_some(),
+                    flag.reasons,
+                ));
+            }
+            text
+        })
+    }
+
+    pub fn flag_details(&self, flag: &ModerationFlag) {
+        self.print(flag, || {
+            format!(
+                "flag_id:     {}\nuser_id:     {}\nchat_id:     {}\nmsg_id:      {}\nsource:      {}\naction:      {}\nreasons:     {}\ncreated_at:  {}\nreviewed_at: {}\n\n{}",
+                flag.id,
+                flag.user_id,
+                optional(flag.chat_id),
+                optional(flag.msg_id),
+                flag.source,
+                flag.action,
+                flag.reasons,
+                flag.created_at,
+                optional(flag.reviewed_at),
+                flag.content,
+            )
+        })
+    }
+
+    pub fn audit_events(&self, events: &[AuditEvent]) {
+        self.print(events, || {
+            let mut text =
+                String::from("EVENT_ID\tUSER_ID\tKIND\tCREATED_AT\tIP\tUSER_AGENT\tDETAIL");
+            for event in events {
+                text.push_str(&format!(
+                    "\n{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                    event.id,
+                    optional(event.user_id),
+                    event.kind,
+                    event.created_at,
+                    optional(event.ip.as_ref()),
+                    optional(event.user_agent.as_ref()),
+                    event.detail,
+                ));
+            }
+            text
+        })
+    }
+
+    pub fn usage(&self, usage: &[Usage]) {
+        self.print(usage, || usage_text(usage))
+    }
+
+    pub fn personas(&self, personas: &[&Persona]) {
+        self.print(personas, || {
+            let mut text = String::from("PERSONA_ID\tNAME\tMODEL\tDESCRIPTION");
+            for persona in personas {
+                text.push_str(&format!(
+                    "\n{}\t{}\t{}\t{}",
+                    persona.id,
+                    persona.name,
+                    optional(persona.model.as_ref()),
+                    persona.description,
+                ));
+            }
+            text
+        })
+    }
+
+    pub fn config(&self, config: &Config) {
+        self.print(config, || format!("{config:#?}"))
+    }
+
+    /// Reports a command that has nothing to show but its outcome
+    pub fn done(&self, message: &str) {
+        #[derive(Serialize)]
+        struct Done<'a> {
+            message: &'a str,
+        }
+
+        self.print(&Done { message }, || message.to_owned())
+    }
+
+    fn print<T: Serialize + ?Sized>(&self, value: &T, text: impl FnOnce() -> String) {
+        if self.json {
+            match serde_json::to_string_pretty(value) {
+                Ok(json) => println!("{json}"),
+                Err(err) => eprintln!("failed to serialize output: {err}"),
+            }
+        } else {
+            println!("{}", text());
+        }
+    }
+}
+
+fn user_line(user: &User) -> String {
+    format!(
+        "{}\t{}\t{}\t{}\t{}",
+        user.user_id, user.email, user.name, user.last_login, user.disabled
+    )
+}
+
+fn key_line(key: &ApiKey) -> String {
+    format!(
+        "{}\t{}\t{}\t{}",
+        key.id, key.user_id, key.created_at, key.name
+    )
+}
+
+/// A nullable column, with `-` for null
+fn optional(value: Option<impl std::fmt::Display>) -> String {
+    value.map_or_else(|| "-".into(), |value| value.to_string())
+}
+
+fn sessions_text(sessions: &[Session]) -> String {
+    let mut text = String::from("CREATED_AT\tEXPIRES_AT\tVALID");
+    for session in sessions {
+        text.push_str(&format!(
+            "\n{}\t{}\t{}",
+            session.created_at,
+            session.expires_at,
+            session.validate()
+        ));
+    }
+    text
+}
+
+fn usage_text(usage: &[Usage]) -> String {
+    let mut text = String::from("USER_ID\tCHATS\tDELETED_CHATS\tUSER_MSGS\tAI_MSGS\tSESSIONS");
+    for usage in usage {
+        text.push_str(&format!(
+            "\n{}\t{}\t{}\t{}\t{}\t{}",
+            usage.user_id,
+            usage.chats,
+            usage.deleted_chats,
+            usage.user_msgs,
+            usage.ai_msgs,
+            usage.sessions
+        ));
+    }
+    text
+}
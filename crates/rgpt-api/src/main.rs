@@ -15,6 +15,8 @@ async fn main_inner() -> Result<(), Box<dyn Error>> {
     let addr = SocketAddr::from(([0, 0, 0, 0], 4002));
     let listener = tokio::net::TcpListener::bind(addr).await?;
 
+    tokio::spawn(rgpt_server::accounts::sweep_deletions(cx.clone()));
+
     rgpt_server::api_service(cx).serve(listener).await?;
 
     Ok(())
@@ -0,0 +1,84 @@
+use rgpt_db::gen_params::GenParams;
+use serde::Serialize;
+
+/// The bounds a deployment puts on client-supplied
+/// generation parameters
+#[derive(Serialize, Debug, Clone)]
+pub struct GenLimits {
+    /// The highest temperature a client may ask for
+    pub max_temperature: f32,
+
+    /// The largest presence or frequency penalty,
+    /// in either direction
+    pub max_penalty: f32,
+
+    /// The most stop sequences a prompt may carry
+    pub max_stop_sequences: usize,
+
+    /// The longest stop sequence, in characters
+    pub max_stop_len: usize,
+}
+
+impl Default for GenLimits {
+    fn default() -> Self {
+        GenLimits {
+            max_temperature: 2.0,
+            max_penalty: 2.0,
+            max_stop_sequences: 4,
+            max_stop_len: 64,
+        }
+    }
+}
+
+impl GenLimits {
+    /// The parameters a reply is generated with
+    ///
+    /// Numbers outside the limits are clamped into them, while
+    /// values with no sensible nearest value, like `NaN` or too
+    /// many stop sequences, are rejected. `max_tokens` is capped
+    /// at `max_tokens` and always set, so the saved parameters
+    /// record the real cap.
+    pub fn apply(&self, params: GenParams, max_tokens: u32) -> Result<GenParams, InvalidGenParams> {
+        let clamp = |value: Option<f32>, min: f32, max: f32| match value {
+            Some(value) if !value.is_finite() => Err(InvalidGenParams),
+            value => Ok(value.map(|value| value.clamp(min, max))),
+        };
+
+        let stop = match params.stop {
+            Some(stop) if stop.len() > self.max_stop_sequences => Err(InvalidGenParams)?,
+            Some(stop)
+                if stop
+                    .iter()
+                    .any(|seq| seq.is_empty() || seq.chars().count() > self.max_stop_len) =>
+            {
+                Err(InvalidGenParams)?
+            }
+            Some(stop) if stop.is_empty() => None,
+            stop => stop,
+        };
+
+        Ok(GenParams {
+            temperature: clamp(params.temperature, 0.0, self.max_temperature)?,
+            top_p: clamp(params.top_p, 0.0, 1.0)?,
+            max_tokens: Some(
+                params
+                    .max_tokens
+                    .unwrap_or(max_tokens)
+                    .min(max_tokens)
+                    .max(1),
+            ),
+            stop,
+            seed: params.seed,
+            presence_penalty: clamp(params.presence_penalty, -self.max_penalty, self.max_penalty)?,
+            frequency_penalty: clamp(
+                params.frequency_penalty,
+                -self.max_penalty,
+                self.max_penalty,
+            )?,
+        })
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid Generation Parameters")]
+pub struct InvalidGenParams;
@@ -0,0 +1,55 @@
+use rgpt_provider::{Action, ModelTarget, PolicyRule};
+use serde::Serialize;
+
+/// How prompts and replies are moderated
+#[derive(Serialize, Debug, Clone)]
+pub struct ModerationConfig {
+    /// The keywords and regular expressions
+    /// checked locally, and what's done with
+    /// text that matches them
+    pub rules: Vec<PolicyRule>,
+
+    /// The provider moderation model text is
+    /// also checked with, if any. Without its
+    /// provider only the rules are checked
+    pub provider_model: Option<ModelTarget>,
+
+    /// What's done with text the provider
+    /// model flags
+    pub provider_action: Action,
+
+    /// The most characters of a reply held back
+    /// before they're checked. Replies are also
+    /// checked at the end of every line
+    pub reply_window: usize,
+
+    /// What a blocked reply ends with
+    pub blocked_reply: BlockedReply,
+}
+
+/// What a reply ends with once moderation blocks it
+///
+/// Either way, the text before the blocked text
+/// is saved and the generation stops.
+#[derive(Serialize, Debug, Clone)]
+pub enum BlockedReply {
+    /// `notice` is appended to the reply, which
+    /// finishes with `content_filter`
+    Truncate { notice: String },
+    /// The reply's stream ends with an error
+    Error,
+}
+
+impl Default for ModerationConfig {
+    fn default() -> Self {
+        ModerationConfig {
+            rules: vec![],
+            provider_model: Some(ModelTarget::new("openai", "omni-moderation-latest")),
+            provider_action: Action::Flag,
+            reply_window: 400,
+            blocked_reply: BlockedReply::Truncate {
+                notice: "\n\n[REPLY WITHHELD BY CONTENT POLICY]".into(),
+            },
+        }
+    }
+}
@@ -1,38 +1,147 @@
-use std::{env, error::Error, sync::Arc};
+use std::{collections::HashMap, env, error::Error, sync::Arc, time::Duration};
 
-use async_openai::config::OpenAIConfig;
-use rgpt_db::Database;
+use async_openai::{Client, config::OpenAIConfig};
+use rgpt_db::{
+    Database,
+    audit::AuditThrottle,
+    store::{PgStore, Store},
+};
+use rgpt_provider::{
+    Embedder, HashEmbedder, ModelRouter, Moderator, ModeratorChain, PolicyModerator,
+    ProviderEmbedder, ProviderModerator,
+};
 use rgpt_stream::StreamRegistry;
 use tokio::sync::Mutex;
 
+use crate::Config;
+
 /// Shared state between request handler threads
 pub struct SharedState {
-    pub db: Arc<Database>,
+    /// The storage the request handlers go through,
+    /// backed by Postgres in production
+    pub store: Arc<dyn Store>,
+
+    /// Sends chat completions over the configured
+    /// model route
+    pub model_router: ModelRouter,
 
-    pub openai_client: async_openai::Client<OpenAIConfig>,
+    /// Embeds documents and prompts for retrieval
+    pub embedder: Arc<dyn Embedder>,
+
+    /// Checks prompts and replies against the
+    /// moderation policy
+    pub moderator: Arc<dyn Moderator>,
 
     pub reqwest_client: reqwest::Client,
 
     pub stream_registry: Mutex<StreamRegistry>,
+
+    /// Holds back repeated audit events from clients
+    /// sending unknown session tokens
+    pub audit_throttle: std::sync::Mutex<AuditThrottle>,
 }
 
 impl SharedState {
-    pub async fn new() -> Result<SharedState, Box<dyn Error>> {
-        let db = Database::establish_arc().await;
+    pub async fn new(config: &Config) -> Result<SharedState, Box<dyn Error>> {
+        let store = Arc::new(PgStore::new(Database::establish_arc().await));
+
+        let mut clients = HashMap::new();
+        for provider in &config.providers {
+            let Ok(api_key) = env::var(&provider.api_key_env) else {
+                eprintln!(
+                    "{} is not set, leaving provider {} out of the model route",
+                    provider.api_key_env, provider.name
+                );
+                continue;
+            };
+            let client =
+                rgpt_provider::client(&provider.api_base, &api_key, &config.provider_policy);
+            clients.insert(provider.name.clone(), client);
+        }
+
+        // The primary model must be servable, as before fallbacks existed
+        if let Some(primary) = config.model_route.first() {
+            if !clients.contains_key(&primary.provider) {
+                Err(format!(
+                    "the primary model's provider {} is not configured",
+                    primary.provider
+                ))?;
+            }
+        }
+
+        SharedState::with_clients(config, store, clients)
+    }
+
+    /// State backed by `store` with no providers, so every
+    /// model call fails and documents are embedded locally.
+    /// For exercising the request handlers without Postgres
+    /// or network access
+    pub fn with_store(
+        config: &Config,
+        store: Arc<dyn Store>,
+    ) -> Result<SharedState, Box<dyn Error>> {
+        SharedState::with_clients(config, store, HashMap::new())
+    }
+
+    fn with_clients(
+        config: &Config,
+        store: Arc<dyn Store>,
+        clients: HashMap<String, Client<OpenAIConfig>>,
+    ) -> Result<SharedState, Box<dyn Error>> {
+        let embedding_client = config
+            .embedding_model
+            .as_ref()
+            .and_then(|target| Some((target, clients.get(&target.provider)?.clone())));
+        let embedder: Arc<dyn Embedder> = match embedding_client {
+            Some((target, client)) => Arc::new(ProviderEmbedder::new(
+                client,
+                &target.model,
+                &config.provider_policy,
+            )),
+            None => {
+                eprintln!("No embedding provider is configured, embedding documents locally");
+                Arc::new(HashEmbedder::new(512))
+            }
+        };
 
-        let api_key = env::var("OPENAI_API_KEY")?;
-        let openai_client = async_openai::Client::with_config(
-            async_openai::config::OpenAIConfig::new().with_api_key(api_key),
+        let moderation = &config.moderation;
+        let mut moderator = ModeratorChain::new().with(PolicyModerator::new(&moderation.rules)?);
+        let moderation_client = moderation
+            .provider_model
+            .as_ref()
+            .and_then(|target| Some((target, clients.get(&target.provider)?.clone())));
+        match moderation_client {
+            Some((target, client)) => {
+                moderator = moderator.with(ProviderModerator::new(
+                    client,
+                    &target.model,
+                    moderation.provider_action,
+                    &config.provider_policy,
+                ));
+            }
+            None => eprintln!("No moderation provider is configured, moderating with rules only"),
+        }
+
+        let model_router = ModelRouter::new(
+            config.provider_policy.clone(),
+            clients,
+            config.model_route.clone(),
         );
+
         let reqwest_client = reqwest::Client::new();
 
         let stream_registry = StreamRegistry::new().into();
 
+        let audit_throttle = AuditThrottle::new(Duration::from_secs(60), 10_000).into();
+
         Ok(SharedState {
-            db,
-            openai_client,
+            store,
+            model_router,
+            embedder,
+            moderator: Arc::new(moderator),
             reqwest_client,
             stream_registry,
+            audit_throttle,
         })
     }
 }
@@ -0,0 +1,84 @@
+//! Validating client generation parameters against `GenLimits`
+
+use rgpt_cfg::gen_limits::GenLimits;
+use rgpt_db::gen_params::GenParams;
+
+const MAX_TOKENS: u32 = 1024;
+
+#[test]
+fn unset_params_get_the_token_cap() {
+    let params = GenLimits::default()
+        .apply(GenParams::default(), MAX_TOKENS)
+        .unwrap();
+
+    assert_eq!(
+        params,
+        GenParams {
+            max_tokens: Some(MAX_TOKENS),
+            ..GenParams::default()
+        }
+    );
+}
+
+#[test]
+fn out_of_range_values_are_clamped() {
+    let params = GenLimits::default()
+        .apply(
+            GenParams {
+                temperature: Some(5.0),
+                top_p: Some(-1.0),
+                max_tokens: Some(100_000),
+                seed: Some(42),
+                presence_penalty: Some(-3.0),
+                frequency_penalty: Some(0.5),
+                ..GenParams::default()
+            },
+            MAX_TOKENS,
+        )
+        .unwrap();
+
+    assert_eq!(params.temperature, Some(2.0));
+    assert_eq!(params.top_p, Some(0.0));
+    assert_eq!(params.max_tokens, Some(MAX_TOKENS));
+    assert_eq!(params.seed, Some(42));
+    assert_eq!(params.presence_penalty, Some(-2.0));
+    assert_eq!(params.frequency_penalty, Some(0.5));
+}
+
+#[test]
+fn invalid_values_are_rejected() {
+    let limits = GenLimits::default();
+    let rejected = |params| limits.apply(params, MAX_TOKENS).is_err();
+
+    assert!(rejected(GenParams {
+        temperature: Some(f32::NAN),
+        ..GenParams::default()
+    }));
+    assert!(rejected(GenParams {
+        stop: Some(vec!["a".into(); limits.max_stop_sequences + 1]),
+        ..GenParams::default()
+    }));
+    assert!(rejected(GenParams {
+        stop: Some(vec![String::new()]),
+        ..GenParams::default()
+    }));
+    assert!(rejected(GenParams {
+        stop: Some(vec!["x".repeat(limits.max_stop_len + 1)]),
+        ..GenParams::default()
+    }));
+}
+
+#[test]
+fn empty_stop_list_is_dropped() {
+    let params = GenLimits::default()
+        .apply(
+            GenParams {
+                stop: Some(vec![]),
+                ..GenParams::default()
+            },
+            MAX_TOKENS,
+        )
+        .unwrap();
+
+    assert_eq!(params.stop, None);
+}
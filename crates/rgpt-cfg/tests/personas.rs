@@ -0,0 +1,138 @@
+//! Loading the persona registry from the built-in personas
+//! and a directory of persona files
+
+use std::{fs, path::PathBuf};
+
+use rgpt_cfg::personas::{
+    DEFAULT_PERSONA, InvalidPersonaFile, Personas, UnknownDefaultPersona, UnknownPersonaModel,
+};
+use rgpt_provider::ModelTarget;
+
+/// A fresh directory holding `files`, by name
+fn persona_dir(test: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rgpt-personas-{}-{test}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    for (name, contents) in files {
+        fs::write(dir.join(name), contents).unwrap();
+    }
+    dir
+}
+
+const PIRATE: &str = r#"{
+    "id": "pirate",
+    "name": "Pirate",
+    "description": "Arr",
+    "system_prompt": "You are a pirate.",
+    "model": "gpt-4.1-mini",
+    "params": {"temperature": 1.2},
+    "output_filters": {"terminal": true}
+}"#;
+
+#[test]
+fn builtins_are_valid() {
+    let personas = Personas::builtin();
+    let ids = personas.iter().map(|p| p.id.as_str()).collect::<Vec<_>>();
+
+    assert_eq!(ids, ["bbs_sysop", "help_desk", "terminal_robot"]);
+    assert!(personas.iter().all(|persona| persona.is_valid()));
+    assert_eq!(personas.default_persona().id, DEFAULT_PERSONA);
+}
+
+#[test]
+fn missing_dir_leaves_the_builtins() {
+    let dir = std::env::temp_dir().join("rgpt-personas-that-do-not-exist");
+    let personas = Personas::load(&dir, DEFAULT_PERSONA).unwrap();
+
+    assert_eq!(personas.iter().count(), 3);
+}
+
+#[test]
+fn files_add_and_replace_personas() {
+    let robot = r#"{
+        "id": "terminal_robot",
+        "name": "Terse Robot",
+        "description": "",
+        "system_prompt": "Beep."
+    }"#;
+    let dir = persona_dir(
+        "add",
+        &[
+            ("pirate.json", PIRATE),
+            ("robot.json", robot),
+            ("notes.txt", "not a persona"),
+        ],
+    );
+    let personas = Personas::load(&dir, "pirate").unwrap();
+
+    assert_eq!(personas.iter().count(), 4);
+    let pirate = personas.default_persona();
+    assert_eq!(pirate.id, "pirate");
+    assert_eq!(pirate.model.as_deref(), Some("gpt-4.1-mini"));
+    assert_eq!(pirate.params.temperature, Some(1.2));
+    assert!(pirate.output_filters.unwrap().terminal);
+    // Left out fields take their defaults
+    assert!(pirate.output_filters.unwrap().markdown);
+
+    let robot = personas.get("terminal_robot").unwrap();
+    assert_eq!(robot.name, "Terse Robot");
+    assert_eq!(robot.system_prompt, "Beep.");
+    assert_eq!(robot.output_filters, None);
+}
+
+#[test]
+fn invalid_files_are_rejected() {
+    let cases = [
+        ("bad-json", "{"),
+        (
+            "unknown-field",
+            r#"{"id": "a", "name": "A", "description": "", "system_prompt": "p", "colour": "red"}"#,
+        ),
+        (
+            "bad-id",
+            r#"{"id": "Not An Id", "name": "A", "description": "", "system_prompt": "p"}"#,
+        ),
+        (
+            "no-prompt",
+            r#"{"id": "a", "name": "A", "description": "", "system_prompt": "  "}"#,
+        ),
+        (
+            "bad-filters",
+            r#"{"id": "a", "name": "A", "description": "", "system_prompt": "p", "output_filters": {"wrap_column": 5}}"#,
+        ),
+    ];
+    for (test, contents) in cases {
+        let dir = persona_dir(test, &[("persona.json", contents)]);
+        let err = Personas::load(&dir, DEFAULT_PERSONA).unwrap_err();
+        assert!(err.is::<InvalidPersonaFile>(), "{test}: {err}");
+    }
+}
+
+#[test]
+fn default_must_exist() {
+    let dir = persona_dir("default", &[]);
+    let err = Personas::load(&dir, "pirate").unwrap_err();
+
+    assert!(err.is::<UnknownDefaultPersona>());
+}
+
+#[test]
+fn unknown_ids_resolve_to_the_default() {
+    let personas = Personas::builtin();
+
+    assert_eq!(personas.resolve("help_desk").id, "help_desk");
+    assert_eq!(personas.resolve("retired_persona").id, DEFAULT_PERSONA);
+}
+
+#[test]
+fn models_must_be_routed() {
+    let dir = persona_dir("models", &[("pirate.json", PIRATE)]);
+    let personas = Personas::load(&dir, DEFAULT_PERSONA).unwrap();
+
+    let routed = [ModelTarget::new("openai", "gpt-4.1-mini")];
+    assert!(personas.check_models(&routed).is_ok());
+
+    let unrouted = [ModelTarget::new("openai", "gpt-4o-mini")];
+    let err = personas.check_models(&unrouted).unwrap_err();
+    assert!(matches!(err, UnknownPersonaModel));
+}
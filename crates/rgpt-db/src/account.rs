@@ -0,0 +1,129 @@
+use std::sync::Arc;
+
+use diesel::{ExpressionMethods, QueryDsl};
+use serde::Serialize;
+
+use crate::{
+    Database, RunQueryDsl,
+    api_key::ApiKey,
+    attachment::Attachment,
+    audit::AuditEvent,
+    chat::Chat,
+    document::{Document, DocumentChunk},
+    instructions::CustomInstructions,
+    msg::Msg,
+    schema,
+    session::Session,
+    share::Share,
+    user::{Usage, User},
+};
+
+/// Everything kept about a user, for them to export
+///
+/// Moderation flags are left out: they're the operators'
+/// notes, and hold nothing the user didn't send or receive.
+#[derive(Serialize, Debug)]
+pub struct AccountData {
+    pub user: User,
+    pub usage: Usage,
+    pub sessions: Vec<Session>,
+    pub api_keys: Vec<ApiKey>,
+    pub instructions: Option<CustomInstructions>,
+    /// Trashed chats included
+    pub chats: Vec<Chat>,
+    /// Every message the user sent or was sent, oldest first,
+    /// whose `parent_message_id`s make up the chats' trees
+    pub msgs: Vec<Msg>,
+    pub attachments: Vec<Attachment>,
+    pub documents: Vec<DocumentPassages>,
+    pub shares: Vec<Share>,
+    /// The user's audit events, oldest first
+    pub activity: Vec<AuditEvent>,
+}
+
+/// A document along with the passages it was split into
+#[derive(Serialize, Clone, Debug)]
+pub struct DocumentPassages {
+    #[serde(flatten)]
+    pub document: Document,
+    pub passages: Vec<String>,
+}
+
+impl DocumentPassages {
+    /// Pairs each document with its chunks' text, in order
+    ///
+    /// Chunks embedded with more than one model are only
+    /// counted once.
+    pub fn collect(documents: Vec<Document>, mut chunks: Vec<DocumentChunk>) -> Vec<Self> {
+        chunks.sort_by_key(|chunk| (chunk.document_id, chunk.chunk_index, chunk.id));
+        chunks.dedup_by_key(|chunk| (chunk.document_id, chunk.chunk_index));
+        documents
+            .into_iter()
+            .map(|document| DocumentPassages {
+                passages: chunks
+                    .iter()
+                    .filter(|chunk| chunk.document_id == document.id)
+                    .map(|chunk| chunk.body.clone())
+                    .collect(),
+                document,
+            })
+            .collect()
+    }
+}
+
+impl AccountData {
+    pub async fn gather(
+        db: Arc<Database>,
+        user_id: i32,
+    ) -> Result<AccountData, libserver::ServiceError> {
+        let user = User::get_by_id(db.clone(), user_id).await?;
+        let usage = user.usage(db.clone()).await?;
+        let sessions = Session::get_all_for_user(db.clone(), user_id).await?;
+        let api_keys = ApiKey::get_all_for_user(db.clone(), user_id).await?;
+        let instructions = CustomInstructions::get_for_user(db.clone(), user_id).await?;
+        let chats = schema::chats::table
+            .filter(schema::chats::user_id.eq(user_id))
+            .order(schema::chats::id)
+            .get_results(db.clone())
+            .await?;
+        let msgs = schema::msgs::table
+            .filter(schema::msgs::user_id.eq(user_id))
+            .order(schema::msgs::id)
+            .get_results(db.clone())
+            .await?;
+        let attachments = schema::attachments::table
+            .filter(schema::attachments::user_id.eq(user_id))
+            .order(schema::attachments::id)
+            .get_results(db.clone())
+            .await?;
+        let documents = Document::get_all_for_user(db.clone(), user_id).await?;
+        let chunks = schema::document_chunks::table
+            .filter(schema::document_chunks::user_id.eq(user_id))
+            .get_results(db.clone())
+            .await?;
+        let shares = schema::chat_shares::table
+            .filter(schema::chat_shares::user_id.eq(user_id))
+            .order(schema::chat_shares::id)
+            .get_results(db.clone())
+            .await?;
+        let activity = schema::audit_events::table
+            .filter(schema::audit_events::user_id.eq(user_id))
+            .order(schema::audit_events::id)
+            .get_results(db)
+            .await?;
+
+        Ok(AccountData {
+            user,
+            usage,
+            sessions,
+            api_keys,
+            instructions,
+            chats,
+            msgs,
+            attachments,
+            documents: DocumentPassages::collect(documents, chunks),
+            shares,
+            activity,
+        })
+    }
+}
@@ -0,0 +1,101 @@
+use std::sync::Arc;
+
+use chrono::NaiveDateTime;
+use diesel::{
+    ExpressionMethods, QueryDsl, Queryable, Selectable, SelectableHelper, prelude::Insertable,
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::{Database, RunQueryDsl, schema};
+
+/// Every API key secret starts with this, which is how the
+/// API tells keys apart from session tokens
+pub const API_KEY_PREFIX: &str = "rgpt-";
+
+#[derive(Queryable, Selectable, Serialize, Clone, Debug)]
+#[diesel(table_name = schema::api_keys)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ApiKey {
+    pub id: i32,
+    pub user_id: i32,
+    pub name: String,
+    /// Only the hash of the secret is stored; the secret
+    /// itself is shown once, when the key is created
+    #[serde(skip_serializing)]
+    pub key_hash: String,
+    pub created_at: NaiveDateTime,
+}
+
+impl ApiKey {
+    /// Creates a key, returning it along with its secret
+    pub async fn create(
+        db: Arc<Database>,
+        user_id: i32,
+        name: String,
+    ) -> Result<(ApiKey, String), libserver::ServiceError> {
+        let secret = generate_secret();
+        let key = diesel::insert_into(schema::api_keys::table)
+            .values(NewApiKey {
+                user_id,
+                name,
+                key_hash: hash_secret(&secret),
+            })
+            .returning(ApiKey::as_returning())
+            .get_result(db)
+            .await?;
+        Ok((key, secret))
+    }
+
+    pub async fn get_by_secret(
+        db: Arc<Database>,
+        secret: &str,
+    ) -> Result<ApiKey, libserver::ServiceError> {
+        let key = schema::api_keys::table
+            .filter(schema::api_keys::key_hash.eq(hash_secret(secret)))
+            .get_result(db)
+            .await?;
+        Ok(key)
+    }
+
+    pub async fn get_all_for_user(
+        db: Arc<Database>,
+        user_id: i32,
+    ) -> Result<Vec<ApiKey>, libserver::ServiceError> {
+        let keys = schema::api_keys::table
+            .filter(schema::api_keys::user_id.eq(user_id))
+            .order(schema::api_keys::id)
+            .get_results(db)
+            .await?;
+        Ok(keys)
+    }
+
+    /// Deletes the key, returning whether it existed
+    pub async fn revoke(db: Arc<Database>, key_id: i32) -> Result<bool, libserver::ServiceError> {
+        let revoked = diesel::delete(schema::api_keys::table.find(key_id))
+            .execute(db)
+            .await?;
+        Ok(revoked > 0)
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = schema::api_keys)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+struct NewApiKey {
+    pub user_id: i32,
+    pub name: String,
+    pub key_hash: String,
+}
+
+pub fn generate_secret() -> String {
+    format!("{API_KEY_PREFIX}{}", uuid::Uuid::new_v4().simple())
+}
+
+/// The hex SHA-256 of a secret, as stored in `key_hash`
+pub fn hash_secret(secret: &str) -> String {
+    Sha256::digest(secret.as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
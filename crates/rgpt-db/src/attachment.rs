@@ -0,0 +1,104 @@
+use std::sync::Arc;
+
+use chrono::NaiveDateTime;
+use diesel::{
+    ExpressionMethods, QueryDsl, Queryable, Selectable, SelectableHelper, prelude::Insertable,
+};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{Database, RunQueryDsl, schema};
+
+/// A text document uploaded to be sent with a message
+#[derive(Queryable, Selectable, Serialize, ToSchema, Clone, Debug)]
+#[diesel(table_name = schema::attachments)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Attachment {
+    pub id: i32,
+    pub user_id: i32,
+    /// The message the attachment was sent with,
+    /// or `null` until it has been sent
+    #[schema(required)]
+    pub msg_id: Option<i32>,
+    /// The file name it was uploaded with
+    pub name: String,
+    pub media_type: String,
+    pub size_bytes: i32,
+    /// The text, when it's kept in the database
+    #[serde(skip_serializing)]
+    pub content: Option<String>,
+    /// The file the text is kept in, when it's
+    /// kept in the attachment directory
+    #[serde(skip_serializing)]
+    pub storage_key: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+impl Attachment {
+    pub async fn create(
+        db: Arc<Database>,
+        new_attachment: NewAttachment,
+    ) -> Result<Attachment, libserver::ServiceError> {
+        let attachment = diesel::insert_into(schema::attachments::table)
+            .values(new_attachment)
+            .returning(Attachment::as_returning())
+            .get_result(db)
+            .await?;
+        Ok(attachment)
+    }
+
+    pub async fn get_by_id(
+        db: Arc<Database>,
+        attachment_id: i32,
+    ) -> Result<Attachment, libserver::ServiceError> {
+        let attachment = schema::attachments::table
+            .find(attachment_id)
+            .get_result(db)
+            .await?;
+        Ok(attachment)
+    }
+
+    /// Links the attachments that haven't been sent yet to `msg_id`
+    pub async fn link_to_msg(
+        db: Arc<Database>,
+        attachment_ids: Vec<i32>,
+        msg_id: i32,
+    ) -> Result<(), libserver::ServiceError> {
+        diesel::update(
+            schema::attachments::table
+                .filter(schema::attachments::id.eq_any(attachment_ids))
+                .filter(schema::attachments::msg_id.is_null()),
+        )
+        .set(schema::attachments::msg_id.eq(msg_id))
+        .execute(db)
+        .await?;
+        Ok(())
+    }
+
+    /// The attachments sent with any of `msg_ids`, in upload order
+    pub async fn get_for_msgs(
+        db: Arc<Database>,
+        msg_ids: Vec<i32>,
+    ) -> Result<Vec<Attachment>, libserver::ServiceError> {
+        let attachments = schema::attachments::table
+            .filter(schema::attachments::msg_id.eq_any(msg_ids))
+            .order(schema::attachments::id)
+            .get_results(db)
+            .await?;
+        Ok(attachments)
+    }
+}
+
+/// An attachment to be saved, with its text in
+/// either `content` or the file named by `storage_key`
+#[derive(Insertable, Clone, Debug)]
+#[diesel(table_name = schema::attachments)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewAttachment {
+    pub user_id: i32,
+    pub name: String,
+    pub media_type: String,
+    pub size_bytes: i32,
+    pub content: Option<String>,
+    pub storage_key: Option<String>,
+}
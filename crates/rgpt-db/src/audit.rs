@@ -0,0 +1,317 @@
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use chrono::NaiveDateTime;
+use diesel::{
+    ExpressionMethods, QueryDsl, Queryable, Selectable, SelectableHelper, prelude::Insertable,
+};
+use serde::Serialize;
+use utoipa::{
+    ToSchema,
+    openapi::schema::{Object, ObjectBuilder, Type},
+};
+
+use crate::{Database, RunQueryDsl, schema, store::Store};
+
+/// A security-relevant event, such as a login or a chat
+/// being trashed
+///
+/// Events are append-only: once recorded they're never
+/// changed, and they outlive the accounts they concern.
+#[derive(Queryable, Selectable, Serialize, ToSchema, Clone, Debug)]
+#[diesel(table_name = schema::audit_events)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct AuditEvent {
+    pub id: i32,
+    /// The account the event concerns, or `null` if
+    /// it isn't known, as with unknown session tokens
+    #[schema(required)]
+    pub user_id: Option<i32>,
+    /// One of the [`AuditKind`] strings
+    #[schema(schema_with = kind_schema)]
+    pub kind: String,
+    pub detail: String,
+    /// The client's address, as the proxy in front of
+    /// the server reported it. Only recorded when the
+    /// server is configured to trust its proxy headers
+    #[schema(required)]
+    pub ip: Option<String>,
+    #[schema(required)]
+    pub user_agent: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+/// What happened
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuditKind {
+    LoginSucceeded,
+    LoginFailed,
+    SessionCreated,
+    /// A session token was unknown, or used for
+    /// another user's resources
+    SessionRejected,
+    /// An expired session was used, and deleted
+    SessionExpired,
+    SessionRevoked,
+    ChatDeleted,
+    ChatRestored,
+    ShareCreated,
+    AccountExported,
+    /// The user asked for their account to be deleted
+    /// once its grace period is over
+    AccountDeletionRequested,
+    AccountDeletionCancelled,
+    /// The account was deleted for good
+    AccountDeleted,
+    /// Anything else an operator did with `rgpt-admin`
+    AdminAction,
+}
+
+impl AuditKind {
+    pub const ALL: [AuditKind; 14] = [
+        AuditKind::LoginSucceeded,
+        AuditKind::LoginFailed,
+        AuditKind::SessionCreated,
+        AuditKind::SessionRejected,
+        AuditKind::SessionExpired,
+        AuditKind::SessionRevoked,
+        AuditKind::ChatDeleted,
+        AuditKind::ChatRestored,
+        AuditKind::ShareCreated,
+        AuditKind::AccountExported,
+        AuditKind::AccountDeletionRequested,
+        AuditKind::AccountDeletionCancelled,
+        AuditKind::AccountDeleted,
+        AuditKind::AdminAction,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditKind::LoginSucceeded => "login_succeeded",
+            AuditKind::LoginFailed => "login_failed",
+            AuditKind::SessionCreated => "session_created",
+            AuditKind::SessionRejected => "session_rejected",
+            AuditKind::SessionExpired => "session_expired",
+            AuditKind::SessionRevoked => "session_revoked",
+            AuditKind::ChatDeleted => "chat_deleted",
+            AuditKind::ChatRestored => "chat_restored",
+            AuditKind::ShareCreated => "share_created",
+            AuditKind::AccountExported => "account_exported",
+            AuditKind::AccountDeletionRequested => "account_deletion_requested",
+            AuditKind::AccountDeletionCancelled => "account_deletion_cancelled",
+            AuditKind::AccountDeleted => "account_deleted",
+            AuditKind::AdminAction => "admin_action",
+        }
+    }
+
+    /// The kind stored as `kind`, if it is one
+    pub fn parse(kind: &str) -> Option<AuditKind> {
+        AuditKind::ALL
+            .into_iter()
+            .find(|known| known.as_str() == kind)
+    }
+}
+
+/// The API schema of an event's `kind`, one
+/// of the [`AuditKind`] strings
+pub fn kind_schema() -> Object {
+    ObjectBuilder::new()
+        .schema_type(Type::String)
+        .enum_values(Some(AuditKind::ALL.map(|kind| kind.as_str())))
+        .build()
+}
+
+/// Where a request came from
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ClientInfo {
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+/// An event to be recorded
+pub struct NewAuditEvent {
+    pub kind: AuditKind,
+    pub user_id: Option<i32>,
+    pub detail: String,
+    pub client: ClientInfo,
+}
+
+impl NewAuditEvent {
+    pub fn new(kind: AuditKind, user_id: Option<i32>, detail: impl Into<String>) -> Self {
+        NewAuditEvent {
+            kind,
+            user_id,
+            detail: detail.into(),
+            client: ClientInfo::default(),
+        }
+    }
+
+    pub fn client(mut self, client: &ClientInfo) -> Self {
+        self.client = client.clone();
+        self
+    }
+}
+
+/// Which events to list, newest first
+#[derive(Clone, Copy, Debug)]
+pub struct AuditQuery {
+    /// Only the events concerning this user
+    pub user_id: Option<i32>,
+    pub kind: Option<AuditKind>,
+    /// Only the events older than this one, to page
+    /// back through the log
+    pub before: Option<i32>,
+    pub limit: usize,
+}
+
+impl AuditQuery {
+    /// Whether `event` is one the query lists, ignoring its limit
+    pub fn matches(&self, event: &AuditEvent) -> bool {
+        self.user_id
+            .is_none_or(|user_id| event.user_id == Some(user_id))
+            && self.kind.is_none_or(|kind| event.kind == kind.as_str())
+            && self.before.is_none_or(|before| event.id < before)
+    }
+}
+
+impl AuditEvent {
+    pub async fn create(
+        db: Arc<Database>,
+        new_event: NewAuditEvent,
+    ) -> Result<AuditEvent, libserver::ServiceError> {
+        let NewAuditEvent {
+            kind,
+            user_id,
+            detail,
+            client: ClientInfo { ip, user_agent },
+        } = new_event;
+        let event = diesel::insert_into(schema::audit_events::table)
+            .values(NewAuditEventRow {
+                user_id,
+                kind: kind.as_str().into(),
+                detail,
+                ip,
+                user_agent,
+            })
+            .returning(AuditEvent::as_returning())
+            .get_result(db)
+            .await?;
+        Ok(event)
+    }
+
+    pub async fn list(
+        db: Arc<Database>,
+        query: &AuditQuery,
+    ) -> Result<Vec<AuditEvent>, libserver::ServiceError> {
+        let mut events = schema::audit_events::table
+            .order(schema::audit_events::id.desc())
+            .limit(query.limit as i64)
+            .into_boxed();
+        if let Some(user_id) = query.user_id {
+            events = events.filter(schema::audit_events::user_id.eq(user_id));
+        }
+        if let Some(kind) = query.kind {
+            events = events.filter(schema::audit_events::kind.eq(kind.as_str()));
+        }
+        if let Some(before) = query.before {
+            events = events.filter(schema::audit_events::id.lt(before));
+        }
+        let events = events.get_results(db).await?;
+        Ok(events)
+    }
+}
+
+/// Records `event`, logging rather than returning a failure
+///
+/// Whatever is being audited has happened, or been refused,
+/// by the time it's recorded, so a log that can't be written
+/// doesn't change the outcome of the request.
+pub async fn record(store: &dyn Store, event: NewAuditEvent) {
+    let kind = event.kind.as_str();
+    if let Err(err) = store.record_audit_event(event).await {
+        eprintln!("failed to record {kind} audit event: {err}");
+    }
+}
+
+/// Holds back repeats of an event from the same client,
+/// so a client can't fill the append-only log by repeating
+/// a request that's refused before anything is looked up,
+/// such as one with a made-up session token
+///
+/// The first event from a client is admitted, then the
+/// rest until `window` has passed are only counted. The
+/// next one admitted after that carries the count. At most
+/// `max_clients` clients are tracked: clients whose window
+/// has passed are forgotten to make room, and while there's
+/// none, events from new clients are held back uncounted.
+pub struct AuditThrottle {
+    window: Duration,
+    max_clients: usize,
+    clients: HashMap<Option<String>, ThrottledClient>,
+}
+
+struct ThrottledClient {
+    admitted_at: Instant,
+    held_back: u32,
+}
+
+impl AuditThrottle {
+    pub fn new(window: Duration, max_clients: usize) -> AuditThrottle {
+        AuditThrottle {
+            window,
+            max_clients,
+            clients: HashMap::new(),
+        }
+    }
+
+    /// Whether to record an event from the client at `ip`
+    ///
+    /// Returns how many of its events were held back since
+    /// the last one admitted, or `None` to hold this one back.
+    pub fn admit(&mut self, ip: Option<&str>, now: Instant) -> Option<u32> {
+        let key = ip.map(str::to_owned);
+        if let Some(client) = self.clients.get_mut(&key) {
+            if now.duration_since(client.admitted_at) < self.window {
+                client.held_back = client.held_back.saturating_add(1);
+                return None;
+            }
+            let held_back = client.held_back;
+            *client = ThrottledClient {
+                admitted_at: now,
+                held_back: 0,
+            };
+            return Some(held_back);
+        }
+
+        if self.clients.len() >= self.max_clients {
+            let window = self.window;
+            self.clients
+                .retain(|_, client| now.duration_since(client.admitted_at) < window);
+            if self.clients.len() >= self.max_clients {
+                return None;
+            }
+        }
+        self.clients.insert(
+            key,
+            ThrottledClient {
+                admitted_at: now,
+                held_back: 0,
+            },
+        );
+        Some(0)
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = schema::audit_events)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+struct NewAuditEventRow {
+    pub user_id: Option<i32>,
+    pub kind: String,
+    pub detail: String,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
@@ -4,24 +4,42 @@ use chrono::NaiveDateTime;
 use diesel::{
     ExpressionMethods, QueryDsl, Queryable, Selectable, SelectableHelper, prelude::Insertable,
 };
+use serde::Serialize;
+use utoipa::ToSchema;
 
 use crate::{
     Database, RunQueryDsl,
     msg::{self, Msg},
+    output_filters::OutputFilters,
     schema,
 };
 
-#[derive(Queryable, Selectable)]
+#[derive(Queryable, Selectable, Serialize, ToSchema, Clone, Debug)]
 #[diesel(table_name = schema::chats)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct Chat {
     pub id: i32,
+    #[schema(required)]
     pub head_msg: Option<i32>,
     pub user_id: i32,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    #[schema(required)]
     pub name: Option<String>,
     pub deleted: bool,
+    /// Whether the model may call the deployment's
+    /// tools when replying in this chat
+    pub tools_enabled: bool,
+    /// How the chat's replies are filtered, or `null`
+    /// if they're filtered as its persona's are
+    #[schema(required)]
+    pub output_filters: Option<OutputFilters>,
+    /// Whether the user's custom instructions are
+    /// merged into this chat's system prompt
+    pub instructions_enabled: bool,
+    /// The id of the persona the chat is answered in, kept
+    /// from when it was created
+    pub persona: String,
 }
 
 impl Chat {
@@ -50,11 +68,13 @@ impl Chat {
         db: Arc<Database>,
         user_id: i32,
         name: Option<String>,
+        persona: String,
     ) -> Result<Chat, libserver::ServiceError> {
         NewChat {
             user_id,
             name,
             deleted: false,
+            persona,
         }
         .create(db)
         .await
@@ -74,6 +94,58 @@ impl Chat {
         Ok(msgs)
     }
 
+    pub async fn set_name(
+        &self,
+        db: Arc<Database>,
+        name: impl Into<String>,
+    ) -> Result<Chat, libserver::ServiceError> {
+        let chat = diesel::update(schema::chats::table.find(self.id))
+            .set(schema::chats::name.eq(name.into()))
+            .returning(Chat::as_returning())
+            .get_result(db)
+            .await?;
+        Ok(chat)
+    }
+
+    pub async fn set_tools_enabled(
+        &self,
+        db: Arc<Database>,
+        enabled: bool,
+    ) -> Result<Chat, libserver::ServiceError> {
+        let chat = diesel::update(schema::chats::table.find(self.id))
+            .set(schema::chats::tools_enabled.eq(enabled))
+            .returning(Chat::as_returning())
+            .get_result(db)
+            .await?;
+        Ok(chat)
+    }
+
+    pub async fn set_instructions_enabled(
+        &self,
+        db: Arc<Database>,
+        enabled: bool,
+    ) -> Result<Chat, libserver::ServiceError> {
+        let chat = diesel::update(schema::chats::table.find(self.id))
+            .set(schema::chats::instructions_enabled.eq(enabled))
+            .returning(Chat::as_returning())
+            .get_result(db)
+            .await?;
+        Ok(chat)
+    }
+
+    pub async fn set_output_filters(
+        &self,
+        db: Arc<Database>,
+        filters: Option<OutputFilters>,
+    ) -> Result<Chat, libserver::ServiceError> {
+        let chat = diesel::update(schema::chats::table.find(self.id))
+            .set(schema::chats::output_filters.eq(filters))
+            .returning(Chat::as_returning())
+            .get_result(db)
+            .await?;
+        Ok(chat)
+    }
+
     pub async fn delete(mut self, db: Arc<Database>) -> Result<(), libserver::ServiceError> {
         self.deleted = true;
         diesel::update(schema::chats::table.find(self.id))
@@ -82,6 +154,15 @@ impl Chat {
             .await?;
         Ok(())
     }
+
+    pub async fn restore(self, db: Arc<Database>) -> Result<Chat, libserver::ServiceError> {
+        let chat = diesel::update(schema::chats::table.find(self.id))
+            .set(schema::chats::deleted.eq(false))
+            .returning(Chat::as_returning())
+            .get_result(db)
+            .await?;
+        Ok(chat)
+    }
 }
 
 #[derive(Insertable)]
@@ -91,6 +172,7 @@ struct NewChat {
     pub user_id: i32,
     pub name: Option<String>,
     pub deleted: bool,
+    pub persona: String,
 }
 
 impl NewChat {
@@ -0,0 +1,204 @@
+use std::{io::Write, sync::Arc};
+
+use chrono::NaiveDateTime;
+use diesel::{
+    AsExpression, ExpressionMethods, FromSqlRow, QueryDsl, Queryable, Selectable, SelectableHelper,
+    deserialize::{self, FromSql},
+    pg::{Pg, PgValue},
+    prelude::Insertable,
+    serialize::{self, IsNull, Output, ToSql},
+    sql_types::Jsonb,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{Database, RunQueryDsl, schema};
+
+/// A document in a user's knowledge base, which
+/// replies can draw on
+#[derive(Queryable, Selectable, Serialize, ToSchema, Clone, Debug)]
+#[diesel(table_name = schema::documents)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Document {
+    pub id: i32,
+    pub user_id: i32,
+    pub name: String,
+    pub created_at: NaiveDateTime,
+}
+
+/// A passage of a document, with its embedding
+#[derive(Queryable, Selectable, Clone, Debug)]
+#[diesel(table_name = schema::document_chunks)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct DocumentChunk {
+    pub id: i32,
+    pub document_id: i32,
+    pub user_id: i32,
+    /// Where the chunk falls in its document, from 0
+    pub chunk_index: i32,
+    pub body: String,
+    /// The model the chunk was embedded with. Only chunks
+    /// embedded by the current model are searched
+    pub embedding_model: String,
+    /// See [`encode_embedding`]
+    pub embedding: Vec<u8>,
+}
+
+/// A chunk of a document to be saved
+pub struct NewChunk {
+    pub body: String,
+    pub embedding_model: String,
+    pub embedding: Vec<f32>,
+}
+
+/// An embedding as it is stored: its values as
+/// little-endian `f32`s, one after the other
+pub fn encode_embedding(embedding: &[f32]) -> Vec<u8> {
+    embedding.iter().flat_map(|x| x.to_le_bytes()).collect()
+}
+
+pub fn decode_embedding(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect()
+}
+
+impl Document {
+    /// Saves a document with its chunks, which are
+    /// numbered in the order given
+    pub async fn create(
+        db: Arc<Database>,
+        user_id: i32,
+        name: String,
+        chunks: Vec<NewChunk>,
+    ) -> Result<Document, libserver::ServiceError> {
+        let document = diesel::insert_into(schema::documents::table)
+            .values((
+                schema::documents::user_id.eq(user_id),
+                schema::documents::name.eq(name),
+            ))
+            .returning(Document::as_returning())
+            .get_result(db.clone())
+            .await?;
+
+        let chunks = chunks
+            .into_iter()
+            .enumerate()
+            .map(|(index, chunk)| NewDocumentChunk {
+                document_id: document.id,
+                user_id,
+                chunk_index: index as i32,
+                body: chunk.body,
+                embedding_model: chunk.embedding_model,
+                embedding: encode_embedding(&chunk.embedding),
+            })
+            .collect::<Vec<_>>();
+        if !chunks.is_empty() {
+            diesel::insert_into(schema::document_chunks::table)
+                .values(chunks)
+                .execute(db)
+                .await?;
+        }
+
+        Ok(document)
+    }
+
+    pub async fn get_by_id(
+        db: Arc<Database>,
+        document_id: i32,
+    ) -> Result<Document, libserver::ServiceError> {
+        let document = schema::documents::table
+            .find(document_id)
+            .get_result(db)
+            .await?;
+        Ok(document)
+    }
+
+    pub async fn get_all_for_user(
+        db: Arc<Database>,
+        user_id: i32,
+    ) -> Result<Vec<Document>, libserver::ServiceError> {
+        let documents = schema::documents::table
+            .filter(schema::documents::user_id.eq(user_id))
+            .order(schema::documents::id)
+            .get_results(db)
+            .await?;
+        Ok(documents)
+    }
+
+    /// Deletes the document along with its chunks
+    pub async fn delete(self, db: Arc<Database>) -> Result<(), libserver::ServiceError> {
+        diesel::delete(schema::documents::table.find(self.id))
+            .execute(db)
+            .await?;
+        Ok(())
+    }
+}
+
+impl DocumentChunk {
+    pub fn embedding(&self) -> Vec<f32> {
+        decode_embedding(&self.embedding)
+    }
+
+    /// The user's chunks embedded with `embedding_model`
+    pub async fn get_all_for_user(
+        db: Arc<Database>,
+        user_id: i32,
+        embedding_model: String,
+    ) -> Result<Vec<DocumentChunk>, libserver::ServiceError> {
+        let chunks = schema::document_chunks::table
+            .filter(schema::document_chunks::user_id.eq(user_id))
+            .filter(schema::document_chunks::embedding_model.eq(embedding_model))
+            .order(schema::document_chunks::id)
+            .get_results(db)
+            .await?;
+        Ok(chunks)
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = schema::document_chunks)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+struct NewDocumentChunk {
+    pub document_id: i32,
+    pub user_id: i32,
+    pub chunk_index: i32,
+    pub body: String,
+    pub embedding_model: String,
+    pub embedding: Vec<u8>,
+}
+
+/// A document chunk a reply was given to draw on, which
+/// the reply cites by its position in the list, from 1
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, PartialEq)]
+pub struct Citation {
+    pub document_id: i32,
+    pub document_name: String,
+    pub chunk_id: i32,
+    pub chunk_index: i32,
+}
+
+/// The citations saved with an AI message
+#[derive(
+    Serialize, Deserialize, ToSchema, AsExpression, FromSqlRow, Clone, Debug, Default, PartialEq,
+)]
+#[diesel(sql_type = Jsonb)]
+#[serde(transparent)]
+pub struct Citations(pub Vec<Citation>);
+
+impl FromSql<Jsonb, Pg> for Citations {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        let value = <serde_json::Value as FromSql<Jsonb, Pg>>::from_sql(bytes)?;
+        Ok(serde_json::from_value(value)?)
+    }
+}
+
+impl ToSql<Jsonb, Pg> for Citations {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        // JSONB's binary format is a version byte followed by the JSON text
+        out.write_all(&[1])?;
+        serde_json::to_writer(out, self)?;
+        Ok(IsNull::No)
+    }
+}
@@ -0,0 +1,80 @@
+use std::io::Write;
+
+use diesel::{
+    AsExpression, FromSqlRow,
+    deserialize::{self, FromSql},
+    pg::{Pg, PgValue},
+    serialize::{self, IsNull, Output, ToSql},
+    sql_types::Jsonb,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Sampling parameters for generating a reply
+///
+/// Every parameter is optional; those left unset use the
+/// provider's default. Clients send these with a prompt, and
+/// the values actually applied, after validation against the
+/// deployment's limits, are saved with the AI message.
+#[derive(
+    Serialize, Deserialize, ToSchema, AsExpression, FromSqlRow, Clone, Debug, Default, PartialEq,
+)]
+#[diesel(sql_type = Jsonb)]
+pub struct GenParams {
+    /// Higher values give more varied replies,
+    /// lower values more deterministic ones
+    pub temperature: Option<f32>,
+
+    /// Nucleus sampling: only tokens within the
+    /// top `top_p` probability mass are considered
+    pub top_p: Option<f32>,
+
+    /// The most tokens the reply may be
+    pub max_tokens: Option<u32>,
+
+    /// Sequences that end the reply when generated
+    pub stop: Option<Vec<String>>,
+
+    /// Makes sampling repeatable, as far as the
+    /// provider supports it
+    pub seed: Option<i64>,
+
+    /// Penalizes tokens that have appeared at all,
+    /// encouraging new topics
+    pub presence_penalty: Option<f32>,
+
+    /// Penalizes tokens by how often they have
+    /// appeared, discouraging repetition
+    pub frequency_penalty: Option<f32>,
+}
+
+impl GenParams {
+    /// These parameters, with those left unset taken from `defaults`
+    pub fn or(self, defaults: &GenParams) -> GenParams {
+        GenParams {
+            temperature: self.temperature.or(defaults.temperature),
+            top_p: self.top_p.or(defaults.top_p),
+            max_tokens: self.max_tokens.or(defaults.max_tokens),
+            stop: self.stop.or_else(|| defaults.stop.clone()),
+            seed: self.seed.or(defaults.seed),
+            presence_penalty: self.presence_penalty.or(defaults.presence_penalty),
+            frequency_penalty: self.frequency_penalty.or(defaults.frequency_penalty),
+        }
+    }
+}
+
+impl FromSql<Jsonb, Pg> for GenParams {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        let value = <serde_json::Value as FromSql<Jsonb, Pg>>::from_sql(bytes)?;
+        Ok(serde_json::from_value(value)?)
+    }
+}
+
+impl ToSql<Jsonb, Pg> for GenParams {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        // JSONB's binary format is a version byte followed by the JSON text
+        out.write_all(&[1])?;
+        serde_json::to_writer(out, self)?;
+        Ok(IsNull::No)
+    }
+}
@@ -0,0 +1,122 @@
+use std::sync::Arc;
+
+use chrono::NaiveDateTime;
+use diesel::{
+    ExpressionMethods, QueryDsl, Queryable, Selectable, SelectableHelper, prelude::Insertable,
+    upsert::excluded,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{Database, RunQueryDsl, schema};
+
+/// What a user has told the model about themselves and
+/// how they'd like it to reply
+///
+/// Merged into the system prompt of the user's chats,
+/// after the deployment's, unless a chat opts out.
+#[derive(Queryable, Selectable, Serialize, ToSchema, Clone, Debug, PartialEq)]
+#[diesel(table_name = schema::custom_instructions)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct CustomInstructions {
+    pub user_id: i32,
+    pub about_me: String,
+    pub response_style: String,
+    /// The language replies should be written in,
+    /// e.g. `German`, or empty for the prompt's own
+    pub language: String,
+    pub updated_at: NaiveDateTime,
+}
+
+/// The instructions a user sets, replacing their old ones.
+/// Fields left out are cleared
+#[derive(Deserialize, ToSchema, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct InstructionsUpdate {
+    pub about_me: String,
+    pub response_style: String,
+    pub language: String,
+}
+
+impl InstructionsUpdate {
+    /// The longest `about_me` and `response_style`, in characters
+    pub const MAX_TEXT_CHARS: usize = 1500;
+    /// The longest `language`, in characters
+    pub const MAX_LANGUAGE_CHARS: usize = 50;
+
+    /// Whether every field is within its length, and the
+    /// language fits on one line
+    pub fn is_valid(&self) -> bool {
+        let fits = |text: &str, max: usize| text.chars().count() <= max;
+        fits(&self.about_me, Self::MAX_TEXT_CHARS)
+            && fits(&self.response_style, Self::MAX_TEXT_CHARS)
+            && fits(&self.language, Self::MAX_LANGUAGE_CHARS)
+            && !self.language.contains(['\n', '\r'])
+    }
+}
+
+impl CustomInstructions {
+    /// Whether there's nothing to merge into the prompt
+    pub fn is_empty(&self) -> bool {
+        [&self.about_me, &self.response_style, &self.language]
+            .iter()
+            .all(|field| field.trim().is_empty())
+    }
+
+    /// The user's instructions, or `None` if they've never set any
+    pub async fn get_for_user(
+        db: Arc<Database>,
+        user_id: i32,
+    ) -> Result<Option<CustomInstructions>, libserver::ServiceError> {
+        let instructions: Vec<CustomInstructions> = schema::custom_instructions::table
+            .find(user_id)
+            .get_results(db)
+            .await?;
+        Ok(instructions.into_iter().next())
+    }
+
+    pub async fn set(
+        db: Arc<Database>,
+        user_id: i32,
+        update: InstructionsUpdate,
+    ) -> Result<CustomInstructions, libserver::ServiceError> {
+        use schema::custom_instructions::dsl;
+
+        let InstructionsUpdate {
+            about_me,
+            response_style,
+            language,
+        } = update;
+        let instructions = diesel::insert_into(dsl::custom_instructions)
+            .values(NewInstructionsRow {
+                user_id,
+                about_me,
+                response_style,
+                language,
+                updated_at: chrono::Utc::now().naive_utc(),
+            })
+            .on_conflict(dsl::user_id)
+            .do_update()
+            .set((
+                dsl::about_me.eq(excluded(dsl::about_me)),
+                dsl::response_style.eq(excluded(dsl::response_style)),
+                dsl::language.eq(excluded(dsl::language)),
+                dsl::updated_at.eq(excluded(dsl::updated_at)),
+            ))
+            .returning(CustomInstructions::as_returning())
+            .get_result(db)
+            .await?;
+        Ok(instructions)
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = schema::custom_instructions)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+struct NewInstructionsRow {
+    pub user_id: i32,
+    pub about_me: String,
+    pub response_style: String,
+    pub language: String,
+    pub updated_at: NaiveDateTime,
+}
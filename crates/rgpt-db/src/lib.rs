@@ -1,13 +1,24 @@
 pub mod schema;
 
+pub mod account;
+pub mod api_key;
+pub mod attachment;
+pub mod audit;
 pub mod chat;
+pub mod document;
+pub mod gen_params;
+pub mod instructions;
+pub mod moderation;
 pub mod msg;
+pub mod output_filters;
 pub mod session;
+pub mod share;
+pub mod store;
 pub mod user;
 
 use std::{env, future::Future, process::Command, sync::Arc};
 
-use diesel_async::{AsyncConnection, AsyncPgConnection};
+use diesel_async::{AsyncConnection, AsyncPgConnection, scoped_futures::ScopedBoxFuture};
 use tokio::sync::Mutex;
 
 pub fn ensure_migrations() {
@@ -23,7 +34,11 @@ pub fn ensure_migrations() {
 pub async fn make_conn() -> AsyncPgConnection {
     let db_url = std::env::var("CONTAINER_DATABASE_URL").expect("DATABASE_URL must be set");
     println!("{db_url}");
-    AsyncPgConnection::establish(&db_url).await.unwrap()
+    make_conn_with_url(&db_url).await
+}
+
+pub async fn make_conn_with_url(db_url: &str) -> AsyncPgConnection {
+    AsyncPgConnection::establish(db_url).await.unwrap()
 }
 
 pub struct Database {
@@ -40,6 +55,29 @@ impl Database {
     pub async fn establish_arc() -> Arc<Database> {
         Arc::new(Self::establish_conn().await)
     }
+
+    /// Runs `callback` in a transaction on the connection,
+    /// committing if it succeeds and rolling back if it
+    /// fails. Other queries wait until it's done
+    pub async fn transaction<'a, R, E, F>(&self, callback: F) -> Result<R, E>
+    where
+        F: for<'r> FnOnce(&'r mut AsyncPgConnection) -> ScopedBoxFuture<'a, 'r, Result<R, E>>
+            + Send
+            + 'a,
+        E: From<diesel::result::Error> + Send + 'a,
+        R: Send + 'a,
+    {
+        let mut conn = self.inner.lock().await;
+        conn.transaction(callback).await
+    }
+
+    /// Connects to a database other than the one in
+    /// `CONTAINER_DATABASE_URL`, such as a test database
+    pub async fn establish_with_url(db_url: &str) -> Arc<Database> {
+        Arc::new(Database {
+            inner: Mutex::new(make_conn_with_url(db_url).await),
+        })
+    }
 }
 
 pub trait RunQueryDsl: diesel_async::RunQueryDsl<AsyncPgConnection> {
@@ -0,0 +1,168 @@
+use std::sync::Arc;
+
+use chrono::{NaiveDateTime, Utc};
+use diesel::{
+    ExpressionMethods, QueryDsl, Queryable, Selectable, SelectableHelper, prelude::Insertable,
+};
+use serde::Serialize;
+
+use crate::{Database, RunQueryDsl, schema};
+
+/// A prompt or reply that moderation flagged or blocked,
+/// kept until an operator has reviewed it
+#[derive(Queryable, Selectable, Serialize, Clone, Debug)]
+#[diesel(table_name = schema::moderation_flags)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ModerationFlag {
+    pub id: i32,
+    pub user_id: i32,
+    pub chat_id: Option<i32>,
+    /// The message the text was saved as, which
+    /// blocked prompts never are
+    pub msg_id: Option<i32>,
+    /// One of the [`FlagSource`] strings
+    pub source: String,
+    /// One of the [`FlagAction`] strings
+    pub action: String,
+    /// What the text matched, separated by `; `
+    pub reasons: String,
+    pub content: String,
+    pub created_at: NaiveDateTime,
+    pub reviewed_at: Option<NaiveDateTime>,
+}
+
+/// Where flagged text came from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FlagSource {
+    Prompt,
+    Reply,
+}
+
+impl FlagSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FlagSource::Prompt => "prompt",
+            FlagSource::Reply => "reply",
+        }
+    }
+}
+
+/// What moderation did with flagged text
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FlagAction {
+    /// The text was let through
+    Flag,
+    /// The prompt was rejected, or the reply
+    /// stopped before the text was shown
+    Block,
+}
+
+impl FlagAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FlagAction::Flag => "flag",
+            FlagAction::Block => "block",
+        }
+    }
+}
+
+/// A flag to be saved
+pub struct NewModerationFlag {
+    pub user_id: i32,
+    pub chat_id: Option<i32>,
+    pub msg_id: Option<i32>,
+    pub source: FlagSource,
+    pub action: FlagAction,
+    pub reasons: Vec<String>,
+    pub content: String,
+}
+
+impl ModerationFlag {
+    pub async fn create(
+        db: Arc<Database>,
+        new_flag: NewModerationFlag,
+    ) -> Result<ModerationFlag, libserver::ServiceError> {
+        let NewModerationFlag {
+            user_id,
+            chat_id,
+            msg_id,
+            source,
+            action,
+            reasons,
+            content,
+        } = new_flag;
+        let flag = diesel::insert_into(schema::moderation_flags::table)
+            .values(NewModerationFlagRow {
+                user_id,
+                chat_id,
+                msg_id,
+                source: source.as_str().into(),
+                action: action.as_str().into(),
+                reasons: reasons.join("; "),
+                content,
+            })
+            .returning(ModerationFlag::as_returning())
+            .get_result(db)
+            .await?;
+        Ok(flag)
+    }
+
+    pub async fn get_by_id(
+        db: Arc<Database>,
+        id: i32,
+    ) -> Result<ModerationFlag, libserver::ServiceError> {
+        let flag = schema::moderation_flags::table
+            .find(id)
+            .get_result(db)
+            .await?;
+        Ok(flag)
+    }
+
+    /// Flags oldest first, only those awaiting
+    /// review unless `reviewed` is set
+    pub async fn list(
+        db: Arc<Database>,
+        reviewed: bool,
+    ) -> Result<Vec<ModerationFlag>, libserver::ServiceError> {
+        let flags = if reviewed {
+            schema::moderation_flags::table
+                .order(schema::moderation_flags::id)
+                .get_results(db)
+                .await?
+        } else {
+            schema::moderation_flags::table
+                .filter(schema::moderation_flags::reviewed_at.is_null())
+                .order(schema::moderation_flags::id)
+                .get_results(db)
+                .await?
+        };
+        Ok(flags)
+    }
+
+    /// Marks the flag as reviewed, so it's no longer
+    /// listed as awaiting review
+    pub async fn review(
+        self,
+        db: Arc<Database>,
+    ) -> Result<ModerationFlag, libserver::ServiceError> {
+        let flag = diesel::update(schema::moderation_flags::table.find(self.id))
+            .set(schema::moderation_flags::reviewed_at.eq(Utc::now().naive_utc()))
+            .returning(ModerationFlag::as_returning())
+            .get_result(db)
+            .await?;
+        Ok(flag)
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = schema::moderation_flags)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+struct NewModerationFlagRow {
+    pub user_id: i32,
+    pub chat_id: Option<i32>,
+    pub msg_id: Option<i32>,
+    pub source: String,
+    pub action: String,
+    pub reasons: String,
+    pub content: String,
+}
@@ -1,20 +1,131 @@
 use std::sync::Arc;
 
 use chrono::NaiveDateTime;
-use diesel::{QueryDsl, Queryable, Selectable, SelectableHelper, prelude::Insertable};
+use diesel::{
+    QueryDsl, Queryable, QueryableByName, Selectable, SelectableHelper,
+    prelude::Insertable,
+    sql_types::{BigInt, Integer, Text},
+};
+use serde::Serialize;
+use utoipa::{
+    ToSchema,
+    openapi::schema::{Object, ObjectBuilder, Type},
+};
 
-use crate::{Database, RunQueryDsl, schema};
+use crate::{Database, RunQueryDsl, document::Citations, gen_params::GenParams, schema};
 
-#[derive(Queryable, Selectable, Clone, Debug)]
+#[derive(Queryable, QueryableByName, Selectable, Serialize, ToSchema, Clone, Debug)]
 #[diesel(table_name = schema::msgs)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct Msg {
     pub id: i32,
     pub body: String,
+    #[schema(schema_with = sender_schema)]
     pub sender: String,
     pub user_id: i32,
+    #[schema(required)]
     pub parent_message_id: Option<i32>,
     pub created_at: NaiveDateTime,
+    #[schema(schema_with = status_schema)]
+    pub status: String,
+    /// The model that generated an AI message,
+    /// or `null` for user messages
+    #[schema(required)]
+    pub model: Option<String>,
+    /// The generation parameters applied to an AI
+    /// message, or `null` for user messages
+    #[schema(required)]
+    pub gen_params: Option<GenParams>,
+    /// The document chunks an AI message was given to
+    /// draw on, or `null` if it was given none
+    #[schema(required)]
+    pub citations: Option<Citations>,
+}
+
+/// How an AI message was generated
+///
+/// User messages are created with the default,
+/// which leaves everything unset.
+#[derive(Clone, Debug, Default)]
+pub struct Generation {
+    /// The model that served the reply, if any did
+    pub model: Option<String>,
+    pub params: Option<GenParams>,
+    /// The document chunks the reply was given
+    pub citations: Option<Citations>,
+}
+
+/// How the generation of a message ended
+///
+/// User messages are always `Complete`. AI messages
+/// are stored with whatever text was generated before
+/// the stream ended, along with why it ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MsgStatus {
+    /// The model finished its reply
+    Complete,
+    /// The provider or the server failed mid-generation
+    Error,
+    /// The provider stream ended without a finish reason
+    Interrupted,
+    /// The user stopped the generation
+    Cancelled,
+    /// Moderation stopped the generation
+    Blocked,
+}
+
+impl MsgStatus {
+    pub const ALL: [MsgStatus; 5] = [
+        MsgStatus::Complete,
+        MsgStatus::Error,
+        MsgStatus::Interrupted,
+        MsgStatus::Cancelled,
+        MsgStatus::Blocked,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MsgStatus::Complete => "complete",
+            MsgStatus::Error => "error",
+            MsgStatus::Interrupted => "interrupted",
+            MsgStatus::Cancelled => "cancelled",
+            MsgStatus::Blocked => "blocked",
+        }
+    }
+
+    /// The status stored as `status`, if it is one
+    pub fn parse(status: &str) -> Option<MsgStatus> {
+        MsgStatus::ALL
+            .into_iter()
+            .find(|known| known.as_str() == status)
+    }
+}
+
+/// The API schema of a message's `sender`, which
+/// the database stores as a plain string
+pub fn sender_schema() -> Object {
+    ObjectBuilder::new()
+        .schema_type(Type::String)
+        .enum_values(Some(["user", "ai", "tool"]))
+        .build()
+}
+
+/// The API schema of `sender` for endpoints that leave
+/// tool messages out, and only list the conversation
+pub fn conversation_sender_schema() -> Object {
+    ObjectBuilder::new()
+        .schema_type(Type::String)
+        .enum_values(Some(["user", "ai"]))
+        .build()
+}
+
+/// The API schema of a message's `status`, one
+/// of the [`MsgStatus`] strings
+pub fn status_schema() -> Object {
+    ObjectBuilder::new()
+        .schema_type(Type::String)
+        .enum_values(Some(MsgStatus::ALL.map(|status| status.as_str())))
+        .build()
 }
 
 impl Msg {
@@ -29,17 +140,89 @@ impl Msg {
         sender: impl Into<String>,
         user_id: i32,
         parent_message_id: Option<i32>,
+    ) -> Result<Msg, libserver::ServiceError> {
+        Msg::create_with_status(
+            db,
+            body,
+            sender,
+            user_id,
+            parent_message_id,
+            MsgStatus::Complete,
+            Generation::default(),
+        )
+        .await
+    }
+
+    pub async fn create_with_status(
+        db: Arc<Database>,
+        body: impl Into<String>,
+        sender: impl Into<String>,
+        user_id: i32,
+        parent_message_id: Option<i32>,
+        status: MsgStatus,
+        generation: Generation,
     ) -> Result<Msg, libserver::ServiceError> {
         NewMsg {
             body: body.into(),
             sender: sender.into(),
             user_id,
             parent_message_id,
+            status: status.as_str().into(),
+            model: generation.model,
+            gen_params: generation.params,
+            citations: generation.citations,
         }
         .create(db)
         .await
     }
 
+    /// The user's most recent user and AI messages containing
+    /// `query`, ignoring case, newest first, leaving out those
+    /// of chats in the trash
+    ///
+    /// Messages don't record their chat, so each is traced back
+    /// to the first message of its tree, which is the first of
+    /// its chat's head too.
+    pub async fn search(
+        db: Arc<Database>,
+        user_id: i32,
+        query: &str,
+        limit: i64,
+    ) -> Result<Vec<Msg>, libserver::ServiceError> {
+        let pattern = format!("%{}%", escape_like(query));
+        let msgs = diesel::sql_query(
+            "WITH RECURSIVE tree (id, root) AS (
+                SELECT id, id FROM msgs
+                WHERE user_id = $1 AND parent_message_id IS NULL
+              UNION ALL
+                SELECT msgs.id, tree.root FROM msgs
+                JOIN tree ON msgs.parent_message_id = tree.id
+            )
+            SELECT msgs.* FROM msgs
+            JOIN tree ON tree.id = msgs.id
+            WHERE msgs.user_id = $1
+              AND msgs.sender IN ('user', 'ai')
+              AND msgs.body ILIKE $2
+              AND NOT EXISTS (
+                SELECT 1 FROM chats
+                JOIN tree AS head ON head.id = chats.head_msg
+                WHERE chats.user_id = $1 AND chats.deleted AND head.root = tree.root
+              )
+            ORDER BY msgs.created_at DESC, msgs.id DESC
+            LIMIT $3",
+        )
+        .bind::<Integer, _>(user_id)
+        .bind::<Text, _>(pattern)
+        .bind::<BigInt, _>(limit)
+        .get_results::<Msg>(db)
+        .await?;
+        Ok(msgs)
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.status == MsgStatus::Complete.as_str()
+    }
+
     pub async fn get_msg_chain(
         self,
         db: Arc<Database>,
@@ -56,6 +239,13 @@ impl Msg {
     }
 }
 
+/// Escapes `LIKE` wildcards so `text` only matches itself
+fn escape_like(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
 #[derive(Insertable)]
 #[diesel(table_name = schema::msgs)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
@@ -64,6 +254,10 @@ struct NewMsg {
     pub sender: String,
     pub user_id: i32,
     pub parent_message_id: Option<i32>,
+    pub status: String,
+    pub model: Option<String>,
+    pub gen_params: Option<GenParams>,
+    pub citations: Option<Citations>,
 }
 
 impl NewMsg {
@@ -0,0 +1,85 @@
+use std::io::Write;
+
+use diesel::{
+    AsExpression, FromSqlRow,
+    deserialize::{self, FromSql},
+    pg::{Pg, PgValue},
+    serialize::{self, IsNull, Output, ToSql},
+    sql_types::Jsonb,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// How replies are rewritten into RetroGPT's plaintext
+/// style as they stream
+///
+/// The deployment has its own, which chats without any
+/// use. Fields left out when a chat's are set take their
+/// defaults: every filter but `terminal` on, without
+/// wrapping.
+#[derive(
+    Serialize, Deserialize, ToSchema, AsExpression, FromSqlRow, Clone, Copy, Debug, PartialEq,
+)]
+#[diesel(sql_type = Jsonb)]
+#[serde(default)]
+pub struct OutputFilters {
+    /// Strips Markdown emphasis, headings and quote
+    /// markers, and turns links into their text
+    /// followed by their URL
+    pub markdown: bool,
+
+    /// Turns LaTeX math into ASCII, so
+    /// `$\frac{a}{b}$` becomes `(a)/(b)`
+    pub latex: bool,
+
+    /// Turns fenced code blocks into blocks
+    /// indented by four spaces
+    pub code_fences: bool,
+
+    /// Upper-cases everything but code,
+    /// like an old terminal
+    pub terminal: bool,
+
+    /// The column lines are hard-wrapped at, from
+    /// 20 to 500, or `null` not to wrap them
+    pub wrap_column: Option<u16>,
+}
+
+impl OutputFilters {
+    pub const MIN_WRAP_COLUMN: u16 = 20;
+    pub const MAX_WRAP_COLUMN: u16 = 500;
+
+    /// Whether the wrap column is in range
+    pub fn is_valid(&self) -> bool {
+        self.wrap_column
+            .is_none_or(|column| (Self::MIN_WRAP_COLUMN..=Self::MAX_WRAP_COLUMN).contains(&column))
+    }
+}
+
+impl Default for OutputFilters {
+    fn default() -> Self {
+        OutputFilters {
+            markdown: true,
+            latex: true,
+            code_fences: true,
+            terminal: false,
+            wrap_column: None,
+        }
+    }
+}
+
+impl FromSql<Jsonb, Pg> for OutputFilters {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        let value = <serde_json::Value as FromSql<Jsonb, Pg>>::from_sql(bytes)?;
+        Ok(serde_json::from_value(value)?)
+    }
+}
+
+impl ToSql<Jsonb, Pg> for OutputFilters {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        // JSONB's binary format is a version byte followed by the JSON text
+        out.write_all(&[1])?;
+        serde_json::to_writer(out, self)?;
+        Ok(IsNull::No)
+    }
+}
@@ -1,5 +1,54 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    api_keys (id) {
+        id -> Int4,
+        user_id -> Int4,
+        name -> Varchar,
+        key_hash -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    attachments (id) {
+        id -> Int4,
+        user_id -> Int4,
+        msg_id -> Nullable<Int4>,
+        name -> Varchar,
+        media_type -> Varchar,
+        size_bytes -> Int4,
+        content -> Nullable<Text>,
+        storage_key -> Nullable<Varchar>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    audit_events (id) {
+        id -> Int4,
+        user_id -> Nullable<Int4>,
+        kind -> Varchar,
+        detail -> Text,
+        ip -> Nullable<Varchar>,
+        user_agent -> Nullable<Text>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    chat_shares (id) {
+        id -> Int4,
+        token -> Varchar,
+        chat_id -> Int4,
+        user_id -> Int4,
+        head_msg -> Int4,
+        name -> Nullable<Varchar>,
+        created_at -> Timestamp,
+        expires_at -> Nullable<Timestamp>,
+    }
+}
+
 diesel::table! {
     chats (id) {
         id -> Int4,
@@ -9,6 +58,56 @@ diesel::table! {
         updated_at -> Timestamp,
         name -> Nullable<Varchar>,
         deleted -> Bool,
+        tools_enabled -> Bool,
+        output_filters -> Nullable<Jsonb>,
+        instructions_enabled -> Bool,
+        persona -> Varchar,
+    }
+}
+
+diesel::table! {
+    custom_instructions (user_id) {
+        user_id -> Int4,
+        about_me -> Text,
+        response_style -> Text,
+        language -> Varchar,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    document_chunks (id) {
+        id -> Int4,
+        document_id -> Int4,
+        user_id -> Int4,
+        chunk_index -> Int4,
+        body -> Text,
+        embedding_model -> Varchar,
+        embedding -> Bytea,
+    }
+}
+
+diesel::table! {
+    documents (id) {
+        id -> Int4,
+        user_id -> Int4,
+        name -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    moderation_flags (id) {
+        id -> Int4,
+        user_id -> Int4,
+        chat_id -> Nullable<Int4>,
+        msg_id -> Nullable<Int4>,
+        source -> Varchar,
+        action -> Varchar,
+        reasons -> Text,
+        content -> Text,
+        created_at -> Timestamp,
+        reviewed_at -> Nullable<Timestamp>,
     }
 }
 
@@ -20,6 +119,10 @@ diesel::table! {
         user_id -> Int4,
         parent_message_id -> Nullable<Int4>,
         created_at -> Timestamp,
+        status -> Varchar,
+        model -> Nullable<Varchar>,
+        gen_params -> Nullable<Jsonb>,
+        citations -> Nullable<Jsonb>,
     }
 }
 
@@ -41,12 +144,40 @@ diesel::table! {
         created_at -> Timestamp,
         updated_at -> Timestamp,
         last_login -> Timestamp,
+        disabled -> Bool,
+        delete_after -> Nullable<Timestamp>,
     }
 }
 
+diesel::joinable!(api_keys -> users (user_id));
+diesel::joinable!(attachments -> msgs (msg_id));
+diesel::joinable!(attachments -> users (user_id));
+diesel::joinable!(chat_shares -> chats (chat_id));
+diesel::joinable!(chat_shares -> msgs (head_msg));
+diesel::joinable!(chat_shares -> users (user_id));
 diesel::joinable!(chats -> msgs (head_msg));
 diesel::joinable!(chats -> users (user_id));
+diesel::joinable!(custom_instructions -> users (user_id));
+diesel::joinable!(document_chunks -> documents (document_id));
+diesel::joinable!(document_chunks -> users (user_id));
+diesel::joinable!(documents -> users (user_id));
+diesel::joinable!(moderation_flags -> chats (chat_id));
+diesel::joinable!(moderation_flags -> msgs (msg_id));
+diesel::joinable!(moderation_flags -> users (user_id));
 diesel::joinable!(msgs -> users (user_id));
 diesel::joinable!(sessions -> users (user_id));
 
-diesel::allow_tables_to_appear_in_same_query!(chats, msgs, sessions, users,);
+diesel::allow_tables_to_appear_in_same_query!(
+    api_keys,
+    attachments,
+    audit_events,
+    chat_shares,
+    chats,
+    custom_instructions,
+    document_chunks,
+    documents,
+    moderation_flags,
+    msgs,
+    sessions,
+    users,
+);
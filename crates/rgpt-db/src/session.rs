@@ -4,13 +4,16 @@ use chrono::{Duration, NaiveDateTime, Utc};
 use diesel::{
     ExpressionMethods, QueryDsl, Queryable, Selectable, SelectableHelper, prelude::Insertable,
 };
+use serde::Serialize;
 
-use crate::{Database, RunQueryDsl, schema, user::User};
+use crate::{Database, RunQueryDsl, schema};
 
-#[derive(Queryable, Selectable)]
+#[derive(Queryable, Selectable, Serialize, Clone, Debug)]
 #[diesel(table_name = schema::sessions)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct Session {
+    /// Never serialized, as it is a bearer credential
+    #[serde(skip_serializing)]
     pub session_token: String,
     pub user_id: i32,
     pub created_at: NaiveDateTime,
@@ -36,6 +39,30 @@ impl Session {
         Ok(())
     }
 
+    pub async fn get_all_for_user(
+        db: Arc<Database>,
+        user_id: i32,
+    ) -> Result<Vec<Session>, libserver::ServiceError> {
+        let sessions = schema::sessions::table
+            .filter(schema::sessions::user_id.eq(user_id))
+            .get_results(db)
+            .await?;
+        Ok(sessions)
+    }
+
+    /// Deletes every session of the user, returning
+    /// how many were revoked
+    pub async fn revoke_all_for_user(
+        db: Arc<Database>,
+        user_id: i32,
+    ) -> Result<usize, libserver::ServiceError> {
+        let revoked =
+            diesel::delete(schema::sessions::table.filter(schema::sessions::user_id.eq(user_id)))
+                .execute(db)
+                .await?;
+        Ok(revoked)
+    }
+
     pub fn validate(&self) -> bool {
         expires_at_is_valid(&self.expires_at)
     }
@@ -44,37 +71,38 @@ impl Session {
         db: Arc<Database>,
         user_id: i32,
     ) -> Result<Session, libserver::ServiceError> {
-        let expires_at: NaiveDateTime = Utc::now().naive_utc() + Duration::hours(1);
         let session_token = uuid::Uuid::new_v4().into();
 
         NewSession {
             user_id,
-            expires_at,
+            expires_at: new_expiry(),
             session_token,
         }
         .create(db)
         .await
     }
 
+    /// The user's session, replacing it if it has
+    /// expired, and whether it was created
     pub async fn get_for_user(
         db: Arc<Database>,
-        user: &User,
-    ) -> Result<Session, libserver::ServiceError> {
+        user_id: i32,
+    ) -> Result<(Session, bool), libserver::ServiceError> {
         let existing_session = schema::sessions::table
-            .filter(schema::sessions::user_id.eq(user.user_id))
+            .filter(schema::sessions::user_id.eq(user_id))
             .limit(1)
             .get_result::<Session>(db.clone())
             .await;
 
         if let Ok(session) = existing_session {
             if session.validate() {
-                return Ok(session);
+                return Ok((session, false));
             } else {
                 session.delete(db.clone()).await?
             }
         }
 
-        Session::create(db, user.user_id).await
+        Ok((Session::create(db, user_id).await?, true))
     }
 }
 
@@ -98,6 +126,11 @@ impl NewSession {
     }
 }
 
+/// The expiry of a session created now
+pub fn new_expiry() -> NaiveDateTime {
+    Utc::now().naive_utc() + Duration::hours(1)
+}
+
 pub fn expires_at_is_valid(expires_at: &NaiveDateTime) -> bool {
     expires_at > &Utc::now().naive_utc()
 }
@@ -0,0 +1,131 @@
+use std::sync::Arc;
+
+use chrono::{NaiveDateTime, Utc};
+use diesel::{
+    ExpressionMethods, QueryDsl, Queryable, Selectable, SelectableHelper, prelude::Insertable,
+};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{Database, RunQueryDsl, schema};
+
+/// A read-only link to a chat as it was when it was shared
+///
+/// Anyone with the token can read the branch ending at
+/// `head_msg`, until the link expires or its owner revokes it.
+#[derive(Queryable, Selectable, Serialize, ToSchema, Clone, Debug)]
+#[diesel(table_name = schema::chat_shares)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Share {
+    pub id: i32,
+    pub token: String,
+    pub chat_id: i32,
+    pub user_id: i32,
+    /// The chat's head when it was shared
+    pub head_msg: i32,
+    /// The chat's name when it was shared
+    #[schema(required)]
+    pub name: Option<String>,
+    pub created_at: NaiveDateTime,
+    /// When the link stops working, or `null`
+    /// if it works until it's revoked
+    #[schema(required)]
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+/// A share to be created, which is given its token
+/// when it's saved
+pub struct NewShare {
+    pub chat_id: i32,
+    pub user_id: i32,
+    pub head_msg: i32,
+    pub name: Option<String>,
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+impl Share {
+    pub async fn create(
+        db: Arc<Database>,
+        new_share: NewShare,
+    ) -> Result<Share, libserver::ServiceError> {
+        let NewShare {
+            chat_id,
+            user_id,
+            head_msg,
+            name,
+            expires_at,
+        } = new_share;
+        let share = diesel::insert_into(schema::chat_shares::table)
+            .values(NewShareRow {
+                token: generate_token(),
+                chat_id,
+                user_id,
+                head_msg,
+                name,
+                expires_at,
+            })
+            .returning(Share::as_returning())
+            .get_result(db)
+            .await?;
+        Ok(share)
+    }
+
+    pub async fn get_by_id(db: Arc<Database>, id: i32) -> Result<Share, libserver::ServiceError> {
+        let share = schema::chat_shares::table.find(id).get_result(db).await?;
+        Ok(share)
+    }
+
+    pub async fn get_by_token(
+        db: Arc<Database>,
+        token: &str,
+    ) -> Result<Share, libserver::ServiceError> {
+        let share = schema::chat_shares::table
+            .filter(schema::chat_shares::token.eq(token))
+            .get_result(db)
+            .await?;
+        Ok(share)
+    }
+
+    pub async fn get_all_for_chat(
+        db: Arc<Database>,
+        chat_id: i32,
+    ) -> Result<Vec<Share>, libserver::ServiceError> {
+        let shares = schema::chat_shares::table
+            .filter(schema::chat_shares::chat_id.eq(chat_id))
+            .order(schema::chat_shares::id)
+            .get_results(db)
+            .await?;
+        Ok(shares)
+    }
+
+    /// Deletes the share, so its link stops working
+    pub async fn revoke(self, db: Arc<Database>) -> Result<(), libserver::ServiceError> {
+        diesel::delete(schema::chat_shares::table.find(self.id))
+            .execute(db)
+            .await?;
+        Ok(())
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at <= Utc::now().naive_utc())
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = schema::chat_shares)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+struct NewShareRow {
+    pub token: String,
+    pub chat_id: i32,
+    pub user_id: i32,
+    pub head_msg: i32,
+    pub name: Option<String>,
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+/// A random token that can't be guessed from
+/// any other share's
+pub fn generate_token() -> String {
+    uuid::Uuid::new_v4().simple().to_string()
+}
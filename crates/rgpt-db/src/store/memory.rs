@@ -0,0 +1,773 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::{Mutex, MutexGuard},
+};
+
+use async_trait::async_trait;
+use chrono::{NaiveDateTime, Utc};
+
+use super::{NotFound, Store, StoreResult};
+use crate::{
+    account::{AccountData, DocumentPassages},
+    api_key::{self, ApiKey},
+    attachment::{Attachment, NewAttachment},
+    audit::{AuditEvent, AuditQuery, ClientInfo, NewAuditEvent},
+    chat::Chat,
+    document::{self, Document, DocumentChunk, NewChunk},
+    instructions::{CustomInstructions, InstructionsUpdate},
+    moderation::{ModerationFlag, NewModerationFlag},
+    msg::{Generation, Msg, MsgStatus},
+    output_filters::OutputFilters,
+    session::{self, Session},
+    share::{self, NewShare, Share},
+    user::{Usage, User},
+};
+
+/// A store that keeps everything in process memory
+///
+/// Mirrors the behaviour of the Postgres schema, including the
+/// default guest user with id 1, so handlers can be exercised
+/// without a database.
+pub struct MemoryStore {
+    tables: Mutex<Tables>,
+}
+
+#[derive(Default)]
+struct Tables {
+    users: BTreeMap<i32, User>,
+    sessions: HashMap<String, Session>,
+    api_keys: BTreeMap<i32, ApiKey>,
+    custom_instructions: BTreeMap<i32, CustomInstructions>,
+    chats: BTreeMap<i32, Chat>,
+    msgs: BTreeMap<i32, Msg>,
+    attachments: BTreeMap<i32, Attachment>,
+    documents: BTreeMap<i32, Document>,
+    chunks: BTreeMap<i32, DocumentChunk>,
+    shares: BTreeMap<i32, Share>,
+    moderation_flags: BTreeMap<i32, ModerationFlag>,
+    audit_events: BTreeMap<i32, AuditEvent>,
+    next_id: i32,
+}
+
+impl Tables {
+    fn next_id(&mut self) -> i32 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    fn chat_mut(&mut self, chat_id: i32) -> StoreResult<&mut Chat> {
+        Ok(self.chats.get_mut(&chat_id).ok_or(NotFound)?)
+    }
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        let store = MemoryStore {
+            tables: Mutex::default(),
+        };
+        store.insert_user(
+            "0".into(),
+            "default@retrogpt.xyz".into(),
+            "Default User".into(),
+        );
+        store
+    }
+
+    fn tables(&self) -> MutexGuard<'_, Tables> {
+        self.tables.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn insert_user(&self, google_id: String, email: String, name: String) -> User {
+        let mut tables = self.tables();
+        let now = Utc::now().naive_utc();
+        let user = User {
+            user_id: tables.next_id(),
+            google_id,
+            email,
+            name,
+            created_at: now,
+            updated_at: now,
+            last_login: now,
+            disabled: false,
+            delete_after: None,
+        };
+        tables.users.insert(user.user_id, user.clone());
+        user
+    }
+}
+
+#[async_trait]
+impl Store for MemoryStore {
+    async fn user_by_id(&self, user_id: i32) -> StoreResult<User> {
+        Ok(self.tables().users.get(&user_id).cloned().ok_or(NotFound)?)
+    }
+
+    async fn user_by_google_id(&self, google_id: &str) -> StoreResult<User> {
+        let tables = self.tables();
+        let user = tables
+            .users
+            .values()
+            .find(|user| user.google_id == google_id);
+        Ok(user.cloned().ok_or(NotFound)?)
+    }
+
+    async fn create_user(
+        &self,
+        google_id: String,
+        email: String,
+        name: String,
+    ) -> StoreResult<User> {
+        let taken = self
+            .tables()
+            .users
+            .values()
+            .any(|user| user.google_id == google_id || user.email == email);
+        if taken {
+            Err(UniqueViolation)?;
+        }
+        Ok(self.insert_user(google_id, email, name))
+    }
+
+    async fn default_user(&self) -> StoreResult<User> {
+        self.user_by_id(1).await
+    }
+
+    async fn account_data(&self, user_id: i32) -> StoreResult<AccountData> {
+        fn owned<T: Clone>(rows: &BTreeMap<i32, T>, owner: impl Fn(&T) -> bool) -> Vec<T> {
+            rows.values().filter(|row| owner(row)).cloned().collect()
+        }
+
+        let tables = self.tables();
+        let user = tables.users.get(&user_id).cloned().ok_or(NotFound)?;
+
+        let chats = owned(&tables.chats, |chat| chat.user_id == user_id);
+        let msgs = owned(&tables.msgs, |msg| msg.user_id == user_id);
+        let mut sessions = tables
+            .sessions
+            .values()
+            .filter(|session| session.user_id == user_id)
+            .cloned()
+            .collect::<Vec<_>>();
+        sessions.sort_by_key(|session| session.created_at);
+        let count = |n: usize| n as i64;
+        let usage = Usage {
+            user_id,
+            chats: count(chats.iter().filter(|chat| !chat.deleted).count()),
+            deleted_chats: count(chats.iter().filter(|chat| chat.deleted).count()),
+            user_msgs: count(msgs.iter().filter(|msg| msg.sender == "user").count()),
+            ai_msgs: count(msgs.iter().filter(|msg| msg.sender == "ai").count()),
+            sessions: count(sessions.len()),
+        };
+
+        Ok(AccountData {
+            user,
+            usage,
+            sessions,
+            api_keys: owned(&tables.api_keys, |key| key.user_id == user_id),
+            instructions: tables.custom_instructions.get(&user_id).cloned(),
+            chats,
+            msgs,
+            attachments: owned(&tables.attachments, |attachment| {
+                attachment.user_id == user_id
+            }),
+            documents: DocumentPassages::collect(
+                owned(&tables.documents, |document| document.user_id == user_id),
+                owned(&tables.chunks, |chunk| chunk.user_id == user_id),
+            ),
+            shares: owned(&tables.shares, |share| share.user_id == user_id),
+            activity: owned(&tables.audit_events, |event| event.user_id == Some(user_id)),
+        })
+    }
+
+    async fn schedule_user_deletion(
+        &self,
+        user_id: i32,
+        delete_after: Option<NaiveDateTime>,
+    ) -> StoreResult<User> {
+        let mut tables = self.tables();
+        let user = tables.users.get_mut(&user_id).ok_or(NotFound)?;
+        user.delete_after = delete_after;
+        Ok(user.clone())
+    }
+
+    async fn users_due_for_deletion(&self, now: NaiveDateTime) -> StoreResult<Vec<User>> {
+        let tables = self.tables();
+        let users = tables
+            .users
+            .values()
+            .filter(|user| {
+                user.delete_after
+                    .is_some_and(|delete_after| delete_after <= now)
+            })
+            .cloned()
+            .collect();
+        Ok(users)
+    }
+
+    async fn delete_user(&self, user_id: i32) -> StoreResult<Vec<Attachment>> {
+        let mut tables = self.tables();
+        tables.users.remove(&user_id).ok_or(NotFound)?;
+        tables
+            .sessions
+            .retain(|_, session| session.user_id != user_id);
+        tables.api_keys.retain(|_, key| key.user_id != user_id);
+        tables.custom_instructions.remove(&user_id);
+        tables.chats.retain(|_, chat| chat.user_id != user_id);
+        tables.msgs.retain(|_, msg| msg.user_id != user_id);
+        tables
+            .documents
+            .retain(|_, document| document.user_id != user_id);
+        tables.chunks.retain(|_, chunk| chunk.user_id != user_id);
+        tables.shares.retain(|_, share| share.user_id != user_id);
+        tables
+            .moderation_flags
+            .retain(|_, flag| flag.user_id != user_id);
+        let (attachments, kept): (BTreeMap<_, _>, _) = std::mem::take(&mut tables.attachments)
+            .into_iter()
+            .partition(|(_, attachment)| attachment.user_id == user_id);
+        tables.attachments = kept;
+        Ok(attachments.into_values().collect())
+    }
+
+    async fn custom_instructions(&self, user_id: i32) -> StoreResult<Option<CustomInstructions>> {
+        Ok(self.tables().custom_instructions.get(&user_id).cloned())
+    }
+
+    async fn set_custom_instructions(
+        &self,
+        user_id: i32,
+        update: InstructionsUpdate,
+    ) -> StoreResult<CustomInstructions> {
+        let mut tables = self.tables();
+        if !tables.users.contains_key(&user_id) {
+            Err(NotFound)?;
+        }
+        let InstructionsUpdate {
+            about_me,
+            response_style,
+            language,
+        } = update;
+        let instructions = CustomInstructions {
+            user_id,
+            about_me,
+            response_style,
+            language,
+            updated_at: Utc::now().naive_utc(),
+        };
+        tables
+            .custom_instructions
+            .insert(user_id, instructions.clone());
+        Ok(instructions)
+    }
+
+    async fn session_by_token(&self, token: &str) -> StoreResult<Session> {
+        Ok(self.tables().sessions.get(token).cloned().ok_or(NotFound)?)
+    }
+
+    async fn session_for_user(&self, user_id: i32) -> StoreResult<(Session, bool)> {
+        let mut tables = self.tables();
+
+        let existing = tables
+            .sessions
+            .values()
+            .find(|session| session.user_id == user_id)
+            .cloned();
+
+        if let Some(session) = existing {
+            if session.validate() {
+                return Ok((session, false));
+            }
+            tables.sessions.remove(&session.session_token);
+        }
+
+        let session = Session {
+            session_token: uuid::Uuid::new_v4().into(),
+            user_id,
+            created_at: Utc::now().naive_utc(),
+            expires_at: session::new_expiry(),
+        };
+        tables
+            .sessions
+            .insert(session.session_token.clone(), session.clone());
+        Ok((session, true))
+    }
+
+    async fn delete_session(&self, token: &str) -> StoreResult<()> {
+        self.tables().sessions.remove(token).ok_or(NotFound)?;
+        Ok(())
+    }
+
+    async fn create_api_key(&self, user_id: i32, name: String) -> StoreResult<(ApiKey, String)> {
+        let mut tables = self.tables();
+        if !tables.users.contains_key(&user_id) {
+            Err(NotFound)?;
+        }
+        let secret = api_key::generate_secret();
+        let key = ApiKey {
+            id: tables.next_id(),
+            user_id,
+            name,
+            key_hash: api_key::hash_secret(&secret),
+            created_at: Utc::now().naive_utc(),
+        };
+        tables.api_keys.insert(key.id, key.clone());
+        Ok((key, secret))
+    }
+
+    async fn api_key_by_secret(&self, secret: &str) -> StoreResult<ApiKey> {
+        let key_hash = api_key::hash_secret(secret);
+        let tables = self.tables();
+        let key = tables
+            .api_keys
+            .values()
+            .find(|key| key.key_hash == key_hash);
+        Ok(key.cloned().ok_or(NotFound)?)
+    }
+
+    async fn chat_by_id(&self, chat_id: i32) -> StoreResult<Chat> {
+        Ok(self.tables().chats.get(&chat_id).cloned().ok_or(NotFound)?)
+    }
+
+    async fn create_chat(
+        &self,
+        user_id: i32,
+        name: Option<String>,
+        persona: String,
+    ) -> StoreResult<Chat> {
+        let mut tables = self.tables();
+        let now = Utc::now().naive_utc();
+        let chat = Chat {
+            id: tables.next_id(),
+            head_msg: None,
+            user_id,
+            created_at: now,
+            updated_at: now,
+            name,
+            deleted: false,
+            tools_enabled: true,
+            output_filters: None,
+            instructions_enabled: true,
+            persona,
+        };
+        tables.chats.insert(chat.id, chat.clone());
+        Ok(chat)
+    }
+
+    async fn user_chats(&self, user_id: i32) -> StoreResult<Vec<Chat>> {
+        let tables = self.tables();
+        let chats = tables
+            .chats
+            .values()
+            .filter(|chat| chat.user_id == user_id && !chat.deleted)
+            .cloned()
+            .collect();
+        Ok(chats)
+    }
+
+    async fn set_chat_name(&self, chat_id: i32, name: String) -> StoreResult<Chat> {
+        let mut tables = self.tables();
+        let chat = tables.chat_mut(chat_id)?;
+        chat.name = Some(name);
+        Ok(chat.clone())
+    }
+
+    async fn set_chat_tools_enabled(&self, chat_id: i32, enabled: bool) -> StoreResult<Chat> {
+        let mut tables = self.tables();
+        let chat = tables.chat_mut(chat_id)?;
+        chat.tools_enabled = enabled;
+        Ok(chat.clone())
+    }
+
+    async fn set_chat_instructions_enabled(
+        &self,
+        chat_id: i32,
+        enabled: bool,
+    ) -> StoreResult<Chat> {
+        let mut tables = self.tables();
+        let chat = tables.chat_mut(chat_id)?;
+        chat.instructions_enabled = enabled;
+        Ok(chat.clone())
+    }
+
+    async fn set_chat_output_filters(
+        &self,
+        chat_id: i32,
+        filters: Option<OutputFilters>,
+    ) -> StoreResult<Chat> {
+        let mut tables = self.tables();
+        let chat = tables.chat_mut(chat_id)?;
+        chat.output_filters = filters;
+        Ok(chat.clone())
+    }
+
+    async fn set_chat_head(&self, chat_id: i32, msg_id: i32) -> StoreResult<Chat> {
+        let mut tables = self.tables();
+        if !tables.msgs.contains_key(&msg_id) {
+            Err(NotFound)?;
+        }
+        let chat = tables.chat_mut(chat_id)?;
+        chat.head_msg = Some(msg_id);
+        chat.updated_at = Utc::now().naive_utc();
+        Ok(chat.clone())
+    }
+
+    async fn delete_chat(&self, chat_id: i32) -> StoreResult<()> {
+        self.tables().chat_mut(chat_id)?.deleted = true;
+        Ok(())
+    }
+
+    async fn restore_chat(&self, chat_id: i32) -> StoreResult<Chat> {
+        let mut tables = self.tables();
+        let chat = tables.chat_mut(chat_id)?;
+        chat.deleted = false;
+        Ok(chat.clone())
+    }
+
+    async fn msg_by_id(&self, msg_id: i32) -> StoreResult<Msg> {
+        Ok(self.tables().msgs.get(&msg_id).cloned().ok_or(NotFound)?)
+    }
+
+    async fn create_msg(
+        &self,
+        body: String,
+        sender: String,
+        user_id: i32,
+        parent_message_id: Option<i32>,
+        status: MsgStatus,
+        generation: Generation,
+    ) -> StoreResult<Msg> {
+        let mut tables = self.tables();
+        if parent_message_id.is_some_and(|parent| !tables.msgs.contains_key(&parent)) {
+            Err(NotFound)?;
+        }
+        let msg = Msg {
+            id: tables.next_id(),
+            body,
+            sender,
+            user_id,
+            parent_message_id,
+            created_at: Utc::now().naive_utc(),
+            status: status.as_str().into(),
+            model: generation.model,
+            gen_params: generation.params,
+            citations: generation.citations,
+        };
+        tables.msgs.insert(msg.id, msg.clone());
+        Ok(msg)
+    }
+
+    async fn search_msgs(&self, user_id: i32, query: &str, limit: usize) -> StoreResult<Vec<Msg>> {
+        let query = query.to_lowercase();
+        let tables = self.tables();
+        // A chat's messages all descend from the first message of its head
+        let root = |mut msg_id: i32| {
+            while let Some(parent) = tables
+                .msgs
+                .get(&msg_id)
+                .and_then(|msg| msg.parent_message_id)
+            {
+                msg_id = parent;
+            }
+            msg_id
+        };
+        let trashed = tables
+            .chats
+            .values()
+            .filter(|chat| chat.user_id == user_id && chat.deleted)
+            .filter_map(|chat| chat.head_msg)
+            .map(root)
+            .collect::<HashSet<_>>();
+        // Ids are handed out in order, so the newest messages have the highest
+        let msgs = tables
+            .msgs
+            .values()
+            .rev()
+            .filter(|msg| msg.user_id == user_id)
+            .filter(|msg| msg.sender == "user" || msg.sender == "ai")
+            .filter(|msg| msg.body.to_lowercase().contains(&query))
+            .filter(|msg| !trashed.contains(&root(msg.id)))
+            .take(limit)
+            .cloned()
+            .collect();
+        Ok(msgs)
+    }
+
+    async fn msg_chain(&self, msg_id: i32) -> StoreResult<Vec<Msg>> {
+        let tables = self.tables();
+        let mut chain = vec![];
+        let mut next = Some(msg_id);
+        while let Some(id) = next {
+            let msg = tables.msgs.get(&id).ok_or(NotFound)?;
+            next = msg.parent_message_id;
+            chain.push(msg.clone());
+        }
+        chain.reverse();
+        Ok(chain)
+    }
+
+    async fn create_attachment(&self, new_attachment: NewAttachment) -> StoreResult<Attachment> {
+        let mut tables = self.tables();
+        if !tables.users.contains_key(&new_attachment.user_id) {
+            Err(NotFound)?;
+        }
+        let NewAttachment {
+            user_id,
+            name,
+            media_type,
+            size_bytes,
+            content,
+            storage_key,
+        } = new_attachment;
+        let attachment = Attachment {
+            id: tables.next_id(),
+            user_id,
+            msg_id: None,
+            name,
+            media_type,
+            size_bytes,
+            content,
+            storage_key,
+            created_at: Utc::now().naive_utc(),
+        };
+        tables.attachments.insert(attachment.id, attachment.clone());
+        Ok(attachment)
+    }
+
+    async fn attachment_by_id(&self, attachment_id: i32) -> StoreResult<Attachment> {
+        let tables = self.tables();
+        let attachment = tables.attachments.get(&attachment_id).cloned();
+        Ok(attachment.ok_or(NotFound)?)
+    }
+
+    async fn link_attachments(&self, msg_id: i32, attachment_ids: &[i32]) -> StoreResult<()> {
+        let mut tables = self.tables();
+        for attachment in tables.attachments.values_mut() {
+            if attachment.msg_id.is_none() && attachment_ids.contains(&attachment.id) {
+                attachment.msg_id = Some(msg_id);
+            }
+        }
+        Ok(())
+    }
+
+    async fn msg_attachments(&self, msg_ids: &[i32]) -> StoreResult<Vec<Attachment>> {
+        let tables = self.tables();
+        let attachments = tables
+            .attachments
+            .values()
+            .filter(|attachment| attachment.msg_id.is_some_and(|id| msg_ids.contains(&id)))
+            .cloned()
+            .collect();
+        Ok(attachments)
+    }
+
+    async fn create_document(
+        &self,
+        user_id: i32,
+        name: String,
+        chunks: Vec<NewChunk>,
+    ) -> StoreResult<Document> {
+        let mut tables = self.tables();
+        if !tables.users.contains_key(&user_id) {
+            Err(NotFound)?;
+        }
+        let document = Document {
+            id: tables.next_id(),
+            user_id,
+            name,
+            created_at: Utc::now().naive_utc(),
+        };
+        for (index, chunk) in chunks.into_iter().enumerate() {
+            let chunk = DocumentChunk {
+                id: tables.next_id(),
+                document_id: document.id,
+                user_id,
+                chunk_index: index as i32,
+                body: chunk.body,
+                embedding_model: chunk.embedding_model,
+                embedding: document::encode_embedding(&chunk.embedding),
+            };
+            tables.chunks.insert(chunk.id, chunk);
+        }
+        tables.documents.insert(document.id, document.clone());
+        Ok(document)
+    }
+
+    async fn document_by_id(&self, document_id: i32) -> StoreResult<Document> {
+        let tables = self.tables();
+        let document = tables.documents.get(&document_id).cloned();
+        Ok(document.ok_or(NotFound)?)
+    }
+
+    async fn user_documents(&self, user_id: i32) -> StoreResult<Vec<Document>> {
+        let tables = self.tables();
+        let documents = tables
+            .documents
+            .values()
+            .filter(|document| document.user_id == user_id)
+            .cloned()
+            .collect();
+        Ok(documents)
+    }
+
+    async fn delete_document(&self, document_id: i32) -> StoreResult<()> {
+        let mut tables = self.tables();
+        tables.documents.remove(&document_id).ok_or(NotFound)?;
+        tables
+            .chunks
+            .retain(|_, chunk| chunk.document_id != document_id);
+        Ok(())
+    }
+
+    async fn user_chunks(
+        &self,
+        user_id: i32,
+        embedding_model: &str,
+    ) -> StoreResult<Vec<DocumentChunk>> {
+        let tables = self.tables();
+        let chunks = tables
+            .chunks
+            .values()
+            .filter(|chunk| chunk.user_id == user_id && chunk.embedding_model == embedding_model)
+            .cloned()
+            .collect();
+        Ok(chunks)
+    }
+
+    async fn create_share(&self, new_share: NewShare) -> StoreResult<Share> {
+        let mut tables = self.tables();
+        if !tables.chats.contains_key(&new_share.chat_id)
+            || !tables.msgs.contains_key(&new_share.head_msg)
+        {
+            Err(NotFound)?;
+        }
+        let NewShare {
+            chat_id,
+            user_id,
+            head_msg,
+            name,
+            expires_at,
+        } = new_share;
+        let share = Share {
+            id: tables.next_id(),
+            token: share::generate_token(),
+            chat_id,
+            user_id,
+            head_msg,
+            name,
+            created_at: Utc::now().naive_utc(),
+            expires_at,
+        };
+        tables.shares.insert(share.id, share.clone());
+        Ok(share)
+    }
+
+    async fn share_by_id(&self, share_id: i32) -> StoreResult<Share> {
+        Ok(self
+            .tables()
+            .shares
+            .get(&share_id)
+            .cloned()
+            .ok_or(NotFound)?)
+    }
+
+    async fn share_by_token(&self, token: &str) -> StoreResult<Share> {
+        let tables = self.tables();
+        let share = tables.shares.values().find(|share| share.token == token);
+        Ok(share.cloned().ok_or(NotFound)?)
+    }
+
+    async fn chat_shares(&self, chat_id: i32) -> StoreResult<Vec<Share>> {
+        let tables = self.tables();
+        let shares = tables
+            .shares
+            .values()
+            .filter(|share| share.chat_id == chat_id)
+            .cloned()
+            .collect();
+        Ok(shares)
+    }
+
+    async fn revoke_share(&self, share_id: i32) -> StoreResult<()> {
+        self.tables().shares.remove(&share_id).ok_or(NotFound)?;
+        Ok(())
+    }
+
+    async fn create_moderation_flag(
+        &self,
+        new_flag: NewModerationFlag,
+    ) -> StoreResult<ModerationFlag> {
+        let mut tables = self.tables();
+        let NewModerationFlag {
+            user_id,
+            chat_id,
+            msg_id,
+            source,
+            action,
+            reasons,
+            content,
+        } = new_flag;
+        if !tables.users.contains_key(&user_id)
+            || chat_id.is_some_and(|chat_id| !tables.chats.contains_key(&chat_id))
+            || msg_id.is_some_and(|msg_id| !tables.msgs.contains_key(&msg_id))
+        {
+            Err(NotFound)?;
+        }
+        let flag = ModerationFlag {
+            id: tables.next_id(),
+            user_id,
+            chat_id,
+            msg_id,
+            source: source.as_str().into(),
+            action: action.as_str().into(),
+            reasons: reasons.join("; "),
+            content,
+            created_at: Utc::now().naive_utc(),
+            reviewed_at: None,
+        };
+        tables.moderation_flags.insert(flag.id, flag.clone());
+        Ok(flag)
+    }
+
+    async fn record_audit_event(&self, new_event: NewAuditEvent) -> StoreResult<AuditEvent> {
+        let mut tables = self.tables();
+        let NewAuditEvent {
+            kind,
+            user_id,
+            detail,
+            client: ClientInfo { ip, user_agent },
+        } = new_event;
+        let event = AuditEvent {
+            id: tables.next_id(),
+            user_id,
+            kind: kind.as_str().into(),
+            detail,
+            ip,
+            user_agent,
+            created_at: Utc::now().naive_utc(),
+        };
+        tables.audit_events.insert(event.id, event.clone());
+        Ok(event)
+    }
+
+    async fn audit_events(&self, query: &AuditQuery) -> StoreResult<Vec<AuditEvent>> {
+        Ok(self
+            .tables()
+            .audit_events
+            .values()
+            .rev()
+            .filter(|event| query.matches(event))
+            .take(query.limit)
+            .cloned()
+            .collect())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Record Already Exists")]
+pub struct UniqueViolation;
@@ -0,0 +1,214 @@
+//! The storage operations the request handlers depend on
+//!
+//! Handlers talk to a `dyn Store` rather than to Postgres directly,
+//! so they can run against [`MemoryStore`] in tests and local dev.
+
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+
+use crate::{
+    account::AccountData,
+    api_key::ApiKey,
+    attachment::{Attachment, NewAttachment},
+    audit::{AuditEvent, AuditQuery, NewAuditEvent},
+    chat::Chat,
+    document::{Document, DocumentChunk, NewChunk},
+    instructions::{CustomInstructions, InstructionsUpdate},
+    moderation::{ModerationFlag, NewModerationFlag},
+    msg::{Generation, Msg, MsgStatus},
+    output_filters::OutputFilters,
+    session::Session,
+    share::{NewShare, Share},
+    user::User,
+};
+
+mod memory;
+mod postgres;
+
+pub use memory::MemoryStore;
+pub use postgres::PgStore;
+
+pub type StoreResult<T> = Result<T, libserver::ServiceError>;
+
+#[async_trait]
+pub trait Store: Send + Sync {
+    async fn user_by_id(&self, user_id: i32) -> StoreResult<User>;
+
+    async fn user_by_google_id(&self, google_id: &str) -> StoreResult<User>;
+
+    async fn create_user(
+        &self,
+        google_id: String,
+        email: String,
+        name: String,
+    ) -> StoreResult<User>;
+
+    /// The shared guest user behind the `__default__` session token
+    async fn default_user(&self) -> StoreResult<User>;
+
+    /// Everything kept about the user, for them to export
+    async fn account_data(&self, user_id: i32) -> StoreResult<AccountData>;
+
+    /// Schedules the user's account to be deleted at
+    /// `delete_after`, or with `None` keeps it
+    async fn schedule_user_deletion(
+        &self,
+        user_id: i32,
+        delete_after: Option<NaiveDateTime>,
+    ) -> StoreResult<User>;
+
+    /// The users whose accounts were to be deleted by `now`
+    async fn users_due_for_deletion(&self, now: NaiveDateTime) -> StoreResult<Vec<User>>;
+
+    /// Deletes the user along with everything of theirs, returning
+    /// their attachments so text kept outside the database can be
+    /// removed too. Audit events are kept
+    async fn delete_user(&self, user_id: i32) -> StoreResult<Vec<Attachment>>;
+
+    /// The user's custom instructions, or `None` if
+    /// they've never set any
+    async fn custom_instructions(&self, user_id: i32) -> StoreResult<Option<CustomInstructions>>;
+
+    /// Replaces the user's custom instructions
+    async fn set_custom_instructions(
+        &self,
+        user_id: i32,
+        update: InstructionsUpdate,
+    ) -> StoreResult<CustomInstructions>;
+
+    async fn session_by_token(&self, token: &str) -> StoreResult<Session>;
+
+    /// Returns the user's session, replacing it if it has expired,
+    /// and whether it was created
+    async fn session_for_user(&self, user_id: i32) -> StoreResult<(Session, bool)>;
+
+    async fn delete_session(&self, token: &str) -> StoreResult<()>;
+
+    /// Creates an API key, returning it along with its secret
+    async fn create_api_key(&self, user_id: i32, name: String) -> StoreResult<(ApiKey, String)>;
+
+    async fn api_key_by_secret(&self, secret: &str) -> StoreResult<ApiKey>;
+
+    async fn chat_by_id(&self, chat_id: i32) -> StoreResult<Chat>;
+
+    /// Creates a chat answered in the persona with id `persona`
+    async fn create_chat(
+        &self,
+        user_id: i32,
+        name: Option<String>,
+        persona: String,
+    ) -> StoreResult<Chat>;
+
+    /// The user's chats that haven't been deleted
+    async fn user_chats(&self, user_id: i32) -> StoreResult<Vec<Chat>>;
+
+    async fn set_chat_name(&self, chat_id: i32, name: String) -> StoreResult<Chat>;
+
+    async fn set_chat_tools_enabled(&self, chat_id: i32, enabled: bool) -> StoreResult<Chat>;
+
+    async fn set_chat_instructions_enabled(&self, chat_id: i32, enabled: bool)
+    -> StoreResult<Chat>;
+
+    /// Sets the chat's output filters, or with `None` has it
+    /// use the deployment's
+    async fn set_chat_output_filters(
+        &self,
+        chat_id: i32,
+        filters: Option<OutputFilters>,
+    ) -> StoreResult<Chat>;
+
+    /// Points the chat's head at `msg_id` and bumps its `updated_at`
+    async fn set_chat_head(&self, chat_id: i32, msg_id: i32) -> StoreResult<Chat>;
+
+    /// Marks the chat as deleted without removing any rows
+    async fn delete_chat(&self, chat_id: i32) -> StoreResult<()>;
+
+    async fn restore_chat(&self, chat_id: i32) -> StoreResult<Chat>;
+
+    async fn msg_by_id(&self, msg_id: i32) -> StoreResult<Msg>;
+
+    async fn create_msg(
+        &self,
+        body: String,
+        sender: String,
+        user_id: i32,
+        parent_message_id: Option<i32>,
+        status: MsgStatus,
+        generation: Generation,
+    ) -> StoreResult<Msg>;
+
+    /// Up to `limit` of the user's user and AI messages that
+    /// contain `query`, ignoring case, newest first. Messages
+    /// of chats in the trash are left out
+    async fn search_msgs(&self, user_id: i32, query: &str, limit: usize) -> StoreResult<Vec<Msg>>;
+
+    /// The message and all of its ancestors, oldest first
+    async fn msg_chain(&self, msg_id: i32) -> StoreResult<Vec<Msg>>;
+
+    async fn create_attachment(&self, new_attachment: NewAttachment) -> StoreResult<Attachment>;
+
+    async fn attachment_by_id(&self, attachment_id: i32) -> StoreResult<Attachment>;
+
+    /// Links the attachments that haven't been sent yet to `msg_id`
+    async fn link_attachments(&self, msg_id: i32, attachment_ids: &[i32]) -> StoreResult<()>;
+
+    /// The attachments sent with any of `msg_ids`, in upload order
+    async fn msg_attachments(&self, msg_ids: &[i32]) -> StoreResult<Vec<Attachment>>;
+
+    async fn create_document(
+        &self,
+        user_id: i32,
+        name: String,
+        chunks: Vec<NewChunk>,
+    ) -> StoreResult<Document>;
+
+    async fn document_by_id(&self, document_id: i32) -> StoreResult<Document>;
+
+    /// The user's documents, oldest first
+    async fn user_documents(&self, user_id: i32) -> StoreResult<Vec<Document>>;
+
+    /// Deletes the document along with its chunks
+    async fn delete_document(&self, document_id: i32) -> StoreResult<()>;
+
+    /// The user's document chunks that were embedded with `embedding_model`
+    async fn user_chunks(
+        &self,
+        user_id: i32,
+        embedding_model: &str,
+    ) -> StoreResult<Vec<DocumentChunk>>;
+
+    async fn create_share(&self, new_share: NewShare) -> StoreResult<Share>;
+
+    async fn share_by_id(&self, share_id: i32) -> StoreResult<Share>;
+
+    async fn share_by_token(&self, token: &str) -> StoreResult<Share>;
+
+    /// The chat's shares, expired ones included, oldest first
+    async fn chat_shares(&self, chat_id: i32) -> StoreResult<Vec<Share>>;
+
+    async fn revoke_share(&self, share_id: i32) -> StoreResult<()>;
+
+    /// Keeps flagged or blocked text for review
+    async fn create_moderation_flag(
+        &self,
+        new_flag: NewModerationFlag,
+    ) -> StoreResult<ModerationFlag>;
+
+    /// Appends an event to the audit log
+    async fn record_audit_event(&self, new_event: NewAuditEvent) -> StoreResult<AuditEvent>;
+
+    /// The audit events the query matches, newest first
+    async fn audit_events(&self, query: &AuditQuery) -> StoreResult<Vec<AuditEvent>>;
+
+    /// The messages on the chat's active branch, oldest first
+    async fn chat_msgs(&self, chat: &Chat) -> StoreResult<Vec<Msg>> {
+        match chat.head_msg {
+            Some(msg_id) => self.msg_chain(msg_id).await,
+            None => Ok(vec![]),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Record Not Found")]
+pub struct NotFound;
@@ -0,0 +1,317 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+
+use super::{Store, StoreResult};
+use crate::{
+    Database,
+    account::AccountData,
+    api_key::ApiKey,
+    attachment::{Attachment, NewAttachment},
+    audit::{AuditEvent, AuditQuery, NewAuditEvent},
+    chat::Chat,
+    document::{Document, DocumentChunk, NewChunk},
+    instructions::{CustomInstructions, InstructionsUpdate},
+    moderation::{ModerationFlag, NewModerationFlag},
+    msg::{Generation, Msg, MsgStatus},
+    output_filters::OutputFilters,
+    session::Session,
+    share::{NewShare, Share},
+    user::User,
+};
+
+/// The production store, backed by the Postgres models
+pub struct PgStore {
+    db: Arc<Database>,
+}
+
+impl PgStore {
+    pub fn new(db: Arc<Database>) -> Self {
+        PgStore { db }
+    }
+}
+
+#[async_trait]
+impl Store for PgStore {
+    async fn user_by_id(&self, user_id: i32) -> StoreResult<User> {
+        User::get_by_id(self.db.clone(), user_id).await
+    }
+
+    async fn user_by_google_id(&self, google_id: &str) -> StoreResult<User> {
+        User::get_by_google_id(self.db.clone(), google_id).await
+    }
+
+    async fn create_user(
+        &self,
+        google_id: String,
+        email: String,
+        name: String,
+    ) -> StoreResult<User> {
+        User::create(self.db.clone(), google_id, email, name).await
+    }
+
+    async fn default_user(&self) -> StoreResult<User> {
+        User::default(self.db.clone()).await
+    }
+
+    async fn account_data(&self, user_id: i32) -> StoreResult<AccountData> {
+        AccountData::gather(self.db.clone(), user_id).await
+    }
+
+    async fn schedule_user_deletion(
+        &self,
+        user_id: i32,
+        delete_after: Option<NaiveDateTime>,
+    ) -> StoreResult<User> {
+        User::get_by_id(self.db.clone(), user_id)
+            .await?
+            .schedule_deletion(self.db.clone(), delete_after)
+            .await
+    }
+
+    async fn users_due_for_deletion(&self, now: NaiveDateTime) -> StoreResult<Vec<User>> {
+        User::due_for_deletion(self.db.clone(), now).await
+    }
+
+    async fn delete_user(&self, user_id: i32) -> StoreResult<Vec<Attachment>> {
+        User::get_by_id(self.db.clone(), user_id)
+            .await?
+            .hard_delete(self.db.clone())
+            .await
+    }
+
+    async fn custom_instructions(&self, user_id: i32) -> StoreResult<Option<CustomInstructions>> {
+        CustomInstructions::get_for_user(self.db.clone(), user_id).await
+    }
+
+    async fn set_custom_instructions(
+        &self,
+        user_id: i32,
+        update: InstructionsUpdate,
+    ) -> StoreResult<CustomInstructions> {
+        CustomInstructions::set(self.db.clone(), user_id, update).await
+    }
+
+    async fn session_by_token(&self, token: &str) -> StoreResult<Session> {
+        Session::get_by_token(self.db.clone(), token.to_owned()).await
+    }
+
+    async fn session_for_user(&self, user_id: i32) -> StoreResult<(Session, bool)> {
+        Session::get_for_user(self.db.clone(), user_id).await
+    }
+
+    async fn delete_session(&self, token: &str) -> StoreResult<()> {
+        Session::get_by_token(self.db.clone(), token.to_owned())
+            .await?
+            .delete(self.db.clone())
+            .await
+    }
+
+    async fn create_api_key(&self, user_id: i32, name: String) -> StoreResult<(ApiKey, String)> {
+        ApiKey::create(self.db.clone(), user_id, name).await
+    }
+
+    async fn api_key_by_secret(&self, secret: &str) -> StoreResult<ApiKey> {
+        ApiKey::get_by_secret(self.db.clone(), secret).await
+    }
+
+    async fn chat_by_id(&self, chat_id: i32) -> StoreResult<Chat> {
+        Chat::get_by_id(self.db.clone(), chat_id).await
+    }
+
+    async fn create_chat(
+        &self,
+        user_id: i32,
+        name: Option<String>,
+        persona: String,
+    ) -> StoreResult<Chat> {
+        Chat::create(self.db.clone(), user_id, name, persona).await
+    }
+
+    async fn user_chats(&self, user_id: i32) -> StoreResult<Vec<Chat>> {
+        User::get_by_id(self.db.clone(), user_id)
+            .await?
+            .get_chats(self.db.clone())
+            .await
+    }
+
+    async fn set_chat_name(&self, chat_id: i32, name: String) -> StoreResult<Chat> {
+        Chat::get_by_id(self.db.clone(), chat_id)
+            .await?
+            .set_name(self.db.clone(), name)
+            .await
+    }
+
+    async fn set_chat_tools_enabled(&self, chat_id: i32, enabled: bool) -> StoreResult<Chat> {
+        Chat::get_by_id(self.db.clone(), chat_id)
+            .await?
+            .set_tools_enabled(self.db.clone(), enabled)
+            .await
+    }
+
+    async fn set_chat_instructions_enabled(
+        &self,
+        chat_id: i32,
+        enabled: bool,
+    ) -> StoreResult<Chat> {
+        Chat::get_by_id(self.db.clone(), chat_id)
+            .await?
+            .set_instructions_enabled(self.db.clone(), enabled)
+            .await
+    }
+
+    async fn set_chat_output_filters(
+        &self,
+        chat_id: i32,
+        filters: Option<OutputFilters>,
+    ) -> StoreResult<Chat> {
+        Chat::get_by_id(self.db.clone(), chat_id)
+            .await?
+            .set_output_filters(self.db.clone(), filters)
+            .await
+    }
+
+    async fn set_chat_head(&self, chat_id: i32, msg_id: i32) -> StoreResult<Chat> {
+        let chat = Chat::get_by_id(self.db.clone(), chat_id).await?;
+        let msg = Msg::get_by_id(self.db.clone(), msg_id).await?;
+        chat.append_to_chat(self.db.clone(), &msg).await
+    }
+
+    async fn delete_chat(&self, chat_id: i32) -> StoreResult<()> {
+        Chat::get_by_id(self.db.clone(), chat_id)
+            .await?
+            .delete(self.db.clone())
+            .await
+    }
+
+    async fn restore_chat(&self, chat_id: i32) -> StoreResult<Chat> {
+        Chat::get_by_id(self.db.clone(), chat_id)
+            .await?
+            .restore(self.db.clone())
+            .await
+    }
+
+    async fn msg_by_id(&self, msg_id: i32) -> StoreResult<Msg> {
+        Msg::get_by_id(self.db.clone(), msg_id).await
+    }
+
+    async fn create_msg(
+        &self,
+        body: String,
+        sender: String,
+        user_id: i32,
+        parent_message_id: Option<i32>,
+        status: MsgStatus,
+        generation: Generation,
+    ) -> StoreResult<Msg> {
+        Msg::create_with_status(
+            self.db.clone(),
+            body,
+            sender,
+            user_id,
+            parent_message_id,
+            status,
+            generation,
+        )
+        .await
+    }
+
+    async fn search_msgs(&self, user_id: i32, query: &str, limit: usize) -> StoreResult<Vec<Msg>> {
+        let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+        Msg::search(self.db.clone(), user_id, query, limit).await
+    }
+
+    async fn msg_chain(&self, msg_id: i32) -> StoreResult<Vec<Msg>> {
+        Msg::get_by_id(self.db.clone(), msg_id)
+            .await?
+            .get_msg_chain(self.db.clone())
+            .await
+    }
+
+    async fn create_attachment(&self, new_attachment: NewAttachment) -> StoreResult<Attachment> {
+        Attachment::create(self.db.clone(), new_attachment).await
+    }
+
+    async fn attachment_by_id(&self, attachment_id: i32) -> StoreResult<Attachment> {
+        Attachment::get_by_id(self.db.clone(), attachment_id).await
+    }
+
+    async fn link_attachments(&self, msg_id: i32, attachment_ids: &[i32]) -> StoreResult<()> {
+        Attachment::link_to_msg(self.db.clone(), attachment_ids.to_vec(), msg_id).await
+    }
+
+    async fn msg_attachments(&self, msg_ids: &[i32]) -> StoreResult<Vec<Attachment>> {
+        Attachment::get_for_msgs(self.db.clone(), msg_ids.to_vec()).await
+    }
+
+    async fn create_document(
+        &self,
+        user_id: i32,
+        name: String,
+        chunks: Vec<NewChunk>,
+    ) -> StoreResult<Document> {
+        Document::create(self.db.clone(), user_id, name, chunks).await
+    }
+
+    async fn document_by_id(&self, document_id: i32) -> StoreResult<Document> {
+        Document::get_by_id(self.db.clone(), document_id).await
+    }
+
+    async fn user_documents(&self, user_id: i32) -> StoreResult<Vec<Document>> {
+        Document::get_all_for_user(self.db.clone(), user_id).await
+    }
+
+    async fn delete_document(&self, document_id: i32) -> StoreResult<()> {
+        Document::get_by_id(self.db.clone(), document_id)
+            .await?
+            .delete(self.db.clone())
+            .await
+    }
+
+    async fn user_chunks(
+        &self,
+        user_id: i32,
+        embedding_model: &str,
+    ) -> StoreResult<Vec<DocumentChunk>> {
+        DocumentChunk::get_all_for_user(self.db.clone(), user_id, embedding_model.to_owned()).await
+    }
+
+    async fn create_share(&self, new_share: NewShare) -> StoreResult<Share> {
+        Share::create(self.db.clone(), new_share).await
+    }
+
+    async fn share_by_id(&self, share_id: i32) -> StoreResult<Share> {
+        Share::get_by_id(self.db.clone(), share_id).await
+    }
+
+    async fn share_by_token(&self, token: &str) -> StoreResult<Share> {
+        Share::get_by_token(self.db.clone(), token).await
+    }
+
+    async fn chat_shares(&self, chat_id: i32) -> StoreResult<Vec<Share>> {
+        Share::get_all_for_chat(self.db.clone(), chat_id).await
+    }
+
+    async fn revoke_share(&self, share_id: i32) -> StoreResult<()> {
+        Share::get_by_id(self.db.clone(), share_id)
+            .await?
+            .revoke(self.db.clone())
+            .await
+    }
+
+    async fn create_moderation_flag(
+        &self,
+        new_flag: NewModerationFlag,
+    ) -> StoreResult<ModerationFlag> {
+        ModerationFlag::create(self.db.clone(), new_flag).await
+    }
+
+    async fn record_audit_event(&self, new_event: NewAuditEvent) -> StoreResult<AuditEvent> {
+        AuditEvent::create(self.db.clone(), new_event).await
+    }
+
+    async fn audit_events(&self, query: &AuditQuery) -> StoreResult<Vec<AuditEvent>> {
+        AuditEvent::list(self.db.clone(), query).await
+    }
+}
@@ -2,10 +2,12 @@ use std::sync::Arc;
 
 use chrono::NaiveDateTime;
 use diesel::{ExpressionMethods, Insertable, QueryDsl, Queryable, Selectable, SelectableHelper};
+use diesel_async::scoped_futures::ScopedFutureExt;
+use serde::Serialize;
 
-use crate::{Database, RunQueryDsl, chat, schema};
+use crate::{Database, RunQueryDsl, attachment::Attachment, chat, schema};
 
-#[derive(Queryable, Selectable)]
+#[derive(Queryable, Selectable, Serialize, Clone, Debug)]
 #[diesel(table_name = schema::users)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct User {
@@ -16,6 +18,21 @@ pub struct User {
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
     pub last_login: NaiveDateTime,
+    pub disabled: bool,
+    /// When the account is deleted for good, if its
+    /// user has asked for it to be
+    pub delete_after: Option<NaiveDateTime>,
+}
+
+/// Row counts of everything a user has created
+#[derive(Serialize, Debug)]
+pub struct Usage {
+    pub user_id: i32,
+    pub chats: i64,
+    pub deleted_chats: i64,
+    pub user_msgs: i64,
+    pub ai_msgs: i64,
+    pub sessions: i64,
 }
 
 impl User {
@@ -69,10 +86,202 @@ impl User {
         Ok(chats)
     }
 
+    pub async fn get_deleted_chats(
+        &self,
+        db: Arc<Database>,
+    ) -> Result<Vec<chat::Chat>, libserver::ServiceError> {
+        let chats = schema::chats::table
+            .filter(schema::chats::user_id.eq(self.user_id))
+            .filter(schema::chats::deleted.eq(true))
+            .get_results(db)
+            .await?;
+        Ok(chats)
+    }
+
     pub async fn default(db: Arc<Database>) -> Result<User, libserver::ServiceError> {
         let user = schema::users::table.find(1).get_result(db).await?;
         Ok(user)
     }
+
+    pub async fn list(db: Arc<Database>) -> Result<Vec<User>, libserver::ServiceError> {
+        let users = schema::users::table
+            .order(schema::users::user_id)
+            .get_results(db)
+            .await?;
+        Ok(users)
+    }
+
+    pub async fn set_disabled(
+        self,
+        db: Arc<Database>,
+        disabled: bool,
+    ) -> Result<User, libserver::ServiceError> {
+        let user = diesel::update(schema::users::table.find(self.user_id))
+            .set(schema::users::disabled.eq(disabled))
+            .returning(User::as_returning())
+            .get_result(db)
+            .await?;
+        Ok(user)
+    }
+
+    /// Schedules the account to be deleted at `delete_after`,
+    /// or with `None` keeps it
+    pub async fn schedule_deletion(
+        self,
+        db: Arc<Database>,
+        delete_after: Option<NaiveDateTime>,
+    ) -> Result<User, libserver::ServiceError> {
+        let user = diesel::update(schema::users::table.find(self.user_id))
+            .set(schema::users::delete_after.eq(delete_after))
+            .returning(User::as_returning())
+            .get_result(db)
+            .await?;
+        Ok(user)
+    }
+
+    /// The users whose accounts were to be deleted by `now`
+    pub async fn due_for_deletion(
+        db: Arc<Database>,
+        now: NaiveDateTime,
+    ) -> Result<Vec<User>, libserver::ServiceError> {
+        let users = schema::users::table
+            .filter(schema::users::delete_after.le(now))
+            .order(schema::users::user_id)
+            .get_results(db)
+            .await?;
+        Ok(users)
+    }
+
+    /// Deletes the user along with everything of theirs, returning
+    /// their attachments so text kept outside the database can be
+    /// removed too
+    ///
+    /// Rows are deleted table by table rather than left to the
+    /// schema's cascades, so the attachments can be returned before
+    /// deleting messages takes them along. It's all one transaction,
+    /// so a purge that fails partway deletes nothing, and the
+    /// attachments are still there to return when it's run again.
+    pub async fn hard_delete(
+        self,
+        db: Arc<Database>,
+    ) -> Result<Vec<Attachment>, libserver::ServiceError> {
+        use schema::{
+            api_keys, attachments, chat_shares, chats, custom_instructions, document_chunks,
+            documents, moderation_flags, msgs, sessions, users,
+        };
+        let user_id = self.user_id;
+
+        let attachments = db
+            .transaction::<_, diesel::result::Error, _>(|conn| {
+                async move {
+                    diesel_async::RunQueryDsl::execute(
+                        diesel::delete(chat_shares::table.filter(chat_shares::user_id.eq(user_id))),
+                        &mut *conn,
+                    )
+                    .await?;
+                    diesel_async::RunQueryDsl::execute(
+                        diesel::delete(
+                            moderation_flags::table.filter(moderation_flags::user_id.eq(user_id)),
+                        ),
+                        &mut *conn,
+                    )
+                    .await?;
+                    diesel_async::RunQueryDsl::execute(
+                        diesel::delete(
+                            document_chunks::table.filter(document_chunks::user_id.eq(user_id)),
+                        ),
+                        &mut *conn,
+                    )
+                    .await?;
+                    diesel_async::RunQueryDsl::execute(
+                        diesel::delete(documents::table.filter(documents::user_id.eq(user_id))),
+                        &mut *conn,
+                    )
+                    .await?;
+                    diesel_async::RunQueryDsl::execute(
+                        diesel::delete(custom_instructions::table.find(user_id)),
+                        &mut *conn,
+                    )
+                    .await?;
+                    diesel_async::RunQueryDsl::execute(
+                        diesel::delete(api_keys::table.filter(api_keys::user_id.eq(user_id))),
+                        &mut *conn,
+                    )
+                    .await?;
+                    diesel_async::RunQueryDsl::execute(
+                        diesel::delete(sessions::table.filter(sessions::user_id.eq(user_id))),
+                        &mut *conn,
+                    )
+                    .await?;
+                    let attachments = diesel_async::RunQueryDsl::get_results(
+                        diesel::delete(attachments::table.filter(attachments::user_id.eq(user_id)))
+                            .returning(Attachment::as_returning()),
+                        &mut *conn,
+                    )
+                    .await?;
+                    diesel_async::RunQueryDsl::execute(
+                        diesel::delete(chats::table.filter(chats::user_id.eq(user_id))),
+                        &mut *conn,
+                    )
+                    .await?;
+                    diesel_async::RunQueryDsl::execute(
+                        diesel::delete(msgs::table.filter(msgs::user_id.eq(user_id))),
+                        &mut *conn,
+                    )
+                    .await?;
+                    diesel_async::RunQueryDsl::execute(
+                        diesel::delete(users::table.find(user_id)),
+                        &mut *conn,
+                    )
+                    .await?;
+                    Ok(attachments)
+                }
+                .scope_boxed()
+            })
+            .await?;
+        Ok(attachments)
+    }
+
+    pub async fn usage(&self, db: Arc<Database>) -> Result<Usage, libserver::ServiceError> {
+        let chats = schema::chats::table
+            .filter(schema::chats::user_id.eq(self.user_id))
+            .filter(schema::chats::deleted.eq(false))
+            .count()
+            .get_result(db.clone())
+            .await?;
+        let deleted_chats = schema::chats::table
+            .filter(schema::chats::user_id.eq(self.user_id))
+            .filter(schema::chats::deleted.eq(true))
+            .count()
+            .get_result(db.clone())
+            .await?;
+        let user_msgs = schema::msgs::table
+            .filter(schema::msgs::user_id.eq(self.user_id))
+            .filter(schema::msgs::sender.eq("user"))
+            .count()
+            .get_result(db.clone())
+            .await?;
+        let ai_msgs = schema::msgs::table
+            .filter(schema::msgs::user_id.eq(self.user_id))
+            .filter(schema::msgs::sender.eq("ai"))
+            .count()
+            .get_result(db.clone())
+            .await?;
+        let sessions = schema::sessions::table
+            .filter(schema::sessions::user_id.eq(self.user_id))
+            .count()
+            .get_result(db)
+            .await?;
+
+        Ok(Usage {
+            user_id: self.user_id,
+            chats,
+            deleted_chats,
+            user_msgs,
+            ai_msgs,
+            sessions,
+        })
+    }
 }
 
 #[derive(Insertable)]
@@ -0,0 +1,46 @@
+//! Holding back repeated audit events from one client
+
+use std::time::{Duration, Instant};
+
+use rgpt_db::audit::AuditThrottle;
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+#[test]
+fn repeats_are_held_back_until_the_window_passes() {
+    let mut throttle = AuditThrottle::new(WINDOW, 10);
+    let start = Instant::now();
+
+    assert_eq!(throttle.admit(Some("203.0.113.7"), start), Some(0));
+    assert_eq!(throttle.admit(Some("203.0.113.7"), start), None);
+    assert_eq!(
+        throttle.admit(Some("203.0.113.7"), start + Duration::from_secs(59)),
+        None
+    );
+    assert_eq!(throttle.admit(Some("203.0.113.7"), start + WINDOW), Some(2));
+    assert_eq!(throttle.admit(Some("203.0.113.7"), start + WINDOW), None);
+}
+
+#[test]
+fn clients_are_throttled_apart() {
+    let mut throttle = AuditThrottle::new(WINDOW, 10);
+    let now = Instant::now();
+
+    assert_eq!(throttle.admit(Some("203.0.113.7"), now), Some(0));
+    assert_eq!(throttle.admit(Some("198.51.100.4"), now), Some(0));
+    assert_eq!(throttle.admit(None, now), Some(0));
+    assert_eq!(throttle.admit(None, now), None);
+}
+
+#[test]
+fn new_clients_are_held_back_while_the_throttle_is_full() {
+    let mut throttle = AuditThrottle::new(WINDOW, 2);
+    let start = Instant::now();
+
+    assert_eq!(throttle.admit(Some("10.0.0.1"), start), Some(0));
+    assert_eq!(throttle.admit(Some("10.0.0.2"), start), Some(0));
+    assert_eq!(throttle.admit(Some("10.0.0.3"), start), None);
+
+    // Once their windows pass, tracked clients make room
+    assert_eq!(throttle.admit(Some("10.0.0.3"), start + WINDOW), Some(0));
+}
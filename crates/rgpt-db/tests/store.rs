@@ -0,0 +1,924 @@
+//! Behavioural tests shared by every `Store` implementation
+//!
+//! Each test runs against `MemoryStore`, and against `PgStore` when
+//! `TEST_DATABASE_URL` points at a migrated Postgres database.
+
+use std::sync::Arc;
+
+use rgpt_db::{
+    Database,
+    attachment::NewAttachment,
+    audit::{AuditKind, AuditQuery, ClientInfo, NewAuditEvent},
+    document::{Citation, Citations, NewChunk},
+    gen_params::GenParams,
+    instructions::InstructionsUpdate,
+    moderation::{FlagAction, FlagSource, NewModerationFlag},
+    msg::{Generation, MsgStatus},
+    output_filters::OutputFilters,
+    share::NewShare,
+    store::{MemoryStore, PgStore, Store},
+};
+
+async fn memory_store() -> Option<Arc<dyn Store>> {
+    Some(Arc::new(MemoryStore::new()))
+}
+
+async fn pg_store() -> Option<Arc<dyn Store>> {
+    let Ok(db_url) = std::env::var("TEST_DATABASE_URL") else {
+        eprintln!("TEST_DATABASE_URL not set, skipping Postgres store test");
+        return None;
+    };
+    Some(Arc::new(PgStore::new(
+        Database::establish_with_url(&db_url).await,
+    )))
+}
+
+/// The persona chats are created with; the store
+/// doesn't know which ones the deployment offers
+const PERSONA: &str = "bbs_sysop";
+
+/// Google ids are unique, so every test creates users with fresh ones
+/// to stay independent of whatever a shared test database holds
+fn google_id() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+async fn create_user(store: &dyn Store) -> rgpt_db::user::User {
+    let google_id = google_id();
+    store
+        .create_user(
+            google_id.clone(),
+            format!("{google_id}@example.com"),
+            "Test User".into(),
+        )
+        .await
+        .unwrap()
+}
+
+async fn user_lookup(store: Arc<dyn Store>) {
+    let user = create_user(&*store).await;
+
+    let by_id = store.user_by_id(user.user_id).await.unwrap();
+    assert_eq!(by_id.google_id, user.google_id);
+
+    let by_google_id = store.user_by_google_id(&user.google_id).await.unwrap();
+    assert_eq!(by_google_id.user_id, user.user_id);
+
+    assert!(store.user_by_google_id(&google_id()).await.is_err());
+}
+
+async fn duplicate_google_id(store: Arc<dyn Store>) {
+    let user = create_user(&*store).await;
+
+    let duplicate = store
+        .create_user(user.google_id, "other@example.com".into(), "Other".into())
+        .await;
+    assert!(duplicate.is_err());
+}
+
+async fn default_user(store: Arc<dyn Store>) {
+    let user = store.default_user().await.unwrap();
+    assert_eq!(user.user_id, 1);
+}
+
+async fn session_lifecycle(store: Arc<dyn Store>) {
+    let user = create_user(&*store).await;
+
+    let (session, created) = store.session_for_user(user.user_id).await.unwrap();
+    assert_eq!(session.user_id, user.user_id);
+    assert!(session.validate());
+    assert!(created);
+
+    let (again, created) = store.session_for_user(user.user_id).await.unwrap();
+    assert_eq!(again.session_token, session.session_token);
+    assert!(!created);
+
+    let by_token = store
+        .session_by_token(&session.session_token)
+        .await
+        .unwrap();
+    assert_eq!(by_token.user_id, user.user_id);
+
+    store.delete_session(&session.session_token).await.unwrap();
+    assert!(
+        store
+            .session_by_token(&session.session_token)
+            .await
+            .is_err()
+    );
+}
+
+async fn api_key_lookup(store: Arc<dyn Store>) {
+    let user = create_user(&*store).await;
+
+    let (key, secret) = store
+        .create_api_key(user.user_id, "laptop".into())
+        .await
+        .unwrap();
+    assert_eq!(key.user_id, user.user_id);
+    assert!(secret.starts_with(rgpt_db::api_key::API_KEY_PREFIX));
+    assert_ne!(key.key_hash, secret);
+
+    let by_secret = store.api_key_by_secret(&secret).await.unwrap();
+    assert_eq!(by_secret.id, key.id);
+
+    let (_, other) = store
+        .create_api_key(user.user_id, "laptop".into())
+        .await
+        .unwrap();
+    assert_ne!(other, secret);
+
+    assert!(store.api_key_by_secret("rgpt-not-a-key").await.is_err());
+}
+
+async fn chat_lifecycle(store: Arc<dyn Store>) {
+    let user = create_user(&*store).await;
+
+    let chat = store
+        .create_chat(user.user_id, None, PERSONA.into())
+        .await
+        .unwrap();
+    assert_eq!(chat.user_id, user.user_id);
+    assert_eq!(chat.head_msg, None);
+    assert!(chat.name.is_none());
+    assert_eq!(chat.persona, PERSONA);
+
+    let renamed = store
+        .set_chat_name(chat.id, "Renamed".into())
+        .await
+        .unwrap();
+    assert_eq!(renamed.name.as_deref(), Some("Renamed"));
+
+    assert!(chat.tools_enabled);
+    let without_tools = store.set_chat_tools_enabled(chat.id, false).await.unwrap();
+    assert!(!without_tools.tools_enabled);
+
+    assert!(chat.instructions_enabled);
+    let opted_out = store
+        .set_chat_instructions_enabled(chat.id, false)
+        .await
+        .unwrap();
+    assert!(!opted_out.instructions_enabled);
+
+    assert_eq!(chat.output_filters, None);
+    let filters = OutputFilters {
+        terminal: true,
+        wrap_column: Some(40),
+        ..OutputFilters::default()
+    };
+    let filtered = store
+        .set_chat_output_filters(chat.id, Some(filters))
+        .await
+        .unwrap();
+    assert_eq!(filtered.output_filters, Some(filters));
+    let unfiltered = store.set_chat_output_filters(chat.id, None).await.unwrap();
+    assert_eq!(unfiltered.output_filters, None);
+
+    let chats = store.user_chats(user.user_id).await.unwrap();
+    assert_eq!(chats.len(), 1);
+    assert_eq!(chats[0].id, chat.id);
+}
+
+async fn msg_chain(store: Arc<dyn Store>) {
+    let user = create_user(&*store).await;
+    let chat = store
+        .create_chat(user.user_id, None, PERSONA.into())
+        .await
+        .unwrap();
+    assert!(store.chat_msgs(&chat).await.unwrap().is_empty());
+
+    let question = store
+        .create_msg(
+            "hello".into(),
+            "user".into(),
+            user.user_id,
+            None,
+            MsgStatus::Complete,
+            Generation::default(),
+        )
+        .await
+        .unwrap();
+    let params = GenParams {
+        temperature: Some(0.5),
+        stop: Some(vec!["END".into()]),
+        seed: Some(7),
+        ..GenParams::default()
+    };
+    let answer = store
+        .create_msg(
+            "hi".into(),
+            "ai".into(),
+            user.user_id,
+            Some(question.id),
+            MsgStatus::Interrupted,
+            Generation {
+                model: Some("gpt-4o-mini".into()),
+                params: Some(params.clone()),
+                citations: None,
+            },
+        )
+        .await
+        .unwrap();
+    assert!(!answer.is_complete());
+
+    let chat = store.set_chat_head(chat.id, answer.id).await.unwrap();
+    assert_eq!(chat.head_msg, Some(answer.id));
+
+    let msgs = store.chat_msgs(&chat).await.unwrap();
+    let bodies = msgs.iter().map(|msg| msg.body.as_str()).collect::<Vec<_>>();
+    assert_eq!(bodies, ["hello", "hi"]);
+    assert_eq!(msgs[1].status, MsgStatus::Interrupted.as_str());
+    assert_eq!(msgs[0].model, None);
+    assert_eq!(msgs[1].model.as_deref(), Some("gpt-4o-mini"));
+    assert_eq!(msgs[0].gen_params, None);
+    assert_eq!(msgs[1].gen_params, Some(params));
+
+    let chain = store.msg_chain(question.id).await.unwrap();
+    assert_eq!(chain.len(), 1);
+}
+
+async fn missing_parent(store: Arc<dyn Store>) {
+    let user = create_user(&*store).await;
+
+    let orphan = store
+        .create_msg(
+            "lost".into(),
+            "user".into(),
+            user.user_id,
+            Some(i32::MAX),
+            MsgStatus::Complete,
+            Generation::default(),
+        )
+        .await;
+    assert!(orphan.is_err());
+}
+
+async fn msg_search(store: Arc<dyn Store>) {
+    let user = create_user(&*store).await;
+    let other = create_user(&*store).await;
+
+    for (user_id, body, sender) in [
+        (user.user_id, "The 100% Rust rewrite", "user"),
+        (user.user_id, "a rust_tool call", "tool"),
+        (user.user_id, "Rust is a language", "ai"),
+        (other.user_id, "rust belongs to someone else", "user"),
+    ] {
+        store
+            .create_msg(
+                body.into(),
+                sender.into(),
+                user_id,
+                None,
+                MsgStatus::Complete,
+                Generation::default(),
+            )
+            .await
+            .unwrap();
+    }
+
+    let found = store.search_msgs(user.user_id, "RUST", 10).await.unwrap();
+    let bodies = found
+        .iter()
+        .map(|msg| msg.body.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(bodies, ["Rust is a language", "The 100% Rust rewrite"]);
+
+    let limited = store.search_msgs(user.user_id, "rust", 1).await.unwrap();
+    assert_eq!(limited.len(), 1);
+
+    // Wildcards in the query only match themselves
+    let literal = store.search_msgs(user.user_id, "100%", 10).await.unwrap();
+    assert_eq!(literal.len(), 1);
+    assert!(
+        store
+            .search_msgs(user.user_id, "_", 10)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    // Every branch of a trashed chat is hidden, not just its head
+    let chat = store
+        .create_chat(user.user_id, None, PERSONA.into())
+        .await
+        .unwrap();
+    let mut parent = None;
+    let mut branches = vec![];
+    for (body, sender) in [
+        ("trashed rust question", "user"),
+        ("trashed rust answer", "ai"),
+    ] {
+        let msg = store
+            .create_msg(
+                body.into(),
+                sender.into(),
+                user.user_id,
+                parent,
+                MsgStatus::Complete,
+                Generation::default(),
+            )
+            .await
+            .unwrap();
+        parent = Some(msg.id);
+        branches.push(msg);
+    }
+    store
+        .create_msg(
+            "trashed rust retry".into(),
+            "ai".into(),
+            user.user_id,
+            Some(branches[0].id),
+            MsgStatus::Complete,
+            Generation::default(),
+        )
+        .await
+        .unwrap();
+    store.set_chat_head(chat.id, branches[1].id).await.unwrap();
+    assert_eq!(
+        store
+            .search_msgs(user.user_id, "trashed", 10)
+            .await
+            .unwrap()
+            .len(),
+        3
+    );
+
+    store.delete_chat(chat.id).await.unwrap();
+    assert!(
+        store
+            .search_msgs(user.user_id, "trashed", 10)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    assert_eq!(
+        store
+            .search_msgs(user.user_id, "rust", 10)
+            .await
+            .unwrap()
+            .len(),
+        2
+    );
+}
+
+async fn attachment_links(store: Arc<dyn Store>) {
+    let user = create_user(&*store).await;
+    let new_attachment = |name: &str| NewAttachment {
+        user_id: user.user_id,
+        name: name.into(),
+        media_type: "text/plain".into(),
+        size_bytes: 5,
+        content: Some("hello".into()),
+        storage_key: None,
+    };
+    let first = store
+        .create_attachment(new_attachment("a.txt"))
+        .await
+        .unwrap();
+    let second = store
+        .create_attachment(new_attachment("b.txt"))
+        .await
+        .unwrap();
+    let unsent = store
+        .create_attachment(new_attachment("c.txt"))
+        .await
+        .unwrap();
+    assert_eq!(first.msg_id, None);
+    assert_eq!(
+        store
+            .attachment_by_id(first.id)
+            .await
+            .unwrap()
+            .content
+            .as_deref(),
+        Some("hello")
+    );
+
+    let msg = store
+        .create_msg(
+            "see attached".into(),
+            "user".into(),
+            user.user_id,
+            None,
+            MsgStatus::Complete,
+            Generation::default(),
+        )
+        .await
+        .unwrap();
+    store
+        .link_attachments(msg.id, &[first.id, second.id])
+        .await
+        .unwrap();
+
+    let linked = store.msg_attachments(&[msg.id]).await.unwrap();
+    let names = linked.iter().map(|a| a.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, ["a.txt", "b.txt"]);
+    assert!(linked.iter().all(|a| a.msg_id == Some(msg.id)));
+
+    // Sent attachments stay with the message they were sent with
+    let other = store
+        .create_msg(
+            "again".into(),
+            "user".into(),
+            user.user_id,
+            Some(msg.id),
+            MsgStatus::Complete,
+            Generation::default(),
+        )
+        .await
+        .unwrap();
+    store
+        .link_attachments(other.id, &[first.id, unsent.id])
+        .await
+        .unwrap();
+    let names = store
+        .msg_attachments(&[other.id])
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|a| a.name)
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["c.txt"]);
+}
+
+async fn document_chunks(store: Arc<dyn Store>) {
+    let user = create_user(&*store).await;
+    let chunk = |body: &str, embedding_model: &str, embedding: Vec<f32>| NewChunk {
+        body: body.into(),
+        embedding_model: embedding_model.into(),
+        embedding,
+    };
+    let document = store
+        .create_document(
+            user.user_id,
+            "notes.md".into(),
+            vec![
+                chunk("first", "hash-2", vec![1.0, 0.0]),
+                chunk("second", "hash-2", vec![0.6, -0.8]),
+                chunk("stale", "hash-3", vec![0.0, 0.0, 1.0]),
+            ],
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        store.document_by_id(document.id).await.unwrap().name,
+        "notes.md"
+    );
+
+    // Only chunks embedded by the asked for model come back
+    let chunks = store.user_chunks(user.user_id, "hash-2").await.unwrap();
+    let found = chunks
+        .iter()
+        .map(|chunk| (chunk.chunk_index, chunk.body.as_str(), chunk.embedding()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        found,
+        [(0, "first", vec![1.0, 0.0]), (1, "second", vec![0.6, -0.8]),]
+    );
+    let stranger = create_user(&*store).await;
+    assert!(
+        store
+            .user_chunks(stranger.user_id, "hash-2")
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    let citations = Citations(vec![Citation {
+        document_id: document.id,
+        document_name: document.name.clone(),
+        chunk_id: chunks[0].id,
+        chunk_index: 0,
+    }]);
+    let reply = store
+        .create_msg(
+            "as noted [1]".into(),
+            "ai".into(),
+            user.user_id,
+            None,
+            MsgStatus::Complete,
+            Generation {
+                citations: Some(citations.clone()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        store.msg_by_id(reply.id).await.unwrap().citations,
+        Some(citations)
+    );
+
+    // Deleting a document takes its chunks with it
+    store.delete_document(document.id).await.unwrap();
+    assert!(store.user_documents(user.user_id).await.unwrap().is_empty());
+    assert!(
+        store
+            .user_chunks(user.user_id, "hash-2")
+            .await
+            .unwrap()
+            .is_empty()
+    );
+}
+
+async fn chat_shares(store: Arc<dyn Store>) {
+    let user = create_user(&*store).await;
+    let chat = store
+        .create_chat(user.user_id, Some("shared".into()), PERSONA.into())
+        .await
+        .unwrap();
+    let msg = store
+        .create_msg(
+            "hello".into(),
+            "user".into(),
+            user.user_id,
+            None,
+            MsgStatus::Complete,
+            Generation::default(),
+        )
+        .await
+        .unwrap();
+    let new_share = |expires_at| NewShare {
+        chat_id: chat.id,
+        user_id: user.user_id,
+        head_msg: msg.id,
+        name: chat.name.clone(),
+        expires_at,
+    };
+
+    let share = store.create_share(new_share(None)).await.unwrap();
+    let past = chrono::Utc::now().naive_utc() - chrono::Duration::hours(1);
+    let expired = store.create_share(new_share(Some(past))).await.unwrap();
+    assert_ne!(share.token, expired.token);
+    assert!(!share.is_expired());
+    assert!(expired.is_expired());
+
+    let found = store.share_by_token(&share.token).await.unwrap();
+    assert_eq!((found.id, found.head_msg), (share.id, msg.id));
+    assert_eq!(found.name.as_deref(), Some("shared"));
+    let ids = store
+        .chat_shares(chat.id)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|share| share.id)
+        .collect::<Vec<_>>();
+    assert_eq!(ids, [share.id, expired.id]);
+
+    store.revoke_share(share.id).await.unwrap();
+    assert!(store.share_by_token(&share.token).await.is_err());
+    assert!(store.share_by_id(expired.id).await.is_ok());
+}
+
+async fn moderation_flags(store: Arc<dyn Store>) {
+    let user = create_user(&*store).await;
+    let chat = store
+        .create_chat(user.user_id, None, PERSONA.into())
+        .await
+        .unwrap();
+    let reply = store
+        .create_msg(
+            "a reply".into(),
+            "ai".into(),
+            user.user_id,
+            None,
+            MsgStatus::Blocked,
+            Generation::default(),
+        )
+        .await
+        .unwrap();
+
+    let blocked = store
+        .create_moderation_flag(NewModerationFlag {
+            user_id: user.user_id,
+            chat_id: Some(chat.id),
+            msg_id: None,
+            source: FlagSource::Prompt,
+            action: FlagAction::Block,
+            reasons: vec!["keyword: foo".into(), "regex: ba+r".into()],
+            content: "foo baaar".into(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(
+        (blocked.source.as_str(), blocked.action.as_str()),
+        ("prompt", "block")
+    );
+    assert_eq!(blocked.reasons, "keyword: foo; regex: ba+r");
+    assert_eq!(blocked.msg_id, None);
+    assert_eq!(blocked.reviewed_at, None);
+
+    let flagged = store
+        .create_moderation_flag(NewModerationFlag {
+            user_id: user.user_id,
+            chat_id: Some(chat.id),
+            msg_id: Some(reply.id),
+            source: FlagSource::Reply,
+            action: FlagAction::Flag,
+            reasons: vec![],
+            content: reply.body.clone(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(flagged.msg_id, Some(reply.id));
+    assert_ne!(flagged.id, blocked.id);
+
+    let missing_msg = NewModerationFlag {
+        user_id: user.user_id,
+        chat_id: None,
+        msg_id: Some(i32::MAX),
+        source: FlagSource::Reply,
+        action: FlagAction::Flag,
+        reasons: vec![],
+        content: String::new(),
+    };
+    assert!(store.create_moderation_flag(missing_msg).await.is_err());
+}
+
+async fn audit_events(store: Arc<dyn Store>) {
+    let user = create_user(&*store).await;
+    let other = create_user(&*store).await;
+    let client = ClientInfo {
+        ip: Some("203.0.113.7".into()),
+        user_agent: Some("test".into()),
+    };
+
+    let login = store
+        .record_audit_event(
+            NewAuditEvent::new(AuditKind::LoginSucceeded, Some(user.user_id), "").client(&client),
+        )
+        .await
+        .unwrap();
+    assert_eq!(login.kind, "login_succeeded");
+    assert_eq!(login.ip.as_deref(), Some("203.0.113.7"));
+    assert_eq!(login.user_agent.as_deref(), Some("test"));
+    for kind in [AuditKind::ChatDeleted, AuditKind::ChatDeleted] {
+        store
+            .record_audit_event(NewAuditEvent::new(kind, Some(user.user_id), "chat 1"))
+            .await
+            .unwrap();
+    }
+    store
+        .record_audit_event(NewAuditEvent::new(
+            AuditKind::LoginSucceeded,
+            Some(other.user_id),
+            "",
+        ))
+        .await
+        .unwrap();
+
+    let query = AuditQuery {
+        user_id: Some(user.user_id),
+        kind: None,
+        before: None,
+        limit: 10,
+    };
+    let events = store.audit_events(&query).await.unwrap();
+    let kinds = events
+        .iter()
+        .map(|event| event.kind.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(kinds, ["chat_deleted", "chat_deleted", "login_succeeded"]);
+
+    let logins = AuditQuery {
+        kind: Some(AuditKind::LoginSucceeded),
+        ..query
+    };
+    let events = store.audit_events(&logins).await.unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].id, login.id);
+
+    let page = AuditQuery { limit: 1, ..query };
+    let first = store.audit_events(&page).await.unwrap();
+    let second = store
+        .audit_events(&AuditQuery {
+            before: Some(first[0].id),
+            ..page
+        })
+        .await
+        .unwrap();
+    assert_eq!(first.len(), 1);
+    assert_eq!(second.len(), 1);
+    assert!(second[0].id < first[0].id);
+}
+
+async fn custom_instructions(store: Arc<dyn Store>) {
+    let user = create_user(&*store).await;
+    assert_eq!(store.custom_instructions(user.user_id).await.unwrap(), None);
+
+    let update = InstructionsUpdate {
+        about_me: "I run a BBS".into(),
+        response_style: "Terse".into(),
+        language: "German".into(),
+    };
+    let set = store
+        .set_custom_instructions(user.user_id, update)
+        .await
+        .unwrap();
+    assert_eq!(set.user_id, user.user_id);
+    assert_eq!(set.language, "German");
+    assert!(!set.is_empty());
+
+    // Setting them again replaces every field
+    let cleared = store
+        .set_custom_instructions(
+            user.user_id,
+            InstructionsUpdate {
+                about_me: "I run two BBSes".into(),
+                ..InstructionsUpdate::default()
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(cleared.about_me, "I run two BBSes");
+    assert_eq!(cleared.language, "");
+    let stored = store
+        .custom_instructions(user.user_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored.about_me, cleared.about_me);
+    assert_eq!(stored.response_style, "");
+
+    let data = store.account_data(user.user_id).await.unwrap();
+    assert_eq!(
+        data.instructions.map(|instructions| instructions.about_me),
+        Some("I run two BBSes".into())
+    );
+
+    store.delete_user(user.user_id).await.unwrap();
+    assert_eq!(store.custom_instructions(user.user_id).await.unwrap(), None);
+}
+
+async fn account_deletion(store: Arc<dyn Store>) {
+    let user = create_user(&*store).await;
+    let (session, _) = store.session_for_user(user.user_id).await.unwrap();
+    let chat = store
+        .create_chat(user.user_id, None, PERSONA.into())
+        .await
+        .unwrap();
+    let msg = store
+        .create_msg(
+            "hello".into(),
+            "user".into(),
+            user.user_id,
+            None,
+            MsgStatus::Complete,
+            Generation::default(),
+        )
+        .await
+        .unwrap();
+    store.set_chat_head(chat.id, msg.id).await.unwrap();
+    let attachment = store
+        .create_attachment(NewAttachment {
+            user_id: user.user_id,
+            name: "a.txt".into(),
+            media_type: "text/plain".into(),
+            size_bytes: 5,
+            content: None,
+            storage_key: Some("a-key".into()),
+        })
+        .await
+        .unwrap();
+    store
+        .link_attachments(msg.id, &[attachment.id])
+        .await
+        .unwrap();
+    store
+        .record_audit_event(NewAuditEvent::new(
+            AuditKind::LoginSucceeded,
+            Some(user.user_id),
+            "",
+        ))
+        .await
+        .unwrap();
+
+    let data = store.account_data(user.user_id).await.unwrap();
+    assert_eq!(data.user.user_id, user.user_id);
+    assert_eq!(data.usage.user_msgs, 1);
+    assert_eq!(data.sessions.len(), 1);
+    assert_eq!(data.chats.len(), 1);
+    assert_eq!(data.msgs.len(), 1);
+    assert_eq!(data.attachments.len(), 1);
+    assert_eq!(data.activity.len(), 1);
+
+    let now = chrono::Utc::now().naive_utc();
+    let past = now - chrono::Duration::minutes(1);
+    let scheduled = store
+        .schedule_user_deletion(user.user_id, Some(past))
+        .await
+        .unwrap();
+    assert!(scheduled.delete_after.is_some());
+    let due = store.users_due_for_deletion(now).await.unwrap();
+    assert!(due.iter().any(|due| due.user_id == user.user_id));
+    let earlier = past - chrono::Duration::minutes(1);
+    let due = store.users_due_for_deletion(earlier).await.unwrap();
+    assert!(!due.iter().any(|due| due.user_id == user.user_id));
+
+    let deleted = store.delete_user(user.user_id).await.unwrap();
+    assert_eq!(deleted.len(), 1);
+    assert_eq!(deleted[0].storage_key.as_deref(), Some("a-key"));
+    assert!(store.user_by_id(user.user_id).await.is_err());
+    assert!(
+        store
+            .session_by_token(&session.session_token)
+            .await
+            .is_err()
+    );
+    assert!(store.chat_by_id(chat.id).await.is_err());
+    assert!(store.msg_by_id(msg.id).await.is_err());
+    assert!(store.attachment_by_id(attachment.id).await.is_err());
+    assert!(store.account_data(user.user_id).await.is_err());
+
+    // The audit log outlives the account
+    let query = AuditQuery {
+        user_id: Some(user.user_id),
+        kind: None,
+        before: None,
+        limit: 10,
+    };
+    assert_eq!(store.audit_events(&query).await.unwrap().len(), 1);
+}
+
+async fn soft_delete(store: Arc<dyn Store>) {
+    let user = create_user(&*store).await;
+    let kept = store
+        .create_chat(user.user_id, None, PERSONA.into())
+        .await
+        .unwrap();
+    let trashed = store
+        .create_chat(user.user_id, None, PERSONA.into())
+        .await
+        .unwrap();
+
+    store.delete_chat(trashed.id).await.unwrap();
+
+    let chats = store.user_chats(user.user_id).await.unwrap();
+    assert_eq!(
+        chats.iter().map(|chat| chat.id).collect::<Vec<_>>(),
+        [kept.id]
+    );
+
+    let trashed = store.chat_by_id(trashed.id).await.unwrap();
+    assert!(trashed.deleted);
+
+    let restored = store.restore_chat(trashed.id).await.unwrap();
+    assert!(!restored.deleted);
+    assert_eq!(store.user_chats(user.user_id).await.unwrap().len(), 2);
+}
+
+async fn missing_records(store: Arc<dyn Store>) {
+    assert!(store.user_by_id(i32::MAX).await.is_err());
+    assert!(store.chat_by_id(i32::MAX).await.is_err());
+    assert!(store.msg_by_id(i32::MAX).await.is_err());
+    assert!(store.session_by_token("not-a-token").await.is_err());
+    assert!(store.delete_chat(i32::MAX).await.is_err());
+}
+
+/// Generates a `memory` and a `postgres` test for each behaviour
+macro_rules! store_tests {
+    ($($name:ident),* $(,)?) => {
+        mod memory {
+            $(
+                #[tokio::test]
+                async fn $name() {
+                    if let Some(store) = super::memory_store().await {
+                        super::$name(store).await;
+                    }
+                }
+            )*
+        }
+
+        mod postgres {
+            $(
+                #[tokio::test]
+                async fn $name() {
+                    if let Some(store) = super::pg_store().await {
+                        super::$name(store).await;
+                    }
+                }
+            )*
+        }
+    };
+}
+
+store_tests! {
+    user_lookup,
+    duplicate_google_id,
+    default_user,
+    session_lifecycle,
+    api_key_lookup,
+    chat_lifecycle,
+    msg_chain,
+    missing_parent,
+    msg_search,
+    attachment_links,
+    document_chunks,
+    chat_shares,
+    moderation_flags,
+    audit_events,
+    custom_instructions,
+    account_deletion,
+    soft_delete,
+    missing_records,
+}
@@ -0,0 +1,75 @@
+use std::{
+    sync::{Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+/// Fails calls fast while a provider is down
+///
+/// After `threshold` consecutive failures the breaker opens, and
+/// calls are refused until `cooldown` has passed. Then one trial
+/// call is let through: if the provider answers it, even to reject
+/// the request, the breaker closes, and if it fails the breaker
+/// stays open for another cooldown.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    failures: u32,
+    open_until: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        CircuitBreaker {
+            threshold: threshold.max(1),
+            cooldown,
+            state: Mutex::default(),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Whether a call may be made now
+    ///
+    /// Once the cooldown has passed this admits a single trial
+    /// call, and refuses others until the trial's cooldown
+    /// passes in turn.
+    pub fn try_acquire(&self) -> bool {
+        let mut state = self.state();
+        let now = Instant::now();
+        match state.open_until {
+            Some(until) if now < until => false,
+            Some(_) => {
+                state.open_until = Some(now + self.cooldown);
+                true
+            }
+            None => true,
+        }
+    }
+
+    pub fn record_success(&self) {
+        *self.state() = State::default();
+    }
+
+    pub fn record_failure(&self) {
+        let mut state = self.state();
+        state.failures = state.failures.saturating_add(1);
+        if state.failures >= self.threshold {
+            state.open_until = Some(Instant::now() + self.cooldown);
+        }
+    }
+
+    /// Whether calls are currently being refused
+    pub fn is_open(&self) -> bool {
+        self.state()
+            .open_until
+            .is_some_and(|until| Instant::now() < until)
+    }
+}
@@ -0,0 +1,143 @@
+use std::time::Duration;
+
+use async_openai::{
+    Client,
+    config::OpenAIConfig,
+    types::{CreateEmbeddingRequest, EmbeddingInput},
+};
+use async_trait::async_trait;
+use libserver::ServiceError;
+use tokio::time::timeout;
+
+use crate::{CallPolicy, ModelTimeout};
+
+/// The most texts sent to a provider in one request
+const EMBEDDING_BATCH: usize = 256;
+
+/// Turns text into vectors whose cosine similarity
+/// reflects how related the texts are
+#[async_trait]
+pub trait Embedder: Send + Sync {
+    /// Names the space the vectors are in; vectors from
+    /// different models can't be compared
+    fn model(&self) -> &str;
+
+    /// Embeds each of `texts`, in order
+    async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, ServiceError>;
+}
+
+/// Embeds through a provider's `/embeddings` endpoint
+///
+/// Calls are bounded by the policy's first token timeout
+/// but aren't retried or routed to other providers.
+pub struct ProviderEmbedder {
+    client: Client<OpenAIConfig>,
+    model: String,
+    timeout: Duration,
+}
+
+impl ProviderEmbedder {
+    pub fn new(client: Client<OpenAIConfig>, model: &str, policy: &CallPolicy) -> Self {
+        ProviderEmbedder {
+            client,
+            model: model.into(),
+            timeout: policy.first_token_timeout,
+        }
+    }
+}
+
+#[async_trait]
+impl Embedder for ProviderEmbedder {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, ServiceError> {
+        let mut vectors = Vec::with_capacity(texts.len());
+
+        for batch in texts.chunks(EMBEDDING_BATCH) {
+            let request = CreateEmbeddingRequest {
+                model: self.model.clone(),
+                input: EmbeddingInput::StringArray(batch.to_vec()),
+                ..Default::default()
+            };
+            let Ok(response) =
+                timeout(self.timeout, self.client.embeddings().create(request)).await
+            else {
+                return Err(ModelTimeout.into());
+            };
+
+            let mut data = response?.data;
+            if data.len() != batch.len() {
+                Err(EmbeddingCountMismatch)?;
+            }
+            data.sort_by_key(|embedding| embedding.index);
+            vectors.extend(data.into_iter().map(|embedding| embedding.embedding));
+        }
+
+        Ok(vectors)
+    }
+}
+
+/// Embeds text locally by hashing its words into a fixed number
+/// of dimensions
+///
+/// Texts that share words end up similar, which is crude next to
+/// a real model but deterministic and free, so it serves tests and
+/// deployments without an embedding provider.
+pub struct HashEmbedder {
+    model: String,
+    dims: usize,
+}
+
+impl HashEmbedder {
+    pub fn new(dims: usize) -> Self {
+        HashEmbedder {
+            model: format!("hash-{dims}"),
+            dims,
+        }
+    }
+
+    /// The normalized vector of one text
+    pub fn vector(&self, text: &str) -> Vec<f32> {
+        let mut vector = vec![0.0; self.dims];
+        let words = text
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty());
+        for word in words {
+            let hash = fnv1a(&word.to_lowercase());
+            // The top bit picks a sign, so unrelated words
+            // sharing a dimension tend to cancel out
+            let sign = if hash >> 63 == 0 { 1.0 } else { -1.0 };
+            vector[(hash % self.dims as u64) as usize] += sign;
+        }
+
+        let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            vector.iter_mut().for_each(|x| *x /= norm);
+        }
+        vector
+    }
+}
+
+#[async_trait]
+impl Embedder for HashEmbedder {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, ServiceError> {
+        Ok(texts.iter().map(|text| self.vector(text)).collect())
+    }
+}
+
+/// A hash that, unlike std's, is the same on every build
+fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Embedding Count Mismatch")]
+pub struct EmbeddingCountMismatch;
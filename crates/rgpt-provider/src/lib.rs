@@ -0,0 +1,91 @@
+//! Chat completions over a route of models on OpenAI-compatible
+//! providers
+//!
+//! Each request is tried against the route's models in order. Every
+//! provider call runs under a [`CallPolicy`]: transient failures are
+//! retried with jittered backoff until a token has been emitted, slow
+//! or stalled replies time out, and a [`CircuitBreaker`] per provider
+//! skips providers that keep failing. A model that still fails hands
+//! the request to the next model in the route.
+//!
+//! Text is embedded for retrieval by an [`Embedder`], either
+//! a provider's embedding model or the local [`HashEmbedder`].
+//! Prompts and replies are checked by a [`Moderator`], which
+//! chains a local [`PolicyModerator`] with an optional
+//! [`ProviderModerator`].
+
+use std::{collections::HashMap, sync::Arc};
+
+use async_openai::{Client, config::OpenAIConfig};
+use serde::Serialize;
+
+pub mod breaker;
+pub mod embed;
+pub mod moderate;
+pub mod policy;
+mod router;
+
+pub use breaker::CircuitBreaker;
+pub use embed::{Embedder, EmbeddingCountMismatch, HashEmbedder, ProviderEmbedder};
+pub use moderate::{
+    Action, Moderator, ModeratorChain, PolicyModerator, PolicyRule, ProviderModerator, Verdict,
+};
+pub use policy::CallPolicy;
+pub use router::{ModelRouter, ModelTimeout, NoModelAvailable};
+
+/// An OpenAI-compatible API that models can be served from
+#[derive(Serialize, Debug, Clone)]
+pub struct ProviderConfig {
+    /// The name model targets refer to the provider by
+    pub name: String,
+
+    /// The base URL of the provider's API
+    pub api_base: String,
+
+    /// The environment variable holding the provider's
+    /// API key. Providers without one are left out
+    pub api_key_env: String,
+}
+
+/// A model on a provider, one step of the model route
+#[derive(Serialize, Debug, Clone)]
+pub struct ModelTarget {
+    pub provider: String,
+    pub model: String,
+}
+
+impl ModelTarget {
+    pub fn new(provider: &str, model: &str) -> Self {
+        ModelTarget {
+            provider: provider.into(),
+            model: model.into(),
+        }
+    }
+}
+
+/// Builds a client for a provider that connects under the policy's
+/// timeout and leaves retrying to the policy
+pub fn client(api_base: &str, api_key: &str, policy: &CallPolicy) -> Client<OpenAIConfig> {
+    let http_client = reqwest::Client::builder()
+        .connect_timeout(policy.connect_timeout)
+        .build()
+        .unwrap_or_default();
+
+    // async-openai retries rate limits and server errors on its own,
+    // for up to 15 minutes by default, which no timeout here could bound
+    let no_backoff = backoff::ExponentialBackoffBuilder::new()
+        .with_max_elapsed_time(Some(std::time::Duration::ZERO))
+        .build();
+
+    Client::with_config(
+        OpenAIConfig::new()
+            .with_api_base(api_base)
+            .with_api_key(api_key),
+    )
+    .with_http_client(http_client)
+    .with_backoff(no_backoff)
+}
+
+/// The clients of a set of providers, by name, sharing one
+/// circuit breaker per provider
+pub(crate) type Providers = HashMap<String, (Client<OpenAIConfig>, Arc<CircuitBreaker>)>;
@@ -0,0 +1,240 @@
+use std::time::Duration;
+
+use async_openai::{Client, config::OpenAIConfig, types::CreateModerationRequestArgs};
+use async_trait::async_trait;
+use libserver::ServiceError;
+use regex::Regex;
+use serde::Serialize;
+use tokio::time::timeout;
+
+use crate::{CallPolicy, ModelTimeout};
+
+/// What's done with text once it's been checked
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Allow,
+    /// Let through, but kept for review
+    Flag,
+    /// Stopped, and kept for review
+    Block,
+}
+
+/// What a moderator made of a text
+#[derive(Clone, PartialEq, Debug)]
+pub struct Verdict {
+    pub action: Action,
+    /// What the text matched, for reviewers
+    pub reasons: Vec<String>,
+}
+
+impl Verdict {
+    pub fn allow() -> Self {
+        Verdict {
+            action: Action::Allow,
+            reasons: vec![],
+        }
+    }
+
+    /// Combines two verdicts on the same text, keeping
+    /// the stricter action and the reasons for both
+    pub fn and(mut self, other: Verdict) -> Self {
+        self.action = self.action.max(other.action);
+        for reason in other.reasons {
+            if !self.reasons.contains(&reason) {
+                self.reasons.push(reason);
+            }
+        }
+        self
+    }
+}
+
+/// Decides whether text may be sent to the model or shown
+/// to the user
+#[async_trait]
+pub trait Moderator: Send + Sync {
+    async fn check(&self, text: &str) -> Result<Verdict, ServiceError>;
+}
+
+/// A rule of the local policy
+#[derive(Serialize, Debug, Clone)]
+pub struct PolicyRule {
+    pub pattern: Pattern,
+    /// What's done with text the pattern matches
+    pub action: Action,
+}
+
+impl PolicyRule {
+    pub fn keyword(keyword: &str, action: Action) -> Self {
+        PolicyRule {
+            pattern: Pattern::Keyword(keyword.into()),
+            action,
+        }
+    }
+
+    pub fn regex(regex: &str, action: Action) -> Self {
+        PolicyRule {
+            pattern: Pattern::Regex(regex.into()),
+            action,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub enum Pattern {
+    /// A word or phrase, matched as whole words in any case
+    Keyword(String),
+    /// A regular expression, matched as written
+    Regex(String),
+}
+
+/// Checks text against keywords and regular expressions, locally
+pub struct PolicyModerator {
+    rules: Vec<CompiledRule>,
+}
+
+struct CompiledRule {
+    regex: Regex,
+    action: Action,
+    reason: String,
+}
+
+impl PolicyModerator {
+    pub fn new(rules: &[PolicyRule]) -> Result<Self, regex::Error> {
+        let rules = rules
+            .iter()
+            .map(|rule| {
+                let (regex, reason) = match &rule.pattern {
+                    Pattern::Keyword(keyword) => (
+                        Regex::new(&format!(r"(?i)\b{}\b", regex::escape(keyword)))?,
+                        format!("keyword: {keyword}"),
+                    ),
+                    Pattern::Regex(regex) => (Regex::new(regex)?, format!("regex: {regex}")),
+                };
+                Ok(CompiledRule {
+                    regex,
+                    action: rule.action,
+                    reason,
+                })
+            })
+            .collect::<Result<_, regex::Error>>()?;
+        Ok(PolicyModerator { rules })
+    }
+
+    /// The verdict of every rule that matches `text`
+    pub fn verdict(&self, text: &str) -> Verdict {
+        self.rules
+            .iter()
+            .filter(|rule| rule.action != Action::Allow && rule.regex.is_match(text))
+            .fold(Verdict::allow(), |verdict, rule| {
+                verdict.and(Verdict {
+                    action: rule.action,
+                    reasons: vec![rule.reason.clone()],
+                })
+            })
+    }
+}
+
+#[async_trait]
+impl Moderator for PolicyModerator {
+    async fn check(&self, text: &str) -> Result<Verdict, ServiceError> {
+        Ok(self.verdict(text))
+    }
+}
+
+/// Checks text with a provider's `/moderations` endpoint
+///
+/// Text the model flags gets `action`, with the categories
+/// it was flagged for as the reasons. Calls are bounded by
+/// the policy's first token timeout but aren't retried.
+pub struct ProviderModerator {
+    client: Client<OpenAIConfig>,
+    model: String,
+    action: Action,
+    timeout: Duration,
+}
+
+impl ProviderModerator {
+    pub fn new(
+        client: Client<OpenAIConfig>,
+        model: &str,
+        action: Action,
+        policy: &CallPolicy,
+    ) -> Self {
+        ProviderModerator {
+            client,
+            model: model.into(),
+            action,
+            timeout: policy.first_token_timeout,
+        }
+    }
+}
+
+#[async_trait]
+impl Moderator for ProviderModerator {
+    async fn check(&self, text: &str) -> Result<Verdict, ServiceError> {
+        let request = CreateModerationRequestArgs::default()
+            .input(text.to_owned())
+            .model(self.model.clone())
+            .build()?;
+        let Ok(response) = timeout(self.timeout, self.client.moderations().create(request)).await
+        else {
+            return Err(ModelTimeout.into());
+        };
+
+        let results = response?.results;
+        let mut verdict = Verdict::allow();
+        for result in results.into_iter().filter(|result| result.flagged) {
+            // The categories are a struct of flags named as in the API
+            let categories = match serde_json::to_value(&result.categories)? {
+                serde_json::Value::Object(categories) => categories,
+                _ => Default::default(),
+            };
+            let reasons = categories
+                .into_iter()
+                .filter(|(_, flagged)| flagged.as_bool() == Some(true))
+                .map(|(category, _)| format!("{}: {category}", self.model))
+                .collect();
+            verdict = verdict.and(Verdict {
+                action: self.action,
+                reasons,
+            });
+        }
+        Ok(verdict)
+    }
+}
+
+/// Runs text past several moderators and combines their verdicts
+///
+/// A moderator that fails is logged and skipped, so a provider
+/// outage leaves the local policy in force rather than stopping
+/// every chat.
+#[derive(Default)]
+pub struct ModeratorChain {
+    moderators: Vec<Box<dyn Moderator>>,
+}
+
+impl ModeratorChain {
+    pub fn new() -> Self {
+        ModeratorChain::default()
+    }
+
+    pub fn with(mut self, moderator: impl Moderator + 'static) -> Self {
+        self.moderators.push(Box::new(moderator));
+        self
+    }
+}
+
+#[async_trait]
+impl Moderator for ModeratorChain {
+    async fn check(&self, text: &str) -> Result<Verdict, ServiceError> {
+        let mut verdict = Verdict::allow();
+        for moderator in &self.moderators {
+            match moderator.check(text).await {
+                Ok(checked) => verdict = verdict.and(checked),
+                Err(err) => eprintln!("moderation check failed, skipping it: {err}"),
+            }
+        }
+        Ok(verdict)
+    }
+}
@@ -0,0 +1,90 @@
+use std::time::Duration;
+
+use async_openai::error::OpenAIError;
+use serde::Serialize;
+
+/// How calls to a provider are bounded and retried
+#[derive(Serialize, Debug, Clone)]
+pub struct CallPolicy {
+    /// How long establishing a connection to
+    /// the provider may take
+    pub connect_timeout: Duration,
+
+    /// How long a provider may take to send the first
+    /// chunk of a streamed reply, or the whole of a
+    /// reply that isn't streamed
+    pub first_token_timeout: Duration,
+
+    /// How long a streamed reply may go between chunks
+    /// before it is abandoned
+    pub idle_timeout: Duration,
+
+    /// How many times a transient failure is retried on
+    /// the same model before failing over. Failures are
+    /// never retried once a token has been emitted
+    pub max_retries: u32,
+
+    /// The base of the exponential backoff between
+    /// retries, each of which waits a random time up to
+    /// `retry_base_delay * 2^attempt`
+    pub retry_base_delay: Duration,
+
+    /// The longest wait between retries
+    pub retry_max_delay: Duration,
+
+    /// How many consecutive failed calls open a
+    /// provider's circuit breaker
+    pub breaker_threshold: u32,
+
+    /// How long an open breaker fails calls fast before
+    /// letting a trial call through
+    pub breaker_cooldown: Duration,
+}
+
+impl Default for CallPolicy {
+    fn default() -> Self {
+        CallPolicy {
+            connect_timeout: Duration::from_secs(5),
+            first_token_timeout: Duration::from_secs(20),
+            idle_timeout: Duration::from_secs(30),
+            max_retries: 2,
+            retry_base_delay: Duration::from_millis(250),
+            retry_max_delay: Duration::from_secs(4),
+            breaker_threshold: 5,
+            breaker_cooldown: Duration::from_secs(30),
+        }
+    }
+}
+
+impl CallPolicy {
+    /// The wait before retry number `attempt`, counting from
+    /// zero, with full jitter so clients that failed together
+    /// don't retry together
+    pub fn retry_delay(&self, attempt: u32) -> Duration {
+        let ceiling = self
+            .retry_base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.retry_max_delay);
+        ceiling.mul_f64(rand::random_range(0.0..=1.0))
+    }
+}
+
+/// Whether a failed call might succeed if made again
+///
+/// Dropped connections, server errors and rate limits are
+/// transient; requests the provider rejected are not.
+pub fn is_transient(err: &OpenAIError) -> bool {
+    match err {
+        OpenAIError::Reqwest(_) | OpenAIError::StreamError(_) => true,
+        // Server errors aren't JSON, so they are reported without a type
+        OpenAIError::ApiError(err) => {
+            err.r#type.is_none()
+                || err.code.as_deref() == Some("rate_limit_exceeded")
+                || matches!(
+                    err.r#type.as_deref(),
+                    Some("server_error" | "requests" | "tokens")
+                )
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,259 @@
+use std::{collections::HashMap, future::Future, sync::Arc, time::Duration};
+
+use async_openai::{
+    Client,
+    config::OpenAIConfig,
+    error::OpenAIError,
+    types::{
+        ChatCompletionResponseStream, CreateChatCompletionRequest, CreateChatCompletionResponse,
+    },
+};
+use futures::{StreamExt, stream};
+use libserver::ServiceError;
+use tokio::time::timeout;
+
+use crate::{CallPolicy, CircuitBreaker, ModelTarget, Providers, policy::is_transient};
+
+/// Sends chat completions to the first model in a route
+/// that serves them
+pub struct ModelRouter {
+    policy: CallPolicy,
+    providers: Providers,
+    route: Vec<ModelTarget>,
+}
+
+/// Why a single provider call failed
+enum CallError {
+    Provider(OpenAIError),
+    Timeout,
+}
+
+impl CallError {
+    fn is_transient(&self) -> bool {
+        match self {
+            CallError::Provider(err) => is_transient(err),
+            CallError::Timeout => true,
+        }
+    }
+
+    /// Whether another model might succeed where this one failed
+    ///
+    /// Invalid arguments are rejected by the client before anything
+    /// is sent, so every model would reject them the same way.
+    fn should_fail_over(&self) -> bool {
+        !matches!(self, CallError::Provider(OpenAIError::InvalidArgument(_)))
+    }
+
+    fn into_service_error(self) -> ServiceError {
+        match self {
+            CallError::Provider(err) => err.into(),
+            CallError::Timeout => ModelTimeout.into(),
+        }
+    }
+}
+
+impl ModelRouter {
+    /// A router over `route`, whose targets name providers in
+    /// `clients`. Targets on other providers are left out
+    pub fn new(
+        policy: CallPolicy,
+        clients: HashMap<String, Client<OpenAIConfig>>,
+        route: Vec<ModelTarget>,
+    ) -> Self {
+        let route = route
+            .into_iter()
+            .filter(|target| {
+                let known = clients.contains_key(&target.provider);
+                if !known {
+                    eprintln!(
+                        "provider {} is not configured, leaving {} out of the model route",
+                        target.provider, target.model
+                    );
+                }
+                known
+            })
+            .collect();
+
+        let providers = clients
+            .into_iter()
+            .map(|(name, client)| {
+                let breaker =
+                    CircuitBreaker::new(policy.breaker_threshold, policy.breaker_cooldown);
+                (name, (client, Arc::new(breaker)))
+            })
+            .collect();
+
+        ModelRouter {
+            policy,
+            providers,
+            route,
+        }
+    }
+
+    /// The models requests are tried against, in order
+    pub fn route(&self) -> &[ModelTarget] {
+        &self.route
+    }
+
+    /// The breaker guarding calls to a provider
+    pub fn breaker(&self, provider: &str) -> Option<&CircuitBreaker> {
+        self.providers.get(provider).map(|(_, breaker)| &**breaker)
+    }
+
+    /// Creates a completion with the first model in the route that
+    /// serves it, returning the model along with the response
+    ///
+    /// The first-token timeout bounds the whole response.
+    pub async fn create(
+        &self,
+        request: CreateChatCompletionRequest,
+    ) -> Result<(String, CreateChatCompletionResponse), ServiceError> {
+        self.try_route(request, |client, request, policy| async move {
+            match timeout(policy.first_token_timeout, client.chat().create(request)).await {
+                Ok(Ok(response)) => Ok(response),
+                Ok(Err(err)) => Err(CallError::Provider(err)),
+                Err(_) => Err(CallError::Timeout),
+            }
+        })
+        .await
+    }
+
+    /// Starts a completion stream with the first model in the route
+    /// that starts replying, returning the model along with the stream
+    ///
+    /// The stream's first chunk is awaited here, since upstream errors
+    /// only surface once the stream is polled. Once it has arrived the
+    /// stream is committed to its model: later failures, including the
+    /// idle timeout, end the stream with an error rather than retrying.
+    pub async fn create_stream(
+        &self,
+        request: CreateChatCompletionRequest,
+    ) -> Result<(String, ChatCompletionResponseStream), ServiceError> {
+        self.try_route(request, |client, request, policy| async move {
+            let started = async {
+                let mut stream = client.chat().create_stream(request).await?;
+                let first = stream.next().await.transpose()?;
+                Ok::<_, OpenAIError>((first, stream))
+            };
+
+            let (first, stream) = match timeout(policy.first_token_timeout, started).await {
+                Ok(Ok(started)) => started,
+                Ok(Err(err)) => return Err(CallError::Provider(err)),
+                Err(_) => return Err(CallError::Timeout),
+            };
+
+            let stream = with_idle_timeout(stream, policy.idle_timeout);
+            let stream: ChatCompletionResponseStream = match first {
+                Some(first) => Box::pin(stream::once(async { Ok(first) }).chain(stream)),
+                None => stream,
+            };
+            Ok(stream)
+        })
+        .await
+    }
+
+    /// Makes `call` against each model in the route until one
+    /// succeeds, retrying transient failures before moving on
+    ///
+    /// A model in the route that the request names is tried first,
+    /// and the rest in order after it.
+    async fn try_route<T, F, Fut>(
+        &self,
+        request: CreateChatCompletionRequest,
+        call: F,
+    ) -> Result<(String, T), ServiceError>
+    where
+        F: Fn(Client<OpenAIConfig>, CreateChatCompletionRequest, CallPolicy) -> Fut,
+        Fut: Future<Output = Result<T, CallError>>,
+    {
+        let mut last_err = None;
+
+        let (named, rest): (Vec<_>, Vec<_>) = self
+            .route
+            .iter()
+            .partition(|target| target.model == request.model);
+        for target in named.into_iter().chain(rest) {
+            let Some((client, breaker)) = self.providers.get(&target.provider) else {
+                continue;
+            };
+
+            let mut attempt = 0;
+            let mut failure = None;
+            while breaker.try_acquire() {
+                let mut request = request.clone();
+                request.model.clone_from(&target.model);
+
+                let err = match call(client.clone(), request, self.policy.clone()).await {
+                    Ok(response) => {
+                        breaker.record_success();
+                        return Ok((target.model.clone(), response));
+                    }
+                    Err(err) => err,
+                };
+
+                if !err.should_fail_over() {
+                    return Err(err.into_service_error());
+                }
+
+                // A provider that rejects a request has still answered
+                // it, so it's up, whether or not the breaker's trial
+                let transient = err.is_transient();
+                if transient {
+                    breaker.record_failure();
+                } else {
+                    breaker.record_success();
+                }
+
+                let retry = transient && attempt < self.policy.max_retries;
+                failure = Some(err.into_service_error());
+                if !retry {
+                    break;
+                }
+                tokio::time::sleep(self.policy.retry_delay(attempt)).await;
+                attempt += 1;
+            }
+
+            match failure {
+                Some(err) => {
+                    eprintln!("model {}/{} failed: {err}", target.provider, target.model);
+                    last_err = Some(err);
+                }
+                None => eprintln!(
+                    "provider {} is failing, skipping {}",
+                    target.provider, target.model
+                ),
+            }
+        }
+
+        Err(last_err.unwrap_or_else(|| NoModelAvailable.into()))
+    }
+}
+
+/// Ends the stream with an error if no chunk arrives within `idle`
+fn with_idle_timeout(
+    stream: ChatCompletionResponseStream,
+    idle: Duration,
+) -> ChatCompletionResponseStream {
+    Box::pin(stream::unfold(Some(stream), move |stream| async move {
+        let mut stream = stream?;
+        match timeout(idle, stream.next()).await {
+            Ok(Some(item)) => Some((item, Some(stream))),
+            Ok(None) => None,
+            Err(_) => {
+                let err = OpenAIError::StreamError(format!(
+                    "no chunk received for {}s",
+                    idle.as_secs_f32()
+                ));
+                Some((Err(err), None))
+            }
+        }
+    }))
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Model Timed Out")]
+pub struct ModelTimeout;
+
+#[derive(Debug, thiserror::Error)]
+#[error("No Model Available")]
+pub struct NoModelAvailable;
@@ -0,0 +1,33 @@
+use rgpt_provider::{Embedder, HashEmbedder};
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+#[tokio::test]
+async fn hash_embeddings_are_deterministic() {
+    let embedder = HashEmbedder::new(256);
+    assert_eq!(embedder.model(), "hash-256");
+
+    let texts = vec!["The quick brown fox".to_owned(), "".to_owned()];
+    let first = embedder.embed(texts.clone()).await.unwrap();
+    let second = HashEmbedder::new(256).embed(texts).await.unwrap();
+    assert_eq!(first, second);
+
+    assert_eq!(first[0].len(), 256);
+    assert!((dot(&first[0], &first[0]) - 1.0).abs() < 1e-5);
+    // Text without words has nothing to point along
+    assert!(first[1].iter().all(|x| *x == 0.0));
+}
+
+#[test]
+fn shared_words_are_similar() {
+    let embedder = HashEmbedder::new(512);
+    let query = embedder.vector("How do I rotate the API keys?");
+    let related = embedder.vector("Rotating API keys: create a new key, then revoke the old keys");
+    let unrelated = embedder.vector("Sourdough needs a ripe starter and a long, slow proof");
+
+    assert!(dot(&query, &related) > dot(&query, &unrelated));
+    // Case doesn't matter
+    assert_eq!(embedder.vector("API Keys"), embedder.vector("api keys"));
+}
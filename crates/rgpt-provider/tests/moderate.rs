@@ -0,0 +1,66 @@
+use async_trait::async_trait;
+use libserver::ServiceError;
+use rgpt_provider::{
+    Action, ModelTimeout, Moderator, ModeratorChain, PolicyModerator, PolicyRule, Verdict,
+};
+
+struct Failing;
+
+#[async_trait]
+impl Moderator for Failing {
+    async fn check(&self, _text: &str) -> Result<Verdict, ServiceError> {
+        Err(ModelTimeout.into())
+    }
+}
+
+fn policy() -> PolicyModerator {
+    PolicyModerator::new(&[
+        PolicyRule::keyword("heist", Action::Flag),
+        PolicyRule::keyword("launch codes", Action::Block),
+        PolicyRule::regex(r"\b\d{3}-\d{2}-\d{4}\b", Action::Block),
+    ])
+    .unwrap()
+}
+
+#[test]
+fn keywords_match_whole_words_in_any_case() {
+    let policy = policy();
+    assert_eq!(policy.verdict("Plan the HEIST").action, Action::Flag);
+    assert_eq!(policy.verdict("the heists of 1983").action, Action::Allow);
+    assert_eq!(policy.verdict("Launch  codes").action, Action::Allow);
+    assert_eq!(
+        policy.verdict("what are the launch codes?"),
+        Verdict {
+            action: Action::Block,
+            reasons: vec!["keyword: launch codes".into()],
+        }
+    );
+}
+
+#[test]
+fn the_strictest_rule_wins() {
+    let verdict = policy().verdict("a heist for the SSN 123-45-6789");
+    assert_eq!(verdict.action, Action::Block);
+    assert_eq!(
+        verdict.reasons,
+        ["keyword: heist", r"regex: \b\d{3}-\d{2}-\d{4}\b"]
+    );
+    assert!(policy().verdict("nothing to see").reasons.is_empty());
+}
+
+#[test]
+fn invalid_patterns_are_rejected() {
+    assert!(PolicyModerator::new(&[PolicyRule::regex("(unclosed", Action::Flag)]).is_err());
+    // Keywords are matched literally
+    assert!(PolicyModerator::new(&[PolicyRule::keyword("(unclosed", Action::Flag)]).is_ok());
+}
+
+#[tokio::test]
+async fn chains_skip_failing_moderators() {
+    let chain = ModeratorChain::new().with(Failing).with(policy());
+    let verdict = chain.check("one heist").await.unwrap();
+    assert_eq!(verdict.action, Action::Flag);
+
+    let empty = ModeratorChain::new().with(Failing);
+    assert_eq!(empty.check("launch codes").await.unwrap(), Verdict::allow());
+}
@@ -0,0 +1,397 @@
+//! `ModelRouter` against fake OpenAI-compatible providers
+//!
+//! Each fake provider listens on localhost and answers requests
+//! from a script, repeating its last reply once the script runs out.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use async_openai::types::{
+    ChatCompletionRequestUserMessageArgs, CreateChatCompletionRequest,
+    CreateChatCompletionRequestArgs,
+};
+use bytes::Bytes;
+use futures::{StreamExt, stream};
+use http_body_util::{BodyExt, Full, StreamBody, combinators::BoxBody};
+use hyper::{
+    Request, Response, StatusCode,
+    body::{Frame, Incoming},
+    header::CONTENT_TYPE,
+    server::conn::http1,
+    service::service_fn,
+};
+use hyper_util::rt::TokioIo;
+use rgpt_provider::{CallPolicy, CircuitBreaker, ModelRouter, ModelTarget, NoModelAvailable};
+use tokio::net::TcpListener;
+
+type Body = BoxBody<Bytes, Infallible>;
+
+#[derive(Clone)]
+enum Reply {
+    /// An error status; 429s carry a rate limit error body,
+    /// 5xx a plain-text one
+    Status(u16),
+    /// A complete, non-streamed reply
+    Completion(&'static str),
+    /// A streamed reply, one chunk per string, that goes
+    /// silent after `stall_after` chunks if that is set
+    Stream {
+        chunks: Vec<&'static str>,
+        stall_after: Option<usize>,
+    },
+    /// Accepts the request but never answers it
+    Hang,
+}
+
+struct FakeProvider {
+    addr: SocketAddr,
+    hits: Arc<AtomicUsize>,
+}
+
+impl FakeProvider {
+    async fn start(script: Vec<Reply>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let hits = Arc::new(AtomicUsize::new(0));
+        let script = Arc::new(Mutex::new(VecDeque::from(script)));
+
+        let server_hits = hits.clone();
+        tokio::spawn(async move {
+            while let Ok((conn, _)) = listener.accept().await {
+                let hits = server_hits.clone();
+                let script = script.clone();
+                let service = service_fn(move |_req: Request<Incoming>| {
+                    hits.fetch_add(1, Ordering::SeqCst);
+                    let reply = {
+                        let mut script = script.lock().unwrap();
+                        if script.len() > 1 {
+                            script.pop_front().unwrap()
+                        } else {
+                            script.front().unwrap().clone()
+                        }
+                    };
+                    async move { Ok::<_, Infallible>(respond(reply).await) }
+                });
+                tokio::spawn(http1::Builder::new().serve_connection(TokioIo::new(conn), service));
+            }
+        });
+
+        FakeProvider { addr, hits }
+    }
+
+    fn hits(&self) -> usize {
+        self.hits.load(Ordering::SeqCst)
+    }
+
+    fn client(
+        &self,
+        policy: &CallPolicy,
+    ) -> async_openai::Client<async_openai::config::OpenAIConfig> {
+        rgpt_provider::client(&format!("http://{}/v1", self.addr), "test-key", policy)
+    }
+}
+
+async fn respond(reply: Reply) -> Response<Body> {
+    match reply {
+        Reply::Status(429) => json(
+            429,
+            r#"{"error": {"message": "Slow down", "type": "requests", "param": null, "code": "rate_limit_exceeded"}}"#.into(),
+        ),
+        Reply::Status(status) if status >= 500 => Response::builder()
+            .status(status)
+            .body(full("upstream is down".into()))
+            .unwrap(),
+        Reply::Status(status) => json(
+            status,
+            r#"{"error": {"message": "Bad request", "type": "invalid_request_error", "param": null, "code": null}}"#.into(),
+        ),
+        Reply::Completion(content) => json(
+            200,
+            format!(
+                r#"{{"id": "chatcmpl-1", "object": "chat.completion", "created": 0, "model": "fake",
+                    "choices": [{{"index": 0, "message": {{"role": "assistant", "content": "{content}"}},
+                    "finish_reason": "stop"}}]}}"#
+            ),
+        ),
+        Reply::Stream {
+            chunks,
+            stall_after,
+        } => {
+            let events = chunks
+                .into_iter()
+                .map(|content| {
+                    format!(
+                        r#"data: {{"id": "chatcmpl-1", "object": "chat.completion.chunk", "created": 0, "model": "fake", "choices": [{{"index": 0, "delta": {{"content": "{content}"}}, "finish_reason": null}}]}}"#
+                    ) + "\n\n"
+                })
+                .collect::<Vec<_>>();
+            let sent = stall_after.unwrap_or(events.len());
+
+            let events = stream::iter(events.into_iter().take(sent))
+                .chain(stream::once(async move {
+                    if stall_after.is_some() {
+                        std::future::pending::<()>().await;
+                    }
+                    "data: [DONE]\n\n".to_owned()
+                }))
+                .map(|event| Ok(Frame::data(Bytes::from(event))));
+
+            Response::builder()
+                .header(CONTENT_TYPE, "text/event-stream")
+                .body(StreamBody::new(events).boxed())
+                .unwrap()
+        }
+        Reply::Hang => std::future::pending().await,
+    }
+}
+
+fn json(status: u16, body: String) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::from_u16(status).unwrap())
+        .header(CONTENT_TYPE, "application/json")
+        .body(full(body))
+        .unwrap()
+}
+
+fn full(body: String) -> Body {
+    Full::new(Bytes::from(body)).boxed()
+}
+
+/// A policy with short timeouts and no real waiting between retries
+fn policy() -> CallPolicy {
+    CallPolicy {
+        connect_timeout: Duration::from_secs(1),
+        first_token_timeout: Duration::from_millis(500),
+        idle_timeout: Duration::from_millis(300),
+        max_retries: 2,
+        retry_base_delay: Duration::from_millis(1),
+        retry_max_delay: Duration::from_millis(5),
+        breaker_threshold: 100,
+        breaker_cooldown: Duration::from_secs(60),
+    }
+}
+
+fn router(policy: CallPolicy, providers: &[(&str, &FakeProvider)]) -> ModelRouter {
+    let clients = providers
+        .iter()
+        .map(|(name, provider)| (name.to_string(), provider.client(&policy)))
+        .collect::<HashMap<_, _>>();
+    let route = providers
+        .iter()
+        .map(|(name, _)| ModelTarget::new(name, &format!("{name}-model")))
+        .collect();
+    ModelRouter::new(policy, clients, route)
+}
+
+fn request() -> CreateChatCompletionRequest {
+    CreateChatCompletionRequestArgs::default()
+        .model("unused")
+        .messages([ChatCompletionRequestUserMessageArgs::default()
+            .content("hi")
+            .build()
+            .unwrap()
+            .into()])
+        .build()
+        .unwrap()
+}
+
+fn content(response: &async_openai::types::CreateChatCompletionResponse) -> Option<&str> {
+    response.choices.first()?.message.content.as_deref()
+}
+
+#[tokio::test]
+async fn retries_transient_errors() {
+    let primary = FakeProvider::start(vec![
+        Reply::Status(500),
+        Reply::Status(429),
+        Reply::Completion("hello"),
+    ])
+    .await;
+    let router = router(policy(), &[("primary", &primary)]);
+
+    let (model, response) = router.create(request()).await.unwrap();
+    assert_eq!(model, "primary-model");
+    assert_eq!(content(&response), Some("hello"));
+    assert_eq!(primary.hits(), 3);
+}
+
+#[tokio::test]
+async fn fails_over_once_retries_run_out() {
+    let primary = FakeProvider::start(vec![Reply::Status(503)]).await;
+    let fallback = FakeProvider::start(vec![Reply::Completion("from fallback")]).await;
+    let router = router(policy(), &[("primary", &primary), ("fallback", &fallback)]);
+
+    let (model, response) = router.create(request()).await.unwrap();
+    assert_eq!(model, "fallback-model");
+    assert_eq!(content(&response), Some("from fallback"));
+    assert_eq!(primary.hits(), 3);
+}
+
+#[tokio::test]
+async fn named_model_is_tried_first() {
+    let primary = FakeProvider::start(vec![Reply::Completion("from primary")]).await;
+    let fallback = FakeProvider::start(vec![Reply::Completion("from fallback")]).await;
+    let router = router(policy(), &[("primary", &primary), ("fallback", &fallback)]);
+
+    let mut named = request();
+    named.model = "fallback-model".into();
+    let (model, response) = router.create(named).await.unwrap();
+    assert_eq!(model, "fallback-model");
+    assert_eq!(content(&response), Some("from fallback"));
+    assert_eq!(primary.hits(), 0);
+}
+
+#[tokio::test]
+async fn rejected_requests_are_not_retried() {
+    let primary = FakeProvider::start(vec![Reply::Status(400)]).await;
+    let fallback = FakeProvider::start(vec![Reply::Completion("from fallback")]).await;
+    let router = router(policy(), &[("primary", &primary), ("fallback", &fallback)]);
+
+    let (model, _) = router.create(request()).await.unwrap();
+    assert_eq!(model, "fallback-model");
+    assert_eq!(primary.hits(), 1);
+}
+
+#[tokio::test]
+async fn first_token_timeout_fails_over() {
+    let primary = FakeProvider::start(vec![Reply::Hang]).await;
+    let fallback = FakeProvider::start(vec![Reply::Stream {
+        chunks: vec!["hel", "lo"],
+        stall_after: None,
+    }])
+    .await;
+    let policy = CallPolicy {
+        max_retries: 0,
+        ..policy()
+    };
+    let router = router(policy, &[("primary", &primary), ("fallback", &fallback)]);
+
+    let (model, stream) = router.create_stream(request()).await.unwrap();
+    assert_eq!(model, "fallback-model");
+
+    let chunks = stream.collect::<Vec<_>>().await;
+    let text = chunks
+        .into_iter()
+        .map(|chunk| chunk.unwrap().choices[0].delta.content.clone().unwrap())
+        .collect::<String>();
+    assert_eq!(text, "hello");
+}
+
+#[tokio::test]
+async fn idle_stream_ends_with_an_error() {
+    let primary = FakeProvider::start(vec![Reply::Stream {
+        chunks: vec!["hel", "lo"],
+        stall_after: Some(1),
+    }])
+    .await;
+    let router = router(policy(), &[("primary", &primary)]);
+
+    let (_, stream) = router.create_stream(request()).await.unwrap();
+    let chunks = stream.collect::<Vec<_>>().await;
+
+    assert_eq!(chunks.len(), 2);
+    assert!(chunks[0].is_ok());
+    assert!(chunks[1].is_err());
+    // Tokens were emitted, so the stall isn't retried
+    assert_eq!(primary.hits(), 1);
+}
+
+#[tokio::test]
+async fn open_breaker_skips_the_provider() {
+    let primary = FakeProvider::start(vec![Reply::Status(500)]).await;
+    let fallback = FakeProvider::start(vec![Reply::Completion("from fallback")]).await;
+    let policy = CallPolicy {
+        max_retries: 0,
+        breaker_threshold: 1,
+        ..policy()
+    };
+    let router = router(policy, &[("primary", &primary), ("fallback", &fallback)]);
+
+    router.create(request()).await.unwrap();
+    assert!(router.breaker("primary").unwrap().is_open());
+
+    let (model, _) = router.create(request()).await.unwrap();
+    assert_eq!(model, "fallback-model");
+    assert_eq!(primary.hits(), 1);
+}
+
+#[tokio::test]
+async fn no_model_available_when_every_breaker_is_open() {
+    let primary = FakeProvider::start(vec![Reply::Status(500)]).await;
+    let policy = CallPolicy {
+        max_retries: 0,
+        breaker_threshold: 1,
+        ..policy()
+    };
+    let router = router(policy, &[("primary", &primary)]);
+
+    assert!(router.create(request()).await.is_err());
+    let err = router.create(request()).await.unwrap_err();
+    assert!(err.is::<NoModelAvailable>());
+    assert_eq!(primary.hits(), 1);
+}
+
+#[tokio::test]
+async fn rejected_trial_closes_the_breaker() {
+    let primary = FakeProvider::start(vec![Reply::Status(500), Reply::Status(400)]).await;
+    let fallback = FakeProvider::start(vec![Reply::Completion("from fallback")]).await;
+    let policy = CallPolicy {
+        max_retries: 0,
+        breaker_threshold: 1,
+        breaker_cooldown: Duration::from_millis(50),
+        ..policy()
+    };
+    let router = router(policy, &[("primary", &primary), ("fallback", &fallback)]);
+
+    router.create(request()).await.unwrap();
+    assert!(router.breaker("primary").unwrap().is_open());
+
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    router.create(request()).await.unwrap();
+    // The provider answered the trial, if only to reject it
+    assert!(!router.breaker("primary").unwrap().is_open());
+    assert_eq!(primary.hits(), 2);
+}
+
+#[tokio::test]
+async fn breaker_lets_a_trial_through_after_cooldown() {
+    let breaker = CircuitBreaker::new(2, Duration::from_millis(50));
+
+    breaker.record_failure();
+    assert!(breaker.try_acquire());
+    breaker.record_failure();
+    assert!(!breaker.try_acquire());
+
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    assert!(breaker.try_acquire());
+    // Only one trial is let through per cooldown
+    assert!(!breaker.try_acquire());
+
+    breaker.record_success();
+    assert!(!breaker.is_open());
+    assert!(breaker.try_acquire());
+}
+
+#[test]
+fn retry_delays_are_jittered_under_the_cap() {
+    let policy = CallPolicy {
+        retry_base_delay: Duration::from_millis(100),
+        retry_max_delay: Duration::from_millis(300),
+        ..CallPolicy::default()
+    };
+
+    for attempt in 0..6 {
+        let ceiling = Duration::from_millis(100 * 2u64.pow(attempt)).min(policy.retry_max_delay);
+        for _ in 0..20 {
+            assert!(policy.retry_delay(attempt) <= ceiling);
+        }
+    }
+}
@@ -0,0 +1,108 @@
+//! Users leaving: exporting everything kept about them, and
+//! deleting their accounts once the grace period is over
+//!
+//! Deletion is requested by the user and carried out by
+//! [`sweep_deletions`], which the server runs in the background.
+
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+use async_compression::tokio::bufread::GzipEncoder;
+use chrono::{NaiveDateTime, Utc};
+use libserver::ServiceError;
+use rgpt_cfg::Context;
+use rgpt_db::{
+    account::AccountData,
+    audit::{self, AuditKind, NewAuditEvent},
+};
+use serde::Serialize;
+use tokio::io::AsyncReadExt;
+
+use crate::attachments;
+
+/// How often accounts past their grace period are looked for
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Everything kept about a user, as they download it
+#[derive(Serialize)]
+pub struct AccountExport {
+    pub exported_at: NaiveDateTime,
+    #[serde(flatten)]
+    pub data: AccountData,
+    /// The text of each attachment, by id. Text that
+    /// can no longer be read is left out
+    pub attachment_texts: BTreeMap<i32, String>,
+}
+
+/// Gathers everything kept about the user
+pub async fn export(cx: &Context, user_id: i32) -> Result<AccountExport, ServiceError> {
+    let data = cx.store().account_data(user_id).await?;
+
+    let mut attachment_texts = BTreeMap::new();
+    for attachment in &data.attachments {
+        match attachments::attachment_text(cx, attachment).await {
+            Ok(text) => {
+                attachment_texts.insert(attachment.id, text);
+            }
+            Err(err) => eprintln!("leaving attachment {} out of export: {err}", attachment.id),
+        }
+    }
+
+    Ok(AccountExport {
+        exported_at: Utc::now().naive_utc(),
+        data,
+        attachment_texts,
+    })
+}
+
+/// The export as a gzipped JSON document
+pub async fn archive(export: &AccountExport) -> Result<Vec<u8>, ServiceError> {
+    let json = serde_json::to_vec_pretty(export)?;
+    let mut archive = vec![];
+    GzipEncoder::new(json.as_slice())
+        .read_to_end(&mut archive)
+        .await?;
+    Ok(archive)
+}
+
+/// Deletes the user for good, along with everything of
+/// theirs but their audit events, and records that it was
+pub async fn purge(cx: &Context, user_id: i32) -> Result<(), ServiceError> {
+    let store = cx.store();
+    let removed = store.delete_user(user_id).await?;
+    attachments::remove_files(&cx.config.attachment_storage, &removed).await;
+
+    let event = NewAuditEvent::new(
+        AuditKind::AccountDeleted,
+        Some(user_id),
+        "grace period over",
+    );
+    audit::record(&*store, event).await;
+    Ok(())
+}
+
+/// Deletes the accounts whose grace period is over, every
+/// [`SWEEP_INTERVAL`], for as long as the server runs
+pub async fn sweep_deletions(cx: Arc<Context>) {
+    let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+    loop {
+        interval.tick().await;
+
+        let due = match cx
+            .store()
+            .users_due_for_deletion(Utc::now().naive_utc())
+            .await
+        {
+            Ok(due) => due,
+            Err(err) => {
+                eprintln!("failed to look for accounts to delete: {err}");
+                continue;
+            }
+        };
+        for user in due {
+            // A purge that fails is rolled back and tried again on the next sweep
+            if let Err(err) = purge(&cx, user.user_id).await {
+                eprintln!("failed to delete user {}: {err}", user.user_id);
+            }
+        }
+    }
+}
@@ -1 +1,5 @@
+pub mod openapi;
+pub mod router;
 pub mod v0_0_1;
+pub mod v0_0_2;
+pub mod v1;
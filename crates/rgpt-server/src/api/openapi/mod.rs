@@ -0,0 +1,136 @@
+//! The OpenAPI document describing every endpoint, generated
+//! from the handlers' `#[utoipa::path]` attributes
+//!
+//! It is served at `/api/openapi.json`, and the frontend's
+//! TypeScript types are generated from it with
+//! `rgpt-admin typescript`.
+
+use std::sync::Arc;
+
+use hyper::StatusCode;
+use libserver::{DynRoute, PathEqRouter, Request, Route};
+use rgpt_cfg::Context;
+use utoipa::{
+    Modify, OpenApi,
+    openapi::security::{ApiKey, ApiKeyValue, Http, HttpAuthScheme, SecurityScheme},
+};
+
+use super::{router::json_response, v0_0_1, v0_0_2, v1};
+
+mod typescript;
+
+pub use typescript::typescript;
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "RetroGPT"),
+    paths(
+        v0_0_1::auth::auth,
+        v0_0_1::user_chats::user_chats,
+        v0_0_1::chat_msgs::chat_msgs,
+        v0_0_1::prompt::prompt,
+        v0_0_1::attach::attach,
+        v0_0_1::delete_chat::delete_chat,
+        v0_0_1::retry::retry,
+        v0_0_2::chats::list_chats,
+        v0_0_2::chats::create_chat,
+        v0_0_2::chats::get_chat,
+        v0_0_2::chats::update_chat,
+        v0_0_2::chats::regenerate_title,
+        v0_0_2::chats::cancel_generation,
+        v0_0_2::chats::delete_chat,
+        v0_0_2::shares::create_share,
+        v0_0_2::shares::list_shares,
+        v0_0_2::shares::revoke_share,
+        v0_0_2::shares::get_share,
+        v0_0_2::shares::fork_share,
+        v0_0_2::socket::open_socket,
+        v0_0_2::messages::list_messages,
+        v0_0_2::messages::create_message,
+        v0_0_2::personas::list_personas,
+        v0_0_2::attachments::upload_attachment,
+        v0_0_2::documents::upload_document,
+        v0_0_2::documents::list_documents,
+        v0_0_2::documents::delete_document,
+        v0_0_2::audit::account_activity,
+        v0_0_2::audit::audit_log,
+        v0_0_2::account::export_account,
+        v0_0_2::account::request_deletion,
+        v0_0_2::account::cancel_deletion,
+        v0_0_2::instructions::get_instructions,
+        v0_0_2::instructions::set_instructions,
+        v1::chat_completions::create_chat_completion,
+        v1::models::list_models,
+        v1::models::get_model,
+    ),
+    modifiers(&SessionToken),
+    security(("session_token" = [])),
+)]
+struct ApiDoc;
+
+/// Documents the `X-Session-Token` header every
+/// endpoint but `auth` authenticates with, and the
+/// bearer API keys the `/v1` endpoints accept
+struct SessionToken;
+
+impl Modify for SessionToken {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        openapi
+            .components
+            .get_or_insert_with(Default::default)
+            .add_security_scheme(
+                "session_token",
+                SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("X-Session-Token"))),
+            );
+        openapi
+            .components
+            .get_or_insert_with(Default::default)
+            .add_security_scheme(
+                "api_key",
+                SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)),
+            );
+    }
+}
+
+pub fn document() -> utoipa::openapi::OpenApi {
+    ApiDoc::openapi()
+}
+
+pub fn route(cx: Arc<Context>) -> DynRoute {
+    let router = PathEqRouter::new("/api/openapi.json");
+
+    Route::from_parts(router, OpenApiService::new(cx)).make_dyn()
+}
+
+pub async fn openapi(_req: Request, _cx: Arc<Context>) -> libserver::ServiceResult {
+    json_response(StatusCode::OK, &document())
+}
+
+#[derive(Clone)]
+pub struct OpenApiService {
+    cx: Arc<Context>,
+}
+
+impl OpenApiService {
+    pub fn new(cx: Arc<Context>) -> Self {
+        OpenApiService { cx }
+    }
+}
+
+impl tower::Service<libserver::Request> for OpenApiService {
+    type Response = libserver::ServiceResponse;
+    type Error = libserver::ServiceError;
+    type Future = libserver::ServiceBoxFuture;
+
+    fn poll_ready(
+        &mut self,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: libserver::Request) -> Self::Future {
+        let cx = self.cx.clone();
+        Box::pin(async move { openapi(req, cx).await })
+    }
+}
@@ -0,0 +1,171 @@
+use std::fmt::Write;
+
+use utoipa::openapi::{
+    OpenApi, RefOr,
+    schema::{AdditionalProperties, ArrayItems, Schema, SchemaType, Type},
+};
+
+const HEADER: &str = "\
+// Generated from the API's OpenAPI document by `rgpt-admin typescript`.
+// Do not edit; change the Rust types and regenerate instead.
+";
+
+/// Renders every schema in the document's components as
+/// an exported TypeScript type of the same name
+///
+/// Objects become interfaces, with properties that aren't
+/// `required` made optional; everything else becomes a
+/// type alias.
+pub fn typescript(doc: &OpenApi) -> String {
+    let mut out = String::from(HEADER);
+
+    let Some(components) = &doc.components else {
+        return out;
+    };
+
+    for (name, schema) in &components.schemas {
+        out.push('\n');
+        match schema {
+            RefOr::T(Schema::Object(object)) if !object.properties.is_empty() => {
+                doc_comment(&mut out, object.description.as_deref(), "");
+                let _ = writeln!(out, "export interface {name} {{");
+                for (property, schema) in &object.properties {
+                    doc_comment(&mut out, schema_description(schema), "  ");
+                    let optional = if object.required.contains(property) {
+                        ""
+                    } else {
+                        "?"
+                    };
+                    let _ = writeln!(out, "  {property}{optional}: {};", ts_type(schema));
+                }
+                out.push_str("}\n");
+            }
+            schema => {
+                doc_comment(&mut out, schema_description(schema), "");
+                let _ = writeln!(out, "export type {name} = {};", ts_type(schema));
+            }
+        }
+    }
+
+    out
+}
+
+fn ts_type(schema: &RefOr<Schema>) -> String {
+    let schema = match schema {
+        RefOr::Ref(reference) => {
+            let name = reference
+                .ref_location
+                .rsplit('/')
+                .next()
+                .unwrap_or_default();
+            return name.to_owned();
+        }
+        RefOr::T(schema) => schema,
+    };
+
+    match schema {
+        Schema::Object(object) => {
+            if let Some(values) = &object.enum_values {
+                return values
+                    .iter()
+                    .map(|value| value.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" | ");
+            }
+
+            if !object.properties.is_empty() {
+                let fields = object
+                    .properties
+                    .iter()
+                    .map(|(property, schema)| {
+                        let optional = if object.required.contains(property) {
+                            ""
+                        } else {
+                            "?"
+                        };
+                        format!("{property}{optional}: {}", ts_type(schema))
+                    })
+                    .collect::<Vec<_>>();
+                return format!("{{ {} }}", fields.join("; "));
+            }
+
+            if let Some(additional) = &object.additional_properties {
+                let value = match additional.as_ref() {
+                    AdditionalProperties::RefOr(schema) => ts_type(schema),
+                    AdditionalProperties::FreeForm(_) => "unknown".into(),
+                };
+                return format!("Record<string, {value}>");
+            }
+
+            schema_type(&object.schema_type)
+        }
+        Schema::Array(array) => {
+            let items = match &array.items {
+                ArrayItems::RefOrSchema(items) => ts_type(items),
+                ArrayItems::False => "never".into(),
+            };
+            let items = if items.contains(' ') {
+                format!("({items})[]")
+            } else {
+                format!("{items}[]")
+            };
+            match &array.schema_type {
+                SchemaType::Array(types) if types.contains(&Type::Null) => {
+                    format!("{items} | null")
+                }
+                _ => items,
+            }
+        }
+        Schema::OneOf(one_of) => union(&one_of.items, " | "),
+        Schema::AnyOf(any_of) => union(&any_of.items, " | "),
+        Schema::AllOf(all_of) => union(&all_of.items, " & "),
+        _ => "unknown".into(),
+    }
+}
+
+fn union(items: &[RefOr<Schema>], separator: &str) -> String {
+    items
+        .iter()
+        .map(ts_type)
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+fn schema_type(schema_type: &SchemaType) -> String {
+    match schema_type {
+        SchemaType::Type(ty) => primitive(ty).into(),
+        SchemaType::Array(types) => types.iter().map(primitive).collect::<Vec<_>>().join(" | "),
+        SchemaType::AnyValue => "unknown".into(),
+    }
+}
+
+fn primitive(ty: &Type) -> &'static str {
+    match ty {
+        Type::String => "string",
+        Type::Integer | Type::Number => "number",
+        Type::Boolean => "boolean",
+        Type::Null => "null",
+        Type::Array => "unknown[]",
+        Type::Object => "Record<string, unknown>",
+    }
+}
+
+fn schema_description(schema: &RefOr<Schema>) -> Option<&str> {
+    match schema {
+        RefOr::T(Schema::Object(object)) => object.description.as_deref(),
+        RefOr::T(Schema::Array(array)) => array.description.as_deref(),
+        RefOr::T(Schema::OneOf(one_of)) => one_of.description.as_deref(),
+        _ => None,
+    }
+}
+
+fn doc_comment(out: &mut String, description: Option<&str>, indent: &str) {
+    let Some(description) = description.filter(|description| !description.is_empty()) else {
+        return;
+    };
+    let _ = writeln!(out, "{indent}/**");
+    for line in description.lines() {
+        let _ = writeln!(out, "{indent} * {line}");
+    }
+    let _ = writeln!(out, "{indent} */");
+}
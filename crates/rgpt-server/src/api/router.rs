@@ -0,0 +1,295 @@
+//! Method and path-parameter dispatch for resource-style APIs
+//!
+//! `libserver`'s routers only match fixed paths and prefixes, so a
+//! [`ResourceService`] is mounted under a `PathPrefixRouter` and does
+//! the rest: it matches patterns like `/chats/{id}/messages` against
+//! the remainder of the path, then dispatches on the method.
+
+use std::{future::Future, str::FromStr, sync::Arc};
+
+use http::header::{ALLOW, CONTENT_TYPE};
+use hyper::{Method, Response, StatusCode};
+use libserver::{Request, ServiceBoxFuture, ServiceError, ServiceResponse, single_frame_body};
+use rgpt_cfg::Context;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+type Handler = Arc<dyn Fn(Request, Arc<Context>, PathParams) -> ServiceBoxFuture + Send + Sync>;
+
+/// The values captured by a pattern's `{name}` segments
+#[derive(Debug, Default)]
+pub struct PathParams {
+    params: Vec<(&'static str, String)>,
+}
+
+impl PathParams {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(param, _)| *param == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Parses a captured value, failing with
+    /// [`InvalidPathParam`] if it doesn't parse
+    pub fn parse<T: FromStr>(&self, name: &str) -> Result<T, InvalidPathParam> {
+        self.get(name)
+            .and_then(|value| value.parse().ok())
+            .ok_or(InvalidPathParam)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Segment {
+    Literal(&'static str),
+    Param(&'static str),
+}
+
+#[derive(Clone)]
+struct ResourceRoute {
+    method: Method,
+    pattern: Vec<Segment>,
+    handler: Handler,
+}
+
+impl ResourceRoute {
+    fn match_path(&self, segments: &[&str]) -> Option<PathParams> {
+        if segments.len() != self.pattern.len() {
+            return None;
+        }
+
+        let mut params = PathParams::default();
+        for (segment, value) in self.pattern.iter().zip(segments) {
+            match segment {
+                Segment::Literal(literal) if literal == value => {}
+                Segment::Literal(_) => return None,
+                Segment::Param(name) => {
+                    let value = urlencoding::decode(value).ok()?.into_owned();
+                    params.params.push((*name, value));
+                }
+            }
+        }
+        Some(params)
+    }
+}
+
+/// Serves every route under `prefix`, answering unknown paths
+/// with `404 Not Found` and known paths requested with the wrong
+/// method with `405 Method Not Allowed`
+///
+/// Handler errors are turned into JSON error responses by
+/// [`error_response`].
+#[derive(Clone)]
+pub struct ResourceService {
+    prefix: &'static str,
+    cx: Arc<Context>,
+    routes: Arc<Vec<ResourceRoute>>,
+}
+
+impl ResourceService {
+    pub fn new(prefix: &'static str, cx: Arc<Context>) -> Self {
+        ResourceService {
+            prefix,
+            cx,
+            routes: Arc::default(),
+        }
+    }
+
+    /// Adds a route for `pattern`, a path relative to the
+    /// prefix whose `{name}` segments capture path parameters
+    pub fn route<F, Fut>(mut self, method: Method, pattern: &'static str, handler: F) -> Self
+    where
+        F: Fn(Request, Arc<Context>, PathParams) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = libserver::ServiceResult> + Send + 'static,
+    {
+        let pattern = split_path(pattern)
+            .map(|segment| match segment.strip_prefix('{') {
+                Some(param) => Segment::Param(param.trim_end_matches('}')),
+                None => Segment::Literal(segment),
+            })
+            .collect();
+
+        let handler: Handler = Arc::new(
+            move |req: Request, cx: Arc<Context>, params: PathParams| -> ServiceBoxFuture {
+                Box::pin(handler(req, cx, params))
+            },
+        );
+
+        Arc::make_mut(&mut self.routes).push(ResourceRoute {
+            method,
+            pattern,
+            handler,
+        });
+        self
+    }
+
+    fn dispatch(&self, req: Request) -> ServiceBoxFuture {
+        let path = req.uri().path().to_owned();
+        let Some(path) = path.strip_prefix(self.prefix) else {
+            return Box::pin(async { status_response(StatusCode::NOT_FOUND) });
+        };
+        let segments = split_path(path).collect::<Vec<_>>();
+
+        let mut allowed = vec![];
+        for route in self.routes.iter() {
+            let Some(params) = route.match_path(&segments) else {
+                continue;
+            };
+            if route.method == req.method() {
+                let response = (route.handler)(req, self.cx.clone(), params);
+                return Box::pin(async move { response.await.or_else(|err| error_response(&err)) });
+            }
+            allowed.push(route.method.as_str());
+        }
+
+        if allowed.is_empty() {
+            return Box::pin(async { status_response(StatusCode::NOT_FOUND) });
+        }
+
+        let allow = allowed.join(", ");
+        Box::pin(async move {
+            let mut response = status_response(StatusCode::METHOD_NOT_ALLOWED)?;
+            response.headers_mut().insert(ALLOW, allow.parse()?);
+            Ok(response)
+        })
+    }
+}
+
+impl tower::Service<libserver::Request> for ResourceService {
+    type Response = libserver::ServiceResponse;
+    type Error = libserver::ServiceError;
+    type Future = libserver::ServiceBoxFuture;
+
+    fn poll_ready(
+        &mut self,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: libserver::Request) -> Self::Future {
+        self.dispatch(req)
+    }
+}
+
+fn split_path(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|segment| !segment.is_empty())
+}
+
+/// Builds a JSON response from a serializable body
+pub fn json_response<T: Serialize>(
+    status: StatusCode,
+    body: &T,
+) -> Result<ServiceResponse, ServiceError> {
+    Ok(Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .body(single_frame_body(serde_json::to_string(body)?))?)
+}
+
+/// An empty-bodied response, e.g. `204 No Content`
+pub fn empty_response(status: StatusCode) -> Result<ServiceResponse, ServiceError> {
+    Ok(Response::builder()
+        .status(status)
+        .body(single_frame_body(""))?)
+}
+
+fn status_response(status: StatusCode) -> Result<ServiceResponse, ServiceError> {
+    json_response(
+        status,
+        &ErrorBody {
+            error: status.canonical_reason().unwrap_or("").into(),
+        },
+    )
+}
+
+/// Reports a handler error as `{"error": "..."}` with the
+/// status code that fits it
+///
+/// Errors this doesn't recognize are internal errors, and
+/// their messages aren't sent to the client.
+pub fn error_response(err: &ServiceError) -> Result<ServiceResponse, ServiceError> {
+    let status = error_status(err);
+    if status == StatusCode::INTERNAL_SERVER_ERROR {
+        eprintln!("internal error: {err}");
+        return status_response(status);
+    }
+    json_response(
+        status,
+        &ErrorBody {
+            error: err.to_string(),
+        },
+    )
+}
+
+/// The status code a handler error is reported with
+pub fn error_status(err: &ServiceError) -> StatusCode {
+    if err.is::<InvalidPathParam>()
+        || err.is::<InvalidBody>()
+        || err.is::<serde_json::Error>()
+        || err.is::<std::string::FromUtf8Error>()
+        || err.is::<http::header::ToStrError>()
+        || err.is::<rgpt_cfg::gen_limits::InvalidGenParams>()
+        || err.is::<crate::api::v0_0_2::chats::NothingToTitle>()
+        || err.is::<crate::attachments::UnsupportedAttachment>()
+        || err.is::<crate::attachments::InvalidAttachment>()
+        || err.is::<crate::retrieval::EmptyDocument>()
+        || err.is::<crate::api::v0_0_2::shares::NothingToShare>()
+        || err.is::<crate::moderation::PromptBlocked>()
+        || err.is::<crate::api::v0_0_2::personas::UnknownPersona>()
+    {
+        StatusCode::BAD_REQUEST
+    } else if err.is::<crate::InvalidSessionTokenHeader>() {
+        StatusCode::UNAUTHORIZED
+    } else if err.is::<crate::UserDisabled>()
+        || err.is::<crate::api::v0_0_2::attachments::GuestAttachment>()
+        || err.is::<crate::api::v0_0_2::documents::GuestDocument>()
+        || err.is::<crate::api::v0_0_2::shares::GuestShare>()
+        || err.is::<crate::api::v0_0_2::socket::GuestSocket>()
+        || err.is::<crate::api::v0_0_2::audit::GuestActivity>()
+        || err.is::<crate::api::v0_0_2::audit::NotAdmin>()
+        || err.is::<crate::api::v0_0_2::account::GuestAccount>()
+        || err.is::<crate::api::v0_0_2::instructions::GuestInstructions>()
+    {
+        StatusCode::FORBIDDEN
+    } else if err.is::<crate::api::v0_0_2::chats::NothingToCancel>()
+        || err.is::<crate::api::v0_0_2::account::NoDeletionScheduled>()
+    {
+        StatusCode::CONFLICT
+    } else if err.is::<crate::RequestTooLarge>() {
+        StatusCode::PAYLOAD_TOO_LARGE
+    } else if err.is::<rgpt_db::store::NotFound>()
+        || err.is::<crate::api::v0_0_1::attach::UnknownAttachToken>()
+        || matches!(
+            err.downcast_ref::<diesel::result::Error>(),
+            Some(diesel::result::Error::NotFound)
+        )
+    {
+        StatusCode::NOT_FOUND
+    } else if err.is::<async_openai::error::OpenAIError>()
+        || err.is::<crate::chat_title::NoTitleGenerated>()
+        || err.is::<rgpt_provider::EmbeddingCountMismatch>()
+    {
+        StatusCode::BAD_GATEWAY
+    } else if err.is::<rgpt_provider::NoModelAvailable>() {
+        StatusCode::SERVICE_UNAVAILABLE
+    } else if err.is::<rgpt_provider::ModelTimeout>() {
+        StatusCode::GATEWAY_TIMEOUT
+    } else {
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+}
+
+/// The body of every error response
+#[derive(Serialize, ToSchema)]
+pub struct ErrorBody {
+    pub error: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid Request Body")]
+pub struct InvalidBody;
+
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid Path Parameter")]
+pub struct InvalidPathParam;
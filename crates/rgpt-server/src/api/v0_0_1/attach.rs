@@ -2,17 +2,48 @@ use std::sync::Arc;
 
 use fastwebsockets::{Frame, OpCode, Payload, upgrade::upgrade};
 use futures::{StreamExt, channel::mpsc::UnboundedReceiver};
-use hyper::body::Bytes;
+use hyper::header::{HeaderMap, HeaderValue, SEC_WEBSOCKET_PROTOCOL};
 use libserver::{DynRoute, PathPrefixRouter, Request, Route, single_frame_body};
 use rgpt_cfg::Context;
+use rgpt_stream::{StreamEvent, protocol::JSON_PROTOCOL};
 use uuid::Uuid;
 
+use crate::api::router::{ErrorBody, error_response};
+
+/// The reason raw clients' sockets are closed with when their reply fails
+const FAILED_REASON: &[u8] = b"Reply Failed";
+
 pub fn route(cx: Arc<Context>) -> DynRoute {
     let router = PathPrefixRouter::new("/api/v0.0.1/attach");
 
     Route::from_parts(router, AttachService::new(cx)).make_dyn()
 }
 
+/// Stream a reply over a WebSocket
+///
+/// Clients offering the `retrogpt.v1` subprotocol get a JSON object
+/// per text frame: `model`, `delta` and `tool_call` events, a new
+/// chat's `title` once it's generated, then one of `done` (with the message id, finish reason and token
+/// usage), `error` or `cancelled`, after which the socket is closed.
+///
+/// Otherwise each text frame is a chunk of the reply. A failed reply
+/// closes the socket with code 1011; only the JSON protocol says why.
+///
+/// Each reply can be attached to once, and only until a minute after
+/// it ends; its token is unknown after that.
+#[utoipa::path(
+    get,
+    path = "/api/v0.0.1/attach/{attach_token}",
+    params(
+        ("attach_token" = String, Path, description = "The token returned when the reply was started"),
+        ("token" = String, Query, description = "The session token"),
+    ),
+    responses(
+        (status = 101, description = "Switching to the WebSocket protocol"),
+        (status = 401, body = ErrorBody),
+        (status = 404, body = ErrorBody),
+    ),
+)]
 pub async fn attach(req: Request, cx: Arc<Context>) -> libserver::ServiceResult {
     crate::check_body_size(&req, cx.config.max_req_size)?;
 
@@ -29,7 +60,8 @@ pub async fn attach(req: Request, cx: Arc<Context>) -> libserver::ServiceResult
         }
     };
 
-    crate::validate_session_token(cx.db(), session_token, None).await?;
+    let client = crate::client_info(&cx, req.headers());
+    crate::validate_session_token(&cx, session_token, None, &client).await?;
 
     let path = req.uri().path();
     let attach_token_str = path.strip_prefix("/api/v0.0.1/attach/").unwrap_or("");
@@ -44,51 +76,94 @@ pub async fn attach(req: Request, cx: Arc<Context>) -> libserver::ServiceResult
         )));
     }
 
-    let attach_token = Uuid::parse_str(attach_token_clean)
-        .map_err(|_| {
-            std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                format!("Invalid attach token: {}", attach_token_clean),
-            )
-        })?;
+    let attach_token = Uuid::parse_str(attach_token_clean).map_err(|_| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Invalid attach token: {}", attach_token_clean),
+        )
+    })?;
 
-    let rx = cx
+    // The token may have been attached to already, or its
+    // reply given up on when nobody attached in time
+    let Some(rx) = cx
         .state
         .stream_registry
         .lock()
         .await
         .try_attach(attach_token)
-        .expect("better err handling later");
+    else {
+        Err(UnknownAttachToken)?
+    };
+
+    let json = offers_json_protocol(req.headers());
 
     let (resp, ws_fut) = upgrade(req)?;
-    let resp = resp.map(|_| single_frame_body(""));
+    let mut resp = resp.map(|_| single_frame_body(""));
+    if json {
+        resp.headers_mut().insert(
+            SEC_WEBSOCKET_PROTOCOL,
+            HeaderValue::from_static(JSON_PROTOCOL),
+        );
+    }
 
-    tokio::spawn(stream_model_response(ws_fut, rx));
+    tokio::spawn(stream_model_response(ws_fut, rx, json));
 
     Ok(resp)
 }
 
+/// Whether the client offered the JSON protocol, among
+/// any others, in its `Sec-WebSocket-Protocol` headers
+pub fn offers_json_protocol(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(SEC_WEBSOCKET_PROTOCOL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|protocol| protocol.trim() == JSON_PROTOCOL)
+}
+
 async fn stream_model_response(
     ws_fut: fastwebsockets::upgrade::UpgradeFut,
-    mut rx: UnboundedReceiver<Bytes>,
+    mut rx: UnboundedReceiver<StreamEvent>,
+    json: bool,
 ) -> Result<(), libserver::ServiceError> {
-    let mut ws = ws_fut
-        .await
-        .inspect_err(|err| {
-            dbg!(err);
-        })
-        .unwrap();
-    while let Some(bytes) = rx.next().await {
-        let bc = bytes.clone().to_vec();
-        String::from_utf8(bc).ok();
-        let frame = Frame::new(true, OpCode::Text, None, Payload::Owned(bytes.to_vec()));
-        // let frame = Frame::text(Payload::from("hello ".as_bytes()));
-        ws.write_frame(frame)
-            .await
-            .inspect_err(|err| {
-                dbg!(err);
-            })
-            .unwrap();
+    let mut ws = ws_fut.await?;
+
+    if json {
+        while let Some(event) = rx.next().await {
+            let frame = Frame::text(Payload::Owned(event.to_json().into_bytes()));
+            // A client that dropped just stops being sent to
+            ws.write_frame(frame).await?;
+            if event.ends_stream() {
+                break;
+            }
+        }
+        ws.write_frame(Frame::close(1000, &[])).await?;
+        return Ok(());
+    }
+
+    while let Some(event) = rx.next().await {
+        let frame = match event {
+            // Raw clients can't tell a model name from reply text, so they
+            // get the model from the chat's messages once the reply is saved
+            StreamEvent::Model(_) => continue,
+            // Likewise, they get a new chat's title from the chat listing
+            StreamEvent::Title(_) => continue,
+            // and the calls the model made from the chat's messages
+            StreamEvent::ToolCall(_) => continue,
+            // They can't tell a finished reply from a dropped socket,
+            // which is what the JSON protocol is for
+            StreamEvent::Done(_) | StreamEvent::Cancelled(_) => continue,
+            StreamEvent::Delta(bytes) => {
+                Frame::new(true, OpCode::Text, None, Payload::Owned(bytes.to_vec()))
+            }
+            // Raw clients only understand text frames, so an error is
+            // reported by closing the socket with an internal error code.
+            // Close reasons are capped at 123 bytes, too short for some
+            // provider errors, which aren't for clients anyway
+            StreamEvent::Error(_) => Frame::close(1011, FAILED_REASON),
+        };
+        ws.write_frame(frame).await?;
     }
     Ok(())
 }
@@ -118,6 +193,10 @@ impl tower::Service<libserver::Request> for AttachService {
 
     fn call(&mut self, req: libserver::Request) -> Self::Future {
         let cx = self.cx.clone();
-        Box::pin(async move { attach(req, cx).await })
+        Box::pin(async move { attach(req, cx).await.or_else(|err| error_response(&err)) })
     }
 }
+
+#[derive(Debug, thiserror::Error)]
+#[error("Unknown Attach Token")]
+pub struct UnknownAttachToken;
@@ -1,9 +1,10 @@
-use std::{borrow::Cow, sync::Arc};
+use std::sync::Arc;
 
 use libserver::{DynRoute, PathEqRouter, Route, single_frame_body};
 use rgpt_cfg::Context;
-use rgpt_db::{session::Session, user::User};
+use rgpt_db::audit::{self, AuditKind, NewAuditEvent};
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
 pub fn route(cx: Arc<Context>) -> DynRoute {
     let router = PathEqRouter::new("/api/v0.0.1/auth");
@@ -11,8 +12,20 @@ pub fn route(cx: Arc<Context>) -> DynRoute {
     Route::from_parts(router, AuthService::new(cx)).make_dyn()
 }
 
+/// Log in with a Google access token
+///
+/// Logins, whether they succeed or fail, are recorded in the
+/// audit log along with the client's address and user agent.
+#[utoipa::path(
+    post,
+    path = "/api/v0.0.1/auth",
+    request_body = AuthServiceInput,
+    responses((status = 200, body = AuthServiceReturn)),
+    security(()),
+)]
 pub async fn auth(req: libserver::Request, cx: Arc<Context>) -> libserver::ServiceResult {
     crate::check_body_size(&req, cx.config.max_req_size)?;
+    let client = crate::client_info(&cx, req.headers());
     let body = crate::collect_body_string(req).await?;
 
     let AuthServiceInput { user_access_token } = serde_json::from_str(&body)?;
@@ -32,41 +45,59 @@ pub async fn auth(req: libserver::Request, cx: Arc<Context>) -> libserver::Servi
     // TODO: Better solution to this???
     debug_assert!(dbg!(user_info_response.status()) == 200);
 
+    let store = cx.store();
     let GoogleUserInfo {
         id: google_id,
         email,
         name,
-    } = user_info_response.json().await?;
+    } = match user_info_response.json().await {
+        Ok(user_info) => user_info,
+        Err(err) => {
+            let event = NewAuditEvent::new(AuditKind::LoginFailed, None, "no Google user info");
+            audit::record(&*store, event.client(&client)).await;
+            return Err(err.into());
+        }
+    };
 
-    let user = match User::get_by_google_id(cx.db(), &google_id).await {
+    let user = match store.user_by_google_id(&google_id).await {
         Ok(user) => user,
-        _ => User::create(cx.db(), google_id, email, name).await?,
+        _ => store.create_user(google_id, email, name).await?,
     };
 
-    let session = Session::get_for_user(cx.db(), &user).await?;
+    if user.disabled {
+        let event = NewAuditEvent::new(AuditKind::LoginFailed, Some(user.user_id), "disabled");
+        audit::record(&*store, event.client(&client)).await;
+        Err(crate::UserDisabled)?;
+    }
+
+    let (session, created) = store.session_for_user(user.user_id).await?;
+    let event = NewAuditEvent::new(AuditKind::LoginSucceeded, Some(user.user_id), "");
+    audit::record(&*store, event.client(&client)).await;
+    if created {
+        let event = NewAuditEvent::new(
+            AuditKind::SessionCreated,
+            Some(user.user_id),
+            format!("expires at {}", session.expires_at),
+        );
+        audit::record(&*store, event.client(&client)).await;
+    }
 
-    let return_body = AuthServiceReturn::new(&session.session_token, user.user_id);
+    let return_body = AuthServiceReturn {
+        session_token: session.session_token,
+        user_id: user.user_id,
+    };
 
     Ok(hyper::Response::new(single_frame_body(
         serde_json::to_string(&return_body)?,
     )))
 }
 
-#[derive(Serialize)]
-struct AuthServiceReturn<'a> {
-    session_token: &'a str,
+#[derive(Serialize, ToSchema)]
+struct AuthServiceReturn {
+    session_token: String,
     user_id: i32,
 }
 
-impl<'a> AuthServiceReturn<'a> {
-    fn new(session_token: &'a str, user_id: i32) -> Self {
-        AuthServiceReturn {
-            session_token,
-            user_id,
-        }
-    }
-}
-
 #[derive(Deserialize)]
 struct GoogleUserInfo {
     id: String,
@@ -103,7 +134,7 @@ impl tower::Service<libserver::Request> for AuthService {
     }
 }
 
-#[derive(Deserialize)]
-struct AuthServiceInput<'a> {
-    user_access_token: Cow<'a, str>,
+#[derive(Deserialize, ToSchema)]
+struct AuthServiceInput {
+    user_access_token: String,
 }
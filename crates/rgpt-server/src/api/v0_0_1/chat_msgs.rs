@@ -3,9 +3,11 @@ use std::sync::Arc;
 use hyper::Response;
 use libserver::{DynRoute, PathEqRouter, Request, Route, single_frame_body};
 use rgpt_cfg::Context;
-use rgpt_db::chat::Chat;
-use serde::Deserialize;
-use serde_json::json;
+use rgpt_db::{attachment::Attachment, document::Citations, msg};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::tools::TOOL_SENDER;
 
 pub fn route(cx: Arc<Context>) -> DynRoute {
     let router = PathEqRouter::new("/api/v0.0.1/chat_msgs");
@@ -13,38 +15,75 @@ pub fn route(cx: Arc<Context>) -> DynRoute {
     Route::from_parts(router, ChatMsgService::new(cx)).make_dyn()
 }
 
+/// The messages on a chat's active branch, oldest first
+#[utoipa::path(
+    post,
+    path = "/api/v0.0.1/chat_msgs",
+    request_body = ChatMsgServiceInput,
+    responses((status = 200, body = Vec<ChatMsg>)),
+)]
 pub async fn chat_msgs(req: Request, cx: Arc<Context>) -> libserver::ServiceResult {
     crate::check_body_size(&req, cx.config.max_req_size)?;
     let headers = req.headers().to_owned();
     let body = crate::collect_body_string(req).await?;
 
     let ChatMsgServiceInput { chat_id } = serde_json::from_str(&body)?;
-    let chat = Chat::get_by_id(cx.db(), chat_id).await?;
-    let _session = crate::validate_session_header(cx.db(), &headers, Some(chat.user_id)).await?;
-
-    let msgs = chat.msg_chain(cx.db()).await?;
-
-    let fmted_mgs = json!(
-        msgs.into_iter()
-            .map(|msg| {
-                json!({
-                    "text": msg.body,
-                    "sender": msg.sender
-                })
+    let store = cx.store();
+    let chat = store.chat_by_id(chat_id).await?;
+    let _session = crate::validate_session_header(&cx, &headers, Some(chat.user_id)).await?;
+
+    let msgs = store.chat_msgs(&chat).await?;
+    let msg_ids = msgs.iter().map(|msg| msg.id).collect::<Vec<_>>();
+    let attachments = store.msg_attachments(&msg_ids).await?;
+
+    // Tool calls are only listed by the v0.0.2 messages
+    // endpoint, which clients of this one don't know about
+    let fmted_mgs = serde_json::to_string(
+        &msgs
+            .into_iter()
+            .filter(|msg| msg.sender != TOOL_SENDER)
+            .map(|msg| ChatMsg {
+                attachments: attachments
+                    .iter()
+                    .filter(|attachment| attachment.msg_id == Some(msg.id))
+                    .cloned()
+                    .collect(),
+                text: msg.body,
+                sender: msg.sender,
+                status: msg.status,
+                model: msg.model,
+                citations: msg.citations,
             })
-            .collect::<Vec<_>>()
-    )
-    .to_string();
+            .collect::<Vec<_>>(),
+    )?;
 
     let body = single_frame_body(fmted_mgs);
     Ok(Response::new(body))
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 struct ChatMsgServiceInput {
     chat_id: i32,
 }
 
+#[derive(Serialize, ToSchema)]
+struct ChatMsg {
+    text: String,
+    #[schema(schema_with = msg::conversation_sender_schema)]
+    sender: String,
+    #[schema(schema_with = msg::status_schema)]
+    status: String,
+    /// The model that generated an AI message
+    #[schema(required)]
+    model: Option<String>,
+    /// The documents a user message was sent with
+    attachments: Vec<Attachment>,
+    /// The document chunks an AI message was given,
+    /// which it cites as `[1]`, `[2]` and so on
+    #[schema(required)]
+    citations: Option<Citations>,
+}
+
 #[derive(Clone)]
 struct ChatMsgService {
     cx: Arc<Context>,
@@ -3,8 +3,14 @@ use std::sync::Arc;
 use hyper::Response;
 use libserver::{DynRoute, PathEqRouter, Request, Route, single_frame_body};
 use rgpt_cfg::Context;
-use rgpt_db::chat::Chat;
+use rgpt_db::{
+    audit::{self, AuditKind, NewAuditEvent},
+    chat::Chat,
+};
 use serde::Deserialize;
+use utoipa::ToSchema;
+
+use crate::api::v0_0_2::socket::push_chat;
 
 pub fn route(cx: Arc<Context>) -> DynRoute {
     let router = PathEqRouter::new("/api/v0.0.1/delete_chat");
@@ -12,6 +18,13 @@ pub fn route(cx: Arc<Context>) -> DynRoute {
     Route::from_parts(router, DeleteChatService::new(cx)).make_dyn()
 }
 
+/// Move a chat to the trash
+#[utoipa::path(
+    post,
+    path = "/api/v0.0.1/delete_chat",
+    request_body = DeleteChatInput,
+    responses((status = 200, description = "The chat was trashed")),
+)]
 pub async fn delete_chat(req: Request, cx: Arc<Context>) -> libserver::ServiceResult {
     crate::check_body_size(&req, cx.config.max_req_size)?;
     let headers = req.headers().to_owned();
@@ -19,11 +32,26 @@ pub async fn delete_chat(req: Request, cx: Arc<Context>) -> libserver::ServiceRe
 
     let DeleteChatInput { chat_id } = serde_json::from_str(&body)?;
 
-    let chat = Chat::get_by_id(cx.db(), chat_id).await?;
+    let store = cx.store();
+    let chat = store.chat_by_id(chat_id).await?;
 
-    crate::validate_session_header(cx.db(), &headers, Some(chat.user_id)).await?;
+    crate::validate_session_header(&cx, &headers, Some(chat.user_id)).await?;
 
-    chat.delete(cx.db()).await?;
+    store.delete_chat(chat.id).await?;
+    let event = NewAuditEvent::new(
+        AuditKind::ChatDeleted,
+        Some(chat.user_id),
+        format!("chat {}", chat.id),
+    );
+    audit::record(&*store, event.client(&crate::client_info(&cx, &headers))).await;
+    push_chat(
+        &cx,
+        &Chat {
+            deleted: true,
+            ..chat
+        },
+    )
+    .await;
 
     Ok(Response::new(single_frame_body("")))
 }
@@ -33,7 +61,7 @@ pub struct DeleteChatService {
     cx: Arc<Context>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 struct DeleteChatInput {
     chat_id: i32,
 }
@@ -1,242 +1,835 @@
-use std::{borrow::Cow, sync::Arc};
+use std::{sync::Arc, time::Duration};
 
 use async_openai::types::{
     ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestMessage,
     ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestUserMessageArgs,
-    CreateChatCompletionRequest, CreateChatCompletionRequestArgs,
+    ChatCompletionStreamOptions, ChatCompletionToolChoiceOption, CreateChatCompletionRequest,
+    CreateChatCompletionRequestArgs, FinishReason, Stop,
 };
-use diesel::{ExpressionMethods, QueryDsl};
 use futures::{StreamExt, channel::mpsc::UnboundedSender};
-use hyper::{Response, body::Bytes};
+use hyper::Response;
 use libserver::{DynRoute, PathEqRouter, Request, Route, single_frame_body};
-use rgpt_cfg::Context;
-use rgpt_db::{RunQueryDsl, chat::Chat, msg::Msg};
-use rgpt_stream::AttachHandle;
+use rgpt_cfg::{Context, moderation::BlockedReply};
+use rgpt_db::{
+    chat::Chat,
+    document::Citations,
+    gen_params::GenParams,
+    moderation::FlagSource,
+    msg::{Generation, Msg, MsgStatus},
+    store::NotFound,
+};
+use rgpt_stream::{AttachHandle, Finished, StreamEvent, Usage};
 use serde::{Deserialize, Serialize};
 use tokio::sync::oneshot;
+use utoipa::ToSchema;
 use uuid::Uuid;
 
+use super::retry::NothingToRetry;
+use crate::{
+    api::v0_0_2::{personas::pick_persona, socket::push_chat},
+    attachments, chat_title,
+    filters::Pipeline,
+    instructions,
+    moderation::{self, FlagTarget, ReplyBlocked, ReplyGate},
+    retrieval,
+    tools::{TOOL_SENDER, ToolCallChunks, ToolContext, ToolRecord, ToolRegistry},
+};
+
+/// How long a finished reply waits for a client to attach
+/// before it's only available from the chat's messages
+const ATTACH_GRACE: Duration = Duration::from_secs(60);
+
 pub fn route(cx: Arc<Context>) -> DynRoute {
     let router = PathEqRouter::new("/api/v0.0.1/prompt");
 
     Route::from_parts(router, PromptService::new(cx)).make_dyn()
 }
 
+/// Send a message, starting a new chat if no `chat_id` is given
+///
+/// The reply is streamed from `attach` with the returned token.
+/// Out of range generation parameters are clamped to the
+/// deployment's limits.
+#[utoipa::path(
+    post,
+    path = "/api/v0.0.1/prompt",
+    request_body = PromptServiceInput,
+    responses((status = 200, body = PromptServiceResponse)),
+)]
 pub async fn prompt(req: Request, cx: Arc<Context>) -> libserver::ServiceResult {
     crate::check_body_size(&req, cx.config.max_req_size)?;
     let headers = req.headers().to_owned();
     let body = crate::collect_body_string(req).await?;
 
-    let PromptServiceInput { text, chat_id } = serde_json::from_str(&body)?;
+    let PromptServiceInput {
+        text,
+        chat_id,
+        params,
+        attachments,
+        persona,
+    } = serde_json::from_str(&body)?;
 
-    let is_first_message_in_chat = chat_id.is_none();
+    let store = cx.store();
 
-    let (_session, mut chat) = match chat_id {
+    let chat = match chat_id {
         Some(id) => {
-            let chat = Chat::get_by_id(cx.db(), id).await?;
-            let session =
-                crate::validate_session_header(cx.db(), &headers, Some(chat.user_id)).await?;
-            (session, chat)
+            let chat = store.chat_by_id(id).await?;
+            crate::validate_session_header(&cx, &headers, Some(chat.user_id)).await?;
+            chat
         }
         None => {
-            let session = crate::validate_session_header(cx.db(), &headers, None).await?;
-            let chat = Chat::create(cx.db(), session.user_id, None).await?;
-            (session, chat)
+            let session = crate::validate_session_header(&cx, &headers, None).await?;
+            let persona = pick_persona(&cx, persona)?;
+            store.create_chat(session.user_id, None, persona).await?
         }
     };
 
-    let user_msg = Msg::create(cx.db(), text, "user", chat.user_id, chat.head_msg).await?;
+    let StartedReply {
+        chat,
+        chat_title,
+        attach_token,
+        ..
+    } = start_reply(cx, chat, text, params, attachments).await?;
+
+    let response = serde_json::to_string(&PromptServiceResponse {
+        chat_id: chat.id,
+        chat_title,
+        attach_token: attach_token.to_string(),
+    })?;
+
+    let body = single_frame_body(response);
+    Ok(Response::new(body))
+}
+
+/// A user message that has been saved, with the AI
+/// reply to it generating in the background
+pub struct StartedReply {
+    pub chat: Chat,
+    pub user_msg: Msg,
+    /// Set when this was the chat's first message, to
+    /// the provisional title it was given
+    pub chat_title: Option<String>,
+    pub attach_token: Uuid,
+}
+
+/// Appends a user message to the chat and starts
+/// streaming the model's reply to it
+///
+/// Untitled chats are given a provisional title from their first
+/// message, which is replaced by a generated one once the reply
+/// is saved. Parameters left unset take the chat persona's, and are
+/// checked against the configured limits, the attachments against
+/// their owner and the text against the moderation policy before
+/// anything is saved.
+pub async fn start_reply(
+    cx: Arc<Context>,
+    chat: Chat,
+    text: String,
+    params: GenParams,
+    attachment_ids: Vec<i32>,
+) -> Result<StartedReply, libserver::ServiceError> {
+    let persona = cx.config.personas.resolve(&chat.persona);
+    let params = cx
+        .config
+        .gen_limits
+        .apply(params.or(&persona.params), cx.config.max_tokens)?;
+    attachments::check_unsent(&cx, chat.user_id, &attachment_ids).await?;
+    let mut target = FlagTarget {
+        user_id: chat.user_id,
+        chat_id: Some(chat.id),
+        msg_id: None,
+    };
+    let verdict = moderation::check_prompt(&cx, target, &text).await?;
+    let store = cx.store();
+
+    let is_first_message_in_chat = chat.head_msg.is_none() && chat.name.is_none();
+
+    let user_msg = store
+        .create_msg(
+            text,
+            "user".into(),
+            chat.user_id,
+            chat.head_msg,
+            MsgStatus::Complete,
+            Generation::default(),
+        )
+        .await?;
+    store.link_attachments(user_msg.id, &attachment_ids).await?;
+    target.msg_id = Some(user_msg.id);
+    moderation::keep(
+        &cx,
+        target,
+        FlagSource::Prompt,
+        verdict,
+        user_msg.body.clone(),
+    )
+    .await;
 
     let chat_title = if is_first_message_in_chat {
-        let chat_title = generate_chat_name(cx.clone(), &user_msg).await?;
-        diesel::update(rgpt_db::schema::chats::table.find(chat.id))
-            .set(rgpt_db::schema::chats::name.eq(chat_title.clone()))
-            .execute(cx.db())
-            .await?;
+        let chat_title = chat_title::fallback_title(&user_msg.body);
+        store.set_chat_name(chat.id, chat_title.clone()).await?;
         Some(chat_title)
     } else {
         None
     };
 
-    chat = chat.append_to_chat(cx.db(), &user_msg).await?;
+    let chat = store.set_chat_head(chat.id, user_msg.id).await?;
+    push_chat(&cx, &chat).await;
 
-    let chat_msgs = chat.msg_chain(cx.db()).await?;
+    let chat_msgs = store.chat_msgs(&chat).await?;
 
-    let model_request = create_chat_request(cx.clone(), chat_msgs)?;
+    let (model_request, citations) =
+        create_chat_request(cx.clone(), &chat, chat_msgs, &params).await?;
 
     let attach_token = Uuid::new_v4();
 
     tokio::spawn(stream_model_response(
-        chat.id,
-        chat.user_id,
+        chat.clone(),
         attach_token,
         chat.head_msg,
         model_request,
+        Generation {
+            model: None,
+            params: Some(params),
+            citations,
+        },
+        is_first_message_in_chat,
         cx.clone(),
     ));
 
-    let response = serde_json::to_string(&PromptServiceResponse {
-        chat_id: chat.id,
-        chat_title: chat_title.as_deref(),
-        attach_token: attach_token.to_string(),
-    })?;
+    Ok(StartedReply {
+        chat,
+        user_msg,
+        chat_title,
+        attach_token,
+    })
+}
 
-    let body = single_frame_body(response);
-    Ok(Response::new(body))
+/// Starts generating a new reply in place of `reply`, as
+/// a sibling of it under the same parent, returning the
+/// token to attach to it with
+///
+/// The new reply is generated with `reply`'s parameters, clamped
+/// to the current limits. Tool calls `reply` made are kept rather
+/// than run again.
+pub async fn regenerate_reply(
+    cx: Arc<Context>,
+    chat: Chat,
+    reply: Msg,
+) -> Result<Uuid, libserver::ServiceError> {
+    let Some(parent_id) = reply.parent_message_id else {
+        return Err(NothingToRetry.into());
+    };
+
+    let chat_msgs = cx.store().msg_chain(parent_id).await?;
+
+    // The limits may have changed since the reply was generated
+    let params = cx
+        .config
+        .gen_limits
+        .apply(reply.gen_params.unwrap_or_default(), cx.config.max_tokens)?;
+    let (model_request, citations) =
+        create_chat_request(cx.clone(), &chat, chat_msgs, &params).await?;
+
+    let attach_token = Uuid::new_v4();
+
+    tokio::spawn(stream_model_response(
+        chat,
+        attach_token,
+        Some(parent_id),
+        model_request,
+        Generation {
+            model: None,
+            params: Some(params),
+            citations,
+        },
+        false,
+        cx,
+    ));
+
+    Ok(attach_token)
 }
 
-#[derive(Serialize)]
-struct PromptServiceResponse<'a> {
+#[derive(Serialize, ToSchema)]
+struct PromptServiceResponse {
     chat_id: i32,
-    chat_title: Option<&'a str>,
+    /// Set when the message started a new chat, to a
+    /// provisional title. The generated title is in the
+    /// chat listing once the reply has finished
+    #[schema(required)]
+    chat_title: Option<String>,
     attach_token: String,
 }
 
-async fn generate_chat_name(
+/// A request for the reply to `msgs` in `chat`, generated
+/// with `params`, which have already been checked against
+/// the configured limits
+///
+/// The system prompt of the chat's persona comes first,
+/// followed by the user's custom instructions unless the
+/// chat opts out, and the persona's model is asked first.
+/// User messages carry the text of their attachments,
+/// as much of it as fits the attachment budget. The chunks
+/// of the user's documents most relevant to their latest
+/// message follow the system messages, and are returned as
+/// the citations to save with the reply.
+pub async fn create_chat_request(
     cx: Arc<Context>,
-    user_msg: &Msg,
-) -> Result<String, libserver::ServiceError> {
-    let prompt = ChatCompletionRequestUserMessageArgs::default()
-        .content(format!(
-            r#"
-           Generate a title to be displayed for the following chat. It must be less than 5 words.
-           Do not respond with anything but the title
-
-           Chat Content:
-           User: {}
-        "#,
-            user_msg.body,
-        ))
-        .build()?
-        .into();
-
-    let request = CreateChatCompletionRequestArgs::default()
-        .model(&cx.config.model_name)
-        .max_tokens(cx.config.max_tokens)
-        .messages([prompt])
-        .build()
-        .unwrap();
-
-    let chat_title = cx
-        .state
-        .openai_client
-        .chat()
-        .create(request)
-        .await?
-        .choices
-        .into_iter()
-        .next()
-        .unwrap()
-        .message
-        .content
-        .unwrap();
+    chat: &Chat,
+    msgs: Vec<Msg>,
+    params: &GenParams,
+) -> Result<(CreateChatCompletionRequest, Option<Citations>), libserver::ServiceError> {
+    let mut attachment_text = attachments::context_text(&cx, &msgs).await?;
 
-    Ok(chat_title)
-}
+    let retrieved = match msgs.iter().rev().find(|msg| msg.sender == "user") {
+        Some(prompt) => retrieval::retrieve(&cx, prompt.user_id, &prompt.body).await?,
+        None => None,
+    };
 
-fn create_chat_request(
-    cx: Arc<Context>,
-    msgs: Vec<Msg>,
-) -> Result<CreateChatCompletionRequest, libserver::ServiceError> {
-    let built_msgs = vec![
-        ChatCompletionRequestSystemMessageArgs::default()
-            .content(cx.config.system_message.clone())
-            .build()?
-            .into(),
-    ]
-    .into_iter()
-    .chain(msgs.into_iter().filter_map(|msg| {
-        match msg.sender.as_str() {
-            "ai" => ChatCompletionRequestAssistantMessageArgs::default()
-                .content(msg.body)
-                .build()
-                .ok()
-                .map(Into::into),
-            "user" => ChatCompletionRequestUserMessageArgs::default()
-                .content(msg.body)
-                .build()
-                .ok()
-                .map(Into::into),
-            _ => None,
+    let persona = cx.config.personas.resolve(&chat.persona);
+    let custom_instructions = if chat.instructions_enabled {
+        cx.store().custom_instructions(chat.user_id).await?
+    } else {
+        None
+    };
+
+    let mut system_msgs =
+        instructions::system_messages(&persona.system_prompt, custom_instructions.as_ref())
+            .into_iter()
+            .map(|content| {
+                ChatCompletionRequestSystemMessageArgs::default()
+                    .content(content)
+                    .build()
+                    .map(Into::into)
+            })
+            .collect::<Result<Vec<ChatCompletionRequestMessage>, _>>()?;
+    let citations = match retrieved {
+        Some(retrieved) => {
+            system_msgs.push(
+                ChatCompletionRequestSystemMessageArgs::default()
+                    .content(retrieved.context)
+                    .build()?
+                    .into(),
+            );
+            Some(retrieved.citations)
         }
-    }))
-    .collect::<Vec<ChatCompletionRequestMessage>>();
+        None => None,
+    };
 
-    let request = CreateChatCompletionRequestArgs::default()
-        .model(&cx.config.model_name)
-        .max_tokens(cx.config.max_tokens)
+    let built_msgs = system_msgs
+        .into_iter()
+        .chain(
+            msgs.into_iter()
+                .flat_map(|msg| -> Vec<ChatCompletionRequestMessage> {
+                    match msg.sender.as_str() {
+                        "ai" => ChatCompletionRequestAssistantMessageArgs::default()
+                            .content(msg.body)
+                            .build()
+                            .ok()
+                            .map(Into::into)
+                            .into_iter()
+                            .collect(),
+                        "user" => ChatCompletionRequestUserMessageArgs::default()
+                            .content(
+                                msg.body + &attachment_text.remove(&msg.id).unwrap_or_default(),
+                            )
+                            .build()
+                            .ok()
+                            .map(Into::into)
+                            .into_iter()
+                            .collect(),
+                        // Earlier tool calls are replayed so the model
+                        // doesn't have to make them again
+                        TOOL_SENDER => serde_json::from_str::<ToolRecord>(&msg.body)
+                            .ok()
+                            .and_then(|record| record.request_messages().ok())
+                            .map(Vec::from)
+                            .unwrap_or_default(),
+                        _ => vec![],
+                    }
+                }),
+        )
+        .collect::<Vec<ChatCompletionRequestMessage>>();
+
+    let mut request = CreateChatCompletionRequestArgs::default()
+        .model(
+            persona
+                .model
+                .as_deref()
+                .unwrap_or(cx.config.primary_model()),
+        )
+        .max_tokens(params.max_tokens.unwrap_or(cx.config.max_tokens))
         .messages(built_msgs)
         .build()?;
-    Ok(request)
+    request.temperature = params.temperature;
+    request.top_p = params.top_p;
+    request.stop = params.stop.clone().map(Stop::StringArray);
+    request.seed = params.seed;
+    request.presence_penalty = params.presence_penalty;
+    request.frequency_penalty = params.frequency_penalty;
+    Ok((request, citations))
 }
 
+/// Generates the reply to `parent_message_id`, streaming it to
+/// whoever attaches with `attach_token` and to the user's session
+/// sockets, and saving it as the chat's head, along with
+/// `generation` and the model that served it
+///
+/// If the chat has tools enabled the model may call them first.
+/// Each call is run, saved as a `tool` message between the prompt
+/// and the reply, and its result sent back to the model, for at
+/// most `max_tool_rounds` rounds. With `generate_title` set the chat
+/// is titled from the exchange in the background once the reply has
+/// ended, and the title pushed to the user's sockets. The generation
+/// can be cancelled through the stream registry until the reply is
+/// saved, which saves the text so far.
+/// A reply nobody attaches to within [`ATTACH_GRACE`] of finishing
+/// stops waiting for them.
+///
+/// The reply goes through the chat's output filters, or its
+/// persona's, or the deployment's, and then moderation, before anyone sees it or
+/// it's saved. A reply moderation blocks ends as configured by
+/// `blocked_reply`, and is kept for review with the blocked text.
 pub async fn stream_model_response(
-    chat_id: i32,
-    user_id: i32,
+    chat: Chat,
     attach_token: Uuid,
     parent_message_id: Option<i32>,
     completion_request: CreateChatCompletionRequest,
+    generation: Generation,
+    generate_title: bool,
     cx: Arc<Context>,
 ) -> Result<(), libserver::ServiceError> {
     let (attach_tx, attach_rx) = oneshot::channel();
     let attach_handle = AttachHandle::new(attach_tx);
+    let mut cancelled = {
+        let mut registry = cx.state.stream_registry.lock().await;
+        registry
+            .register(attach_token, attach_handle)
+            .expect("unreachable");
+        registry.start_generation(attach_token, chat.id, chat.user_id)
+    };
+    let (user_id, chat_id) = (chat.user_id, chat.id);
+    let publish = |event: StreamEvent| {
+        let cx = cx.clone();
+        async move {
+            cx.state
+                .stream_registry
+                .lock()
+                .await
+                .publish(attach_token, user_id, chat_id, event)
+        }
+    };
+
+    let tools = if chat.tools_enabled && cx.config.max_tool_rounds > 0 {
+        ToolRegistry::enabled(&cx.config.tools)
+    } else {
+        ToolRegistry::none()
+    };
+    let tool_cx = ToolContext {
+        store: cx.store(),
+        user_id: chat.user_id,
+        chat_id: chat.id,
+    };
+
+    let mut request = completion_request;
+    if !tools.is_empty() {
+        request.tools = Some(tools.definitions());
+    }
+    // Usage comes in a last chunk without choices
+    request.stream_options = Some(ChatCompletionStreamOptions {
+        include_usage: true,
+    });
+
+    let mut channel = Err::<UnboundedSender<StreamEvent>, _>(attach_rx);
+    let persona_filters = cx.config.personas.resolve(&chat.persona).output_filters;
+    let mut pipeline = Pipeline::new(
+        &chat
+            .output_filters
+            .or(persona_filters)
+            .unwrap_or(cx.config.output_filters),
+    );
+    let mut gate = ReplyGate::new(
+        cx.state.moderator.clone(),
+        cx.config.moderation.reply_window,
+    );
+    let mut buf = String::new();
+    let mut served_model = None;
+    let mut usage = None::<Usage>;
+    // Tool calls are saved in a chain under the prompt,
+    // and the reply goes under the last of them
+    let mut reply_parent = parent_message_id;
+    let mut tool_rounds = 0;
+
+    let outcome = 'reply: loop {
+        let (model, mut stream) = match cx.state.model_router.create_stream(request.clone()).await {
+            Ok(started) => started,
+            Err(err) => break Outcome::Failed(err.to_string()),
+        };
+        if served_model.as_ref() != Some(&model) {
+            if let Ok(ref tx) = channel {
+                let _ = tx.unbounded_send(StreamEvent::Model(model.clone()));
+            }
+            publish(StreamEvent::Model(model.clone())).await;
+        }
+        served_model = Some(model.clone());
+
+        let mut finish_reason = None;
+        let mut tool_calls = ToolCallChunks::default();
+
+        let streamed = loop {
+            if let Err(ref mut rx) = channel {
+                if let Ok(tx) = rx.try_recv() {
+                    // A client that has gone away must not stop the
+                    // generation, so send failures are ignored here
+                    let _ = tx.unbounded_send(StreamEvent::Model(model.clone()));
+                    let _ = tx.unbounded_send(StreamEvent::Delta(buf.clone().into()));
+                    channel = Ok(tx);
+                }
+            }
+
+            let next = tokio::select! {
+                Ok(()) = &mut cancelled => break Err(Outcome::Cancelled),
+                next = stream.next() => next,
+            };
+            match next {
+                Some(Ok(stream_chunk)) => {
+                    if let Some(chunk_usage) = stream_chunk.usage {
+                        let total = usage.get_or_insert_with(Usage::default);
+                        total.prompt_tokens += chunk_usage.prompt_tokens;
+                        total.completion_tokens += chunk_usage.completion_tokens;
+                        total.total_tokens += chunk_usage.total_tokens;
+                    }
+                    let Some(choice) = stream_chunk.choices.into_iter().next() else {
+                        continue;
+                    };
+                    let filtered = choice
+                        .delta
+                        .content
+                        .map(|chunk| pipeline.push(&chunk))
+                        .unwrap_or_default();
+                    let chunk = match gate.push(&filtered).await {
+                        Ok(passed) => passed,
+                        Err(ReplyBlocked) => break Err(Outcome::Blocked),
+                    };
+                    if !chunk.is_empty() {
+                        buf.push_str(&chunk);
+                        let delta = StreamEvent::Delta(chunk.into());
+                        if let Ok(ref mut tx) = channel {
+                            let _ = tx.unbounded_send(delta.clone());
+                        }
+                        publish(delta).await;
+                    }
+                    for chunk in choice.delta.tool_calls.into_iter().flatten() {
+                        tool_calls.push(chunk);
+                    }
+                    if choice.finish_reason.is_some() {
+                        finish_reason = choice.finish_reason;
+                    }
+                }
+                Some(Err(err)) => break Err(Outcome::Failed(err.to_string())),
+                None => break Ok(()),
+            }
+        };
+        if let Err(outcome) = streamed {
+            break outcome;
+        }
+
+        let calls = match finish_reason {
+            Some(FinishReason::ToolCalls) => tool_calls.into_calls(),
+            Some(reason) => break Outcome::Finished(reason),
+            None => break Outcome::Interrupted,
+        };
+        if calls.is_empty() {
+            break Outcome::Finished(FinishReason::ToolCalls);
+        }
+
+        for call in calls {
+            let tool_call = StreamEvent::ToolCall(call.function.name.clone());
+            if let Ok(ref tx) = channel {
+                let _ = tx.unbounded_send(tool_call.clone());
+            }
+            publish(tool_call).await;
+            let record = tools.call(&tool_cx, call).await;
+            match save_tool_call(&cx, chat.user_id, reply_parent, &record, &model).await {
+                Ok(msg) => reply_parent = Some(msg.id),
+                Err(err) => break 'reply Outcome::Failed(err.to_string()),
+            }
+            match record.request_messages() {
+                Ok(msgs) => request.messages.extend(msgs),
+                Err(err) => break 'reply Outcome::Failed(err.to_string()),
+            }
+        }
+
+        tool_rounds += 1;
+        if tool_rounds >= cx.config.max_tool_rounds {
+            // The last round has to answer with what it has
+            request.tool_choice = Some(ChatCompletionToolChoiceOption::None);
+        }
+    };
+
+    // The filters and moderation may be holding back the end of the reply
+    let blocked_reply = &cx.config.moderation.blocked_reply;
+    let (outcome, rest) = match gate.finish(&pipeline.finish()).await {
+        Ok(rest) => (outcome, rest),
+        Err(ReplyBlocked) => match blocked_reply {
+            BlockedReply::Truncate { notice } => (Outcome::Blocked, notice.clone()),
+            BlockedReply::Error => (Outcome::Blocked, String::new()),
+        },
+    };
+    if !rest.is_empty() {
+        buf.push_str(&rest);
+        let delta = StreamEvent::Delta(rest.into());
+        if let Ok(ref tx) = channel {
+            let _ = tx.unbounded_send(delta.clone());
+        }
+        publish(delta).await;
+    }
+
+    // Past here there's nothing left to cancel
     cx.state
         .stream_registry
         .lock()
         .await
-        .register(attach_token, attach_handle)
-        .expect("unreachable");
-
-    let mut channel = Err::<UnboundedSender<Bytes>, _>(attach_rx);
-    let mut buf = String::new();
+        .finish_generation(attach_token);
+
+    let status = match outcome {
+        Outcome::Finished(_) => MsgStatus::Complete,
+        Outcome::Interrupted => MsgStatus::Interrupted,
+        Outcome::Cancelled => MsgStatus::Cancelled,
+        Outcome::Failed(_) => MsgStatus::Error,
+        Outcome::Blocked => MsgStatus::Blocked,
+    };
 
-    let mut stream = Box::pin(
-        cx.state
-            .openai_client
-            .chat()
-            .create_stream(completion_request)
-            .await?
-            .filter_map(|c| async move {
-                c.ok()
-                    .and_then(|stream_chunk| stream_chunk.choices.into_iter().next())
-                    .and_then(|stream_chunk| stream_chunk.delta.content)
+    // Whatever was generated is kept, even if the reply is incomplete
+    let saved = save_model_response(
+        cx.clone(),
+        chat.id,
+        chat.user_id,
+        reply_parent,
+        &buf,
+        status,
+        Generation {
+            model: served_model.clone(),
+            ..generation
+        },
+    )
+    .await;
+
+    let target = FlagTarget {
+        user_id: chat.user_id,
+        chat_id: Some(chat.id),
+        msg_id: saved.as_ref().ok().map(|msg| msg.id),
+    };
+    let verdict = gate.verdict().clone();
+    moderation::keep(&cx, target, FlagSource::Reply, verdict, gate.text()).await;
+
+    let last_event = match (outcome, saved) {
+        // Provider and database errors aren't for clients,
+        // who only learn that the reply failed
+        (Outcome::Failed(err), _) => {
+            eprintln!("reply in chat {chat_id} failed: {err}");
+            StreamEvent::Error(ReplyFailed.to_string())
+        }
+        (_, Err(err)) => {
+            eprintln!("failed to save reply in chat {chat_id}: {err}");
+            StreamEvent::Error(ReplyFailed.to_string())
+        }
+        (Outcome::Cancelled, Ok(msg)) => StreamEvent::Cancelled(msg.id),
+        (Outcome::Finished(reason), Ok(msg)) => StreamEvent::Done(Finished {
+            msg_id: msg.id,
+            finish_reason: finish_reason_name(reason).into(),
+            usage,
+        }),
+        (Outcome::Interrupted, Ok(msg)) => StreamEvent::Done(Finished {
+            msg_id: msg.id,
+            finish_reason: "interrupted".into(),
+            usage,
+        }),
+        (Outcome::Blocked, Ok(msg)) => match blocked_reply {
+            BlockedReply::Truncate { .. } => StreamEvent::Done(Finished {
+                msg_id: msg.id,
+                finish_reason: finish_reason_name(FinishReason::ContentFilter).into(),
+                usage,
             }),
-    );
+            BlockedReply::Error => StreamEvent::Error(ReplyBlocked.to_string()),
+        },
+    };
 
-    loop {
-        if let Err(ref mut rx) = channel {
-            if let Ok(tx) = rx.try_recv() {
-                tx.unbounded_send(buf.clone().into())?;
-                channel = Ok(tx);
-            }
-        }
+    if let Ok(chat) = cx.store().chat_by_id(chat.id).await {
+        push_chat(&cx, &chat).await;
+    }
 
-        if let Some(chunk) = stream.next().await {
-            buf.push_str(&chunk);
-            if let Ok(ref mut tx) = channel {
-                tx.unbounded_send(chunk.into())?;
+    // Sockets get the ending straight away, without
+    // waiting for a client to attach
+    publish(last_event.clone()).await;
+
+    // Titling is a model call of its own, so it mustn't hold up
+    // the ending on sockets. The title reaches them once it lands
+    let titling = generate_title.then(|| {
+        let cx = cx.clone();
+        let reply = buf.clone();
+        tokio::spawn(async move {
+            let title = match title_new_chat(&cx, chat_id, parent_message_id, &reply).await {
+                Ok(title) => title,
+                Err(err) => {
+                    eprintln!("failed to title chat {chat_id}: {err}");
+                    return None;
+                }
+            };
+            cx.state.stream_registry.lock().await.publish(
+                attach_token,
+                user_id,
+                chat_id,
+                StreamEvent::Title(title.clone()),
+            );
+            if let Ok(chat) = cx.store().chat_by_id(chat_id).await {
+                push_chat(&cx, &chat).await;
             }
-        } else {
-            let ai_msg = Msg::create(cx.db(), &buf, "ai", user_id, parent_message_id).await?;
-            let chat = Chat::get_by_id(cx.db(), chat_id).await?;
-            chat.append_to_chat(cx.db(), &ai_msg).await?;
-
-            match channel {
-                Ok(_) => return Ok(()),
-                Err(ref mut rx) => {
-                    let tx = rx.await?;
-                    tx.unbounded_send(buf.into())?;
-                    return Ok(());
+            Some(title)
+        })
+    });
+
+    let tx = match channel {
+        Ok(tx) => tx,
+        Err(mut rx) => {
+            let tx = match tokio::time::timeout(ATTACH_GRACE, &mut rx).await {
+                Ok(attached) => attached?,
+                Err(_) => {
+                    let unattached = cx
+                        .state
+                        .stream_registry
+                        .lock()
+                        .await
+                        .handle_map
+                        .remove(&attach_token);
+                    // A client that attached as time ran out has been sent on
+                    if unattached.is_some() {
+                        return Ok(());
+                    }
+                    rx.await?
                 }
+            };
+            if let Some(model) = served_model {
+                let _ = tx.unbounded_send(StreamEvent::Model(model));
             }
-        };
+            let _ = tx.unbounded_send(StreamEvent::Delta(buf.into()));
+            tx
+        }
+    };
+
+    // The attach socket closes on the ending, so it
+    // waits for the title to be sent first
+    if let Some(titling) = titling {
+        if let Ok(Some(title)) = titling.await {
+            let _ = tx.unbounded_send(StreamEvent::Title(title));
+        }
+    }
+    let _ = tx.unbounded_send(last_event);
+
+    Ok(())
+}
+
+/// How the generation of a reply ended
+enum Outcome {
+    /// The model finished for the given reason
+    Finished(FinishReason),
+    /// The provider's stream ended without a finish reason
+    Interrupted,
+    Cancelled,
+    /// The reply failed with this error, which is only logged
+    Failed(String),
+    /// Moderation stopped the reply
+    Blocked,
+}
+
+/// The name OpenAI's API gives a finish reason
+fn finish_reason_name(reason: FinishReason) -> &'static str {
+    match reason {
+        FinishReason::Stop => "stop",
+        FinishReason::Length => "length",
+        FinishReason::ToolCalls => "tool_calls",
+        FinishReason::ContentFilter => "content_filter",
+        FinishReason::FunctionCall => "function_call",
     }
 }
 
-#[derive(Deserialize)]
-struct PromptServiceInput<'a> {
-    pub text: Cow<'a, str>,
+/// Titles a chat from its first user message and the reply to it
+async fn title_new_chat(
+    cx: &Context,
+    chat_id: i32,
+    user_msg_id: Option<i32>,
+    reply: &str,
+) -> Result<String, libserver::ServiceError> {
+    let Some(user_msg_id) = user_msg_id else {
+        return Err(NotFound.into());
+    };
+    let user_msg = cx.store().msg_by_id(user_msg_id).await?;
+    chat_title::title_chat(cx, chat_id, &user_msg, reply).await
+}
+
+/// Saves a tool call and its result under `parent_message_id`
+async fn save_tool_call(
+    cx: &Context,
+    user_id: i32,
+    parent_message_id: Option<i32>,
+    record: &ToolRecord,
+    model: &str,
+) -> Result<Msg, libserver::ServiceError> {
+    let msg = cx
+        .store()
+        .create_msg(
+            serde_json::to_string(record)?,
+            TOOL_SENDER.into(),
+            user_id,
+            parent_message_id,
+            MsgStatus::Complete,
+            Generation {
+                model: Some(model.into()),
+                params: None,
+                citations: None,
+            },
+        )
+        .await?;
+    Ok(msg)
+}
+
+async fn save_model_response(
+    cx: Arc<Context>,
+    chat_id: i32,
+    user_id: i32,
+    parent_message_id: Option<i32>,
+    body: &str,
+    status: MsgStatus,
+    generation: Generation,
+) -> Result<Msg, libserver::ServiceError> {
+    let store = cx.store();
+    let ai_msg = store
+        .create_msg(
+            body.into(),
+            "ai".into(),
+            user_id,
+            parent_message_id,
+            status,
+            generation,
+        )
+        .await?;
+    store.set_chat_head(chat_id, ai_msg.id).await?;
+    Ok(ai_msg)
+}
+
+#[derive(Deserialize, ToSchema)]
+struct PromptServiceInput {
+    pub text: String,
     pub chat_id: Option<i32>,
+    /// Sampling parameters for the reply
+    #[serde(default)]
+    pub params: GenParams,
+    /// Ids of uploaded attachments to send with the message
+    #[serde(default)]
+    pub attachments: Vec<i32>,
+    /// The id of the persona a new chat is answered in, or the
+    /// deployment's default if left out. Ignored with `chat_id`
+    pub persona: Option<String>,
 }
 
 #[derive(Clone)]
@@ -267,3 +860,7 @@ impl tower::Service<libserver::Request> for PromptService {
         Box::pin(async move { prompt(req, cx).await })
     }
 }
+
+#[derive(Debug, thiserror::Error)]
+#[error("Reply Failed")]
+pub struct ReplyFailed;
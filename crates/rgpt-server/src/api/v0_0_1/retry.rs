@@ -0,0 +1,106 @@
+use std::sync::Arc;
+
+use hyper::Response;
+use libserver::{DynRoute, PathEqRouter, Request, Route, single_frame_body};
+use rgpt_cfg::Context;
+use rgpt_db::store::NotFound;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::prompt::regenerate_reply;
+
+pub fn route(cx: Arc<Context>) -> DynRoute {
+    let router = PathEqRouter::new("/api/v0.0.1/retry");
+
+    Route::from_parts(router, RetryService::new(cx)).make_dyn()
+}
+
+/// Generate a new reply in place of an AI message that
+/// ended with an error or was interrupted
+///
+/// The failed reply stays in the message tree; the new
+/// reply is a sibling of it under the same parent, generated
+/// with the failed reply's parameters. Tool calls the failed
+/// reply made are kept rather than run again.
+#[utoipa::path(
+    post,
+    path = "/api/v0.0.1/retry",
+    request_body = RetryServiceInput,
+    responses((status = 200, body = RetryServiceResponse)),
+)]
+pub async fn retry(req: Request, cx: Arc<Context>) -> libserver::ServiceResult {
+    crate::check_body_size(&req, cx.config.max_req_size)?;
+    let headers = req.headers().to_owned();
+    let body = crate::collect_body_string(req).await?;
+
+    let RetryServiceInput { chat_id } = serde_json::from_str(&body)?;
+    let store = cx.store();
+    let chat = store.chat_by_id(chat_id).await?;
+    crate::validate_session_header(&cx, &headers, Some(chat.user_id)).await?;
+    // Chats in the trash can't be added to
+    if chat.deleted {
+        Err(NotFound)?;
+    }
+
+    let failed_msg = match chat.head_msg {
+        Some(id) => store.msg_by_id(id).await?,
+        None => Err(NothingToRetry)?,
+    };
+
+    if failed_msg.sender != "ai" || failed_msg.is_complete() {
+        Err(NothingToRetry)?;
+    }
+
+    let attach_token = regenerate_reply(cx, chat, failed_msg).await?;
+
+    let response = serde_json::to_string(&RetryServiceResponse {
+        chat_id,
+        attach_token: attach_token.to_string(),
+    })?;
+
+    Ok(Response::new(single_frame_body(response)))
+}
+
+#[derive(Deserialize, ToSchema)]
+struct RetryServiceInput {
+    chat_id: i32,
+}
+
+#[derive(Serialize, ToSchema)]
+struct RetryServiceResponse {
+    chat_id: i32,
+    attach_token: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Chat Has No Failed Reply To Retry")]
+pub struct NothingToRetry;
+
+#[derive(Clone)]
+pub struct RetryService {
+    cx: Arc<Context>,
+}
+
+impl RetryService {
+    pub fn new(cx: Arc<Context>) -> Self {
+        RetryService { cx }
+    }
+}
+
+impl tower::Service<libserver::Request> for RetryService {
+    type Response = libserver::ServiceResponse;
+    type Error = libserver::ServiceError;
+    type Future = libserver::ServiceBoxFuture;
+
+    fn poll_ready(
+        &mut self,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: libserver::Request) -> Self::Future {
+        let cx = self.cx.clone();
+        Box::pin(async move { retry(req, cx).await })
+    }
+}
@@ -3,9 +3,8 @@ use std::sync::Arc;
 use hyper::Response;
 use libserver::{DynRoute, PathEqRouter, Request, Route, single_frame_body};
 use rgpt_cfg::Context;
-use rgpt_db::user::User;
-use serde::Deserialize;
-use serde_json::json;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
 pub fn route(cx: Arc<Context>) -> DynRoute {
     let router = PathEqRouter::new("/api/v0.0.1/user_chats");
@@ -13,47 +12,64 @@ pub fn route(cx: Arc<Context>) -> DynRoute {
     Route::from_parts(router, UserChatsService::new(cx)).make_dyn()
 }
 
+/// List the session user's chats
+#[utoipa::path(
+    post,
+    path = "/api/v0.0.1/user_chats",
+    request_body = UserChatsServiceInput,
+    responses((status = 200, body = UserChatsServiceReturn)),
+)]
 pub async fn user_chats(req: Request, cx: Arc<Context>) -> libserver::ServiceResult {
     crate::check_body_size(&req, cx.config.max_req_size)?;
     let headers = req.headers().to_owned();
     let body = crate::collect_body_string(req).await?;
 
     let UserChatsServiceInput { user_id } = serde_json::from_str(&body)?;
-    let session = crate::validate_session_header(cx.db(), &headers, user_id).await?;
+    let store = cx.store();
+    let session = crate::validate_session_header(&cx, &headers, user_id).await?;
 
-    let user = User::get_by_id(cx.db(), session.user_id).await?;
+    let user = store.user_by_id(session.user_id).await?;
 
-    let chats = if user.user_id == 1 {
+    let chats = if crate::is_guest(user.user_id) {
         vec![]
     } else {
-        user.get_chats(cx.db()).await?
+        store.user_chats(user.user_id).await?
     };
 
     let chats = chats
         .into_iter()
-        .map(|chat| {
-            json!({
-                "id": chat.id,
-                "name": chat.name.unwrap_or("Untitled Chat".into())
-            })
+        .map(|chat| ChatSummary {
+            id: chat.id,
+            name: chat.name.unwrap_or("Untitled Chat".into()),
         })
-        .collect::<Vec<_>>();
+        .collect();
 
-    let fmted_chats = json!({
-        "user_id": session.user_id,
-        "chats": chats
-    })
-    .to_string();
+    let fmted_chats = serde_json::to_string(&UserChatsServiceReturn {
+        user_id: session.user_id,
+        chats,
+    })?;
 
     let body = single_frame_body(fmted_chats);
     Ok(Response::new(body))
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 struct UserChatsServiceInput {
     user_id: Option<i32>,
 }
 
+#[derive(Serialize, ToSchema)]
+struct UserChatsServiceReturn {
+    user_id: i32,
+    chats: Vec<ChatSummary>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct ChatSummary {
+    id: i32,
+    name: String,
+}
+
 #[derive(Clone)]
 pub struct UserChatsService {
     cx: Arc<Context>,
@@ -0,0 +1,165 @@
+use std::sync::Arc;
+
+use chrono::{Duration, NaiveDateTime, Utc};
+use hyper::{
+    StatusCode,
+    header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+};
+use libserver::{Request, single_frame_body};
+use rgpt_cfg::Context;
+use rgpt_db::{
+    audit::{self, AuditKind, NewAuditEvent},
+    session::Session,
+};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{
+    accounts,
+    api::router::{ErrorBody, PathParams, empty_response, json_response},
+};
+
+/// Download everything kept about the session user
+///
+/// A gzipped JSON document of the user's account, usage,
+/// sessions, API keys, chats (trashed ones included) with all
+/// of their messages, attachments, documents, share links and
+/// account activity.
+#[utoipa::path(
+    get,
+    path = "/api/v0.0.2/account/export",
+    responses(
+        (status = 200, description = "A gzipped JSON document", content_type = "application/gzip"),
+        (status = 401, body = ErrorBody),
+        (status = 403, body = ErrorBody),
+    ),
+)]
+pub async fn export_account(
+    req: Request,
+    cx: Arc<Context>,
+    _params: PathParams,
+) -> libserver::ServiceResult {
+    let session = account_session(&cx, &req).await?;
+
+    let export = accounts::export(&cx, session.user_id).await?;
+    let archive = accounts::archive(&export).await?;
+
+    let event = NewAuditEvent::new(AuditKind::AccountExported, Some(session.user_id), "")
+        .client(&crate::client_info(&cx, req.headers()));
+    audit::record(&*cx.store(), event).await;
+
+    let filename = format!("retrogpt-export-{}.json.gz", session.user_id);
+    Ok(hyper::Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/gzip")
+        .header(
+            CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{filename}\""),
+        )
+        .body(single_frame_body(archive))?)
+}
+
+/// Schedule the session user's account for deletion
+///
+/// The account, with its chats, messages, attachments and
+/// everything else but its audit events, is deleted for good
+/// once the deployment's grace period is over. Until then it
+/// keeps working, and the deletion can be cancelled. Asking
+/// again leaves the date as it was.
+#[utoipa::path(
+    post,
+    path = "/api/v0.0.2/account/deletion",
+    responses(
+        (status = 202, body = DeletionScheduled),
+        (status = 401, body = ErrorBody),
+        (status = 403, body = ErrorBody),
+    ),
+)]
+pub async fn request_deletion(
+    req: Request,
+    cx: Arc<Context>,
+    _params: PathParams,
+) -> libserver::ServiceResult {
+    let session = account_session(&cx, &req).await?;
+    let store = cx.store();
+
+    let user = store.user_by_id(session.user_id).await?;
+    if let Some(delete_after) = user.delete_after {
+        return json_response(StatusCode::ACCEPTED, &DeletionScheduled { delete_after });
+    }
+
+    let grace = Duration::days(cx.config.account_deletion_grace_days.into());
+    let delete_after = Utc::now().naive_utc() + grace;
+    store
+        .schedule_user_deletion(session.user_id, Some(delete_after))
+        .await?;
+
+    let event = NewAuditEvent::new(
+        AuditKind::AccountDeletionRequested,
+        Some(session.user_id),
+        format!("deleting after {delete_after}"),
+    )
+    .client(&crate::client_info(&cx, req.headers()));
+    audit::record(&*store, event).await;
+
+    json_response(StatusCode::ACCEPTED, &DeletionScheduled { delete_after })
+}
+
+/// Cancel the session user's scheduled account deletion
+#[utoipa::path(
+    delete,
+    path = "/api/v0.0.2/account/deletion",
+    responses(
+        (status = 204),
+        (status = 401, body = ErrorBody),
+        (status = 403, body = ErrorBody),
+        (status = 409, body = ErrorBody),
+    ),
+)]
+pub async fn cancel_deletion(
+    req: Request,
+    cx: Arc<Context>,
+    _params: PathParams,
+) -> libserver::ServiceResult {
+    let session = account_session(&cx, &req).await?;
+    let store = cx.store();
+
+    let user = store.user_by_id(session.user_id).await?;
+    if user.delete_after.is_none() {
+        Err(NoDeletionScheduled)?;
+    }
+    store.schedule_user_deletion(session.user_id, None).await?;
+
+    let event = NewAuditEvent::new(
+        AuditKind::AccountDeletionCancelled,
+        Some(session.user_id),
+        "",
+    )
+    .client(&crate::client_info(&cx, req.headers()));
+    audit::record(&*store, event).await;
+
+    empty_response(StatusCode::NO_CONTENT)
+}
+
+/// The session of a user with an account of their own
+async fn account_session(cx: &Context, req: &Request) -> Result<Session, libserver::ServiceError> {
+    let session = crate::validate_session_header(cx, req.headers(), None).await?;
+    if crate::is_guest(session.user_id) {
+        Err(GuestAccount)?;
+    }
+    Ok(session)
+}
+
+#[derive(Serialize, ToSchema)]
+struct DeletionScheduled {
+    /// When the account will be deleted
+    delete_after: NaiveDateTime,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Guests Have No Account To Export Or Delete")]
+pub struct GuestAccount;
+
+#[derive(Debug, thiserror::Error)]
+#[error("No Account Deletion Is Scheduled")]
+pub struct NoDeletionScheduled;
@@ -0,0 +1,55 @@
+use std::sync::Arc;
+
+use hyper::StatusCode;
+use libserver::Request;
+use rgpt_cfg::Context;
+use rgpt_db::attachment::Attachment;
+
+use crate::{
+    api::router::{ErrorBody, InvalidBody, PathParams, json_response},
+    attachments,
+};
+
+/// Upload a document to send with a message
+///
+/// The body is the document's text and `name` its file name. Plain
+/// text, Markdown, CSV, JSON and source code are accepted, up to the
+/// deployment's attachment size limit. Send it by passing its id in
+/// a message's `attachments`.
+#[utoipa::path(
+    post,
+    path = "/api/v0.0.2/attachments",
+    params(("name" = String, Query, description = "The document's file name")),
+    request_body(content = String, content_type = "text/plain"),
+    responses(
+        (status = 201, body = Attachment),
+        (status = 400, body = ErrorBody),
+        (status = 401, body = ErrorBody),
+        (status = 403, body = ErrorBody),
+        (status = 413, body = ErrorBody),
+    ),
+)]
+pub async fn upload_attachment(
+    req: Request,
+    cx: Arc<Context>,
+    _params: PathParams,
+) -> libserver::ServiceResult {
+    crate::check_body_size(&req, cx.config.max_attachment_size)?;
+    let Some(name) = crate::extract_query_param(req.uri(), "name") else {
+        return Err(InvalidBody.into());
+    };
+
+    let session = crate::validate_session_header(&cx, req.headers(), None).await?;
+    if crate::is_guest(session.user_id) {
+        Err(GuestAttachment)?;
+    }
+
+    let bytes = crate::collect_body_bytes(req).await?;
+    let attachment = attachments::save_attachment(&cx, session.user_id, &name, bytes).await?;
+
+    json_response(StatusCode::CREATED, &attachment)
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Guests Can't Upload Attachments")]
+pub struct GuestAttachment;
@@ -0,0 +1,139 @@
+use std::sync::Arc;
+
+use hyper::StatusCode;
+use libserver::Request;
+use rgpt_cfg::Context;
+use rgpt_db::audit::{AuditEvent, AuditKind, AuditQuery};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::api::router::{ErrorBody, InvalidBody, PathParams, json_response};
+
+/// How many events are listed when the request doesn't say
+const DEFAULT_LIMIT: usize = 50;
+
+/// The most events listed at once
+const MAX_LIMIT: usize = 200;
+
+/// List the session user's account activity, newest first
+///
+/// Logins, sessions, trashed chats and share links, along with
+/// the address and user agent they came from. Pass the oldest
+/// listed event's id as `before` to page back.
+#[utoipa::path(
+    get,
+    path = "/api/v0.0.2/account/activity",
+    params(
+        ("before" = Option<i32>, Query, description = "Only list events older than this one"),
+        ("limit" = Option<usize>, Query, description = "At most 200, 50 by default"),
+    ),
+    responses(
+        (status = 200, body = AuditEventList),
+        (status = 400, body = ErrorBody),
+        (status = 401, body = ErrorBody),
+        (status = 403, body = ErrorBody),
+    ),
+)]
+pub async fn account_activity(
+    req: Request,
+    cx: Arc<Context>,
+    _params: PathParams,
+) -> libserver::ServiceResult {
+    let store = cx.store();
+    let session = crate::validate_session_header(&cx, req.headers(), None).await?;
+    if crate::is_guest(session.user_id) {
+        Err(GuestActivity)?;
+    }
+
+    let query = AuditQuery {
+        user_id: Some(session.user_id),
+        ..page_query(req.uri())?
+    };
+    let events = store.audit_events(&query).await?;
+
+    json_response(StatusCode::OK, &AuditEventList { events })
+}
+
+/// List the audit log of every account, newest first
+///
+/// Only the users in the deployment's `admin_user_ids` may
+/// read it. Events can be narrowed to one user or one kind.
+#[utoipa::path(
+    get,
+    path = "/api/v0.0.2/audit",
+    params(
+        ("user_id" = Option<i32>, Query, description = "Only list events concerning this user"),
+        ("kind" = Option<String>, Query, description = "Only list events of this kind"),
+        ("before" = Option<i32>, Query, description = "Only list events older than this one"),
+        ("limit" = Option<usize>, Query, description = "At most 200, 50 by default"),
+    ),
+    responses(
+        (status = 200, body = AuditEventList),
+        (status = 400, body = ErrorBody),
+        (status = 401, body = ErrorBody),
+        (status = 403, body = ErrorBody),
+    ),
+)]
+pub async fn audit_log(
+    req: Request,
+    cx: Arc<Context>,
+    _params: PathParams,
+) -> libserver::ServiceResult {
+    let store = cx.store();
+    let session = crate::validate_session_header(&cx, req.headers(), None).await?;
+    if !cx.config.admin_user_ids.contains(&session.user_id) {
+        Err(NotAdmin)?;
+    }
+
+    let uri = req.uri();
+    let user_id = query_param(uri, "user_id")?;
+    let kind = match crate::extract_query_param(uri, "kind") {
+        Some(kind) => Some(AuditKind::parse(&kind).ok_or(InvalidBody)?),
+        None => None,
+    };
+    let query = AuditQuery {
+        user_id,
+        kind,
+        ..page_query(uri)?
+    };
+    let events = store.audit_events(&query).await?;
+
+    json_response(StatusCode::OK, &AuditEventList { events })
+}
+
+/// The `before` and `limit` query parameters, for every user
+fn page_query(uri: &hyper::Uri) -> Result<AuditQuery, InvalidBody> {
+    let limit = query_param(uri, "limit")?.unwrap_or(DEFAULT_LIMIT);
+    if limit == 0 || limit > MAX_LIMIT {
+        return Err(InvalidBody);
+    }
+
+    Ok(AuditQuery {
+        user_id: None,
+        kind: None,
+        before: query_param(uri, "before")?,
+        limit,
+    })
+}
+
+fn query_param<T: std::str::FromStr>(
+    uri: &hyper::Uri,
+    name: &str,
+) -> Result<Option<T>, InvalidBody> {
+    crate::extract_query_param(uri, name)
+        .map(|value| value.parse().map_err(|_| InvalidBody))
+        .transpose()
+}
+
+#[derive(Serialize, ToSchema)]
+struct AuditEventList {
+    events: Vec<AuditEvent>,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Guests Have No Account Activity")]
+pub struct GuestActivity;
+
+#[derive(Debug, thiserror::Error)]
+#[error("Only Admins Can Read The Audit Log")]
+pub struct NotAdmin;
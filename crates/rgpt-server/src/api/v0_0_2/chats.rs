@@ -0,0 +1,330 @@
+use std::sync::Arc;
+
+use hyper::{StatusCode, header::LOCATION};
+use libserver::Request;
+use rgpt_cfg::Context;
+use rgpt_db::{
+    audit::{self, AuditKind, NewAuditEvent},
+    chat::Chat,
+    output_filters::OutputFilters,
+};
+use serde::{Deserialize, Deserializer, Serialize};
+use utoipa::ToSchema;
+
+use super::{PREFIX, json_body, personas::pick_persona, session_chat, socket::push_chat};
+use crate::{
+    api::router::{ErrorBody, InvalidBody, PathParams, empty_response, json_response},
+    chat_title,
+    tools::TOOL_SENDER,
+};
+
+/// List the session user's chats
+#[utoipa::path(
+    get,
+    path = "/api/v0.0.2/chats",
+    responses(
+        (status = 200, body = ChatList),
+        (status = 401, body = ErrorBody),
+    ),
+)]
+pub async fn list_chats(
+    req: Request,
+    cx: Arc<Context>,
+    _params: PathParams,
+) -> libserver::ServiceResult {
+    let store = cx.store();
+    let session = crate::validate_session_header(&cx, req.headers(), None).await?;
+
+    let chats = if crate::is_guest(session.user_id) {
+        vec![]
+    } else {
+        store.user_chats(session.user_id).await?
+    };
+
+    json_response(StatusCode::OK, &ChatList { chats })
+}
+
+/// Create an empty chat
+#[utoipa::path(
+    post,
+    path = "/api/v0.0.2/chats",
+    request_body = CreateChatInput,
+    responses(
+        (status = 201, body = Chat, headers(("Location" = String))),
+        (status = 400, body = ErrorBody),
+        (status = 401, body = ErrorBody),
+    ),
+)]
+pub async fn create_chat(
+    req: Request,
+    cx: Arc<Context>,
+    _params: PathParams,
+) -> libserver::ServiceResult {
+    let headers = req.headers().to_owned();
+    let CreateChatInput { name, persona } = json_body(req, &cx).await?;
+    let name = name.map(validate_name).transpose()?;
+    let persona = pick_persona(&cx, persona)?;
+
+    let store = cx.store();
+    let session = crate::validate_session_header(&cx, &headers, None).await?;
+    let chat = store.create_chat(session.user_id, name, persona).await?;
+    push_chat(&cx, &chat).await;
+
+    let mut response = json_response(StatusCode::CREATED, &chat)?;
+    response
+        .headers_mut()
+        .insert(LOCATION, format!("{PREFIX}/chats/{}", chat.id).parse()?);
+    Ok(response)
+}
+
+/// Get a chat
+#[utoipa::path(
+    get,
+    path = "/api/v0.0.2/chats/{id}",
+    params(("id" = i32, Path)),
+    responses(
+        (status = 200, body = Chat),
+        (status = 401, body = ErrorBody),
+        (status = 404, body = ErrorBody),
+    ),
+)]
+pub async fn get_chat(
+    req: Request,
+    cx: Arc<Context>,
+    params: PathParams,
+) -> libserver::ServiceResult {
+    let (_session, chat) = session_chat(&cx, req.headers(), &params).await?;
+
+    json_response(StatusCode::OK, &chat)
+}
+
+/// Rename a chat, turn its tools or the user's custom
+/// instructions on or off, or set how its replies are filtered
+///
+/// Setting `output_filters` to `null` puts the chat back
+/// on the deployment's filters.
+#[utoipa::path(
+    patch,
+    path = "/api/v0.0.2/chats/{id}",
+    params(("id" = i32, Path)),
+    request_body = UpdateChatInput,
+    responses(
+        (status = 200, body = Chat),
+        (status = 400, body = ErrorBody),
+        (status = 401, body = ErrorBody),
+        (status = 404, body = ErrorBody),
+    ),
+)]
+pub async fn update_chat(
+    req: Request,
+    cx: Arc<Context>,
+    params: PathParams,
+) -> libserver::ServiceResult {
+    let (_session, chat) = session_chat(&cx, req.headers(), &params).await?;
+    let UpdateChatInput {
+        name,
+        tools_enabled,
+        instructions_enabled,
+        output_filters,
+    } = json_body(req, &cx).await?;
+    let name = name.map(validate_name).transpose()?;
+    if let Some(Some(filters)) = output_filters {
+        if !filters.is_valid() {
+            Err(InvalidBody)?;
+        }
+    }
+
+    let store = cx.store();
+    let chat = match name {
+        Some(name) => store.set_chat_name(chat.id, name).await?,
+        None => chat,
+    };
+    let chat = match tools_enabled {
+        Some(enabled) => store.set_chat_tools_enabled(chat.id, enabled).await?,
+        None => chat,
+    };
+    let chat = match instructions_enabled {
+        Some(enabled) => {
+            store
+                .set_chat_instructions_enabled(chat.id, enabled)
+                .await?
+        }
+        None => chat,
+    };
+    let chat = match output_filters {
+        Some(filters) => store.set_chat_output_filters(chat.id, filters).await?,
+        None => chat,
+    };
+    push_chat(&cx, &chat).await;
+
+    json_response(StatusCode::OK, &chat)
+}
+
+/// Regenerate a chat's title
+///
+/// The model is asked for a new title from the chat's first
+/// message and the reply to it. If it fails, the chat keeps
+/// its current title and the error is returned.
+#[utoipa::path(
+    post,
+    path = "/api/v0.0.2/chats/{id}/title",
+    params(("id" = i32, Path)),
+    responses(
+        (status = 200, body = Chat),
+        (status = 400, body = ErrorBody),
+        (status = 401, body = ErrorBody),
+        (status = 404, body = ErrorBody),
+        (status = 502, body = ErrorBody),
+    ),
+)]
+pub async fn regenerate_title(
+    req: Request,
+    cx: Arc<Context>,
+    params: PathParams,
+) -> libserver::ServiceResult {
+    let (_session, chat) = session_chat(&cx, req.headers(), &params).await?;
+
+    let store = cx.store();
+    let msgs = store.chat_msgs(&chat).await?;
+    // Tool calls between the message and its reply aren't
+    // part of the conversation the title describes
+    let mut msgs = msgs
+        .iter()
+        .filter(|msg| msg.sender != TOOL_SENDER)
+        .skip_while(|msg| msg.sender != "user")
+        .map(|msg| msg.body.as_str());
+    let Some(user_text) = msgs.next() else {
+        return Err(NothingToTitle.into());
+    };
+    let reply_text = msgs.next().unwrap_or_default();
+
+    let title = chat_title::generate_title(&cx, user_text, reply_text).await?;
+    let chat = store.set_chat_name(chat.id, title).await?;
+    push_chat(&cx, &chat).await;
+
+    json_response(StatusCode::OK, &chat)
+}
+
+/// Stop generating a chat's reply
+///
+/// Answers `202 Accepted` once the generation has been told to
+/// stop. The text generated so far is saved as a `cancelled`
+/// message, and attached clients get a `cancelled` event.
+#[utoipa::path(
+    post,
+    path = "/api/v0.0.2/chats/{id}/cancel",
+    params(("id" = i32, Path)),
+    responses(
+        (status = 202, description = "The generation is stopping"),
+        (status = 401, body = ErrorBody),
+        (status = 404, body = ErrorBody),
+        (status = 409, body = ErrorBody),
+    ),
+)]
+pub async fn cancel_generation(
+    req: Request,
+    cx: Arc<Context>,
+    params: PathParams,
+) -> libserver::ServiceResult {
+    let (_session, chat) = session_chat(&cx, req.headers(), &params).await?;
+    if !cx.state.stream_registry.lock().await.cancel_chat(chat.id) {
+        Err(NothingToCancel)?;
+    }
+
+    empty_response(StatusCode::ACCEPTED)
+}
+
+/// Move a chat to the trash
+#[utoipa::path(
+    delete,
+    path = "/api/v0.0.2/chats/{id}",
+    params(("id" = i32, Path)),
+    responses(
+        (status = 204, description = "The chat was trashed"),
+        (status = 401, body = ErrorBody),
+        (status = 404, body = ErrorBody),
+    ),
+)]
+pub async fn delete_chat(
+    req: Request,
+    cx: Arc<Context>,
+    params: PathParams,
+) -> libserver::ServiceResult {
+    let (_session, chat) = session_chat(&cx, req.headers(), &params).await?;
+    let store = cx.store();
+    store.delete_chat(chat.id).await?;
+    let event = NewAuditEvent::new(
+        AuditKind::ChatDeleted,
+        Some(chat.user_id),
+        format!("chat {}", chat.id),
+    );
+    audit::record(
+        &*store,
+        event.client(&crate::client_info(&cx, req.headers())),
+    )
+    .await;
+    push_chat(
+        &cx,
+        &Chat {
+            deleted: true,
+            ..chat
+        },
+    )
+    .await;
+
+    empty_response(StatusCode::NO_CONTENT)
+}
+
+fn validate_name(name: String) -> Result<String, InvalidBody> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(InvalidBody);
+    }
+    Ok(name.to_owned())
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Chat Has No Messages To Title")]
+pub struct NothingToTitle;
+
+#[derive(Debug, thiserror::Error)]
+#[error("Chat Has No Reply Generating")]
+pub struct NothingToCancel;
+
+#[derive(Serialize, ToSchema)]
+struct ChatList {
+    chats: Vec<Chat>,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct CreateChatInput {
+    name: Option<String>,
+    /// The id of the persona the chat is answered in,
+    /// or the deployment's default if left out
+    persona: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct UpdateChatInput {
+    name: Option<String>,
+    /// Whether the model may call tools in the chat
+    tools_enabled: Option<bool>,
+    /// Whether the user's custom instructions are
+    /// merged into the chat's system prompt
+    instructions_enabled: Option<bool>,
+    /// How the chat's replies are filtered, or `null`
+    /// for the deployment's filters
+    #[serde(default, deserialize_with = "present")]
+    #[schema(value_type = Option<OutputFilters>)]
+    output_filters: Option<Option<OutputFilters>>,
+}
+
+/// Tells a field that's `null` from one that's missing,
+/// which `#[serde(default)]` leaves `None`
+fn present<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::deserialize(deserializer).map(Some)
+}
@@ -0,0 +1,115 @@
+use std::sync::Arc;
+
+use hyper::StatusCode;
+use libserver::Request;
+use rgpt_cfg::Context;
+use rgpt_db::{document::Document, store::NotFound};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{
+    api::router::{ErrorBody, InvalidBody, PathParams, empty_response, json_response},
+    attachments, retrieval,
+};
+
+/// Add a document to the session user's knowledge base
+///
+/// The body is the document's text and `name` its file name, which
+/// must be one that attachments accept. The document is split into
+/// passages, and those most relevant to each prompt are given to the
+/// model, which cites them in its reply.
+#[utoipa::path(
+    post,
+    path = "/api/v0.0.2/documents",
+    params(("name" = String, Query, description = "The document's file name")),
+    request_body(content = String, content_type = "text/plain"),
+    responses(
+        (status = 201, body = Document),
+        (status = 400, body = ErrorBody),
+        (status = 401, body = ErrorBody),
+        (status = 403, body = ErrorBody),
+        (status = 413, body = ErrorBody),
+    ),
+)]
+pub async fn upload_document(
+    req: Request,
+    cx: Arc<Context>,
+    _params: PathParams,
+) -> libserver::ServiceResult {
+    crate::check_body_size(&req, cx.config.max_attachment_size)?;
+    let Some(name) = crate::extract_query_param(req.uri(), "name") else {
+        return Err(InvalidBody.into());
+    };
+
+    let session = crate::validate_session_header(&cx, req.headers(), None).await?;
+    if crate::is_guest(session.user_id) {
+        Err(GuestDocument)?;
+    }
+
+    let bytes = crate::collect_body_bytes(req).await?;
+    let (name, text) = attachments::read_text_document(&name, bytes)?;
+    let document = retrieval::add_document(&cx, session.user_id, name, &text).await?;
+
+    json_response(StatusCode::CREATED, &document)
+}
+
+/// List the session user's documents
+#[utoipa::path(
+    get,
+    path = "/api/v0.0.2/documents",
+    responses(
+        (status = 200, body = DocumentList),
+        (status = 401, body = ErrorBody),
+    ),
+)]
+pub async fn list_documents(
+    req: Request,
+    cx: Arc<Context>,
+    _params: PathParams,
+) -> libserver::ServiceResult {
+    let store = cx.store();
+    let session = crate::validate_session_header(&cx, req.headers(), None).await?;
+    let documents = store.user_documents(session.user_id).await?;
+
+    json_response(StatusCode::OK, &DocumentList { documents })
+}
+
+/// Delete a document, which later replies no longer draw on
+///
+/// Replies that already cite it keep their citations.
+#[utoipa::path(
+    delete,
+    path = "/api/v0.0.2/documents/{id}",
+    params(("id" = i32, Path)),
+    responses(
+        (status = 204, description = "The document was deleted"),
+        (status = 401, body = ErrorBody),
+        (status = 404, body = ErrorBody),
+    ),
+)]
+pub async fn delete_document(
+    req: Request,
+    cx: Arc<Context>,
+    params: PathParams,
+) -> libserver::ServiceResult {
+    let store = cx.store();
+    let session = crate::validate_session_header(&cx, req.headers(), None).await?;
+    let document = store.document_by_id(params.parse("id")?).await?;
+
+    // Someone else's document is reported as missing, so ids can't be probed
+    if document.user_id != session.user_id {
+        Err(NotFound)?;
+    }
+    store.delete_document(document.id).await?;
+
+    empty_response(StatusCode::NO_CONTENT)
+}
+
+#[derive(Serialize, ToSchema)]
+struct DocumentList {
+    documents: Vec<Document>,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Guests Can't Upload Documents")]
+pub struct GuestDocument;
@@ -0,0 +1,100 @@
+use std::sync::Arc;
+
+use hyper::StatusCode;
+use libserver::Request;
+use rgpt_cfg::Context;
+use rgpt_db::{
+    instructions::{CustomInstructions, InstructionsUpdate},
+    session::Session,
+};
+
+use super::json_body;
+use crate::api::router::{ErrorBody, InvalidBody, PathParams, json_response};
+
+/// Read the session user's custom instructions
+///
+/// A user who has never set any gets them empty, with
+/// `updated_at` set to now.
+#[utoipa::path(
+    get,
+    path = "/api/v0.0.2/account/instructions",
+    responses(
+        (status = 200, body = CustomInstructions),
+        (status = 401, body = ErrorBody),
+        (status = 403, body = ErrorBody),
+    ),
+)]
+pub async fn get_instructions(
+    req: Request,
+    cx: Arc<Context>,
+    _params: PathParams,
+) -> libserver::ServiceResult {
+    let session = instructions_session(&cx, &req).await?;
+
+    let instructions = cx
+        .store()
+        .custom_instructions(session.user_id)
+        .await?
+        .unwrap_or_else(|| CustomInstructions {
+            user_id: session.user_id,
+            about_me: String::new(),
+            response_style: String::new(),
+            language: String::new(),
+            updated_at: chrono::Utc::now().naive_utc(),
+        });
+
+    json_response(StatusCode::OK, &instructions)
+}
+
+/// Replace the session user's custom instructions
+///
+/// They're merged into the system prompt of the user's chats,
+/// after the deployment's, which they can't override. Chats
+/// opt out with `instructions_enabled`. Fields left out are
+/// cleared; `about_me` and `response_style` are at most 1500
+/// characters, and `language` at most 50 on one line.
+#[utoipa::path(
+    put,
+    path = "/api/v0.0.2/account/instructions",
+    request_body = InstructionsUpdate,
+    responses(
+        (status = 200, body = CustomInstructions),
+        (status = 400, body = ErrorBody),
+        (status = 401, body = ErrorBody),
+        (status = 403, body = ErrorBody),
+    ),
+)]
+pub async fn set_instructions(
+    req: Request,
+    cx: Arc<Context>,
+    _params: PathParams,
+) -> libserver::ServiceResult {
+    let session = instructions_session(&cx, &req).await?;
+    let update: InstructionsUpdate = json_body(req, &cx).await?;
+    if !update.is_valid() {
+        Err(InvalidBody)?;
+    }
+
+    let instructions = cx
+        .store()
+        .set_custom_instructions(session.user_id, update)
+        .await?;
+
+    json_response(StatusCode::OK, &instructions)
+}
+
+/// The session of a user with an account of their own
+async fn instructions_session(
+    cx: &Context,
+    req: &Request,
+) -> Result<Session, libserver::ServiceError> {
+    let session = crate::validate_session_header(cx, req.headers(), None).await?;
+    if crate::is_guest(session.user_id) {
+        Err(GuestInstructions)?;
+    }
+    Ok(session)
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Guests Cannot Set Custom Instructions")]
+pub struct GuestInstructions;
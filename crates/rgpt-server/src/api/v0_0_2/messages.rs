@@ -0,0 +1,121 @@
+use std::sync::Arc;
+
+use hyper::StatusCode;
+use libserver::Request;
+use rgpt_cfg::Context;
+use rgpt_db::{attachment::Attachment, chat::Chat, gen_params::GenParams, msg::Msg};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::{json_body, session_chat};
+use crate::api::{
+    router::{ErrorBody, InvalidBody, PathParams, json_response},
+    v0_0_1::prompt::{StartedReply, start_reply},
+};
+
+/// The messages on a chat's active branch, oldest first
+#[utoipa::path(
+    get,
+    path = "/api/v0.0.2/chats/{id}/messages",
+    params(("id" = i32, Path)),
+    responses(
+        (status = 200, body = MessageList),
+        (status = 401, body = ErrorBody),
+        (status = 404, body = ErrorBody),
+    ),
+)]
+pub async fn list_messages(
+    req: Request,
+    cx: Arc<Context>,
+    params: PathParams,
+) -> libserver::ServiceResult {
+    let (_session, chat) = session_chat(&cx, req.headers(), &params).await?;
+    let store = cx.store();
+    let messages = store.chat_msgs(&chat).await?;
+    let msg_ids = messages.iter().map(|msg| msg.id).collect::<Vec<_>>();
+    let attachments = store.msg_attachments(&msg_ids).await?;
+
+    json_response(
+        StatusCode::OK,
+        &MessageList {
+            messages,
+            attachments,
+        },
+    )
+}
+
+/// Send a message
+///
+/// Answers `202 Accepted` as soon as the message is saved;
+/// the reply is streamed from `/api/v0.0.1/attach/{attach_token}`.
+#[utoipa::path(
+    post,
+    path = "/api/v0.0.2/chats/{id}/messages",
+    params(("id" = i32, Path)),
+    request_body = CreateMessageInput,
+    responses(
+        (status = 202, body = CreateMessageOutput),
+        (status = 400, body = ErrorBody),
+        (status = 401, body = ErrorBody),
+        (status = 404, body = ErrorBody),
+    ),
+)]
+pub async fn create_message(
+    req: Request,
+    cx: Arc<Context>,
+    params: PathParams,
+) -> libserver::ServiceResult {
+    let (_session, chat) = session_chat(&cx, req.headers(), &params).await?;
+    let CreateMessageInput {
+        text,
+        params,
+        attachments,
+    } = json_body(req, &cx).await?;
+
+    if text.trim().is_empty() {
+        Err(InvalidBody)?;
+    }
+
+    let StartedReply {
+        chat,
+        user_msg,
+        attach_token,
+        ..
+    } = start_reply(cx, chat, text, params, attachments).await?;
+
+    json_response(
+        StatusCode::ACCEPTED,
+        &CreateMessageOutput {
+            chat,
+            message: user_msg,
+            attach_token: attach_token.to_string(),
+        },
+    )
+}
+
+#[derive(Serialize, ToSchema)]
+struct MessageList {
+    messages: Vec<Msg>,
+    /// The attachments sent with the messages,
+    /// each naming its message in `msg_id`
+    attachments: Vec<Attachment>,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct CreateMessageInput {
+    text: String,
+    /// Sampling parameters for the reply, clamped
+    /// to the deployment's limits
+    #[serde(default)]
+    params: GenParams,
+    /// Ids of uploaded attachments to send with the message
+    #[serde(default)]
+    attachments: Vec<i32>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct CreateMessageOutput {
+    chat: Chat,
+    message: Msg,
+    attach_token: String,
+}
@@ -0,0 +1,122 @@
+//! Resource-style routes, mounted next to v0.0.1
+//!
+//! Unlike v0.0.1, reads are `GET`s, resources are named in the path
+//! and failures are reported with fitting status codes. Replies are
+//! still streamed through v0.0.1's `attach` socket, or pushed to the
+//! session socket along with the rest of the user's events.
+
+use std::sync::Arc;
+
+use hyper::{HeaderMap, Method};
+use libserver::{DynRoute, PathPrefixRouter, Request, Route};
+use rgpt_cfg::Context;
+use rgpt_db::{chat::Chat, session::Session, store::NotFound};
+use serde::de::DeserializeOwned;
+
+use super::router::{PathParams, ResourceService};
+
+pub mod account;
+pub mod attachments;
+pub mod audit;
+pub mod chats;
+pub mod documents;
+pub mod instructions;
+pub mod messages;
+pub mod personas;
+pub mod shares;
+pub mod socket;
+
+const PREFIX: &str = "/api/v0.0.2";
+
+pub fn route(cx: Arc<Context>) -> DynRoute {
+    let router = PathPrefixRouter::new(PREFIX);
+
+    let service = ResourceService::new(PREFIX, cx)
+        .route(Method::GET, "/chats", chats::list_chats)
+        .route(Method::POST, "/chats", chats::create_chat)
+        .route(Method::GET, "/chats/{id}", chats::get_chat)
+        .route(Method::PATCH, "/chats/{id}", chats::update_chat)
+        .route(Method::DELETE, "/chats/{id}", chats::delete_chat)
+        .route(Method::POST, "/chats/{id}/title", chats::regenerate_title)
+        .route(Method::POST, "/chats/{id}/cancel", chats::cancel_generation)
+        .route(Method::GET, "/chats/{id}/shares", shares::list_shares)
+        .route(Method::POST, "/chats/{id}/shares", shares::create_share)
+        .route(
+            Method::DELETE,
+            "/chats/{id}/shares/{share_id}",
+            shares::revoke_share,
+        )
+        .route(Method::GET, "/chats/{id}/messages", messages::list_messages)
+        .route(
+            Method::POST,
+            "/chats/{id}/messages",
+            messages::create_message,
+        )
+        .route(Method::GET, "/personas", personas::list_personas)
+        .route(Method::POST, "/attachments", attachments::upload_attachment)
+        .route(Method::GET, "/documents", documents::list_documents)
+        .route(Method::POST, "/documents", documents::upload_document)
+        .route(
+            Method::DELETE,
+            "/documents/{id}",
+            documents::delete_document,
+        )
+        .route(Method::GET, "/shares/{token}", shares::get_share)
+        .route(Method::POST, "/shares/{token}/fork", shares::fork_share)
+        .route(Method::GET, "/socket", socket::open_socket)
+        .route(Method::GET, "/account/activity", audit::account_activity)
+        .route(Method::GET, "/account/export", account::export_account)
+        .route(
+            Method::GET,
+            "/account/instructions",
+            instructions::get_instructions,
+        )
+        .route(
+            Method::PUT,
+            "/account/instructions",
+            instructions::set_instructions,
+        )
+        .route(Method::POST, "/account/deletion", account::request_deletion)
+        .route(
+            Method::DELETE,
+            "/account/deletion",
+            account::cancel_deletion,
+        )
+        .route(Method::GET, "/audit", audit::audit_log);
+
+    Route::from_parts(router, service).make_dyn()
+}
+
+/// Looks up the chat named by the `{id}` path parameter on
+/// behalf of the session's user
+///
+/// Chats that are deleted or belong to someone else are
+/// reported as not found rather than forbidden, so ids
+/// can't be probed. So is every chat asked for by a guest,
+/// since guests' chats all belong to the one guest user.
+async fn session_chat(
+    cx: &Context,
+    headers: &HeaderMap,
+    params: &PathParams,
+) -> Result<(Session, Chat), libserver::ServiceError> {
+    let store = cx.store();
+    let session = crate::validate_session_header(cx, headers, None).await?;
+    let chat = store.chat_by_id(params.parse("id")?).await?;
+
+    if crate::is_guest(session.user_id) || chat.user_id != session.user_id || chat.deleted {
+        Err(NotFound)?;
+    }
+
+    Ok((session, chat))
+}
+
+/// Reads a JSON request body, treating an empty body as `{}`
+async fn json_body<T: DeserializeOwned>(
+    req: Request,
+    cx: &Context,
+) -> Result<T, libserver::ServiceError> {
+    crate::check_body_size(&req, cx.config.max_req_size)?;
+    let body = crate::collect_body_string(req).await?;
+    let body = if body.trim().is_empty() { "{}" } else { &body };
+    Ok(serde_json::from_str(body)?)
+}
@@ -0,0 +1,93 @@
+use std::sync::Arc;
+
+use hyper::StatusCode;
+use libserver::Request;
+use rgpt_cfg::{Context, personas::Persona};
+use rgpt_db::{gen_params::GenParams, output_filters::OutputFilters};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::api::router::{ErrorBody, PathParams, json_response};
+
+/// List the personas chats can be answered in
+///
+/// Pass a persona's `id` as `persona` when creating a chat.
+/// Chats keep the persona they were created with. Personas'
+/// system prompts aren't listed.
+#[utoipa::path(
+    get,
+    path = "/api/v0.0.2/personas",
+    responses(
+        (status = 200, body = PersonaList),
+        (status = 401, body = ErrorBody),
+    ),
+)]
+pub async fn list_personas(
+    req: Request,
+    cx: Arc<Context>,
+    _params: PathParams,
+) -> libserver::ServiceResult {
+    crate::validate_session_header(&cx, req.headers(), None).await?;
+
+    let personas = &cx.config.personas;
+    json_response(
+        StatusCode::OK,
+        &PersonaList {
+            default: personas.default_persona().id.clone(),
+            personas: personas.iter().map(PersonaInfo::from).collect(),
+        },
+    )
+}
+
+/// The id of the persona a new chat is created with: the one
+/// asked for, or the deployment's default
+pub fn pick_persona(cx: &Context, persona: Option<String>) -> Result<String, UnknownPersona> {
+    let personas = &cx.config.personas;
+    match persona {
+        Some(id) if personas.get(&id).is_some() => Ok(id),
+        Some(_) => Err(UnknownPersona),
+        None => Ok(personas.default_persona().id.clone()),
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+struct PersonaList {
+    /// The persona chats get when they don't pick one
+    default: String,
+    personas: Vec<PersonaInfo>,
+}
+
+/// A persona as it's offered to users
+#[derive(Serialize, ToSchema)]
+struct PersonaInfo {
+    id: String,
+    name: String,
+    description: String,
+    /// The model tried first, or `null` to follow
+    /// the deployment's route
+    #[schema(required)]
+    model: Option<String>,
+    /// Sampling parameters for prompts that leave them unset
+    params: GenParams,
+    /// How replies are filtered in chats that haven't set
+    /// their own, or `null` for the deployment's
+    #[schema(required)]
+    output_filters: Option<OutputFilters>,
+}
+
+impl From<&Persona> for PersonaInfo {
+    fn from(persona: &Persona) -> Self {
+        PersonaInfo {
+            id: persona.id.clone(),
+            name: persona.name.clone(),
+            description: persona.description.clone(),
+            model: persona.model.clone(),
+            params: persona.params.clone(),
+            output_filters: persona.output_filters,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Unknown Persona")]
+pub struct UnknownPersona;
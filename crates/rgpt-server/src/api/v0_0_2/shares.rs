@@ -0,0 +1,322 @@
+use std::sync::Arc;
+
+use chrono::{Duration, NaiveDateTime, Utc};
+use hyper::{StatusCode, header::LOCATION};
+use libserver::Request;
+use rgpt_cfg::Context;
+use rgpt_db::{
+    audit::{self, AuditKind, NewAuditEvent},
+    chat::Chat,
+    document::Citations,
+    msg::{self, Generation, Msg, MsgStatus},
+    share::{NewShare, Share},
+    store::NotFound,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::{PREFIX, json_body, session_chat, socket::push_chat};
+use crate::{
+    api::router::{ErrorBody, InvalidBody, PathParams, empty_response, json_response},
+    tools::TOOL_SENDER,
+};
+
+/// The longest a share link can be made to last for
+const MAX_SHARE_HOURS: u32 = 24 * 365;
+
+/// Share a chat as it is now
+///
+/// Anyone with the returned token can read the chat's active branch
+/// as it was when it was shared, from `/shares/{token}`, until the
+/// link expires or is revoked. Later messages aren't shared.
+#[utoipa::path(
+    post,
+    path = "/api/v0.0.2/chats/{id}/shares",
+    params(("id" = i32, Path)),
+    request_body = CreateShareInput,
+    responses(
+        (status = 201, body = Share, headers(("Location" = String))),
+        (status = 400, body = ErrorBody),
+        (status = 401, body = ErrorBody),
+        (status = 404, body = ErrorBody),
+    ),
+)]
+pub async fn create_share(
+    req: Request,
+    cx: Arc<Context>,
+    params: PathParams,
+) -> libserver::ServiceResult {
+    let (_session, chat) = session_chat(&cx, req.headers(), &params).await?;
+    let client = crate::client_info(&cx, req.headers());
+    let CreateShareInput { expires_in_hours } = json_body(req, &cx).await?;
+
+    let Some(head_msg) = chat.head_msg else {
+        return Err(NothingToShare.into());
+    };
+    let expires_at = expires_in_hours.map(expiry).transpose()?;
+
+    let store = cx.store();
+    let share = store
+        .create_share(NewShare {
+            chat_id: chat.id,
+            user_id: chat.user_id,
+            head_msg,
+            name: chat.name,
+            expires_at,
+        })
+        .await?;
+    let event = NewAuditEvent::new(
+        AuditKind::ShareCreated,
+        Some(chat.user_id),
+        format!("share {} of chat {}", share.id, chat.id),
+    );
+    audit::record(&*store, event.client(&client)).await;
+
+    let mut response = json_response(StatusCode::CREATED, &share)?;
+    response.headers_mut().insert(
+        LOCATION,
+        format!("{PREFIX}/shares/{}", share.token).parse()?,
+    );
+    Ok(response)
+}
+
+/// List a chat's share links, expired ones included
+#[utoipa::path(
+    get,
+    path = "/api/v0.0.2/chats/{id}/shares",
+    params(("id" = i32, Path)),
+    responses(
+        (status = 200, body = ShareList),
+        (status = 401, body = ErrorBody),
+        (status = 404, body = ErrorBody),
+    ),
+)]
+pub async fn list_shares(
+    req: Request,
+    cx: Arc<Context>,
+    params: PathParams,
+) -> libserver::ServiceResult {
+    let (_session, chat) = session_chat(&cx, req.headers(), &params).await?;
+    let shares = cx.store().chat_shares(chat.id).await?;
+
+    json_response(StatusCode::OK, &ShareList { shares })
+}
+
+/// Revoke a share link
+#[utoipa::path(
+    delete,
+    path = "/api/v0.0.2/chats/{id}/shares/{share_id}",
+    params(("id" = i32, Path), ("share_id" = i32, Path)),
+    responses(
+        (status = 204, description = "The link was revoked"),
+        (status = 401, body = ErrorBody),
+        (status = 404, body = ErrorBody),
+    ),
+)]
+pub async fn revoke_share(
+    req: Request,
+    cx: Arc<Context>,
+    params: PathParams,
+) -> libserver::ServiceResult {
+    let (_session, chat) = session_chat(&cx, req.headers(), &params).await?;
+    let store = cx.store();
+    let share = store.share_by_id(params.parse("share_id")?).await?;
+
+    if share.chat_id != chat.id {
+        Err(NotFound)?;
+    }
+    store.revoke_share(share.id).await?;
+
+    empty_response(StatusCode::NO_CONTENT)
+}
+
+/// Read a shared chat
+///
+/// No session is needed. Links that have expired or been revoked,
+/// or whose chat is in the trash, are reported as not found.
+#[utoipa::path(
+    get,
+    path = "/api/v0.0.2/shares/{token}",
+    params(("token" = String, Path)),
+    responses(
+        (status = 200, body = SharedChat),
+        (status = 404, body = ErrorBody),
+    ),
+    security(()),
+)]
+pub async fn get_share(
+    _req: Request,
+    cx: Arc<Context>,
+    params: PathParams,
+) -> libserver::ServiceResult {
+    let (share, msgs) = live_share(&cx, &params).await?;
+
+    json_response(
+        StatusCode::OK,
+        &SharedChat {
+            name: share.name,
+            shared_at: share.created_at,
+            expires_at: share.expires_at,
+            messages: msgs.into_iter().map(SharedMsg::from).collect(),
+        },
+    )
+}
+
+/// Copy a shared chat into the session user's chats
+///
+/// The copy is a new chat the user can carry on, starting
+/// from the shared messages.
+#[utoipa::path(
+    post,
+    path = "/api/v0.0.2/shares/{token}/fork",
+    params(("token" = String, Path)),
+    responses(
+        (status = 201, body = Chat, headers(("Location" = String))),
+        (status = 401, body = ErrorBody),
+        (status = 403, body = ErrorBody),
+        (status = 404, body = ErrorBody),
+    ),
+)]
+pub async fn fork_share(
+    req: Request,
+    cx: Arc<Context>,
+    params: PathParams,
+) -> libserver::ServiceResult {
+    let store = cx.store();
+    let session = crate::validate_session_header(&cx, req.headers(), None).await?;
+    // Guests can't list their chats, so they'd never see the copy
+    if crate::is_guest(session.user_id) {
+        Err(GuestShare)?;
+    }
+    let (share, msgs) = live_share(&cx, &params).await?;
+
+    // The copy is answered in the persona the original was
+    let persona = store.chat_by_id(share.chat_id).await?.persona;
+    let mut chat = store
+        .create_chat(session.user_id, share.name, persona)
+        .await?;
+    let mut parent = None;
+    for msg in msgs {
+        let status = MsgStatus::parse(&msg.status).unwrap_or(MsgStatus::Complete);
+        let copy = store
+            .create_msg(
+                msg.body,
+                msg.sender,
+                session.user_id,
+                parent,
+                status,
+                Generation {
+                    model: msg.model,
+                    params: msg.gen_params,
+                    citations: msg.citations,
+                },
+            )
+            .await?;
+        parent = Some(copy.id);
+    }
+    if let Some(head) = parent {
+        chat = store.set_chat_head(chat.id, head).await?;
+    }
+    push_chat(&cx, &chat).await;
+
+    let mut response = json_response(StatusCode::CREATED, &chat)?;
+    response
+        .headers_mut()
+        .insert(LOCATION, format!("{PREFIX}/chats/{}", chat.id).parse()?);
+    Ok(response)
+}
+
+/// Looks up the share named by the `{token}` path parameter,
+/// with the user and AI messages it shares, oldest first
+///
+/// Tool messages are left out: their results can hold the
+/// owner's other chats, which weren't shared.
+async fn live_share(
+    cx: &Context,
+    params: &PathParams,
+) -> Result<(Share, Vec<Msg>), libserver::ServiceError> {
+    let store = cx.store();
+    let Some(token) = params.get("token") else {
+        return Err(NotFound.into());
+    };
+    let share = store.share_by_token(token).await?;
+    let chat = store.chat_by_id(share.chat_id).await?;
+    if share.is_expired() || chat.deleted {
+        Err(NotFound)?;
+    }
+
+    let msgs = store
+        .msg_chain(share.head_msg)
+        .await?
+        .into_iter()
+        .filter(|msg| msg.sender != TOOL_SENDER)
+        .collect();
+    Ok((share, msgs))
+}
+
+fn expiry(hours: u32) -> Result<NaiveDateTime, InvalidBody> {
+    if hours == 0 || hours > MAX_SHARE_HOURS {
+        return Err(InvalidBody);
+    }
+    Ok(Utc::now().naive_utc() + Duration::hours(hours.into()))
+}
+
+#[derive(Deserialize, ToSchema)]
+struct CreateShareInput {
+    /// How many hours the link works for, up to a
+    /// year. Without it the link works until revoked
+    expires_in_hours: Option<u32>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct ShareList {
+    shares: Vec<Share>,
+}
+
+/// A shared chat, as it was when it was shared
+#[derive(Serialize, ToSchema)]
+struct SharedChat {
+    #[schema(required)]
+    name: Option<String>,
+    shared_at: NaiveDateTime,
+    #[schema(required)]
+    expires_at: Option<NaiveDateTime>,
+    messages: Vec<SharedMsg>,
+}
+
+/// A message of a shared chat, without
+/// anything that identifies its owner
+#[derive(Serialize, ToSchema)]
+struct SharedMsg {
+    text: String,
+    #[schema(schema_with = msg::conversation_sender_schema)]
+    sender: String,
+    #[schema(schema_with = msg::status_schema)]
+    status: String,
+    created_at: NaiveDateTime,
+    #[schema(required)]
+    model: Option<String>,
+    #[schema(required)]
+    citations: Option<Citations>,
+}
+
+impl From<Msg> for SharedMsg {
+    fn from(msg: Msg) -> Self {
+        SharedMsg {
+            text: msg.body,
+            sender: msg.sender,
+            status: msg.status,
+            created_at: msg.created_at,
+            model: msg.model,
+            citations: msg.citations,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Chat Has No Messages To Share")]
+pub struct NothingToShare;
+
+#[derive(Debug, thiserror::Error)]
+#[error("Guests Can't Share Chats")]
+pub struct GuestShare;
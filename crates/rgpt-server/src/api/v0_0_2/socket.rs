@@ -0,0 +1,374 @@
+use std::{sync::Arc, time::Duration};
+
+use fastwebsockets::{
+    FragmentCollectorRead, Frame, OpCode, Payload,
+    upgrade::{UpgradeFut, upgrade},
+};
+use futures::{StreamExt, channel::mpsc};
+use hyper::StatusCode;
+use libserver::{Request, ServiceError, single_frame_body};
+use rgpt_cfg::Context;
+use rgpt_db::{chat::Chat, gen_params::GenParams, session::Session, store::NotFound};
+use rgpt_stream::UserEvent;
+use serde::{Deserialize, Serialize};
+
+use super::{chats::NothingToCancel, personas::pick_persona};
+use crate::api::{
+    router::{ErrorBody, PathParams, error_status},
+    v0_0_1::{
+        prompt::{StartedReply, regenerate_reply, start_reply},
+        retry::NothingToRetry,
+    },
+};
+
+/// How often the server pings a socket
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How long a socket can go without sending anything,
+/// pongs included, before it's closed
+const IDLE_TIMEOUT: Duration = Duration::from_secs(75);
+
+/// Open a socket for all of the session user's chats
+///
+/// The client sends commands as JSON text frames: `prompt`, with the
+/// fields `/prompt` takes; `regenerate`, which replaces the `chat_id`'s
+/// last reply with a new one; `cancel`, which stops the `chat_id`'s
+/// reply; and `ping`. A command may carry an `id`, which is echoed in
+/// the `accepted`, `rejected` or `pong` frame answering it.
+///
+/// The server pushes the events of every generation in the user's chats,
+/// as the `retrogpt.v1` protocol sends them with a `chat_id` added,
+/// starting with what generations already in progress have sent. It
+/// also pushes a `chat` event whenever one of their chats is created or
+/// changes. Sockets are pinged every 30 seconds, and closed once nothing
+/// has been heard from them for 75, or once their session is revoked or
+/// expires or their user is disabled.
+#[utoipa::path(
+    get,
+    path = "/api/v0.0.2/socket",
+    params(
+        ("token" = Option<String>, Query, description = "The session token, for clients that can't set headers"),
+    ),
+    responses(
+        (status = 101, description = "Switching to the WebSocket protocol"),
+        (status = 401, body = ErrorBody),
+        (status = 403, body = ErrorBody),
+    ),
+)]
+pub async fn open_socket(
+    req: Request,
+    cx: Arc<Context>,
+    _params: PathParams,
+) -> libserver::ServiceResult {
+    // Browsers can't set headers on WebSocket requests
+    let session_token = match crate::extract_query_param(req.uri(), "token") {
+        Some(token) => token,
+        None => match req.headers().get("X-Session-Token") {
+            Some(token) => token.to_str()?.to_owned(),
+            None => Err(crate::InvalidSessionTokenHeader)?,
+        },
+    };
+    let client = crate::client_info(&cx, req.headers());
+    let session = crate::validate_session_token(&cx, session_token, None, &client).await?;
+
+    if crate::is_guest(session.user_id) {
+        Err(GuestSocket)?;
+    }
+
+    let (resp, ws_fut) = upgrade(req)?;
+    tokio::spawn(run_socket(ws_fut, cx, session));
+
+    Ok(resp.map(|_| single_frame_body("")))
+}
+
+/// Serves a socket until it closes, goes quiet or
+/// its session ends
+async fn run_socket(
+    ws_fut: UpgradeFut,
+    cx: Arc<Context>,
+    session: Session,
+) -> Result<(), ServiceError> {
+    let Session {
+        session_token,
+        user_id,
+        ..
+    } = session;
+
+    let mut ws = ws_fut.await?;
+    ws.set_max_message_size(cx.config.max_req_size as usize);
+    let (read, mut write) = ws.split(tokio::io::split);
+    let mut read = FragmentCollectorRead::new(read);
+
+    // Frames are all written by one task, so pushed events,
+    // pings and answers to commands can't interleave
+    let (frames, mut outgoing) = mpsc::unbounded::<Frame<'static>>();
+    let writer = tokio::spawn(async move {
+        while let Some(frame) = outgoing.next().await {
+            let closing = frame.opcode == OpCode::Close;
+            if write.write_frame(frame).await.is_err() || closing {
+                break;
+            }
+        }
+    });
+
+    let mut events = cx.state.stream_registry.lock().await.subscribe(user_id);
+    let pusher = tokio::spawn({
+        let frames = frames.clone();
+        async move {
+            while let Some(event) = events.next().await {
+                if frames.unbounded_send(text_frame(event.to_json())).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+    let pinger = tokio::spawn({
+        let frames = frames.clone();
+        let cx = cx.clone();
+        let session_token = session_token.clone();
+        async move {
+            let mut interval = tokio::time::interval(PING_INTERVAL);
+            // The first tick is immediate
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                // Events stop within a ping of the session ending
+                if !session_stands(&cx, &session_token).await {
+                    let _ = frames.unbounded_send(session_ended());
+                    break;
+                }
+                let ping = Frame::new(true, OpCode::Ping, None, Payload::Owned(vec![]));
+                if frames.unbounded_send(ping).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+
+    // Pongs and close replies the protocol calls for
+    let mut obligated = |frame: Frame<'_>| {
+        let sent = frames
+            .unbounded_send(Frame::new(
+                frame.fin,
+                frame.opcode,
+                None,
+                Payload::Owned(frame.payload.to_vec()),
+            ))
+            .map_err(|_| std::io::Error::from(std::io::ErrorKind::BrokenPipe));
+        async move { sent }
+    };
+
+    loop {
+        let frame = match tokio::time::timeout(IDLE_TIMEOUT, read.read_frame(&mut obligated)).await
+        {
+            Ok(Ok(frame)) => frame,
+            Ok(Err(_)) => break,
+            Err(_) => {
+                let _ = frames.unbounded_send(Frame::close(1001, b"Idle"));
+                break;
+            }
+        };
+        match frame.opcode {
+            OpCode::Text => {
+                if !session_stands(&cx, &session_token).await {
+                    let _ = frames.unbounded_send(session_ended());
+                    break;
+                }
+                let answer = run_command(&cx, user_id, &frame.payload).await;
+                let answer = serde_json::to_string(&answer).expect("answers always serialize");
+                let _ = frames.unbounded_send(text_frame(answer));
+            }
+            OpCode::Close => break,
+            _ => {}
+        }
+    }
+
+    pusher.abort();
+    pinger.abort();
+    drop(frames);
+    let _ = writer.await;
+    Ok(())
+}
+
+fn text_frame(text: String) -> Frame<'static> {
+    Frame::text(Payload::Owned(text.into_bytes()))
+}
+
+fn session_ended() -> Frame<'static> {
+    Frame::close(1008, b"Session Ended")
+}
+
+/// Whether a socket's session still stands: it hasn't been
+/// revoked or expired, and its user is neither disabled
+/// nor deleted
+async fn session_stands(cx: &Context, session_token: &str) -> bool {
+    let store = cx.store();
+    let Ok(session) = store.session_by_token(session_token).await else {
+        return false;
+    };
+    if !session.validate() {
+        return false;
+    }
+    store
+        .user_by_id(session.user_id)
+        .await
+        .is_ok_and(|user| !user.disabled)
+}
+
+async fn run_command(cx: &Arc<Context>, user_id: i32, text: &[u8]) -> Answer {
+    let Command { id, action } = match serde_json::from_slice(text) {
+        Ok(command) => command,
+        Err(err) => {
+            return Answer::Rejected {
+                id: None,
+                error: err.to_string(),
+            };
+        }
+    };
+
+    let performed = match action {
+        Action::Ping => return Answer::Pong { id },
+        Action::Prompt {
+            chat_id,
+            text,
+            params,
+            attachments,
+            persona,
+        } => prompt(cx, user_id, chat_id, text, params, attachments, persona).await,
+        Action::Regenerate { chat_id } => regenerate(cx, user_id, chat_id).await,
+        Action::Cancel { chat_id } => cancel(cx, user_id, chat_id).await,
+    };
+
+    match performed {
+        Ok(chat_id) => Answer::Accepted { id, chat_id },
+        Err(err) if error_status(&err) == StatusCode::INTERNAL_SERVER_ERROR => {
+            eprintln!("internal error: {err}");
+            Answer::Rejected {
+                id,
+                error: "Internal Server Error".into(),
+            }
+        }
+        Err(err) => Answer::Rejected {
+            id,
+            error: err.to_string(),
+        },
+    }
+}
+
+async fn prompt(
+    cx: &Arc<Context>,
+    user_id: i32,
+    chat_id: Option<i32>,
+    text: String,
+    params: GenParams,
+    attachments: Vec<i32>,
+    persona: Option<String>,
+) -> Result<i32, ServiceError> {
+    let chat = match chat_id {
+        Some(chat_id) => user_chat(cx, user_id, chat_id).await?,
+        None => {
+            let persona = pick_persona(cx, persona)?;
+            cx.store().create_chat(user_id, None, persona).await?
+        }
+    };
+    let StartedReply { chat, .. } =
+        start_reply(cx.clone(), chat, text, params, attachments).await?;
+    Ok(chat.id)
+}
+
+async fn regenerate(cx: &Arc<Context>, user_id: i32, chat_id: i32) -> Result<i32, ServiceError> {
+    let chat = user_chat(cx, user_id, chat_id).await?;
+    let Some(head_msg) = chat.head_msg else {
+        return Err(NothingToRetry.into());
+    };
+    // Unlike `/retry`, replies that finished can be regenerated
+    let reply = cx.store().msg_by_id(head_msg).await?;
+    if reply.sender != "ai" {
+        Err(NothingToRetry)?;
+    }
+
+    regenerate_reply(cx.clone(), chat, reply).await?;
+    Ok(chat_id)
+}
+
+async fn cancel(cx: &Context, user_id: i32, chat_id: i32) -> Result<i32, ServiceError> {
+    let chat = user_chat(cx, user_id, chat_id).await?;
+    if !cx.state.stream_registry.lock().await.cancel_chat(chat.id) {
+        Err(NothingToCancel)?;
+    }
+    Ok(chat_id)
+}
+
+/// Looks up one of the user's chats, reporting other
+/// users' and deleted chats as not found
+async fn user_chat(cx: &Context, user_id: i32, chat_id: i32) -> Result<Chat, ServiceError> {
+    let chat = cx.store().chat_by_id(chat_id).await?;
+    if chat.user_id != user_id || chat.deleted {
+        Err(NotFound)?;
+    }
+    Ok(chat)
+}
+
+/// Pushes a chat that was created or changed to
+/// its user's sockets
+pub async fn push_chat(cx: &Context, chat: &Chat) {
+    cx.state
+        .stream_registry
+        .lock()
+        .await
+        .push(chat.user_id, UserEvent::Chat(chat.clone()));
+}
+
+#[derive(Deserialize)]
+struct Command {
+    id: Option<String>,
+    #[serde(flatten)]
+    action: Action,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Action {
+    Prompt {
+        chat_id: Option<i32>,
+        text: String,
+        #[serde(default)]
+        params: GenParams,
+        #[serde(default)]
+        attachments: Vec<i32>,
+        /// Picks the persona of a new chat
+        persona: Option<String>,
+    },
+    Regenerate {
+        chat_id: i32,
+    },
+    Cancel {
+        chat_id: i32,
+    },
+    Ping,
+}
+
+/// The server's answer to a command
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Answer {
+    /// The command was carried out. For prompts and regenerations
+    /// the reply's events follow, tagged with `chat_id`
+    Accepted {
+        id: Option<String>,
+        chat_id: i32,
+    },
+    /// The command failed, for the reason an HTTP
+    /// request for it would have given
+    Rejected {
+        id: Option<String>,
+        error: String,
+    },
+    Pong {
+        id: Option<String>,
+    },
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Guests Can't Open Sockets")]
+pub struct GuestSocket;
@@ -0,0 +1,411 @@
+use std::sync::Arc;
+
+use async_openai::types::{
+    ChatCompletionRequestAssistantMessageContent, ChatCompletionRequestAssistantMessageContentPart,
+    ChatCompletionRequestMessage, ChatCompletionRequestSystemMessageArgs,
+    ChatCompletionRequestUserMessageContent, ChatCompletionRequestUserMessageContentPart,
+    CreateChatCompletionRequest,
+};
+use futures::{StreamExt, channel::mpsc::UnboundedSender};
+use http::header::{CACHE_CONTROL, CONTENT_TYPE};
+use hyper::{
+    Response, StatusCode,
+    body::{Bytes, Frame},
+};
+use libserver::{BodyInner, Request, ServiceError, make_body_from_stream};
+use rgpt_cfg::Context;
+use rgpt_db::{
+    chat::Chat,
+    moderation::FlagSource,
+    msg::{Generation, MsgStatus},
+};
+
+use super::{
+    OpenAiError, OpenAiErrorBody, ToolsUnsupported, UnknownModel, authenticate, models,
+    openai_error_response,
+};
+use crate::{
+    api::{
+        router::{InvalidBody, PathParams, json_response},
+        v0_0_1::prompt::ReplyFailed,
+        v0_0_2::socket::push_chat,
+    },
+    chat_title::title_from_text,
+    moderation::{self, FlagTarget},
+};
+
+/// Set on responses to requests with `"store": true`
+const CHAT_ID_HEADER: &str = "X-RetroGPT-Chat-Id";
+
+/// The model in the route that served the request
+const MODEL_HEADER: &str = "X-RetroGPT-Model";
+
+/// Create a chat completion
+///
+/// Takes OpenAI's chat completion request and returns its response,
+/// or a stream of `text/event-stream` chunks ending in `data: [DONE]`
+/// when `stream` is set. The configured system message is prepended
+/// and `max_completion_tokens` is capped at the configured limit.
+/// One choice is generated whatever `n` is, log probabilities, logit
+/// biases, audio and service tiers are left out, and requests with
+/// `tools` or `functions` are rejected.
+/// The last user message is checked against the moderation policy
+/// first; replies aren't moderated.
+///
+/// With `"store": true` the exchange is saved as a chat, whose id
+/// is returned in the `X-RetroGPT-Chat-Id` header. The model that
+/// served the request is returned in `X-RetroGPT-Model`.
+#[utoipa::path(
+    post,
+    path = "/v1/chat/completions",
+    request_body(content = Object, description = "An OpenAI chat completion request"),
+    responses(
+        (status = 200, description = "An OpenAI chat completion, or a stream of chunks", body = Object,
+            headers(
+                ("X-RetroGPT-Chat-Id" = i32, description = "The stored chat, if `store` was set"),
+                ("X-RetroGPT-Model" = String, description = "The model that served the request"),
+            )),
+        (status = 400, body = OpenAiErrorBody),
+        (status = 401, body = OpenAiErrorBody),
+        (status = 404, body = OpenAiErrorBody),
+        (status = 502, body = OpenAiErrorBody),
+    ),
+    security(("api_key" = [])),
+)]
+pub async fn create_chat_completion(
+    req: Request,
+    cx: Arc<Context>,
+    _params: PathParams,
+) -> libserver::ServiceResult {
+    chat_completion(req, cx)
+        .await
+        .or_else(|err| openai_error_response(&err))
+}
+
+async fn chat_completion(req: Request, cx: Arc<Context>) -> libserver::ServiceResult {
+    crate::check_body_size(&req, cx.config.max_req_size)?;
+    let user = authenticate(&cx, req.headers()).await?;
+
+    let body = crate::collect_body_string(req).await?;
+    let mut request: CreateChatCompletionRequest =
+        serde_json::from_str(&body).map_err(|_| InvalidBody)?;
+
+    if !models::is_served_model(&cx, &request.model) {
+        Err(UnknownModel)?;
+    }
+
+    let prompt = exchange_texts(&request.messages)
+        .into_iter()
+        .rev()
+        .find(|(sender, _)| *sender == "user");
+    if let Some((_, text)) = prompt {
+        let target = FlagTarget {
+            user_id: user.user_id,
+            chat_id: None,
+            msg_id: None,
+        };
+        let verdict = moderation::check_prompt(&cx, target, &text).await?;
+        moderation::keep(&cx, target, FlagSource::Prompt, verdict, text).await;
+    }
+
+    let persist = request.store.take().unwrap_or(false);
+    let exchange = persist.then(|| exchange_texts(&request.messages));
+    let requested_model = std::mem::take(&mut request.model);
+    apply_policy(&cx, &mut request)?;
+
+    if request.stream.unwrap_or(false) {
+        return stream_completion(cx, request, requested_model, user.user_id, exchange).await;
+    }
+
+    let (model, mut response) = cx.state.model_router.create(request).await?;
+    response.model = requested_model;
+
+    let chat = match exchange {
+        Some(exchange) => {
+            let reply = Reply {
+                text: response
+                    .choices
+                    .first()
+                    .and_then(|choice| choice.message.content.clone())
+                    .unwrap_or_default(),
+                status: MsgStatus::Complete,
+                model: model.clone(),
+            };
+            Some(persist_exchange(&cx, user.user_id, exchange, reply).await?)
+        }
+        None => None,
+    };
+
+    let mut response = json_response(StatusCode::OK, &response)?;
+    response.headers_mut().insert(MODEL_HEADER, model.parse()?);
+    if let Some(chat) = chat {
+        response
+            .headers_mut()
+            .insert(CHAT_ID_HEADER, chat.id.to_string().parse()?);
+    }
+    Ok(response)
+}
+
+/// Prepends the default persona's system prompt, caps the
+/// number of tokens the model may generate, and leaves out
+/// what the deployment doesn't offer
+fn apply_policy(
+    cx: &Context,
+    request: &mut CreateChatCompletionRequest,
+) -> Result<(), ServiceError> {
+    // The provider would run the client's tools, not the deployment's
+    #[allow(deprecated)]
+    let wants_tools = request.tools.is_some()
+        || request.tool_choice.is_some()
+        || request.functions.is_some()
+        || request.function_call.is_some();
+    if wants_tools {
+        Err(ToolsUnsupported)?;
+    }
+
+    let system = ChatCompletionRequestSystemMessageArgs::default()
+        .content(cx.config.personas.default_persona().system_prompt.clone())
+        .build()?;
+    request.messages.insert(0, system.into());
+
+    #[allow(deprecated)]
+    let requested = request.max_completion_tokens.or(request.max_tokens.take());
+    request.max_completion_tokens = Some(requested.map_or(cx.config.max_tokens, |tokens| {
+        tokens.min(cx.config.max_tokens)
+    }));
+
+    // Every choice costs as much as the first, past the token cap
+    request.n = None;
+    request.parallel_tool_calls = None;
+    request.logprobs = None;
+    request.top_logprobs = None;
+    request.logit_bias = None;
+    request.audio = None;
+    request.modalities = None;
+    request.service_tier = None;
+
+    Ok(())
+}
+
+async fn stream_completion(
+    cx: Arc<Context>,
+    request: CreateChatCompletionRequest,
+    requested_model: String,
+    user_id: i32,
+    exchange: Option<Vec<(&'static str, String)>>,
+) -> libserver::ServiceResult {
+    let (model, stream) = cx.state.model_router.create_stream(request).await?;
+    let (tx, rx) = futures::channel::mpsc::unbounded::<Bytes>();
+
+    let chat = match &exchange {
+        Some(_) => {
+            let persona = cx.config.personas.default_persona().id.clone();
+            Some(cx.store().create_chat(user_id, None, persona).await?)
+        }
+        None => None,
+    };
+
+    let chat_id = chat.as_ref().map(|chat| chat.id);
+    let model_header = model.parse()?;
+    tokio::spawn(async move {
+        let (text, status) = forward_stream(stream, requested_model, &tx).await;
+
+        if let (Some(chat), Some(exchange)) = (chat, exchange) {
+            let reply = Reply {
+                text,
+                status,
+                model,
+            };
+            if let Err(err) = store_exchange(&cx, &chat, exchange, reply).await {
+                eprintln!("failed to store chat completion: {err}");
+            }
+        }
+
+        let _ = tx.unbounded_send(Bytes::from_static(b"data: [DONE]\n\n"));
+    });
+
+    let body = make_body_from_stream(rx.map(|bytes| -> BodyInner { Ok(Frame::data(bytes)) }));
+    let mut response = Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "text/event-stream")
+        .header(CACHE_CONTROL, "no-cache")
+        .body(body)?;
+    response.headers_mut().insert(MODEL_HEADER, model_header);
+    if let Some(chat_id) = chat_id {
+        response
+            .headers_mut()
+            .insert(CHAT_ID_HEADER, chat_id.to_string().parse()?);
+    }
+    Ok(response)
+}
+
+/// Relays upstream chunks as server-sent events, returning
+/// the text of the first choice and how the reply ended
+///
+/// A client that has gone away must not stop the generation,
+/// so send failures are ignored.
+async fn forward_stream(
+    mut stream: async_openai::types::ChatCompletionResponseStream,
+    requested_model: String,
+    tx: &UnboundedSender<Bytes>,
+) -> (String, MsgStatus) {
+    let mut reply = String::new();
+    let mut finished = false;
+
+    loop {
+        match stream.next().await {
+            Some(Ok(mut chunk)) => {
+                chunk.model.clone_from(&requested_model);
+                for choice in chunk.choices.iter().filter(|choice| choice.index == 0) {
+                    if let Some(content) = &choice.delta.content {
+                        reply.push_str(content);
+                    }
+                    finished |= choice.finish_reason.is_some();
+                }
+                if let Ok(json) = serde_json::to_string(&chunk) {
+                    let _ = tx.unbounded_send(sse_event(&json));
+                }
+            }
+            // Like internal errors, provider errors aren't for clients
+            Some(Err(err)) => {
+                eprintln!("streamed completion failed: {err}");
+                let error = OpenAiErrorBody {
+                    error: OpenAiError {
+                        message: ReplyFailed.to_string(),
+                        kind: "api_error".into(),
+                        param: None,
+                        code: None,
+                    },
+                };
+                if let Ok(json) = serde_json::to_string(&error) {
+                    let _ = tx.unbounded_send(sse_event(&json));
+                }
+                return (reply, MsgStatus::Error);
+            }
+            None if finished => return (reply, MsgStatus::Complete),
+            None => return (reply, MsgStatus::Interrupted),
+        }
+    }
+}
+
+fn sse_event(data: &str) -> Bytes {
+    format!("data: {data}\n\n").into()
+}
+
+/// The texts of the request's user and assistant messages, in the
+/// senders RetroGPT stores them under
+///
+/// Other roles and non-text content aren't part of a RetroGPT chat.
+fn exchange_texts(messages: &[ChatCompletionRequestMessage]) -> Vec<(&'static str, String)> {
+    messages
+        .iter()
+        .filter_map(|message| match message {
+            ChatCompletionRequestMessage::User(message) => {
+                let text = match &message.content {
+                    ChatCompletionRequestUserMessageContent::Text(text) => text.clone(),
+                    ChatCompletionRequestUserMessageContent::Array(parts) => parts
+                        .iter()
+                        .filter_map(|part| match part {
+                            ChatCompletionRequestUserMessageContentPart::Text(part) => {
+                                Some(part.text.as_str())
+                            }
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                };
+                Some(("user", text))
+            }
+            ChatCompletionRequestMessage::Assistant(message) => {
+                let text = match message.content.as_ref()? {
+                    ChatCompletionRequestAssistantMessageContent::Text(text) => text.clone(),
+                    ChatCompletionRequestAssistantMessageContent::Array(parts) => parts
+                        .iter()
+                        .filter_map(|part| match part {
+                            ChatCompletionRequestAssistantMessageContentPart::Text(part) => {
+                                Some(part.text.as_str())
+                            }
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                };
+                Some(("ai", text))
+            }
+            _ => None,
+        })
+        .filter(|(_, text)| !text.is_empty())
+        .collect()
+}
+
+async fn persist_exchange(
+    cx: &Context,
+    user_id: i32,
+    exchange: Vec<(&'static str, String)>,
+    reply: Reply,
+) -> Result<Chat, ServiceError> {
+    let persona = cx.config.personas.default_persona().id.clone();
+    let chat = cx.store().create_chat(user_id, None, persona).await?;
+    store_exchange(cx, &chat, exchange, reply).await
+}
+
+/// The model's reply to a stored exchange
+struct Reply {
+    text: String,
+    status: MsgStatus,
+    model: String,
+}
+
+/// Saves the request's messages and the reply as the chat's
+/// messages, naming the chat after the first user message
+async fn store_exchange(
+    cx: &Context,
+    chat: &Chat,
+    exchange: Vec<(&'static str, String)>,
+    reply: Reply,
+) -> Result<Chat, ServiceError> {
+    let store = cx.store();
+
+    if let Some(name) = exchange
+        .iter()
+        .find(|(sender, _)| *sender == "user")
+        .and_then(|(_, text)| title_from_text(text))
+    {
+        store.set_chat_name(chat.id, name).await?;
+    }
+
+    let mut parent = None;
+    let msgs = exchange
+        .into_iter()
+        .map(|(sender, text)| (sender, text, MsgStatus::Complete, Generation::default()))
+        .chain([(
+            "ai",
+            reply.text,
+            reply.status,
+            Generation {
+                model: Some(reply.model),
+                params: None,
+                citations: None,
+            },
+        )]);
+    for (sender, text, status, generation) in msgs {
+        let msg = store
+            .create_msg(
+                text,
+                sender.into(),
+                chat.user_id,
+                parent,
+                status,
+                generation,
+            )
+            .await?;
+        parent = Some(msg.id);
+    }
+
+    let chat = match parent {
+        Some(head) => store.set_chat_head(chat.id, head).await?,
+        None => store.chat_by_id(chat.id).await?,
+    };
+    push_chat(cx, &chat).await;
+    Ok(chat)
+}
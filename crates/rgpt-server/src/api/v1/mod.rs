@@ -0,0 +1,178 @@
+//! An OpenAI-compatible API, so existing OpenAI clients and SDKs
+//! can be pointed at RetroGPT
+//!
+//! Requests are authenticated with an API key (or a session token)
+//! sent as `Authorization: Bearer ...`, and are sent upstream over
+//! RetroGPT's own model route, with its system message and token
+//! limit. Errors use OpenAI's `{"error": {"message", "type", "param",
+//! "code"}}` shape.
+
+use std::sync::Arc;
+
+use http::header::AUTHORIZATION;
+use hyper::{HeaderMap, Method, StatusCode};
+use libserver::{DynRoute, PathPrefixRouter, Route, ServiceError, ServiceResponse};
+use rgpt_cfg::Context;
+use rgpt_db::{api_key::API_KEY_PREFIX, user::User};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use super::router::{ResourceService, error_status, json_response};
+use crate::moderation::PromptBlocked;
+
+pub mod chat_completions;
+pub mod models;
+
+const PREFIX: &str = "/v1";
+
+pub fn route(cx: Arc<Context>) -> DynRoute {
+    let router = PathPrefixRouter::new(PREFIX);
+
+    let service = ResourceService::new(PREFIX, cx)
+        .route(
+            Method::POST,
+            "/chat/completions",
+            chat_completions::create_chat_completion,
+        )
+        .route(Method::GET, "/models", models::list_models)
+        .route(Method::GET, "/models/{model}", models::get_model);
+
+    Route::from_parts(router, service).make_dyn()
+}
+
+/// Resolves the user behind a request's credentials
+///
+/// `Authorization: Bearer` carries either an API key, recognized
+/// by its `rgpt-` prefix, or a session token; `X-Session-Token` is
+/// accepted too. The shared guest user can't use this API.
+async fn authenticate(cx: &Context, headers: &HeaderMap) -> Result<User, ServiceError> {
+    let store = cx.store();
+
+    let bearer = match headers.get(AUTHORIZATION) {
+        Some(value) => Some(
+            value
+                .to_str()?
+                .strip_prefix("Bearer ")
+                .ok_or(InvalidApiKey)?
+                .trim()
+                .to_owned(),
+        ),
+        None => None,
+    };
+
+    let user_id = match bearer {
+        Some(secret) if secret.starts_with(API_KEY_PREFIX) => {
+            store
+                .api_key_by_secret(&secret)
+                .await
+                .map_err(|_| InvalidApiKey)?
+                .user_id
+        }
+        Some(token) if token != "__default__" => {
+            crate::validate_session_token(cx, token, None, &crate::client_info(cx, headers))
+                .await
+                .map_err(|_| InvalidApiKey)?
+                .user_id
+        }
+        Some(_) => Err(InvalidApiKey)?,
+        None => {
+            crate::validate_session_header(cx, headers, None)
+                .await
+                .map_err(|_| InvalidApiKey)?
+                .user_id
+        }
+    };
+
+    if crate::is_guest(user_id) {
+        Err(InvalidApiKey)?;
+    }
+
+    let user = store.user_by_id(user_id).await?;
+    if user.disabled {
+        Err(crate::UserDisabled)?;
+    }
+
+    Ok(user)
+}
+
+/// Reports a handler error in OpenAI's error format
+fn openai_error_response(err: &ServiceError) -> Result<ServiceResponse, ServiceError> {
+    let (status, kind, code) = if err.is::<UnknownModel>() {
+        (
+            StatusCode::NOT_FOUND,
+            "invalid_request_error",
+            Some("model_not_found"),
+        )
+    } else if err.is::<InvalidApiKey>() {
+        (
+            StatusCode::UNAUTHORIZED,
+            "invalid_request_error",
+            Some("invalid_api_key"),
+        )
+    } else if err.is::<ToolsUnsupported>() {
+        (
+            StatusCode::BAD_REQUEST,
+            "invalid_request_error",
+            Some("unsupported_parameter"),
+        )
+    } else if err.is::<PromptBlocked>() {
+        (
+            StatusCode::BAD_REQUEST,
+            "invalid_request_error",
+            Some("content_policy_violation"),
+        )
+    } else {
+        match error_status(err) {
+            status if status.is_server_error() => (status, "api_error", None),
+            status => (status, "invalid_request_error", None),
+        }
+    };
+
+    let message = if status == StatusCode::INTERNAL_SERVER_ERROR {
+        eprintln!("internal error: {err}");
+        "Internal Server Error".into()
+    } else {
+        err.to_string()
+    };
+
+    json_response(
+        status,
+        &OpenAiErrorBody {
+            error: OpenAiError {
+                message,
+                kind: kind.into(),
+                param: None,
+                code: code.map(Into::into),
+            },
+        },
+    )
+}
+
+/// The body of every `/v1` error response
+#[derive(Serialize, ToSchema)]
+pub struct OpenAiErrorBody {
+    pub error: OpenAiError,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct OpenAiError {
+    pub message: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[schema(required)]
+    pub param: Option<String>,
+    #[schema(required)]
+    pub code: Option<String>,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid API Key")]
+pub struct InvalidApiKey;
+
+#[derive(Debug, thiserror::Error)]
+#[error("Model Not Found")]
+pub struct UnknownModel;
+
+#[derive(Debug, thiserror::Error)]
+#[error("Tools Are Not Supported")]
+pub struct ToolsUnsupported;
@@ -0,0 +1,101 @@
+use std::sync::Arc;
+
+use hyper::StatusCode;
+use libserver::Request;
+use rgpt_cfg::Context;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use super::{OpenAiErrorBody, UnknownModel, authenticate, openai_error_response};
+use crate::api::router::{PathParams, json_response};
+
+/// List the models that can be requested
+///
+/// RetroGPT serves a single model.
+#[utoipa::path(
+    get,
+    path = "/v1/models",
+    responses(
+        (status = 200, body = ModelList),
+        (status = 401, body = OpenAiErrorBody),
+    ),
+    security(("api_key" = [])),
+)]
+pub async fn list_models(
+    req: Request,
+    cx: Arc<Context>,
+    _params: PathParams,
+) -> libserver::ServiceResult {
+    async {
+        authenticate(&cx, req.headers()).await?;
+
+        json_response(
+            StatusCode::OK,
+            &ModelList {
+                object: "list".into(),
+                data: vec![model(&cx)],
+            },
+        )
+    }
+    .await
+    .or_else(|err| openai_error_response(&err))
+}
+
+/// Get a model
+#[utoipa::path(
+    get,
+    path = "/v1/models/{model}",
+    params(("model" = String, Path)),
+    responses(
+        (status = 200, body = Model),
+        (status = 401, body = OpenAiErrorBody),
+        (status = 404, body = OpenAiErrorBody),
+    ),
+    security(("api_key" = [])),
+)]
+pub async fn get_model(
+    req: Request,
+    cx: Arc<Context>,
+    params: PathParams,
+) -> libserver::ServiceResult {
+    async {
+        authenticate(&cx, req.headers()).await?;
+
+        if !is_served_model(&cx, params.get("model").unwrap_or_default()) {
+            Err(UnknownModel)?;
+        }
+
+        json_response(StatusCode::OK, &model(&cx))
+    }
+    .await
+    .or_else(|err| openai_error_response(&err))
+}
+
+/// Whether `model` names the model the API advertises, or
+/// the primary model of the route it is served by
+pub(super) fn is_served_model(cx: &Context, model: &str) -> bool {
+    model == cx.config.api_model_id || model == cx.config.primary_model()
+}
+
+fn model(cx: &Context) -> Model {
+    Model {
+        id: cx.config.api_model_id.clone(),
+        object: "model".into(),
+        created: 0,
+        owned_by: "retrogpt".into(),
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ModelList {
+    pub object: String,
+    pub data: Vec<Model>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct Model {
+    pub id: String,
+    pub object: String,
+    pub created: u32,
+    pub owned_by: String,
+}
@@ -0,0 +1,250 @@
+//! Text documents sent along with prompts
+//!
+//! Attachments are uploaded on their own, then linked to the user
+//! message they are sent with. When a completion request is built,
+//! their text is appended to that message's, newest messages first,
+//! until the deployment's token budget runs out.
+
+use std::{collections::HashMap, path::Path};
+
+use libserver::ServiceError;
+use rgpt_cfg::{AttachmentStorage, Context};
+use rgpt_db::{
+    attachment::{Attachment, NewAttachment},
+    moderation::FlagSource,
+    msg::Msg,
+};
+
+use crate::moderation::{self, FlagTarget};
+
+/// The file extensions accepted, all of which hold plain text
+const TEXT_EXTENSIONS: &[&str] = &[
+    "txt", "md", "markdown", "csv", "tsv", "json", "jsonl", "yaml", "yml", "toml", "xml", "html",
+    "css", "sql", "log", "rs", "py", "js", "jsx", "ts", "tsx", "go", "java", "kt", "swift", "c",
+    "h", "cpp", "hpp", "cs", "rb", "php", "sh", "lua",
+];
+
+/// The most attachments one message can be sent with
+pub const MAX_ATTACHMENTS_PER_MSG: usize = 8;
+
+/// A rough rule of thumb for English text and code,
+/// which is close enough for budgeting
+const CHARS_PER_TOKEN: usize = 4;
+
+/// Saves an uploaded document for `user_id`
+///
+/// Only UTF-8 text with one of the accepted extensions is
+/// taken. It's sent to the model along with a prompt, so it's
+/// moderated as one, then kept where `Config::attachment_storage`
+/// says.
+pub async fn save_attachment(
+    cx: &Context,
+    user_id: i32,
+    name: &str,
+    bytes: Vec<u8>,
+) -> Result<Attachment, ServiceError> {
+    let (name, text) = read_text_document(name, bytes)?;
+    let media_type = media_type(&name).ok_or(UnsupportedAttachment)?;
+    let size_bytes = i32::try_from(text.len())?;
+
+    let target = FlagTarget {
+        user_id,
+        chat_id: None,
+        msg_id: None,
+    };
+    let verdict = moderation::check_prompt(cx, target, &text).await?;
+    moderation::keep(cx, target, FlagSource::Prompt, verdict, text.clone()).await;
+
+    let (content, storage_key) = match &cx.config.attachment_storage {
+        AttachmentStorage::Database => (Some(text), None),
+        AttachmentStorage::Directory(dir) => {
+            let key = uuid::Uuid::new_v4().simple().to_string();
+            tokio::fs::create_dir_all(dir).await?;
+            tokio::fs::write(dir.join(&key), text).await?;
+            (None, Some(key))
+        }
+    };
+
+    cx.store()
+        .create_attachment(NewAttachment {
+            user_id,
+            name,
+            media_type: media_type.into(),
+            size_bytes,
+            content,
+            storage_key,
+        })
+        .await
+}
+
+/// Reads an uploaded document, returning its file name and text
+///
+/// Only UTF-8 text with one of the accepted extensions is taken,
+/// and only the file name is kept, whatever path the client sent.
+pub fn read_text_document(name: &str, bytes: Vec<u8>) -> Result<(String, String), ServiceError> {
+    let name = Path::new(name.trim())
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or(UnsupportedAttachment)?
+        .to_owned();
+    if media_type(&name).is_none() {
+        Err(UnsupportedAttachment)?;
+    }
+    let text = String::from_utf8(bytes).map_err(|_| UnsupportedAttachment)?;
+    if text.contains('\0') {
+        Err(UnsupportedAttachment)?;
+    }
+    Ok((name, text))
+}
+
+/// The media type of an accepted file name, or
+/// `None` if its extension isn't accepted
+pub fn media_type(name: &str) -> Option<&'static str> {
+    let extension = Path::new(name).extension()?.to_str()?.to_ascii_lowercase();
+    if !TEXT_EXTENSIONS.contains(&extension.as_str()) {
+        return None;
+    }
+    Some(match extension.as_str() {
+        "md" | "markdown" => "text/markdown",
+        "csv" => "text/csv",
+        "json" => "application/json",
+        "html" => "text/html",
+        _ => "text/plain",
+    })
+}
+
+/// Checks that `user_id` can send the attachments with a
+/// new message: they must be theirs and not sent yet
+pub async fn check_unsent(
+    cx: &Context,
+    user_id: i32,
+    attachment_ids: &[i32],
+) -> Result<(), ServiceError> {
+    if attachment_ids.len() > MAX_ATTACHMENTS_PER_MSG {
+        Err(InvalidAttachment)?;
+    }
+    let store = cx.store();
+    for &id in attachment_ids {
+        // Someone else's attachment is reported like a missing
+        // one, so ids can't be probed
+        let attachment = store.attachment_by_id(id).await.ok();
+        if !attachment.is_some_and(|a| a.user_id == user_id && a.msg_id.is_none()) {
+            Err(InvalidAttachment)?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads an attachment's text from wherever it is kept
+pub async fn attachment_text(
+    cx: &Context,
+    attachment: &Attachment,
+) -> Result<String, ServiceError> {
+    match (&attachment.content, &attachment.storage_key) {
+        (Some(content), _) => Ok(content.clone()),
+        (None, Some(key)) => match &cx.config.attachment_storage {
+            AttachmentStorage::Directory(dir) => {
+                Ok(tokio::fs::read_to_string(dir.join(key)).await?)
+            }
+            AttachmentStorage::Database => Err(AttachmentUnavailable.into()),
+        },
+        (None, None) => Err(AttachmentUnavailable.into()),
+    }
+}
+
+/// Removes the files the attachments' text was kept in, once
+/// their rows are deleted
+///
+/// Text kept in the database went with the rows. A file that
+/// can't be removed is logged and left behind.
+pub async fn remove_files(storage: &AttachmentStorage, attachments: &[Attachment]) {
+    let AttachmentStorage::Directory(dir) = storage else {
+        return;
+    };
+    for key in attachments.iter().filter_map(|a| a.storage_key.as_ref()) {
+        if let Err(err) = tokio::fs::remove_file(dir.join(key)).await {
+            eprintln!("failed to remove attachment file {key}: {err}");
+        }
+    }
+}
+
+/// The attachment text to append to each of `msgs`, by
+/// message id, within `Config::attachment_token_budget`
+pub async fn context_text(
+    cx: &Context,
+    msgs: &[Msg],
+) -> Result<HashMap<i32, String>, ServiceError> {
+    let msg_ids = msgs.iter().map(|msg| msg.id).collect::<Vec<_>>();
+    let attachments = cx.store().msg_attachments(&msg_ids).await?;
+
+    let mut texts = vec![];
+    for attachment in attachments {
+        let Some(msg_id) = attachment.msg_id else {
+            continue;
+        };
+        // A lost file shouldn't make the rest of the chat unusable
+        let text = attachment_text(cx, &attachment)
+            .await
+            .unwrap_or_else(|err| {
+                eprintln!("failed to read attachment {}: {err}", attachment.id);
+                String::new()
+            });
+        texts.push(AttachmentText {
+            msg_id,
+            name: attachment.name,
+            text,
+        });
+    }
+
+    Ok(fit_to_budget(texts, cx.config.attachment_token_budget))
+}
+
+/// An attachment's text, ready to be added to its message
+pub struct AttachmentText {
+    pub msg_id: i32,
+    pub name: String,
+    pub text: String,
+}
+
+/// Renders `attachments`, given in upload order, as the text to
+/// append to each message
+///
+/// The newest attachments are more likely to matter, so they are
+/// given the budget first. Once it runs out, an attachment is cut
+/// short and the ones before it are only named.
+pub fn fit_to_budget(attachments: Vec<AttachmentText>, budget_tokens: u32) -> HashMap<i32, String> {
+    let mut budget = budget_tokens as usize * CHARS_PER_TOKEN;
+    let mut appended = HashMap::<i32, String>::new();
+
+    for AttachmentText { msg_id, name, text } in attachments.into_iter().rev() {
+        let block = if budget == 0 {
+            format!("\n\n<attachment name=\"{name}\">[left out, too long]</attachment>")
+        } else if let Some((end, _)) = text.char_indices().nth(budget) {
+            budget = 0;
+            format!(
+                "\n\n<attachment name=\"{name}\">\n{}\n[cut short]\n</attachment>",
+                &text[..end]
+            )
+        } else {
+            budget -= text.chars().count();
+            format!("\n\n<attachment name=\"{name}\">\n{text}\n</attachment>")
+        };
+        // Walking backwards, so each block goes in front
+        // of the ones from later uploads
+        appended.entry(msg_id).or_default().insert_str(0, &block);
+    }
+
+    appended
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Unsupported Attachment")]
+pub struct UnsupportedAttachment;
+
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid Attachment")]
+pub struct InvalidAttachment;
+
+#[derive(Debug, thiserror::Error)]
+#[error("Attachment Unavailable")]
+pub struct AttachmentUnavailable;
@@ -0,0 +1,106 @@
+//! Naming chats after their first exchange
+//!
+//! A new chat is given a provisional title from the first user
+//! message straight away, so it can be listed. Once the first reply
+//! is saved, the model is asked for a proper title in the
+//! background, informed by both sides of the exchange. If that
+//! fails, the provisional title stays.
+
+use async_openai::types::{ChatCompletionRequestUserMessageArgs, CreateChatCompletionRequestArgs};
+use libserver::ServiceError;
+use rgpt_cfg::Context;
+use rgpt_db::msg::Msg;
+
+/// The longest chat name taken from a message's text
+const CHAT_NAME_LEN: usize = 40;
+
+/// How much of each message the model is shown
+/// when titling a chat
+const TITLE_CONTEXT_LEN: usize = 1000;
+
+/// Titles are a few words, so replies are cut short
+const TITLE_MAX_TOKENS: u32 = 24;
+
+/// The first non-empty line of `text`, shortened
+/// to [`CHAT_NAME_LEN`] characters
+pub fn title_from_text(text: &str) -> Option<String> {
+    let line = text.lines().map(str::trim).find(|line| !line.is_empty())?;
+    Some(truncate(line, CHAT_NAME_LEN))
+}
+
+/// The title a chat gets when the model can't give it one
+pub fn fallback_title(user_text: &str) -> String {
+    title_from_text(user_text).unwrap_or_else(|| "Untitled Chat".into())
+}
+
+/// Asks the model for a title for a chat that opened with
+/// `user_text` and was answered with `reply_text`
+pub async fn generate_title(
+    cx: &Context,
+    user_text: &str,
+    reply_text: &str,
+) -> Result<String, ServiceError> {
+    let prompt = ChatCompletionRequestUserMessageArgs::default()
+        .content(format!(
+            r#"
+           Generate a title to be displayed for the following chat. It must be less than 5 words.
+           Do not respond with anything but the title
+
+           Chat Content:
+           User: {}
+           AI: {}
+        "#,
+            truncate(user_text, TITLE_CONTEXT_LEN),
+            truncate(reply_text, TITLE_CONTEXT_LEN),
+        ))
+        .build()?
+        .into();
+
+    let request = CreateChatCompletionRequestArgs::default()
+        .model(cx.config.primary_model())
+        .max_tokens(TITLE_MAX_TOKENS)
+        .messages([prompt])
+        .build()?;
+
+    let (_, response) = cx.state.model_router.create(request).await?;
+    let title = response
+        .choices
+        .into_iter()
+        .next()
+        .and_then(|choice| choice.message.content)
+        .and_then(|content| {
+            title_from_text(content.trim_matches(|c: char| c == '"' || c.is_whitespace()))
+        });
+
+    Ok(title.ok_or(NoTitleGenerated)?)
+}
+
+/// Titles the chat from its first exchange, falling back to
+/// [`fallback_title`] if the model fails, and returns the title
+pub async fn title_chat(
+    cx: &Context,
+    chat_id: i32,
+    user_msg: &Msg,
+    reply_text: &str,
+) -> Result<String, ServiceError> {
+    let title = generate_title(cx, &user_msg.body, reply_text)
+        .await
+        .unwrap_or_else(|err| {
+            eprintln!("failed to generate a title for chat {chat_id}: {err}");
+            fallback_title(&user_msg.body)
+        });
+
+    cx.store().set_chat_name(chat_id, title.clone()).await?;
+    Ok(title)
+}
+
+fn truncate(text: &str, len: usize) -> String {
+    match text.char_indices().nth(len) {
+        Some((end, _)) => format!("{}...", text[..end].trim_end()),
+        None => text.to_owned(),
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("No Title Generated")]
+pub struct NoTitleGenerated;
@@ -0,0 +1,81 @@
+use super::OutputFilter;
+
+/// Turns fenced code blocks into blocks indented by four
+/// spaces, dropping the fences and their info strings
+#[derive(Default, Debug)]
+pub struct CodeFences {
+    held: Vec<char>,
+    line: FenceLine,
+    in_block: bool,
+}
+
+#[derive(Default, Clone, Copy, PartialEq, Debug)]
+enum FenceLine {
+    #[default]
+    Start,
+    /// In a fence, which is dropped up to
+    /// and including its newline
+    Fence,
+    Text,
+}
+
+impl CodeFences {
+    fn run(&mut self, finishing: bool) -> String {
+        let mut out = String::new();
+        let mut i = 0;
+        while let Some(&c) = self.held.get(i) {
+            match self.line {
+                FenceLine::Start => {
+                    let line = &self.held[i..];
+                    let indent = line.iter().take_while(|&&c| c == ' ').count();
+                    let rest = &line[indent..];
+                    let marker = match rest.first() {
+                        Some('~') => '~',
+                        _ => '`',
+                    };
+                    let marks = rest.iter().take_while(|&&c| c == marker).count();
+                    if indent < 4 && marks >= 3 {
+                        self.in_block = !self.in_block;
+                        self.line = FenceLine::Fence;
+                        i += indent + marks;
+                        continue;
+                    }
+                    // Could still become a fence
+                    if indent < 4 && marks == rest.len() && !finishing {
+                        break;
+                    }
+                    if self.in_block && c != '\n' {
+                        out.push_str("    ");
+                    }
+                    self.line = FenceLine::Text;
+                }
+                FenceLine::Fence => {
+                    if c == '\n' {
+                        self.line = FenceLine::Start;
+                    }
+                    i += 1;
+                }
+                FenceLine::Text => {
+                    out.push(c);
+                    if c == '\n' {
+                        self.line = FenceLine::Start;
+                    }
+                    i += 1;
+                }
+            }
+        }
+        self.held.drain(..i);
+        out
+    }
+}
+
+impl OutputFilter for CodeFences {
+    fn push(&mut self, chunk: &str) -> String {
+        self.held.extend(chunk.chars());
+        self.run(false)
+    }
+
+    fn finish(&mut self) -> String {
+        self.run(true)
+    }
+}
@@ -0,0 +1,405 @@
+use super::{CodeLines, Line, OutputFilter};
+
+/// The most math inline delimiters are held back for,
+/// looking for the delimiter that closes them
+const INLINE_LIMIT: usize = 300;
+
+/// The most display math is held back for
+const DISPLAY_LIMIT: usize = 2000;
+
+/// Turns LaTeX math, between `$`, `$$`, `\(` or `\[` delimiters,
+/// into ASCII
+///
+/// A `$` only opens math when it's followed by something other than
+/// whitespace, and only closes it when it follows something other than
+/// whitespace and isn't followed by a digit, so prices are left alone.
+#[derive(Debug)]
+pub struct Latex {
+    held: Vec<char>,
+    code: CodeLines,
+    line: Line,
+}
+
+impl Default for Latex {
+    fn default() -> Self {
+        Latex {
+            held: vec![],
+            code: CodeLines::default(),
+            line: Line::Start,
+        }
+    }
+}
+
+/// What was found looking for a closing delimiter
+enum Search {
+    Found(usize),
+    /// It might be in text yet to arrive
+    Pending,
+    Missing,
+}
+
+impl Latex {
+    fn run(&mut self, finishing: bool) -> String {
+        let mut out = String::new();
+        let mut i = 0;
+        while let Some(&c) = self.held.get(i) {
+            if self.line == Line::Start {
+                self.line = match self.code.classify(&self.held[i..], finishing) {
+                    Some(true) => Line::Code,
+                    Some(false) => Line::Text,
+                    None => break,
+                };
+            }
+            if c == '\n' {
+                out.push(c);
+                self.line = Line::Start;
+                i += 1;
+                continue;
+            }
+            if self.line == Line::Code {
+                out.push(c);
+                i += 1;
+                continue;
+            }
+
+            let next = self.held.get(i + 1).copied();
+            if matches!(c, '$' | '\\') && next.is_none() && !finishing {
+                break;
+            }
+            let math = match (c, next) {
+                ('$', Some('$')) => self.find(i + 2, &['$', '$'], true, finishing),
+                ('$', Some(next)) if !next.is_whitespace() => self.find_dollar(i + 1, finishing),
+                ('\\', Some('(')) => self.find(i + 2, &['\\', ')'], false, finishing),
+                ('\\', Some('[')) => self.find(i + 2, &['\\', ']'], true, finishing),
+                // An escaped dollar isn't math
+                ('\\', Some('$')) => {
+                    out.push_str("\\$");
+                    i += 2;
+                    continue;
+                }
+                _ => Search::Missing,
+            };
+            match math {
+                Search::Found(end) => {
+                    let open = if c == '$' && next == Some('$') || c == '\\' {
+                        2
+                    } else {
+                        1
+                    };
+                    let body = self.held[i + open..end].iter().collect::<String>();
+                    out.push_str(&math_to_ascii(&body));
+                    i = end + open;
+                }
+                Search::Pending => break,
+                Search::Missing => {
+                    out.push(c);
+                    i += 1;
+                }
+            }
+        }
+        self.held.drain(..i);
+        out
+    }
+
+    /// Looks for `close` from `from`, within a line
+    /// unless `multiline` is set
+    fn find(&self, from: usize, close: &[char], multiline: bool, finishing: bool) -> Search {
+        let limit = if multiline {
+            DISPLAY_LIMIT
+        } else {
+            INLINE_LIMIT
+        };
+        let mut j = from;
+        loop {
+            if j - from > limit {
+                return Search::Missing;
+            }
+            match self.held.get(j) {
+                None if finishing => return Search::Missing,
+                None => return Search::Pending,
+                Some('\n') if !multiline => return Search::Missing,
+                Some(_) if self.held[j..].starts_with(close) => return Search::Found(j),
+                // The closing delimiter may be split
+                Some(_) if j + close.len() > self.held.len() && !finishing => {
+                    return Search::Pending;
+                }
+                Some(_) => j += 1,
+            }
+        }
+    }
+
+    /// Looks for the `$` closing inline math from `from`
+    fn find_dollar(&self, from: usize, finishing: bool) -> Search {
+        let mut j = from;
+        loop {
+            if j - from > INLINE_LIMIT {
+                return Search::Missing;
+            }
+            match self.held.get(j) {
+                None if finishing => return Search::Missing,
+                None => return Search::Pending,
+                Some('\n') => return Search::Missing,
+                Some('$') if j > from && !self.held[j - 1].is_whitespace() => {
+                    match self.held.get(j + 1) {
+                        None if !finishing => return Search::Pending,
+                        Some(after) if after.is_ascii_digit() => j += 1,
+                        _ => return Search::Found(j),
+                    }
+                }
+                Some(_) => j += 1,
+            }
+        }
+    }
+}
+
+impl OutputFilter for Latex {
+    fn push(&mut self, chunk: &str) -> String {
+        self.held.extend(chunk.chars());
+        self.run(false)
+    }
+
+    fn finish(&mut self) -> String {
+        self.run(true)
+    }
+}
+
+/// Turns LaTeX math into ASCII, so `\frac{a+1}{2}`
+/// becomes `(a+1)/2` and `\alpha \leq \pi` becomes
+/// `alpha <= pi`
+pub fn math_to_ascii(math: &str) -> String {
+    let chars = math.chars().collect::<Vec<_>>();
+    let ascii = Parser {
+        chars: &chars,
+        pos: 0,
+    }
+    .sequence(false);
+
+    // Commands leave runs of spaces behind them
+    ascii
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_owned()
+}
+
+struct Parser<'a> {
+    chars: &'a [char],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    /// Converts up to the end of the input, or with
+    /// `in_group` set, the `}` closing the group
+    fn sequence(&mut self, in_group: bool) -> String {
+        let mut out = String::new();
+        let mut last_was_command = false;
+        while let Some(&c) = self.chars.get(self.pos) {
+            if c == '}' && in_group {
+                self.pos += 1;
+                break;
+            }
+            let is_command = c == '\\';
+            let atom = self.atom();
+            // `\alpha\beta` is two words
+            if (is_command || last_was_command)
+                && out.ends_with(|c: char| c.is_ascii_alphanumeric())
+                && atom.starts_with(|c: char| c.is_ascii_alphanumeric())
+            {
+                out.push(' ');
+            }
+            out.push_str(&atom);
+            last_was_command = is_command;
+        }
+        out
+    }
+
+    /// Converts the next command with its arguments,
+    /// group, script or character
+    fn atom(&mut self) -> String {
+        let c = self.chars[self.pos];
+        self.pos += 1;
+        match c {
+            '\\' => self.command(),
+            '{' => self.sequence(true),
+            '}' => String::new(),
+            '^' => format!("^{}", wrap(&self.argument())),
+            '_' => format!("_{}", wrap(&self.argument())),
+            '&' | '~' => " ".into(),
+            c => c.to_string(),
+        }
+    }
+
+    /// The next argument, a group or a single atom
+    fn argument(&mut self) -> String {
+        while self.chars.get(self.pos) == Some(&' ') {
+            self.pos += 1;
+        }
+        match self.chars.get(self.pos) {
+            Some(_) => self.atom(),
+            None => String::new(),
+        }
+    }
+
+    /// An optional argument in square brackets
+    fn optional(&mut self) -> Option<String> {
+        if self.chars.get(self.pos) != Some(&'[') {
+            return None;
+        }
+        let start = self.pos + 1;
+        let end = self.chars[start..]
+            .iter()
+            .position(|&c| c == ']')
+            .map_or(self.chars.len(), |len| start + len);
+        self.pos = (end + 1).min(self.chars.len());
+        Some(
+            Parser {
+                chars: &self.chars[start..end],
+                pos: 0,
+            }
+            .sequence(false),
+        )
+    }
+
+    fn command(&mut self) -> String {
+        let start = self.pos;
+        while self
+            .chars
+            .get(self.pos)
+            .is_some_and(|c| c.is_ascii_alphabetic())
+        {
+            self.pos += 1;
+        }
+        if self.pos == start {
+            let Some(&symbol) = self.chars.get(self.pos) else {
+                return String::new();
+            };
+            self.pos += 1;
+            return match symbol {
+                ',' | ':' | ';' | ' ' => " ".into(),
+                '!' => String::new(),
+                '\\' => "\n".into(),
+                symbol => symbol.to_string(),
+            };
+        }
+
+        let name = self.chars[start..self.pos].iter().collect::<String>();
+        match name.as_str() {
+            "frac" | "dfrac" | "tfrac" | "cfrac" => {
+                let numerator = self.argument();
+                let denominator = self.argument();
+                format!("{}/{}", wrap(&numerator), wrap(&denominator))
+            }
+            "sqrt" => {
+                let index = self.optional();
+                let radicand = self.argument();
+                match index {
+                    Some(index) => format!("root({index}, {radicand})"),
+                    None => format!("sqrt({radicand})"),
+                }
+            }
+            "binom" | "dbinom" | "tbinom" => {
+                let n = self.argument();
+                let k = self.argument();
+                format!("C({n}, {k})")
+            }
+            // Fonts, text and accents are their argument
+            "text" | "textrm" | "textbf" | "textit" | "mbox" | "mathrm" | "mathbf" | "mathit"
+            | "mathsf" | "mathtt" | "mathbb" | "mathcal" | "mathfrak" | "boldsymbol"
+            | "operatorname" | "hat" | "widehat" | "bar" | "overline" | "underline" | "vec"
+            | "tilde" | "widetilde" | "dot" | "ddot" => self.argument(),
+            // Sizing is dropped, along with the `.` of `\left.`
+            "left" | "right" | "big" | "Big" | "bigg" | "Bigg" | "bigl" | "bigr" | "Bigl"
+            | "Bigr" | "displaystyle" | "textstyle" | "limits" | "nolimits" => {
+                if self.chars.get(self.pos) == Some(&'.') {
+                    self.pos += 1;
+                }
+                String::new()
+            }
+            "begin" | "end" => {
+                self.argument();
+                String::new()
+            }
+            name => symbol(name).unwrap_or(name).to_owned(),
+        }
+    }
+}
+
+/// Parenthesizes anything longer than a word or number
+fn wrap(text: &str) -> String {
+    let text = text.trim();
+    if text.chars().all(|c| c.is_ascii_alphanumeric() || c == '.') {
+        text.to_owned()
+    } else {
+        format!("({text})")
+    }
+}
+
+/// The ASCII for a command that stands for a symbol
+fn symbol(name: &str) -> Option<&'static str> {
+    let ascii = match name {
+        "times" => " x ",
+        "cdot" | "ast" => " * ",
+        "div" => " / ",
+        "pm" => " +/- ",
+        "mp" => " -/+ ",
+        "leq" | "le" | "leqslant" => " <= ",
+        "geq" | "ge" | "geqslant" => " >= ",
+        "neq" | "ne" => " != ",
+        "approx" => " ~= ",
+        "equiv" => " == ",
+        "sim" | "propto" => " ~ ",
+        "ll" => " << ",
+        "gg" => " >> ",
+        "to" | "rightarrow" => " -> ",
+        "leftarrow" | "gets" => " <- ",
+        "Rightarrow" | "implies" => " => ",
+        "Leftarrow" => " <= ",
+        "leftrightarrow" => " <-> ",
+        "Leftrightarrow" | "iff" => " <=> ",
+        "mapsto" => " |-> ",
+        "in" => " in ",
+        "notin" => " not in ",
+        "subset" => " subset ",
+        "subseteq" => " subseteq ",
+        "cup" => " union ",
+        "cap" => " intersect ",
+        "land" | "wedge" => " and ",
+        "lor" | "vee" => " or ",
+        "neg" | "lnot" => "not ",
+        "forall" => "for all ",
+        "exists" => "exists ",
+        "emptyset" | "varnothing" => "{}",
+        "infty" => "infinity",
+        "partial" => "d",
+        "nabla" => "nabla",
+        "sum" => "sum ",
+        "prod" => "prod ",
+        "int" => "integral ",
+        "oint" => "contour integral ",
+        "lim" => "lim",
+        "cdots" | "ldots" | "dots" | "vdots" | "ddots" => "...",
+        "circ" => "o",
+        "degree" => " degrees",
+        "prime" => "'",
+        "parallel" => " || ",
+        "mid" => " | ",
+        "langle" => "<",
+        "rangle" => ">",
+        "lfloor" | "lceil" => "[",
+        "rfloor" | "rceil" => "]",
+        "lbrace" => "{",
+        "rbrace" => "}",
+        "quad" | "qquad" => " ",
+        "ell" => "l",
+        "varepsilon" | "epsilon" => "epsilon",
+        "vartheta" => "theta",
+        "varphi" => "phi",
+        "varpi" => "pi",
+        "varrho" => "rho",
+        "varsigma" => "sigma",
+        _ => return None,
+    };
+    Some(ascii)
+}
@@ -0,0 +1,370 @@
+use super::{CodeLines, Line, OutputFilter};
+
+/// The most text a `[` is held back for,
+/// looking for the rest of a link
+const LINK_LIMIT: usize = 300;
+
+/// Strips Markdown: emphasis, strikethrough, code spans,
+/// heading and quote markers, and escapes
+///
+/// Links become their text followed by their URL in parentheses,
+/// bullets become `-` and rules become `---`. Code is left alone.
+#[derive(Debug)]
+pub struct Markdown {
+    held: Vec<char>,
+    code: CodeLines,
+    line: Line,
+    /// The last character sent, which tells emphasis
+    /// markers that open from ones that close
+    prev: char,
+    code_span: bool,
+    /// The emphasis markers open on the line
+    open: Vec<char>,
+}
+
+impl Default for Markdown {
+    fn default() -> Self {
+        Markdown {
+            held: vec![],
+            code: CodeLines::default(),
+            line: Line::Start,
+            prev: '\n',
+            code_span: false,
+            open: vec![],
+        }
+    }
+}
+
+/// What was found looking for a link
+enum Link {
+    Found {
+        text: String,
+        url: String,
+        end: usize,
+    },
+    /// Its end might be in text yet to arrive
+    Pending,
+    Missing,
+}
+
+impl Markdown {
+    fn run(&mut self, finishing: bool) -> String {
+        let mut out = String::new();
+        let mut i = 0;
+        while i < self.held.len() {
+            let next = match self.line {
+                Line::Start => self.line_start(i, finishing, &mut out),
+                Line::Code => {
+                    let c = self.held[i];
+                    self.emit(&mut out, c);
+                    if c == '\n' {
+                        self.line = Line::Start;
+                    }
+                    Some(i + 1)
+                }
+                Line::Text => self.inline(i, finishing, &mut out),
+            };
+            match next {
+                Some(next) => i = next,
+                None => break,
+            }
+        }
+        self.held.drain(..i);
+        out
+    }
+
+    fn emit(&mut self, out: &mut String, c: char) {
+        out.push(c);
+        self.prev = c;
+    }
+
+    fn emit_str(&mut self, out: &mut String, text: &str) {
+        out.push_str(text);
+        if let Some(last) = text.chars().last() {
+            self.prev = last;
+        }
+    }
+
+    /// Handles the markers a line can start with, returning
+    /// where the rest of the line starts, or `None` if more
+    /// of it is needed to tell what it starts with
+    fn line_start(&mut self, i: usize, finishing: bool, out: &mut String) -> Option<usize> {
+        match self.code.classify(&self.held[i..], finishing) {
+            None => return None,
+            Some(true) => {
+                self.line = Line::Code;
+                return Some(i);
+            }
+            Some(false) => {}
+        }
+
+        let line = &self.held[i..];
+        let indent = line.iter().take_while(|&&c| c == ' ').count();
+        let rest = &line[indent..];
+        let Some(&first) = rest.first() else {
+            self.line = Line::Text;
+            return Some(i);
+        };
+
+        match first {
+            '#' => {
+                let hashes = rest.iter().take_while(|&&c| c == '#').count();
+                match rest.get(hashes) {
+                    None if !finishing => None,
+                    Some(' ') if hashes <= 6 => {
+                        let spaces = rest[hashes..].iter().take_while(|&&c| c == ' ').count();
+                        self.line = Line::Text;
+                        Some(i + indent + hashes + spaces)
+                    }
+                    Some('\n') if hashes <= 6 => {
+                        self.line = Line::Text;
+                        Some(i + indent + hashes)
+                    }
+                    _ => {
+                        self.line = Line::Text;
+                        Some(i)
+                    }
+                }
+            }
+            // Quotes can hold any other kind of line,
+            // so the line starts again after them
+            '>' => match rest.get(1) {
+                None if !finishing => None,
+                Some(' ') => Some(i + indent + 2),
+                _ => Some(i + indent + 1),
+            },
+            '-' | '*' | '_' | '+' => {
+                let line_end = rest.iter().position(|&c| c == '\n');
+                let body = &rest[..line_end.unwrap_or(rest.len())];
+                if first != '+' && body.iter().all(|&c| c == first || c == ' ') {
+                    if line_end.is_none() && !finishing {
+                        return None;
+                    }
+                    if body.iter().filter(|&&c| c == first).count() >= 3 {
+                        let end = i + indent + body.len();
+                        self.emit_str(out, "---");
+                        self.line = Line::Text;
+                        return Some(end);
+                    }
+                }
+                match rest.get(1) {
+                    None if !finishing => None,
+                    Some(' ') if first != '_' => {
+                        let bullet = format!("{}- ", &" ".repeat(indent));
+                        self.emit_str(out, &bullet);
+                        self.line = Line::Text;
+                        Some(i + indent + 2)
+                    }
+                    _ => {
+                        self.line = Line::Text;
+                        Some(i)
+                    }
+                }
+            }
+            _ => {
+                self.line = Line::Text;
+                Some(i)
+            }
+        }
+    }
+
+    /// Handles the text at `i`, returning where to carry on from,
+    /// or `None` if more text is needed to tell what it is
+    fn inline(&mut self, i: usize, finishing: bool, out: &mut String) -> Option<usize> {
+        let c = self.held[i];
+        let next = self.held.get(i + 1).copied();
+        match c {
+            '\n' => {
+                self.emit(out, c);
+                self.line = Line::Start;
+                self.code_span = false;
+                self.open.clear();
+                Some(i + 1)
+            }
+            '`' => {
+                let run = self.run_of(i);
+                if i + run == self.held.len() && !finishing {
+                    return None;
+                }
+                self.code_span = !self.code_span;
+                Some(i + run)
+            }
+            _ if self.code_span => {
+                self.emit(out, c);
+                Some(i + 1)
+            }
+            '\\' => match next {
+                None if !finishing => None,
+                Some(escaped) if escaped.is_ascii_punctuation() => {
+                    self.emit(out, escaped);
+                    Some(i + 2)
+                }
+                _ => {
+                    self.emit(out, c);
+                    Some(i + 1)
+                }
+            },
+            '*' | '_' | '~' => self.delimiter(i, finishing, out),
+            // Images are links to their source
+            '!' => match next {
+                None if !finishing => None,
+                Some('[') => match self.link_at(i + 1, finishing) {
+                    Link::Pending => None,
+                    Link::Found { text, url, end } => {
+                        self.emit_link(out, &text, &url);
+                        Some(end)
+                    }
+                    Link::Missing => {
+                        self.emit(out, c);
+                        Some(i + 1)
+                    }
+                },
+                _ => {
+                    self.emit(out, c);
+                    Some(i + 1)
+                }
+            },
+            '[' => match self.link_at(i, finishing) {
+                Link::Pending => None,
+                Link::Found { text, url, end } => {
+                    self.emit_link(out, &text, &url);
+                    Some(end)
+                }
+                Link::Missing => {
+                    self.emit(out, c);
+                    Some(i + 1)
+                }
+            },
+            c => {
+                self.emit(out, c);
+                Some(i + 1)
+            }
+        }
+    }
+
+    /// How many of the character at `i` there are in a row from it
+    fn run_of(&self, i: usize) -> usize {
+        let c = self.held[i];
+        self.held[i..].iter().take_while(|&&m| m == c).count()
+    }
+
+    /// Drops a run of emphasis or strikethrough markers, unless
+    /// it's standing in for itself, like the `*` in `2*3` or the
+    /// `_` in `snake_case`
+    fn delimiter(&mut self, i: usize, finishing: bool, out: &mut String) -> Option<usize> {
+        let c = self.held[i];
+        let run = self.run_of(i);
+        let next = match self.held.get(i + run) {
+            Some(&next) => next,
+            None if finishing => '\n',
+            None => return None,
+        };
+        let prev = self.prev;
+        let literal = self.held[i..i + run].iter().collect::<String>();
+
+        if c == '~' {
+            if run < 2 {
+                self.emit_str(out, &literal);
+            }
+            return Some(i + run);
+        }
+
+        // Underscores within words aren't emphasis
+        let can_open = !next.is_whitespace() && (c == '*' || !prev.is_alphanumeric());
+        let can_close = !prev.is_whitespace() && (c == '*' || !next.is_alphanumeric());
+        let is_product = c == '*' && run == 1 && prev.is_ascii_digit() && next.is_ascii_digit();
+
+        if is_product {
+            self.emit_str(out, &literal);
+        } else if let Some(open) = self
+            .open
+            .iter()
+            .rposition(|&m| m == c)
+            .filter(|_| can_close)
+        {
+            self.open.truncate(open);
+        } else if can_open {
+            self.open.push(c);
+        } else {
+            self.emit_str(out, &literal);
+        }
+        Some(i + run)
+    }
+
+    /// Looks for a `[text](url)` link starting at `i`
+    fn link_at(&self, i: usize, finishing: bool) -> Link {
+        let ran_out = || {
+            if finishing {
+                Link::Missing
+            } else {
+                Link::Pending
+            }
+        };
+
+        let mut close = i + 1;
+        loop {
+            if close - i > LINK_LIMIT {
+                return Link::Missing;
+            }
+            match self.held.get(close) {
+                None => return ran_out(),
+                Some('\n') => return Link::Missing,
+                Some(']') => break,
+                Some(_) => close += 1,
+            }
+        }
+        match self.held.get(close + 1) {
+            None => return ran_out(),
+            Some('(') => {}
+            Some(_) => return Link::Missing,
+        }
+        let mut end = close + 2;
+        loop {
+            if end - i > LINK_LIMIT {
+                return Link::Missing;
+            }
+            match self.held.get(end) {
+                None => return ran_out(),
+                Some('\n' | ' ') => return Link::Missing,
+                Some(')') => break,
+                Some(_) => end += 1,
+            }
+        }
+
+        Link::Found {
+            text: self.held[i + 1..close].iter().collect(),
+            url: self.held[close + 2..end].iter().collect(),
+            end: end + 1,
+        }
+    }
+
+    fn emit_link(&mut self, out: &mut String, text: &str, url: &str) {
+        // The text can have emphasis of its own
+        let mut inner = Markdown {
+            line: Line::Text,
+            prev: self.prev,
+            ..Markdown::default()
+        };
+        let text = inner.push(text) + &inner.finish();
+
+        let link = if text.is_empty() || text == url {
+            url.to_owned()
+        } else if url.is_empty() {
+            text
+        } else {
+            format!("{text} ({url})")
+        };
+        self.emit_str(out, &link);
+    }
+}
+
+impl OutputFilter for Markdown {
+    fn push(&mut self, chunk: &str) -> String {
+        self.held.extend(chunk.chars());
+        self.run(false)
+    }
+
+    fn finish(&mut self) -> String {
+        self.run(true)
+    }
+}
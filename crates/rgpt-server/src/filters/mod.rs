@@ -0,0 +1,136 @@
+//! Filters that rewrite replies into RetroGPT's plaintext
+//! style as they stream
+//!
+//! Replies arrive in chunks that can split anything, a `**` or a
+//! `\frac` included, so each filter holds back text whose meaning
+//! depends on what follows until it arrives or the reply ends.
+
+use rgpt_db::output_filters::OutputFilters;
+
+pub mod code_fences;
+pub mod latex;
+pub mod markdown;
+pub mod terminal;
+pub mod wrap;
+
+use code_fences::CodeFences;
+use latex::Latex;
+use markdown::Markdown;
+use terminal::Terminal;
+use wrap::Wrap;
+
+pub trait OutputFilter: Send {
+    /// Filters the next chunk of the reply, returning
+    /// the text that's ready to send
+    fn push(&mut self, chunk: &str) -> String;
+
+    /// Returns whatever was held back, once
+    /// the reply has ended
+    fn finish(&mut self) -> String;
+}
+
+/// The filters a reply goes through, in order
+pub struct Pipeline {
+    filters: Vec<Box<dyn OutputFilter>>,
+}
+
+impl Pipeline {
+    pub fn new(config: &OutputFilters) -> Self {
+        let mut filters: Vec<Box<dyn OutputFilter>> = vec![];
+        // Fences go first, so the filters after them can tell
+        // code by its indent and leave it alone
+        if config.code_fences {
+            filters.push(Box::new(CodeFences::default()));
+        }
+        // LaTeX goes before Markdown, whose escapes and emphasis
+        // would eat its backslashes and underscores
+        if config.latex {
+            filters.push(Box::new(Latex::default()));
+        }
+        if config.markdown {
+            filters.push(Box::new(Markdown::default()));
+        }
+        if config.terminal {
+            filters.push(Box::new(Terminal::default()));
+        }
+        if let Some(column) = config.wrap_column {
+            filters.push(Box::new(Wrap::new(column.into())));
+        }
+        Pipeline { filters }
+    }
+
+    pub fn push(&mut self, chunk: &str) -> String {
+        self.filters
+            .iter_mut()
+            .fold(chunk.to_owned(), |text, filter| filter.push(&text))
+    }
+
+    pub fn finish(&mut self) -> String {
+        let mut text = String::new();
+        for filter in &mut self.filters {
+            text = filter.push(&text);
+            text.push_str(&filter.finish());
+        }
+        text
+    }
+}
+
+/// Filters a whole text at once
+pub fn filter_text(config: &OutputFilters, text: &str) -> String {
+    let mut pipeline = Pipeline::new(config);
+    pipeline.push(text) + &pipeline.finish()
+}
+
+/// Where a filter is in the line it's reading
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Line {
+    /// At its start, where what kind of line it is
+    /// hasn't been worked out yet
+    Start,
+    /// In a line of code, which is passed through
+    Code,
+    Text,
+}
+
+/// Tells code lines from the rest, for filters that pass
+/// code through untouched
+///
+/// Code is lines indented by four spaces or a tab, which is what
+/// the fence filter turns fenced blocks into, and fenced blocks,
+/// fences included, for when it's turned off.
+#[derive(Default, Debug)]
+struct CodeLines {
+    in_fence: bool,
+}
+
+impl CodeLines {
+    /// Whether the line `line` starts with is code, or `None` if
+    /// more of it is needed to tell. With `finishing` set no more
+    /// of it is coming.
+    fn classify(&mut self, line: &[char], finishing: bool) -> Option<bool> {
+        let mut indent = 0;
+        let mut rest = line;
+        while let Some((&c, tail)) = rest.split_first() {
+            match c {
+                ' ' => indent += 1,
+                '\t' => indent += 4,
+                _ => break,
+            }
+            rest = tail;
+        }
+        if indent >= 4 {
+            return Some(true);
+        }
+
+        let ticks = rest.iter().take_while(|&&c| c == '`').count();
+        if ticks >= 3 {
+            self.in_fence = !self.in_fence;
+            return Some(true);
+        }
+        // Still only spaces and backticks, which could become a fence
+        if ticks == rest.len() && !finishing {
+            return None;
+        }
+        Some(self.in_fence)
+    }
+}
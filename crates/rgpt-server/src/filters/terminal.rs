@@ -0,0 +1,56 @@
+use super::{CodeLines, Line, OutputFilter};
+
+/// Upper-cases everything but code, like an old terminal
+#[derive(Debug)]
+pub struct Terminal {
+    held: Vec<char>,
+    code: CodeLines,
+    line: Line,
+}
+
+impl Default for Terminal {
+    fn default() -> Self {
+        Terminal {
+            held: vec![],
+            code: CodeLines::default(),
+            line: Line::Start,
+        }
+    }
+}
+
+impl Terminal {
+    fn run(&mut self, finishing: bool) -> String {
+        let mut out = String::new();
+        let mut i = 0;
+        while let Some(&c) = self.held.get(i) {
+            if self.line == Line::Start {
+                self.line = match self.code.classify(&self.held[i..], finishing) {
+                    Some(true) => Line::Code,
+                    Some(false) => Line::Text,
+                    None => break,
+                };
+            }
+            match self.line {
+                Line::Text => out.extend(c.to_uppercase()),
+                _ => out.push(c),
+            }
+            if c == '\n' {
+                self.line = Line::Start;
+            }
+            i += 1;
+        }
+        self.held.drain(..i);
+        out
+    }
+}
+
+impl OutputFilter for Terminal {
+    fn push(&mut self, chunk: &str) -> String {
+        self.held.extend(chunk.chars());
+        self.run(false)
+    }
+
+    fn finish(&mut self) -> String {
+        self.run(true)
+    }
+}
@@ -0,0 +1,109 @@
+use super::OutputFilter;
+
+/// Hard-wraps lines at a column, breaking them between
+/// words, or within words too long for any line
+#[derive(Debug)]
+pub struct Wrap {
+    column: usize,
+    /// How many characters the current line has
+    col: usize,
+    /// The word being read, which is placed once it ends
+    word: String,
+    word_len: usize,
+    /// The whitespace before the word, which is
+    /// dropped if the word starts a new line
+    spaces: String,
+}
+
+impl Wrap {
+    pub fn new(column: usize) -> Self {
+        Wrap {
+            column: column.max(1),
+            col: 0,
+            word: String::new(),
+            word_len: 0,
+            spaces: String::new(),
+        }
+    }
+
+    fn push_char(&mut self, c: char, out: &mut String) {
+        match c {
+            '\n' => {
+                self.place_word(out);
+                // Trailing whitespace is dropped
+                self.spaces.clear();
+                out.push('\n');
+                self.col = 0;
+            }
+            ' ' | '\t' => {
+                self.place_word(out);
+                self.spaces.push(c);
+            }
+            c => {
+                self.word.push(c);
+                self.word_len += 1;
+                if self.word_len > self.column {
+                    self.break_word(out);
+                }
+            }
+        }
+    }
+
+    fn place_word(&mut self, out: &mut String) {
+        if self.word.is_empty() {
+            return;
+        }
+        let spaces_len = self.spaces.chars().count();
+        if self.col > 0 && self.col + spaces_len + self.word_len > self.column {
+            out.push('\n');
+            self.col = 0;
+        } else if self.col + spaces_len + self.word_len <= self.column {
+            out.push_str(&self.spaces);
+            self.col += spaces_len;
+        }
+        // Otherwise the line starts with the word, and an
+        // indent would push it past the column
+        self.spaces.clear();
+        out.push_str(&self.word);
+        self.col += self.word_len;
+        self.word.clear();
+        self.word_len = 0;
+    }
+
+    /// Sends the part of a word too long for any line
+    /// that fits on a line of its own
+    fn break_word(&mut self, out: &mut String) {
+        if self.col > 0 {
+            out.push('\n');
+            self.col = 0;
+        }
+        self.spaces.clear();
+        let split = self
+            .word
+            .char_indices()
+            .nth(self.column)
+            .map_or(self.word.len(), |(index, _)| index);
+        let rest = self.word.split_off(split);
+        out.push_str(&self.word);
+        out.push('\n');
+        self.word_len = rest.chars().count();
+        self.word = rest;
+    }
+}
+
+impl OutputFilter for Wrap {
+    fn push(&mut self, chunk: &str) -> String {
+        let mut out = String::new();
+        for c in chunk.chars() {
+            self.push_char(c, &mut out);
+        }
+        out
+    }
+
+    fn finish(&mut self) -> String {
+        let mut out = String::new();
+        self.place_word(&mut out);
+        self.spaces.clear();
+        out
+    }
+}
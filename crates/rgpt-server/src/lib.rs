@@ -1,26 +1,41 @@
-use std::{net::SocketAddr, path::PathBuf, sync::Arc};
+use std::{net::SocketAddr, sync::Arc, time::Instant};
 
 use http_body_util::BodyExt;
-use hyper::{HeaderMap, body::Body};
-use libserver::{DynRoute, NOT_FOUND, Route, ServiceBuilder, StaticDirRouter};
+use hyper::{HeaderMap, body::Body, header::USER_AGENT};
+use libserver::{DynRoute, NOT_FOUND, PathPrefixRouter, Route, ServiceBuilder, StaticDirRouter};
 use rgpt_cfg::Context;
-use rgpt_db::{Database, session::Session, user::User};
+use rgpt_db::{
+    audit::{self, AuditKind, ClientInfo, NewAuditEvent},
+    session::Session,
+};
 use tokio::net::TcpListener;
 
+pub mod accounts;
 pub mod api;
+pub mod attachments;
+pub mod chat_title;
+pub mod filters;
+pub mod instructions;
+pub mod moderation;
+pub mod retrieval;
 pub mod serve_static;
+pub mod tools;
 
-use serve_static::StaticAssetService;
+use serve_static::{StaticAssetService, StaticOptions};
 
 pub fn static_asset_service(cx: Arc<Context>) -> libserver::Service {
     ServiceBuilder::new()
-        .with_dyn_route(static_asset_route(cx.static_dir()))
+        .with_dyn_route(static_asset_route(cx.clone()))
+        .with_dyn_route(static_fallback_route(cx))
         .with_fallback(NOT_FOUND)
 }
 
 pub fn api_service(cx: Arc<Context>) -> libserver::Service {
     ServiceBuilder::new()
-        .with_dyn_route(api::v0_0_1::route(cx))
+        .with_dyn_route(api::v0_0_1::route(cx.clone()))
+        .with_dyn_route(api::v0_0_2::route(cx.clone()))
+        .with_dyn_route(api::v1::route(cx.clone()))
+        .with_dyn_route(api::openapi::route(cx))
         .with_fallback(NOT_FOUND)
 }
 
@@ -28,9 +43,15 @@ pub async fn run_server(cx: Arc<Context>) -> Result<(), Box<dyn std::error::Erro
     let addr = SocketAddr::from(([0, 0, 0, 0], cx.port()));
     let listener = TcpListener::bind(addr).await?;
 
+    tokio::spawn(accounts::sweep_deletions(cx.clone()));
+
     ServiceBuilder::new()
-        .with_dyn_route(static_asset_route(cx.static_dir()))
+        .with_dyn_route(static_asset_route(cx.clone()))
         .with_dyn_route(api::v0_0_1::route(cx.clone()))
+        .with_dyn_route(api::v0_0_2::route(cx.clone()))
+        .with_dyn_route(api::v1::route(cx.clone()))
+        .with_dyn_route(api::openapi::route(cx.clone()))
+        .with_dyn_route(static_fallback_route(cx.clone()))
         .with_fallback(NOT_FOUND)
         .serve(listener)
         .await?;
@@ -38,8 +59,22 @@ pub async fn run_server(cx: Arc<Context>) -> Result<(), Box<dyn std::error::Erro
     Ok(())
 }
 
-pub fn static_asset_route(path: PathBuf) -> DynRoute {
-    Route::from_parts(StaticDirRouter::new(&path), StaticAssetService::new(&path)).make_dyn()
+pub fn static_asset_route(cx: Arc<Context>) -> DynRoute {
+    let path = cx.static_dir();
+    let service = StaticAssetService::with_options(&path, StaticOptions::from_config(&cx.config));
+
+    Route::from_parts(StaticDirRouter::new(&path), service).make_dyn()
+}
+
+/// Catches every path that no other route matched, so the static
+/// service can answer it with the SPA shell or a proper 404
+///
+/// Must be registered after all other routes.
+pub fn static_fallback_route(cx: Arc<Context>) -> DynRoute {
+    let path = cx.static_dir();
+    let service = StaticAssetService::with_options(&path, StaticOptions::from_config(&cx.config));
+
+    Route::from_parts(PathPrefixRouter::new("/"), service).make_dyn()
 }
 
 pub fn check_body_size(req: &libserver::Request, max_size: u64) -> Result<(), RequestTooLarge> {
@@ -64,8 +99,21 @@ pub async fn collect_body_string(
     Ok(string)
 }
 
+/// The default user, whom every `__default__` session belongs to
+pub const GUEST_USER_ID: i32 = 1;
+
+/// Whether `user_id` is the guest user
+///
+/// Every guest shares the one default user, so nothing stored
+/// under it belongs to any one guest. Guests mustn't see each
+/// other's chats, documents or activity, or act as one another,
+/// so anything tied to an account is off limits to them.
+pub fn is_guest(user_id: i32) -> bool {
+    user_id == GUEST_USER_ID
+}
+
 pub async fn validate_session_header(
-    db: Arc<Database>,
+    cx: &Context,
     headers: &HeaderMap,
     user_id: Option<i32>,
 ) -> Result<Session, libserver::ServiceError> {
@@ -74,7 +122,36 @@ pub async fn validate_session_header(
         None => Err(InvalidSessionTokenHeader)?,
     };
 
-    validate_session_token(db, session_token, user_id).await
+    validate_session_token(cx, session_token, user_id, &client_info(cx, headers)).await
+}
+
+/// Where the request came from
+///
+/// The address is only known when the server is configured to
+/// trust the proxy in front of it, which reports it in `X-Real-IP`
+/// or `X-Forwarded-For`. Otherwise a client could claim any.
+pub fn client_info(cx: &Context, headers: &HeaderMap) -> ClientInfo {
+    let header = |name| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned)
+    };
+    let ip = cx
+        .config
+        .trust_proxy_headers
+        .then(|| {
+            header("X-Real-IP").or_else(|| {
+                let forwarded = header("X-Forwarded-For")?;
+                Some(forwarded.split(',').next()?.trim().to_owned())
+            })
+        })
+        .flatten();
+
+    ClientInfo {
+        ip,
+        user_agent: header(USER_AGENT.as_str()),
+    }
 }
 
 pub fn extract_query_param(uri: &hyper::Uri, param_name: &str) -> Option<String> {
@@ -95,27 +172,69 @@ pub fn extract_query_param(uri: &hyper::Uri, param_name: &str) -> Option<String>
     })
 }
 
+/// Looks up the session behind a token, checking it belongs
+/// to `user_id` if one is given
+///
+/// Unknown, mismatched and expired tokens are recorded in the
+/// audit log as coming from `client`, unknown ones no more than
+/// the audit throttle admits. Expired sessions are deleted.
 pub async fn validate_session_token(
-    db: Arc<Database>,
+    cx: &Context,
     session_token: String,
     user_id: Option<i32>,
+    client: &ClientInfo,
 ) -> Result<Session, libserver::ServiceError> {
+    let store = cx.store();
     if session_token == "__default__" {
-        let default_user = User::default(db.clone()).await?;
-        let session = Session::get_for_user(db.clone(), &default_user).await?;
+        let default_user = store.default_user().await?;
+        if default_user.disabled {
+            Err(UserDisabled)?;
+        }
+        let (session, _) = store.session_for_user(default_user.user_id).await?;
         return Ok(session);
     }
 
-    let session = Session::get_by_token(db.clone(), session_token).await?;
+    let session = match store.session_by_token(&session_token).await {
+        Ok(session) => session,
+        Err(err) => {
+            let admitted = cx
+                .state
+                .audit_throttle
+                .lock()
+                .unwrap_or_else(|err| err.into_inner())
+                .admit(client.ip.as_deref(), Instant::now());
+            if let Some(held_back) = admitted {
+                let detail = match held_back {
+                    0 => "unknown token".to_owned(),
+                    held_back => format!("unknown token, {held_back} more held back"),
+                };
+                let event = NewAuditEvent::new(AuditKind::SessionRejected, None, detail);
+                audit::record(&*store, event.client(client)).await;
+            }
+            return Err(err);
+        }
+    };
 
     if let Some(user_id) = user_id {
         if session.user_id != user_id {
+            let event = NewAuditEvent::new(
+                AuditKind::SessionRejected,
+                Some(session.user_id),
+                format!("used for user {user_id}"),
+            );
+            audit::record(&*store, event.client(client)).await;
             Err(InvalidSessionTokenHeader)?;
         };
     }
 
     if !session.validate() {
-        session.delete(db.clone()).await?;
+        store.delete_session(&session.session_token).await?;
+        let event = NewAuditEvent::new(
+            AuditKind::SessionExpired,
+            Some(session.user_id),
+            format!("expired at {}", session.expires_at),
+        );
+        audit::record(&*store, event.client(client)).await;
         Err(InvalidSessionTokenHeader)?
     } else {
         Ok(session)
@@ -129,3 +248,7 @@ pub struct RequestTooLarge;
 #[derive(Debug, thiserror::Error)]
 #[error("Invalid Session Token Header")]
 pub struct InvalidSessionTokenHeader;
+
+#[derive(Debug, thiserror::Error)]
+#[error("User Disabled")]
+pub struct UserDisabled;
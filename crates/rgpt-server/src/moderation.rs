@@ -0,0 +1,178 @@
+//! Moderation of prompts before they're sent to the model and
+//! of replies before they're shown
+//!
+//! Flagged text is let through and blocked text is stopped, and
+//! both are kept as moderation flags for operators to review.
+
+use std::sync::Arc;
+
+use libserver::ServiceError;
+use rgpt_cfg::Context;
+use rgpt_db::moderation::{FlagAction, FlagSource, NewModerationFlag};
+use rgpt_provider::{Action, Moderator, Verdict};
+
+/// Who moderated text came from, and where it was sent
+/// or generated
+#[derive(Clone, Copy, Debug)]
+pub struct FlagTarget {
+    pub user_id: i32,
+    pub chat_id: Option<i32>,
+    /// The message the text was saved as, if it was
+    pub msg_id: Option<i32>,
+}
+
+/// Checks a prompt, failing with [`PromptBlocked`] if it's
+/// blocked, which is kept for review
+///
+/// Flagged prompts are let through, and it's up to the caller
+/// to [`keep`] their verdict once they're saved.
+pub async fn check_prompt(
+    cx: &Context,
+    target: FlagTarget,
+    text: &str,
+) -> Result<Verdict, ServiceError> {
+    let verdict = cx.state.moderator.check(text).await?;
+    if verdict.action == Action::Block {
+        keep(cx, target, FlagSource::Prompt, verdict, text.into()).await;
+        Err(PromptBlocked)?;
+    }
+    Ok(verdict)
+}
+
+/// Keeps `content` for review if its verdict flagged or blocked it
+///
+/// The text has already been let through or stopped by then, so
+/// a flag that can't be saved is logged rather than returned.
+pub async fn keep(
+    cx: &Context,
+    target: FlagTarget,
+    source: FlagSource,
+    verdict: Verdict,
+    content: String,
+) {
+    let action = match verdict.action {
+        Action::Allow => return,
+        Action::Flag => FlagAction::Flag,
+        Action::Block => FlagAction::Block,
+    };
+    let FlagTarget {
+        user_id,
+        chat_id,
+        msg_id,
+    } = target;
+    let flag = NewModerationFlag {
+        user_id,
+        chat_id,
+        msg_id,
+        source,
+        action,
+        reasons: verdict.reasons,
+        content,
+    };
+    if let Err(err) = cx.store().create_moderation_flag(flag).await {
+        eprintln!("failed to keep moderation flag for user {user_id}: {err}");
+    }
+}
+
+/// Holds a reply back as it streams until moderation has
+/// checked it
+///
+/// Text is checked at the end of every line, or once `window`
+/// characters of it are waiting, along with up to `window`
+/// characters of the text that passed before it, so matches
+/// that straddle two checks aren't missed. A moderator that
+/// fails lets the text through.
+pub struct ReplyGate {
+    moderator: Arc<dyn Moderator>,
+    window: usize,
+    passed: String,
+    held: String,
+    verdict: Verdict,
+}
+
+impl ReplyGate {
+    pub fn new(moderator: Arc<dyn Moderator>, window: usize) -> Self {
+        ReplyGate {
+            moderator,
+            window: window.max(1),
+            passed: String::new(),
+            held: String::new(),
+            verdict: Verdict::allow(),
+        }
+    }
+
+    /// Takes the next chunk of the reply, returning the
+    /// text that has passed moderation
+    pub async fn push(&mut self, chunk: &str) -> Result<String, ReplyBlocked> {
+        if self.verdict.action == Action::Block {
+            return Err(ReplyBlocked);
+        }
+        self.held.push_str(chunk);
+
+        let ready = match self.held.rfind('\n') {
+            Some(newline) => newline + 1,
+            None if self.held.chars().count() >= self.window => self.held.len(),
+            None => return Ok(String::new()),
+        };
+        self.check(ready).await
+    }
+
+    /// Takes the last of the reply, returning whatever of
+    /// the text held back passes moderation
+    pub async fn finish(&mut self, rest: &str) -> Result<String, ReplyBlocked> {
+        if self.verdict.action == Action::Block {
+            return Err(ReplyBlocked);
+        }
+        self.held.push_str(rest);
+        if self.held.is_empty() {
+            return Ok(String::new());
+        }
+        self.check(self.held.len()).await
+    }
+
+    /// The verdict on everything checked so far
+    pub fn verdict(&self) -> &Verdict {
+        &self.verdict
+    }
+
+    /// Everything the gate was given, the text it
+    /// blocked included
+    pub fn text(&self) -> String {
+        format!("{}{}", self.passed, self.held)
+    }
+
+    /// Checks the first `ready` bytes of the held text
+    async fn check(&mut self, ready: usize) -> Result<String, ReplyBlocked> {
+        let context = self
+            .passed
+            .char_indices()
+            .rev()
+            .nth(self.window - 1)
+            .map_or(0, |(start, _)| start);
+        let text = format!("{}{}", &self.passed[context..], &self.held[..ready]);
+
+        let verdict = match self.moderator.check(&text).await {
+            Ok(verdict) => verdict,
+            Err(err) => {
+                eprintln!("reply moderation failed, letting the text through: {err}");
+                Verdict::allow()
+            }
+        };
+        self.verdict = std::mem::replace(&mut self.verdict, Verdict::allow()).and(verdict);
+        if self.verdict.action == Action::Block {
+            return Err(ReplyBlocked);
+        }
+
+        let passed = self.held.drain(..ready).collect::<String>();
+        self.passed.push_str(&passed);
+        Ok(passed)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Prompt Blocked")]
+pub struct PromptBlocked;
+
+#[derive(Debug, thiserror::Error)]
+#[error("Reply Blocked")]
+pub struct ReplyBlocked;
@@ -0,0 +1,200 @@
+//! Retrieval over users' documents
+//!
+//! Documents are split into overlapping chunks, each embedded with
+//! the deployment's embedder and saved with its vector. When a reply
+//! is generated, the user's latest message is embedded too and the
+//! chunks closest to it are given to the model, numbered so the
+//! reply can cite them. Similarity is computed here rather than in
+//! the database, so Postgres needs no vector extension.
+
+use std::collections::HashMap;
+
+use libserver::ServiceError;
+use rgpt_cfg::Context;
+use rgpt_db::document::{Citation, Citations, Document, DocumentChunk, NewChunk};
+
+/// About how long chunks are, in characters
+pub const CHUNK_CHARS: usize = 1500;
+
+/// About how many characters each chunk repeats from the
+/// end of the one before, so passages split between two
+/// chunks are whole in at least one
+pub const CHUNK_OVERLAP: usize = 200;
+
+/// Splits `text` into chunks of at most `max_chars` characters,
+/// each starting about `overlap` characters before the last ended
+///
+/// Chunks end at whitespace where there is some in their second
+/// half, so words are only split when they are very long.
+pub fn chunk_text(text: &str, max_chars: usize, overlap: usize) -> Vec<String> {
+    let chars = text.chars().collect::<Vec<_>>();
+    let mut chunks = vec![];
+    let mut start = 0;
+
+    while start < chars.len() {
+        let mut end = (start + max_chars).min(chars.len());
+        if end < chars.len() {
+            let window = &chars[start + max_chars / 2..end];
+            if let Some(space) = window.iter().rposition(|c| c.is_whitespace()) {
+                end = start + max_chars / 2 + space;
+            }
+        }
+
+        let chunk = chars[start..end].iter().collect::<String>();
+        if !chunk.trim().is_empty() {
+            chunks.push(chunk.trim().to_owned());
+        }
+        if end == chars.len() {
+            break;
+        }
+
+        // The overlap starts at a word where it can
+        let mut next = end.saturating_sub(overlap).max(start + 1);
+        if let Some(space) = chars[next..end].iter().position(|c| c.is_whitespace()) {
+            next += space + 1;
+        }
+        start = next;
+    }
+
+    chunks
+}
+
+/// The cosine similarity of two vectors, or 0 if
+/// either has no direction
+pub fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    let dot = a.iter().zip(b).map(|(x, y)| x * y).sum::<f32>();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a * norm_b)
+}
+
+/// The `k` chunks most similar to `query`, most similar first,
+/// leaving out any scoring below `min_score`
+pub fn top_k(
+    query: &[f32],
+    chunks: Vec<DocumentChunk>,
+    k: usize,
+    min_score: f32,
+) -> Vec<(f32, DocumentChunk)> {
+    let mut scored = chunks
+        .into_iter()
+        .map(|chunk| (cosine(query, &chunk.embedding()), chunk))
+        .filter(|(score, _)| *score >= min_score)
+        .collect::<Vec<_>>();
+    scored.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+    scored.truncate(k);
+    scored
+}
+
+/// Chunks, embeds and saves a document for `user_id`
+pub async fn add_document(
+    cx: &Context,
+    user_id: i32,
+    name: String,
+    text: &str,
+) -> Result<Document, ServiceError> {
+    let bodies = chunk_text(text, CHUNK_CHARS, CHUNK_OVERLAP);
+    if bodies.is_empty() {
+        Err(EmptyDocument)?;
+    }
+
+    let embedder = &cx.state.embedder;
+    let embeddings = embedder.embed(bodies.clone()).await?;
+    let chunks = bodies
+        .into_iter()
+        .zip(embeddings)
+        .map(|(body, embedding)| NewChunk {
+            body,
+            embedding_model: embedder.model().into(),
+            embedding,
+        })
+        .collect();
+
+    cx.store().create_document(user_id, name, chunks).await
+}
+
+/// The document chunks relevant to a prompt, ready
+/// to be given to the model
+pub struct Retrieved {
+    /// The chunks as a system message, numbered in
+    /// the order of `citations`
+    pub context: String,
+    pub citations: Citations,
+}
+
+/// Looks through the user's documents for chunks relevant to `query`
+///
+/// Embedding failures aren't fatal: the reply is generated
+/// without the documents rather than not at all.
+pub async fn retrieve(
+    cx: &Context,
+    user_id: i32,
+    query: &str,
+) -> Result<Option<Retrieved>, ServiceError> {
+    let store = cx.store();
+    let embedder = &cx.state.embedder;
+
+    // Users without documents don't need their prompts embedded
+    let chunks = store.user_chunks(user_id, embedder.model()).await?;
+    if chunks.is_empty() || query.trim().is_empty() {
+        return Ok(None);
+    }
+
+    let query_vector = match embedder.embed(vec![query.into()]).await {
+        Ok(mut vectors) if !vectors.is_empty() => vectors.swap_remove(0),
+        Ok(_) => return Ok(None),
+        Err(err) => {
+            eprintln!("failed to embed a prompt for retrieval: {err}");
+            return Ok(None);
+        }
+    };
+
+    let found = top_k(
+        &query_vector,
+        chunks,
+        cx.config.retrieval_top_k,
+        cx.config.retrieval_min_score,
+    );
+    if found.is_empty() {
+        return Ok(None);
+    }
+
+    let mut names = HashMap::new();
+    let mut context = "Excerpts from the user's documents that may help with their latest \
+                       message follow. If you use one, cite it by its number in square \
+                       brackets, like [1]."
+        .to_owned();
+    let mut citations = vec![];
+    for (n, (_, chunk)) in found.into_iter().enumerate() {
+        if !names.contains_key(&chunk.document_id) {
+            let document = store.document_by_id(chunk.document_id).await?;
+            names.insert(document.id, document.name);
+        }
+        let document_name = names[&chunk.document_id].clone();
+
+        context.push_str(&format!(
+            "\n\n[{}] {document_name}, part {}\n{}",
+            n + 1,
+            chunk.chunk_index + 1,
+            chunk.body
+        ));
+        citations.push(Citation {
+            document_id: chunk.document_id,
+            document_name,
+            chunk_id: chunk.id,
+            chunk_index: chunk.chunk_index,
+        });
+    }
+
+    Ok(Some(Retrieved {
+        context,
+        citations: Citations(citations),
+    }))
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Document Has No Text")]
+pub struct EmptyDocument;
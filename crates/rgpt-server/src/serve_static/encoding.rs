@@ -0,0 +1,92 @@
+use hyper::{HeaderMap, header::ACCEPT_ENCODING};
+
+/// A content coding the static server can respond with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Brotli,
+    Gzip,
+}
+
+impl Encoding {
+    /// Preferred first when the client weighs encodings equally
+    const ALL: [Encoding; 2] = [Encoding::Brotli, Encoding::Gzip];
+
+    /// The value of the `Content-Encoding` header
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    /// The extension of a precompressed sibling file
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gz",
+        }
+    }
+}
+
+/// The encodings allowed by the request's `Accept-Encoding`,
+/// most preferred first
+pub fn accepted_encodings(headers: &HeaderMap) -> Vec<Encoding> {
+    let Some(accept) = headers
+        .get(ACCEPT_ENCODING)
+        .and_then(|accept| accept.to_str().ok())
+    else {
+        return vec![];
+    };
+
+    let mut wildcard = None;
+    let mut weights = [None; Encoding::ALL.len()];
+
+    for item in accept.split(',') {
+        let mut params = item.split(';').map(str::trim);
+        let coding = params.next().unwrap_or_default().to_ascii_lowercase();
+        let q = params
+            .find_map(|param| param.strip_prefix("q=").or(param.strip_prefix("Q=")))
+            .map_or(Some(1.0), |q| q.parse::<f32>().ok());
+        let Some(q) = q else {
+            continue;
+        };
+
+        match coding.as_str() {
+            "*" => wildcard = Some(q),
+            coding => {
+                if let Some(i) = Encoding::ALL.iter().position(|e| e.name() == coding) {
+                    weights[i] = Some(q);
+                }
+            }
+        }
+    }
+
+    let mut accepted = Encoding::ALL
+        .into_iter()
+        .zip(weights)
+        .filter_map(|(encoding, q)| match q.or(wildcard) {
+            Some(q) if q > 0.0 => Some((encoding, q)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    // Stable, so equal weights keep the server's preference
+    accepted.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+    accepted.into_iter().map(|(encoding, _)| encoding).collect()
+}
+
+/// Whether a file of this MIME type benefits from compression
+pub fn is_compressible(mime: &str) -> bool {
+    mime.starts_with("text/")
+        || mime.ends_with("+xml")
+        || mime.ends_with("+json")
+        || matches!(
+            mime,
+            "application/javascript"
+                | "application/json"
+                | "application/xml"
+                | "application/wasm"
+                | "font/ttf"
+                | "font/otf"
+        )
+}
@@ -0,0 +1,576 @@
+use async_compression::{
+    Level,
+    tokio::bufread::{BrotliEncoder, GzipEncoder},
+};
+use futures::{Stream, StreamExt, stream};
+use hyper::{
+    HeaderMap, Method, Response, StatusCode,
+    body::{Bytes, Frame},
+    header::{
+        ACCEPT_RANGES, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_RANGE,
+        CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, RANGE, VARY,
+    },
+};
+use libserver::{
+    BodyInner, Request, ServiceBoxFuture, ServiceError, ServiceResponse, make_body_from_stream,
+    single_frame_body,
+};
+use rgpt_cfg::Config;
+use std::{
+    fs::Metadata,
+    io::{self, SeekFrom},
+    path::{Component, Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    fs::{self, File},
+    io::{AsyncRead, AsyncReadExt, AsyncSeekExt, BufReader},
+};
+use tokio_util::codec::{BytesCodec, FramedRead};
+
+pub mod encoding;
+pub mod range;
+
+use encoding::Encoding;
+use range::{ByteRange, RangeRequest};
+
+/// Files smaller than this are not worth compressing on the fly
+const MIN_COMPRESS_LEN: u64 = 1024;
+
+type FileStream = Pin<Box<dyn Stream<Item = BodyInner> + Send + Sync>>;
+
+#[derive(Clone)]
+pub struct StaticAssetService {
+    path: PathBuf,
+    options: Arc<StaticOptions>,
+}
+
+impl StaticAssetService {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self::with_options(path, StaticOptions::default())
+    }
+
+    pub fn with_options(path: impl Into<PathBuf>, options: StaticOptions) -> Self {
+        Self {
+            path: path.into(),
+            options: Arc::new(options),
+        }
+    }
+}
+
+/// Caching and encoding behaviour of the static asset service
+#[derive(Clone, Debug)]
+pub struct StaticOptions {
+    /// `Cache-Control` for files without a content hash in
+    /// their name, such as `index.html`
+    pub cache_control: String,
+
+    /// `Cache-Control` for Vite's content-hashed build output
+    /// under `assets/`, which never changes once served
+    pub immutable_cache_control: String,
+
+    /// Whether compressible files without a `.br` or `.gz`
+    /// sibling are compressed while they are streamed
+    pub compress_on_the_fly: bool,
+
+    /// Whether unknown paths outside of `assets/` without a
+    /// file extension are answered with `index.html`, so that
+    /// client-side routes survive a reload
+    pub spa_fallback: bool,
+
+    /// A page, relative to the static dir, sent as the body
+    /// of `404 Not Found` responses
+    pub not_found_page: Option<PathBuf>,
+}
+
+impl StaticOptions {
+    pub fn from_config(config: &Config) -> Self {
+        StaticOptions {
+            cache_control: config.static_cache_control.clone(),
+            immutable_cache_control: config.static_immutable_cache_control.clone(),
+            compress_on_the_fly: config.static_compress_on_the_fly,
+            spa_fallback: config.static_spa_fallback,
+            not_found_page: config.static_not_found_page.clone(),
+        }
+    }
+
+    fn cache_control_for(&self, rel_path: &Path) -> &str {
+        if is_hashed_asset(rel_path) {
+            &self.immutable_cache_control
+        } else {
+            &self.cache_control
+        }
+    }
+}
+
+impl Default for StaticOptions {
+    fn default() -> Self {
+        StaticOptions {
+            cache_control: "no-cache".into(),
+            immutable_cache_control: "public, max-age=31536000, immutable".into(),
+            compress_on_the_fly: false,
+            spa_fallback: false,
+            not_found_page: None,
+        }
+    }
+}
+
+impl tower::Service<Request> for StaticAssetService {
+    type Response = ServiceResponse;
+    type Error = ServiceError;
+    type Future = ServiceBoxFuture;
+
+    fn poll_ready(
+        &mut self,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        Box::pin(serve_static(self.path.clone(), self.options.clone(), req))
+    }
+}
+
+/// The form in which a file is sent to the client
+struct Representation {
+    path: PathBuf,
+    metadata: Metadata,
+    encoding: Option<Encoding>,
+    /// Whether `encoding` is applied while streaming
+    /// rather than read from a precompressed sibling
+    compress: bool,
+}
+
+impl Representation {
+    fn identity(path: PathBuf, metadata: Metadata) -> Self {
+        Representation {
+            path,
+            metadata,
+            encoding: None,
+            compress: false,
+        }
+    }
+
+    /// Each encoding gets its own tag, and on-the-fly output
+    /// isn't byte-for-byte reproducible so its tag is weak
+    fn etag(&self) -> String {
+        let mtime = self
+            .metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .unwrap_or_default();
+        let tag = format!("{:x}-{:x}", mtime.as_secs(), self.metadata.len());
+
+        match (self.encoding, self.compress) {
+            (None, _) => format!("\"{tag}\""),
+            (Some(encoding), false) => format!("\"{tag}-{}\"", encoding.extension()),
+            (Some(encoding), true) => format!("W/\"{tag}-{}\"", encoding.extension()),
+        }
+    }
+}
+
+async fn serve_static(
+    base: PathBuf,
+    options: Arc<StaticOptions>,
+    req: Request,
+) -> Result<ServiceResponse, ServiceError> {
+    if req.method() != Method::GET && req.method() != Method::HEAD {
+        return status_response(StatusCode::METHOD_NOT_ALLOWED);
+    }
+
+    let Some(rel_path) = parse_path(req.uri().path()) else {
+        return status_response(StatusCode::BAD_REQUEST);
+    };
+
+    let base = fs::canonicalize(base).await?;
+    let (path, cache_control) = match resolve_path(&base, &rel_path).await {
+        Ok(path) => (path, options.cache_control_for(&rel_path)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            if !(options.spa_fallback && is_spa_route(&rel_path)) {
+                return not_found(&base, &options).await;
+            }
+            match resolve_path(&base, Path::new("index.html")).await {
+                // The shell must be revalidated so a deploy is picked
+                // up by every client-side route, not just `/`
+                Ok(index) => (index, "no-cache"),
+                Err(_) => return not_found(&base, &options).await,
+            }
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    let headers = req.headers();
+    let mime = parse_mime(&path);
+    let metadata = fs::metadata(&path).await?;
+
+    // Byte ranges always refer to the identity encoding,
+    // which is what media players expect when seeking
+    let repr = if headers.contains_key(RANGE) {
+        Representation::identity(path, metadata)
+    } else {
+        select_representation(&base, path, metadata, &mime, headers, &options).await
+    };
+
+    let etag = repr.etag();
+    let last_modified = repr.metadata.modified().ok().map(truncate_to_secs);
+
+    let builder = Response::builder()
+        .header(ETAG, &etag)
+        .header(CACHE_CONTROL, cache_control)
+        .header(VARY, "Accept-Encoding");
+    let builder = match last_modified {
+        Some(time) => builder.header(LAST_MODIFIED, httpdate::fmt_http_date(time)),
+        None => builder,
+    };
+
+    if is_not_modified(headers, &etag, last_modified) {
+        return Ok(builder
+            .status(StatusCode::NOT_MODIFIED)
+            .body(single_frame_body(""))?);
+    }
+
+    let is_head = req.method() == Method::HEAD;
+
+    let Some(encoding) = repr.encoding else {
+        let file_len = repr.metadata.len();
+        let builder = builder.header(ACCEPT_RANGES, "bytes");
+
+        return match range::range_request(headers, &etag, last_modified, file_len) {
+            RangeRequest::Full => {
+                let builder = builder
+                    .status(StatusCode::OK)
+                    .header(CONTENT_TYPE, &mime)
+                    .header(CONTENT_LENGTH, file_len);
+                if is_head {
+                    return Ok(builder.body(single_frame_body(""))?);
+                }
+                let stream = get_file_stream(&repr.path, None).await?;
+                Ok(builder.body(make_body_from_stream(stream))?)
+            }
+            RangeRequest::Partial(ranges) => {
+                serve_ranges(builder, &repr.path, &mime, file_len, ranges, is_head).await
+            }
+            RangeRequest::Unsatisfiable => Ok(builder
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(CONTENT_RANGE, format!("bytes */{file_len}"))
+                .body(single_frame_body(""))?),
+        };
+    };
+
+    let builder = builder
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, &mime)
+        .header(CONTENT_ENCODING, encoding.name());
+
+    // The compressed length isn't known until the file has been streamed
+    let builder = if repr.compress {
+        builder
+    } else {
+        builder.header(CONTENT_LENGTH, repr.metadata.len())
+    };
+
+    if is_head {
+        return Ok(builder.body(single_frame_body(""))?);
+    }
+
+    let stream = if repr.compress {
+        get_compressed_stream(&repr.path, encoding).await?
+    } else {
+        get_file_stream(&repr.path, None).await?
+    };
+    Ok(builder.body(make_body_from_stream(stream))?)
+}
+
+/// Picks the best encoding of `path` the client accepts
+///
+/// A precompressed `.br` or `.gz` sibling is preferred, then on-the-fly
+/// compression when enabled, and finally the file as-is.
+async fn select_representation(
+    base: &Path,
+    path: PathBuf,
+    metadata: Metadata,
+    mime: &str,
+    headers: &HeaderMap,
+    options: &StaticOptions,
+) -> Representation {
+    let accepted = encoding::accepted_encodings(headers);
+
+    for &encoding in &accepted {
+        if let Some((sibling, sibling_metadata)) = find_precompressed(base, &path, encoding).await {
+            return Representation {
+                path: sibling,
+                metadata: sibling_metadata,
+                encoding: Some(encoding),
+                compress: false,
+            };
+        }
+    }
+
+    let compress = options.compress_on_the_fly
+        && metadata.len() >= MIN_COMPRESS_LEN
+        && encoding::is_compressible(mime);
+
+    match accepted.first() {
+        Some(&encoding) if compress => Representation {
+            path,
+            metadata,
+            encoding: Some(encoding),
+            compress: true,
+        },
+        _ => Representation::identity(path, metadata),
+    }
+}
+
+async fn find_precompressed(
+    base: &Path,
+    path: &Path,
+    encoding: Encoding,
+) -> Option<(PathBuf, Metadata)> {
+    let mut sibling = path.as_os_str().to_owned();
+    sibling.push(".");
+    sibling.push(encoding.extension());
+
+    let sibling = fs::canonicalize(sibling).await.ok()?;
+    let metadata = fs::metadata(&sibling).await.ok()?;
+
+    (sibling.starts_with(base) && metadata.is_file()).then_some((sibling, metadata))
+}
+
+/// Responds with `206 Partial Content`, using a
+/// `multipart/byteranges` body for more than one range
+async fn serve_ranges(
+    builder: http::response::Builder,
+    path: &Path,
+    mime: &str,
+    file_len: u64,
+    ranges: Vec<ByteRange>,
+    is_head: bool,
+) -> Result<ServiceResponse, ServiceError> {
+    let builder = builder.status(StatusCode::PARTIAL_CONTENT);
+
+    if let [range] = ranges[..] {
+        let builder = builder
+            .header(CONTENT_TYPE, mime)
+            .header(CONTENT_RANGE, range.content_range(file_len))
+            .header(CONTENT_LENGTH, range.len());
+        if is_head {
+            return Ok(builder.body(single_frame_body(""))?);
+        }
+        let stream = get_file_stream(path, Some(range)).await?;
+        return Ok(builder.body(make_body_from_stream(stream))?);
+    }
+
+    let boundary = uuid::Uuid::new_v4().simple().to_string();
+    let part_headers = ranges
+        .iter()
+        .map(|range| {
+            format!(
+                "\r\n--{boundary}\r\nContent-Type: {mime}\r\nContent-Range: {}\r\n\r\n",
+                range.content_range(file_len)
+            )
+        })
+        .collect::<Vec<_>>();
+    let closing = format!("\r\n--{boundary}--\r\n");
+
+    let content_len = part_headers.iter().map(|h| h.len() as u64).sum::<u64>()
+        + ranges.iter().map(ByteRange::len).sum::<u64>()
+        + closing.len() as u64;
+
+    let builder = builder
+        .header(
+            CONTENT_TYPE,
+            format!("multipart/byteranges; boundary={boundary}"),
+        )
+        .header(CONTENT_LENGTH, content_len);
+
+    if is_head {
+        return Ok(builder.body(single_frame_body(""))?);
+    }
+
+    let mut parts: Vec<FileStream> = Vec::with_capacity(ranges.len() + 1);
+    for (range, part_header) in ranges.into_iter().zip(part_headers) {
+        let header = stream::once(async move { data_frame(part_header) });
+        parts.push(Box::pin(
+            header.chain(get_file_stream(path, Some(range)).await?),
+        ));
+    }
+    parts.push(Box::pin(stream::once(async move { data_frame(closing) })));
+
+    Ok(builder.body(make_body_from_stream(stream::iter(parts).flatten()))?)
+}
+
+/// Turns the request path into a path relative to the static dir
+///
+/// Returns `None` for anything that could step outside of it,
+/// including percent-encoded `..` segments.
+pub fn parse_path(uri_path: &str) -> Option<PathBuf> {
+    let decoded = urlencoding::decode(uri_path).ok()?;
+    if decoded.contains(['\0', '\\']) {
+        return None;
+    }
+
+    let mut rel_path = PathBuf::new();
+    for segment in decoded.split('/') {
+        match segment {
+            "" | "." => continue,
+            ".." => return None,
+            segment => {
+                let mut components = Path::new(segment).components();
+                match (components.next(), components.next()) {
+                    (Some(Component::Normal(_)), None) => rel_path.push(segment),
+                    _ => return None,
+                }
+            }
+        }
+    }
+    Some(rel_path)
+}
+
+/// Finds the file to serve within the canonical `base`,
+/// following symlinks
+///
+/// A symlink that resolves outside of the static dir is treated
+/// as if the file did not exist.
+pub async fn resolve_path(base: &Path, rel_path: &Path) -> io::Result<PathBuf> {
+    let mut path = fs::canonicalize(base.join(rel_path)).await?;
+    if path.is_dir() {
+        path = fs::canonicalize(path.join("index.html")).await?;
+    }
+
+    if path.starts_with(base) && path.is_file() {
+        Ok(path)
+    } else {
+        Err(io::ErrorKind::NotFound.into())
+    }
+}
+
+/// Whether a missing path looks like a client-side route
+/// rather than a file the page tried to load, or an API
+/// path that no route matched
+fn is_spa_route(rel_path: &Path) -> bool {
+    let reserved = ["assets", "api", "v1"];
+    !reserved.iter().any(|prefix| rel_path.starts_with(prefix)) && rel_path.extension().is_none()
+}
+
+/// Responds with `404 Not Found`, using the configured
+/// not found page as the body if there is one
+async fn not_found(base: &Path, options: &StaticOptions) -> Result<ServiceResponse, ServiceError> {
+    let page = match &options.not_found_page {
+        Some(page) => resolve_path(base, page).await.ok(),
+        None => None,
+    };
+
+    let Some(page) = page else {
+        return status_response(StatusCode::NOT_FOUND);
+    };
+
+    let body = fs::read(&page).await?;
+    Ok(Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .header(CONTENT_TYPE, parse_mime(&page))
+        .header(CACHE_CONTROL, "no-cache")
+        .body(single_frame_body(body))?)
+}
+
+/// Whether the file is one of Vite's `assets/[name]-[hash].[ext]` outputs
+fn is_hashed_asset(rel_path: &Path) -> bool {
+    if !rel_path.starts_with("assets") {
+        return false;
+    }
+
+    let Some(stem) = rel_path.file_stem().and_then(|stem| stem.to_str()) else {
+        return false;
+    };
+
+    const HASH_LEN: usize = 8;
+    match stem.len().checked_sub(HASH_LEN + 1) {
+        Some(dash) => {
+            stem.as_bytes()[dash] == b'-'
+                && stem[dash + 1..]
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+        }
+        None => false,
+    }
+}
+
+fn truncate_to_secs(time: SystemTime) -> SystemTime {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+/// Evaluates `If-None-Match` and `If-Modified-Since`
+///
+/// `If-Modified-Since` is ignored when `If-None-Match` is present,
+/// as required by RFC 9110.
+pub fn is_not_modified(headers: &HeaderMap, etag: &str, last_modified: Option<SystemTime>) -> bool {
+    if let Some(if_none_match) = headers.get(IF_NONE_MATCH) {
+        let Ok(if_none_match) = if_none_match.to_str() else {
+            return false;
+        };
+        return if_none_match.split(',').map(str::trim).any(|tag| {
+            tag == "*" || tag.trim_start_matches("W/") == etag.trim_start_matches("W/")
+        });
+    }
+
+    let since = headers
+        .get(IF_MODIFIED_SINCE)
+        .and_then(|since| since.to_str().ok())
+        .and_then(|since| httpdate::parse_http_date(since).ok());
+
+    match (since, last_modified) {
+        (Some(since), Some(last_modified)) => last_modified <= since,
+        _ => false,
+    }
+}
+
+fn status_response(status: StatusCode) -> Result<ServiceResponse, ServiceError> {
+    Ok(Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "text/plain; charset=utf-8")
+        .body(single_frame_body(status.canonical_reason().unwrap_or("")))?)
+}
+
+fn parse_mime(path: &Path) -> String {
+    mime_guess::from_path(path)
+        .first_or_octet_stream()
+        .to_string()
+}
+
+async fn get_file_stream(path: &Path, range: Option<ByteRange>) -> io::Result<FileStream> {
+    let mut file = File::open(path).await?;
+    match range {
+        Some(range) => {
+            file.seek(SeekFrom::Start(range.start)).await?;
+            Ok(frame_stream(file.take(range.len())))
+        }
+        None => Ok(frame_stream(file)),
+    }
+}
+
+async fn get_compressed_stream(path: &Path, encoding: Encoding) -> io::Result<FileStream> {
+    let file = BufReader::new(File::open(path).await?);
+    match encoding {
+        Encoding::Brotli => Ok(frame_stream(BrotliEncoder::with_quality(
+            file,
+            Level::Precise(4),
+        ))),
+        Encoding::Gzip => Ok(frame_stream(GzipEncoder::new(file))),
+    }
+}
+
+fn frame_stream(reader: impl AsyncRead + Send + Sync + 'static) -> FileStream {
+    let stream = FramedRead::new(reader, BytesCodec::new());
+    Box::pin(stream.map(|f| -> BodyInner { data_frame(f?) }))
+}
+
+fn data_frame(data: impl Into<Bytes>) -> BodyInner {
+    Ok(Frame::data(data.into()))
+}
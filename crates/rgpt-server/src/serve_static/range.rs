@@ -0,0 +1,153 @@
+use std::time::SystemTime;
+
+use hyper::{
+    HeaderMap,
+    header::{IF_RANGE, RANGE},
+};
+
+/// Requests with more ranges than this are served in full
+/// rather than as a multipart response
+const MAX_RANGES: usize = 32;
+
+/// An inclusive range of bytes within a file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    pub fn len(&self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    pub fn content_range(&self, file_len: u64) -> String {
+        format!("bytes {}-{}/{}", self.start, self.end, file_len)
+    }
+}
+
+/// What to serve for a request's `Range` header
+#[derive(Debug, PartialEq, Eq)]
+pub enum RangeRequest {
+    /// No usable `Range` header, so the whole file is served
+    Full,
+    /// The ranges to serve, sorted and with overlaps merged
+    Partial(Vec<ByteRange>),
+    /// None of the requested ranges overlap the file
+    Unsatisfiable,
+}
+
+/// Decides which part of a file of `file_len` bytes to serve
+///
+/// The `Range` header is only honoured when `If-Range`, if present,
+/// still matches the current representation.
+pub fn range_request(
+    headers: &HeaderMap,
+    etag: &str,
+    last_modified: Option<SystemTime>,
+    file_len: u64,
+) -> RangeRequest {
+    let Some(range) = headers.get(RANGE).and_then(|range| range.to_str().ok()) else {
+        return RangeRequest::Full;
+    };
+
+    if !if_range_matches(headers, etag, last_modified) {
+        return RangeRequest::Full;
+    }
+
+    parse_range(range, file_len)
+}
+
+fn if_range_matches(headers: &HeaderMap, etag: &str, last_modified: Option<SystemTime>) -> bool {
+    let Some(if_range) = headers.get(IF_RANGE) else {
+        return true;
+    };
+    let Ok(if_range) = if_range.to_str() else {
+        return false;
+    };
+
+    // `If-Range` requires a strong comparison, so weak
+    // validators on either side never match
+    if if_range.starts_with('"') {
+        return !etag.starts_with("W/") && if_range == etag;
+    }
+    if if_range.starts_with("W/") {
+        return false;
+    }
+
+    match (httpdate::parse_http_date(if_range), last_modified) {
+        (Ok(date), Some(last_modified)) => date == last_modified,
+        _ => false,
+    }
+}
+
+/// Parses a `bytes=` range header as described in RFC 9110
+///
+/// A header that can't be parsed is ignored rather than rejected.
+pub fn parse_range(header: &str, file_len: u64) -> RangeRequest {
+    let Some(specs) = header.trim().strip_prefix("bytes=") else {
+        return RangeRequest::Full;
+    };
+
+    let mut ranges = Vec::new();
+    for spec in specs.split(',').map(str::trim) {
+        let Some((start, end)) = spec.split_once('-') else {
+            return RangeRequest::Full;
+        };
+
+        let range = match (start.trim(), end.trim()) {
+            ("", "") => return RangeRequest::Full,
+            ("", suffix) => {
+                let Ok(suffix) = suffix.parse::<u64>() else {
+                    return RangeRequest::Full;
+                };
+                (suffix > 0 && file_len > 0).then(|| ByteRange {
+                    start: file_len.saturating_sub(suffix),
+                    end: file_len - 1,
+                })
+            }
+            (start, end) => {
+                let Ok(start) = start.parse::<u64>() else {
+                    return RangeRequest::Full;
+                };
+                let end = match end {
+                    "" => u64::MAX,
+                    end => match end.parse::<u64>() {
+                        Ok(end) if end >= start => end,
+                        _ => return RangeRequest::Full,
+                    },
+                };
+                (start < file_len).then(|| ByteRange {
+                    start,
+                    end: end.min(file_len - 1),
+                })
+            }
+        };
+
+        ranges.extend(range);
+    }
+
+    if ranges.is_empty() {
+        return RangeRequest::Unsatisfiable;
+    }
+    if ranges.len() > MAX_RANGES {
+        return RangeRequest::Full;
+    }
+
+    RangeRequest::Partial(merge_ranges(ranges))
+}
+
+fn merge_ranges(mut ranges: Vec<ByteRange>) -> Vec<ByteRange> {
+    ranges.sort_by_key(|range| range.start);
+
+    let mut merged: Vec<ByteRange> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end.saturating_add(1) => {
+                last.end = last.end.max(range.end);
+            }
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
@@ -0,0 +1,161 @@
+use async_trait::async_trait;
+use libserver::ServiceError;
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use super::{InvalidToolArguments, Tool, ToolContext};
+
+/// Longer expressions are refused, which also bounds
+/// how deeply the parser can recurse
+const MAX_EXPRESSION_LEN: usize = 1000;
+
+/// Evaluates arithmetic, which models are unreliable at
+pub struct Calculator;
+
+#[derive(Deserialize)]
+struct Arguments {
+    expression: String,
+}
+
+#[async_trait]
+impl Tool for Calculator {
+    fn name(&self) -> &'static str {
+        "calculator"
+    }
+
+    fn description(&self) -> &'static str {
+        "Evaluates an arithmetic expression. Supports numbers, + - * / % ^ and parentheses."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "expression": {
+                    "type": "string",
+                    "description": "The expression to evaluate, like (2 + 3) * 4 ^ 2",
+                },
+            },
+            "required": ["expression"],
+        })
+    }
+
+    async fn call(&self, _cx: &ToolContext, arguments: Value) -> Result<String, ServiceError> {
+        let Arguments { expression } =
+            serde_json::from_value(arguments).map_err(|_| InvalidToolArguments)?;
+        Ok(evaluate(&expression)?.to_string())
+    }
+}
+
+/// Evaluates an arithmetic expression
+///
+/// `^` binds tightest and is right-associative, then unary minus,
+/// then `*`, `/` and `%`, then `+` and `-`.
+pub fn evaluate(expression: &str) -> Result<f64, InvalidExpression> {
+    if expression.len() > MAX_EXPRESSION_LEN {
+        return Err(InvalidExpression);
+    }
+    let mut parser = Parser {
+        chars: expression.chars().filter(|c| !c.is_whitespace()).collect(),
+        pos: 0,
+    };
+    let value = parser.sum()?;
+    // Trailing input means the expression didn't parse as a whole,
+    // and dividing by zero isn't an answer worth giving the model
+    if parser.pos != parser.chars.len() || !value.is_finite() {
+        return Err(InvalidExpression);
+    }
+    Ok(value)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let matched = self.peek() == Some(c);
+        if matched {
+            self.pos += 1;
+        }
+        matched
+    }
+
+    fn sum(&mut self) -> Result<f64, InvalidExpression> {
+        let mut value = self.product()?;
+        loop {
+            if self.eat('+') {
+                value += self.product()?;
+            } else if self.eat('-') {
+                value -= self.product()?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn product(&mut self) -> Result<f64, InvalidExpression> {
+        let mut value = self.unary()?;
+        loop {
+            if self.eat('*') {
+                value *= self.unary()?;
+            } else if self.eat('/') {
+                value /= self.unary()?;
+            } else if self.eat('%') {
+                value %= self.unary()?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<f64, InvalidExpression> {
+        if self.eat('-') {
+            Ok(-self.unary()?)
+        } else if self.eat('+') {
+            self.unary()
+        } else {
+            self.power()
+        }
+    }
+
+    fn power(&mut self) -> Result<f64, InvalidExpression> {
+        let base = self.atom()?;
+        if self.eat('^') {
+            // Right-associative, and binds tighter than a minus
+            // on its base but not one on its exponent
+            Ok(base.powf(self.unary()?))
+        } else {
+            Ok(base)
+        }
+    }
+
+    fn atom(&mut self) -> Result<f64, InvalidExpression> {
+        if self.eat('(') {
+            let value = self.sum()?;
+            if !self.eat(')') {
+                return Err(InvalidExpression);
+            }
+            return Ok(value);
+        }
+
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit() || c == '.') {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos]
+            .iter()
+            .collect::<String>()
+            .parse()
+            .map_err(|_| InvalidExpression)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid Expression")]
+pub struct InvalidExpression;
@@ -0,0 +1,88 @@
+use async_trait::async_trait;
+use libserver::ServiceError;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use super::{InvalidToolArguments, Tool, ToolContext};
+
+/// The most messages a search returns
+const MAX_RESULTS: usize = 10;
+
+/// How much of each message is returned
+const SNIPPET_LEN: usize = 300;
+
+/// Finds messages in the user's own chats, so the model
+/// can recall earlier conversations
+pub struct ChatSearch;
+
+#[derive(Deserialize)]
+struct Arguments {
+    query: String,
+    limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct Match {
+    sender: String,
+    text: String,
+    created_at: String,
+}
+
+#[async_trait]
+impl Tool for ChatSearch {
+    fn name(&self) -> &'static str {
+        "search_chats"
+    }
+
+    fn description(&self) -> &'static str {
+        "Searches the user's earlier chats for messages containing some text, newest first."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "query": {
+                    "type": "string",
+                    "description": "The text to look for, ignoring case",
+                },
+                "limit": {
+                    "type": "integer",
+                    "description": format!("The most messages to return, at most {MAX_RESULTS}"),
+                },
+            },
+            "required": ["query"],
+        })
+    }
+
+    async fn call(&self, cx: &ToolContext, arguments: Value) -> Result<String, ServiceError> {
+        let Arguments { query, limit } =
+            serde_json::from_value(arguments).map_err(|_| InvalidToolArguments)?;
+        let query = query.trim();
+        if query.is_empty() {
+            Err(InvalidToolArguments)?;
+        }
+
+        if crate::is_guest(cx.user_id) {
+            return Ok("Chat search is not available to guests".into());
+        }
+
+        let limit = limit.unwrap_or(MAX_RESULTS).clamp(1, MAX_RESULTS);
+        let matches = cx
+            .store
+            .search_msgs(cx.user_id, query, limit)
+            .await?
+            .into_iter()
+            .map(|msg| Match {
+                text: match msg.body.char_indices().nth(SNIPPET_LEN) {
+                    Some((end, _)) => format!("{}...", &msg.body[..end]),
+                    None => msg.body,
+                },
+                sender: msg.sender,
+                created_at: msg.created_at.format("%Y-%m-%d %H:%M").to_string(),
+            })
+            .collect::<Vec<_>>();
+
+        Ok(serde_json::to_string(&matches)?)
+    }
+}
@@ -0,0 +1,52 @@
+use async_trait::async_trait;
+use chrono::{FixedOffset, Utc};
+use libserver::ServiceError;
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use super::{InvalidToolArguments, Tool, ToolContext};
+
+/// Tells the model the current date and time, which
+/// it can't know from its training data
+pub struct CurrentDatetime;
+
+#[derive(Deserialize)]
+struct Arguments {
+    #[serde(default)]
+    utc_offset_minutes: i32,
+}
+
+#[async_trait]
+impl Tool for CurrentDatetime {
+    fn name(&self) -> &'static str {
+        "current_datetime"
+    }
+
+    fn description(&self) -> &'static str {
+        "Returns the current date, time and weekday, in UTC unless an offset is given."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "utc_offset_minutes": {
+                    "type": "integer",
+                    "description": "The time zone's offset from UTC in minutes, like -300 for UTC-5",
+                },
+            },
+        })
+    }
+
+    async fn call(&self, _cx: &ToolContext, arguments: Value) -> Result<String, ServiceError> {
+        let Arguments { utc_offset_minutes } =
+            serde_json::from_value(arguments).map_err(|_| InvalidToolArguments)?;
+        let offset = utc_offset_minutes
+            .checked_mul(60)
+            .and_then(FixedOffset::east_opt)
+            .ok_or(InvalidToolArguments)?;
+
+        let now = Utc::now().with_timezone(&offset);
+        Ok(now.format("%A, %Y-%m-%d %H:%M:%S %:z").to_string())
+    }
+}
@@ -0,0 +1,229 @@
+//! Tools the model can call while replying
+//!
+//! Each tool describes its arguments with a JSON schema, which is
+//! sent to the model with the request, and runs on the server when
+//! the model calls it. A reply runs model → tool calls → tool
+//! results → model until the model answers in text, and every call
+//! is saved in the message tree as a `tool` message holding a
+//! [`ToolRecord`].
+
+use std::sync::Arc;
+
+use async_openai::{
+    error::OpenAIError,
+    types::{
+        ChatCompletionMessageToolCall, ChatCompletionMessageToolCallChunk,
+        ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestMessage,
+        ChatCompletionRequestToolMessageArgs, ChatCompletionTool, ChatCompletionToolType,
+        FunctionCall, FunctionObject,
+    },
+};
+use async_trait::async_trait;
+use libserver::ServiceError;
+use rgpt_db::store::Store;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+pub mod calculator;
+pub mod chat_search;
+pub mod datetime;
+
+use calculator::Calculator;
+use chat_search::ChatSearch;
+use datetime::CurrentDatetime;
+
+/// The sender of messages that record a tool call
+pub const TOOL_SENDER: &str = "tool";
+
+/// The most calls a single model turn may make, which also
+/// bounds the indices a streamed call chunk can claim
+const MAX_CALLS_PER_TURN: usize = 16;
+
+/// A function the model can call
+#[async_trait]
+pub trait Tool: Send + Sync {
+    /// The name the model calls the tool by
+    fn name(&self) -> &'static str;
+
+    /// Tells the model what the tool does and when to use it
+    fn description(&self) -> &'static str;
+
+    /// The JSON schema of the tool's arguments
+    fn parameters(&self) -> Value;
+
+    /// Runs the tool, returning the text the model is given
+    ///
+    /// Errors are reported to the model as the result, so it
+    /// can correct its arguments or answer without the tool.
+    async fn call(&self, cx: &ToolContext, arguments: Value) -> Result<String, ServiceError>;
+}
+
+/// What a tool call runs on behalf of
+pub struct ToolContext {
+    pub store: Arc<dyn Store>,
+    pub user_id: i32,
+    pub chat_id: i32,
+}
+
+/// The tools available to a reply
+pub struct ToolRegistry {
+    tools: Vec<Box<dyn Tool>>,
+}
+
+impl ToolRegistry {
+    /// Every built-in tool
+    pub fn builtin() -> Self {
+        ToolRegistry {
+            tools: vec![
+                Box::new(Calculator),
+                Box::new(CurrentDatetime),
+                Box::new(ChatSearch),
+            ],
+        }
+    }
+
+    /// The built-in tools named in `names`
+    pub fn enabled(names: &[String]) -> Self {
+        let mut registry = ToolRegistry::builtin();
+        registry
+            .tools
+            .retain(|tool| names.iter().any(|name| name == tool.name()));
+        registry
+    }
+
+    /// No tools, for replies that mustn't call any
+    pub fn none() -> Self {
+        ToolRegistry { tools: vec![] }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tools.is_empty()
+    }
+
+    /// The tools as they are described to the model
+    pub fn definitions(&self) -> Vec<ChatCompletionTool> {
+        self.tools
+            .iter()
+            .map(|tool| ChatCompletionTool {
+                r#type: ChatCompletionToolType::Function,
+                function: FunctionObject {
+                    name: tool.name().into(),
+                    description: Some(tool.description().into()),
+                    parameters: Some(tool.parameters()),
+                    strict: None,
+                },
+            })
+            .collect()
+    }
+
+    /// Runs a call the model made, returning its record
+    pub async fn call(&self, cx: &ToolContext, call: ChatCompletionMessageToolCall) -> ToolRecord {
+        let FunctionCall { name, arguments } = call.function;
+
+        let result = match self.tools.iter().find(|tool| tool.name() == name) {
+            Some(tool) => match serde_json::from_str(&arguments) {
+                Ok(parsed) => tool.call(cx, parsed).await,
+                Err(err) => Err(err.into()),
+            },
+            None => Err(UnknownTool.into()),
+        };
+
+        ToolRecord {
+            id: call.id,
+            name,
+            arguments,
+            result: result.unwrap_or_else(|err| format!("error: {err}")),
+        }
+    }
+}
+
+/// The tool calls of a streamed model turn, which arrive
+/// as fragments keyed by each call's index
+#[derive(Default)]
+pub struct ToolCallChunks {
+    calls: Vec<ChatCompletionMessageToolCall>,
+}
+
+impl ToolCallChunks {
+    pub fn push(&mut self, chunk: ChatCompletionMessageToolCallChunk) {
+        let index = chunk.index as usize;
+        if index >= MAX_CALLS_PER_TURN {
+            return;
+        }
+        if self.calls.len() <= index {
+            self.calls
+                .resize_with(index + 1, || ChatCompletionMessageToolCall {
+                    id: String::new(),
+                    r#type: ChatCompletionToolType::Function,
+                    function: FunctionCall {
+                        name: String::new(),
+                        arguments: String::new(),
+                    },
+                });
+        }
+
+        let call = &mut self.calls[index];
+        if let Some(id) = chunk.id {
+            call.id = id;
+        }
+        if let Some(function) = chunk.function {
+            call.function
+                .name
+                .push_str(&function.name.unwrap_or_default());
+            call.function
+                .arguments
+                .push_str(&function.arguments.unwrap_or_default());
+        }
+    }
+
+    /// The complete calls, skipping any the model left without a name
+    pub fn into_calls(self) -> Vec<ChatCompletionMessageToolCall> {
+        self.calls
+            .into_iter()
+            .filter(|call| !call.function.name.is_empty())
+            .collect()
+    }
+}
+
+/// A tool call and its result, saved as the body of a `tool` message
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ToolRecord {
+    /// The id the model gave the call
+    pub id: String,
+    pub name: String,
+    /// The arguments as the model wrote them, in JSON
+    pub arguments: String,
+    pub result: String,
+}
+
+impl ToolRecord {
+    /// The call and its result as the messages the model expects:
+    /// an assistant message making the call, and the tool's reply
+    pub fn request_messages(self) -> Result<[ChatCompletionRequestMessage; 2], OpenAIError> {
+        let call = ChatCompletionRequestAssistantMessageArgs::default()
+            .tool_calls(vec![ChatCompletionMessageToolCall {
+                id: self.id.clone(),
+                r#type: ChatCompletionToolType::Function,
+                function: FunctionCall {
+                    name: self.name,
+                    arguments: self.arguments,
+                },
+            }])
+            .build()?
+            .into();
+        let result = ChatCompletionRequestToolMessageArgs::default()
+            .tool_call_id(self.id)
+            .content(self.result)
+            .build()?
+            .into();
+        Ok([call, result])
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Unknown Tool")]
+pub struct UnknownTool;
+
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid Tool Arguments")]
+pub struct InvalidToolArguments;
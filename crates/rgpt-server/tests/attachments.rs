@@ -0,0 +1,77 @@
+use rgpt_server::attachments::{AttachmentText, fit_to_budget, media_type};
+
+fn attachment(msg_id: i32, name: &str, text: &str) -> AttachmentText {
+    AttachmentText {
+        msg_id,
+        name: name.into(),
+        text: text.into(),
+    }
+}
+
+#[test]
+fn accepted_documents() {
+    assert_eq!(media_type("notes.txt"), Some("text/plain"));
+    assert_eq!(media_type("README.MD"), Some("text/markdown"));
+    assert_eq!(media_type("data.csv"), Some("text/csv"));
+    assert_eq!(media_type("package.json"), Some("application/json"));
+    assert_eq!(media_type("main.rs"), Some("text/plain"));
+    assert_eq!(media_type("index.ts"), Some("text/plain"));
+
+    for name in [
+        "photo.png",
+        "report.pdf",
+        "archive.tar.gz",
+        "Makefile",
+        ".txt",
+    ] {
+        assert_eq!(media_type(name), None, "{name} was accepted");
+    }
+}
+
+#[test]
+fn attachments_within_budget() {
+    let appended = fit_to_budget(
+        vec![
+            attachment(1, "a.txt", "first"),
+            attachment(1, "b.txt", "second"),
+            attachment(3, "c.txt", "third"),
+        ],
+        100,
+    );
+
+    assert_eq!(
+        appended[&1],
+        "\n\n<attachment name=\"a.txt\">\nfirst\n</attachment>\
+         \n\n<attachment name=\"b.txt\">\nsecond\n</attachment>"
+    );
+    assert_eq!(
+        appended[&3],
+        "\n\n<attachment name=\"c.txt\">\nthird\n</attachment>"
+    );
+}
+
+#[test]
+fn newest_attachments_get_the_budget() {
+    // A budget of 2 tokens is roughly 8 characters
+    let appended = fit_to_budget(
+        vec![
+            attachment(1, "old.txt", "left out entirely"),
+            attachment(2, "mid.txt", "cut short here"),
+            attachment(3, "new.txt", "newest"),
+        ],
+        2,
+    );
+
+    assert_eq!(
+        appended[&3],
+        "\n\n<attachment name=\"new.txt\">\nnewest\n</attachment>"
+    );
+    assert_eq!(
+        appended[&2],
+        "\n\n<attachment name=\"mid.txt\">\ncu\n[cut short]\n</attachment>"
+    );
+    assert_eq!(
+        appended[&1],
+        "\n\n<attachment name=\"old.txt\">[left out, too long]</attachment>"
+    );
+}
@@ -0,0 +1,190 @@
+use rgpt_db::output_filters::OutputFilters;
+use rgpt_server::filters::{Pipeline, filter_text, latex::math_to_ascii};
+
+fn only(filter: &str) -> OutputFilters {
+    OutputFilters {
+        markdown: filter == "markdown",
+        latex: filter == "latex",
+        code_fences: filter == "code_fences",
+        terminal: filter == "terminal",
+        wrap_column: None,
+    }
+}
+
+/// Filters `text` a character at a time, as
+/// if every character were its own chunk
+fn filter_chars(config: &OutputFilters, text: &str) -> String {
+    let mut pipeline = Pipeline::new(config);
+    let mut out = String::new();
+    for c in text.chars() {
+        out += &pipeline.push(&c.to_string());
+    }
+    out + &pipeline.finish()
+}
+
+#[test]
+fn markdown_is_stripped() {
+    let markdown = only("markdown");
+    let cases = [
+        ("**bold** and *italic*", "bold and italic"),
+        ("__bold__ and _italic_", "bold and italic"),
+        ("***both***", "both"),
+        ("~~struck~~ out", "struck out"),
+        ("run `cargo test` now", "run cargo test now"),
+        ("## Heading\nbody", "Heading\nbody"),
+        ("> quoted\n> - item", "quoted\n- item"),
+        ("* one\n+ two\n  - nested", "- one\n- two\n  - nested"),
+        ("above\n***\nbelow", "above\n---\nbelow"),
+        (
+            "see [the docs](https://example.com)",
+            "see the docs (https://example.com)",
+        ),
+        ("[https://a.b](https://a.b)", "https://a.b"),
+        ("![a cat](cat.png)", "a cat (cat.png)"),
+        ("\\*not emphasis\\*", "*not emphasis*"),
+    ];
+    for (text, stripped) in cases {
+        assert_eq!(filter_text(&markdown, text), stripped, "{text:?}");
+    }
+}
+
+#[test]
+fn markdown_leaves_plain_text_alone() {
+    let markdown = only("markdown");
+    for text in [
+        "snake_case_names stay",
+        "2*3 is 6",
+        "a * b",
+        "#hashtag",
+        "-5 degrees",
+        "[not a link] (really)",
+        "an unclosed [bracket",
+        "tilde ~ alone",
+    ] {
+        assert_eq!(filter_text(&markdown, text), text, "{text:?}");
+    }
+}
+
+#[test]
+fn latex_becomes_ascii() {
+    let cases = [
+        (r"\frac{a+1}{2}", "(a+1)/2"),
+        (r"\frac12", "1/2"),
+        (r"x^2 + y_{i,j}", "x^2 + y_(i,j)"),
+        (r"\sqrt{x^2+1}", "sqrt(x^2+1)"),
+        (r"\sqrt[3]{8}", "root(3, 8)"),
+        (r"\alpha \leq \pi", "alpha <= pi"),
+        (r"a \times b \cdot c", "a x b * c"),
+        (r"\binom{n}{k}", "C(n, k)"),
+        (r"\left( \frac{1}{x} \right)", "( 1/x )"),
+        (r"\text{area} = \pi r^2", "area = pi r^2"),
+        (r"\mathbb{R} \to \mathbb{R}", "R -> R"),
+        (r"\sum_{i=1}^{n} i", "sum _(i=1)^n i"),
+        (r"\alpha\beta", "alpha beta"),
+        (r"\int_0^\infty", "integral _0^infinity"),
+    ];
+    for (math, ascii) in cases {
+        assert_eq!(math_to_ascii(math), ascii, "{math:?}");
+    }
+}
+
+#[test]
+fn latex_delimiters() {
+    let latex = only("latex");
+    let cases = [
+        (r"so $x^{2}$ grows", "so x^2 grows"),
+        (r"$$\frac{a}{b}$$", "a/b"),
+        (r"where \(\alpha > 0\)", "where alpha > 0"),
+        ("\\[\n\\sqrt{2}\n\\]", "sqrt(2)"),
+        (r"it costs $5 or $10", "it costs $5 or $10"),
+        (r"between $ and $", "between $ and $"),
+        (r"a \$ sign", r"a \$ sign"),
+        ("unclosed $x\nhere", "unclosed $x\nhere"),
+    ];
+    for (text, filtered) in cases {
+        assert_eq!(filter_text(&latex, text), filtered, "{text:?}");
+    }
+}
+
+#[test]
+fn code_fences_become_indented() {
+    let fences = only("code_fences");
+    assert_eq!(
+        filter_text(&fences, "Try:\n```rust\nfn main() {\n\n}\n```\nDone."),
+        "Try:\n    fn main() {\n\n    }\nDone.",
+    );
+    assert_eq!(filter_text(&fences, "~~~\nplain\n~~~"), "    plain\n");
+    assert_eq!(filter_text(&fences, "``inline`` code"), "``inline`` code");
+}
+
+#[test]
+fn terminal_mode_upper_cases() {
+    let terminal = only("terminal");
+    assert_eq!(
+        filter_text(&terminal, "Hello there\n    let x = 1;\nbye"),
+        "HELLO THERE\n    let x = 1;\nBYE",
+    );
+}
+
+#[test]
+fn wrapping() {
+    let wrap = OutputFilters {
+        wrap_column: Some(20),
+        ..only("wrap")
+    };
+    assert_eq!(
+        filter_text(&wrap, "the quick brown fox jumps over the lazy dog"),
+        "the quick brown fox\njumps over the lazy\ndog",
+    );
+    assert_eq!(filter_text(&wrap, "short\nlines stay"), "short\nlines stay");
+    assert_eq!(
+        filter_text(&wrap, "abcdefghijklmnopqrstuvwxyz"),
+        "abcdefghijklmnopqrst\nuvwxyz",
+    );
+}
+
+#[test]
+fn code_is_protected() {
+    let text = "Use **this**:\n```\nlet s = \"**not bold**\";\nlet m = $x$;\n```\n";
+    let filtered = filter_text(&OutputFilters::default(), text);
+    assert_eq!(
+        filtered,
+        "Use this:\n    let s = \"**not bold**\";\n    let m = $x$;\n",
+    );
+
+    // Without the fence filter, fenced code is still left alone
+    let unfenced = OutputFilters {
+        code_fences: false,
+        ..OutputFilters::default()
+    };
+    assert_eq!(
+        filter_text(&unfenced, "```\n# not a heading\n```\n# heading"),
+        "```\n# not a heading\n```\nheading",
+    );
+}
+
+#[test]
+fn filters_are_chunk_safe() {
+    let text = "# Title\n\nSome **bold**, _italic_ and `code`, with $\\frac{1}{2}$ \
+                and [a link](https://example.com).\n\n```python\nprint('**hi**')\n```\n\
+                > - quoted ~~item~~\n\\[ \\alpha \\leq \\beta \\]\nIt costs $5.\n***\nend";
+    let configs = [
+        OutputFilters::default(),
+        OutputFilters {
+            terminal: true,
+            wrap_column: Some(24),
+            ..OutputFilters::default()
+        },
+        OutputFilters {
+            code_fences: false,
+            ..OutputFilters::default()
+        },
+    ];
+    for config in configs {
+        assert_eq!(
+            filter_chars(&config, text),
+            filter_text(&config, text),
+            "{config:?}",
+        );
+    }
+}
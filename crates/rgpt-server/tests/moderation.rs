@@ -0,0 +1,53 @@
+use std::sync::Arc;
+
+use rgpt_provider::{Action, PolicyModerator, PolicyRule};
+use rgpt_server::moderation::ReplyGate;
+
+fn gate(window: usize) -> ReplyGate {
+    let policy = PolicyModerator::new(&[
+        PolicyRule::keyword("heist", Action::Flag),
+        PolicyRule::keyword("launch codes", Action::Block),
+    ])
+    .unwrap();
+    ReplyGate::new(Arc::new(policy), window)
+}
+
+#[tokio::test]
+async fn replies_are_held_until_checked() {
+    let mut gate = gate(100);
+    assert_eq!(gate.push("Hello").await.unwrap(), "");
+    assert_eq!(gate.push(" there\nHow").await.unwrap(), "Hello there\n");
+    assert_eq!(gate.push(" are").await.unwrap(), "");
+    assert_eq!(gate.finish(" you?").await.unwrap(), "How are you?");
+    assert_eq!(gate.verdict().action, Action::Allow);
+}
+
+#[tokio::test]
+async fn long_lines_are_checked_in_windows() {
+    let mut gate = gate(10);
+    assert_eq!(gate.push("abcdefgh").await.unwrap(), "");
+    assert_eq!(gate.push("ijkl").await.unwrap(), "abcdefghijkl");
+    assert_eq!(gate.finish("").await.unwrap(), "");
+}
+
+#[tokio::test]
+async fn flagged_replies_are_let_through() {
+    let mut gate = gate(100);
+    assert_eq!(gate.push("The heist\n").await.unwrap(), "The heist\n");
+    assert_eq!(gate.verdict().action, Action::Flag);
+    assert_eq!(gate.verdict().reasons, ["keyword: heist"]);
+}
+
+#[tokio::test]
+async fn blocked_replies_stop() {
+    let mut gate = gate(12);
+    assert_eq!(gate.push("Fine so far\n").await.unwrap(), "Fine so far\n");
+    assert_eq!(gate.push("the launch c").await.unwrap(), "the launch c");
+    // The keyword straddles two checks
+    assert!(gate.push("odes\n").await.is_err());
+    assert!(gate.push("more").await.is_err());
+    assert!(gate.finish("").await.is_err());
+
+    assert_eq!(gate.verdict().action, Action::Block);
+    assert_eq!(gate.text(), "Fine so far\nthe launch codes\n");
+}
@@ -0,0 +1,114 @@
+use rgpt_db::document::{DocumentChunk, encode_embedding};
+use rgpt_provider::HashEmbedder;
+use rgpt_server::retrieval::{chunk_text, cosine, top_k};
+
+fn chunk(id: i32, embedding: &[f32]) -> DocumentChunk {
+    DocumentChunk {
+        id,
+        document_id: 1,
+        user_id: 2,
+        chunk_index: id,
+        body: format!("chunk {id}"),
+        embedding_model: "test".into(),
+        embedding: encode_embedding(embedding),
+    }
+}
+
+#[test]
+fn short_text_is_one_chunk() {
+    assert_eq!(chunk_text("  a short note \n", 100, 10), ["a short note"]);
+    assert!(chunk_text(" \n\t", 100, 10).is_empty());
+}
+
+#[test]
+fn chunks_break_at_words_and_overlap() {
+    let text = "alpha bravo charlie delta echo foxtrot golf hotel india juliet";
+    let chunks = chunk_text(text, 20, 8);
+
+    assert!(chunks.len() > 1);
+    for chunk in &chunks {
+        assert!(chunk.chars().count() <= 20, "{chunk:?} is too long");
+        // Every chunk is made of whole words
+        for word in chunk.split_whitespace() {
+            assert!(
+                text.split_whitespace().any(|w| w == word),
+                "{word:?} was split"
+            );
+        }
+    }
+    // Each chunk picks up where the last left off, repeating a little
+    for pair in chunks.windows(2) {
+        let last_word = pair[0].split_whitespace().last().unwrap();
+        assert!(pair[1].contains(last_word), "{pair:?} don't overlap");
+    }
+    assert!(chunks.last().unwrap().ends_with("juliet"));
+}
+
+#[test]
+fn unbroken_text_is_still_chunked() {
+    let text = "x".repeat(25);
+    let chunks = chunk_text(&text, 10, 3);
+    assert_eq!(
+        chunks,
+        [
+            "x".repeat(10),
+            "x".repeat(10),
+            "x".repeat(10),
+            "x".repeat(4)
+        ]
+    );
+}
+
+#[test]
+fn cosine_similarity() {
+    assert!((cosine(&[1.0, 0.0], &[2.0, 0.0]) - 1.0).abs() < 1e-6);
+    assert!(cosine(&[1.0, 0.0], &[0.0, 3.0]).abs() < 1e-6);
+    assert!((cosine(&[1.0, 0.0], &[-1.0, 0.0]) + 1.0).abs() < 1e-6);
+    assert_eq!(cosine(&[0.0, 0.0], &[1.0, 0.0]), 0.0);
+}
+
+#[test]
+fn closest_chunks_first() {
+    let chunks = vec![
+        chunk(1, &[0.0, 1.0]),
+        chunk(2, &[1.0, 0.0]),
+        chunk(3, &[0.8, 0.6]),
+        chunk(4, &[-1.0, 0.0]),
+    ];
+
+    let found = top_k(&[1.0, 0.0], chunks.clone(), 2, 0.0);
+    let ids = found.iter().map(|(_, chunk)| chunk.id).collect::<Vec<_>>();
+    assert_eq!(ids, [2, 3]);
+
+    // Chunks below the minimum score are left out, however few remain
+    let found = top_k(&[1.0, 0.0], chunks, 4, 0.5);
+    let ids = found.iter().map(|(_, chunk)| chunk.id).collect::<Vec<_>>();
+    assert_eq!(ids, [2, 3]);
+}
+
+#[test]
+fn relevant_passages_are_found() {
+    let embedder = HashEmbedder::new(512);
+    let passages = [
+        "To rotate an API key, create a new key and then revoke the old key.",
+        "Chats in the trash can be restored until they are purged.",
+        "The music player plays a playlist of retro tracks.",
+    ];
+    let chunks = passages
+        .iter()
+        .enumerate()
+        .map(|(i, passage)| {
+            let mut chunk = chunk(i as i32, &embedder.vector(passage));
+            chunk.body = passage.to_string();
+            chunk
+        })
+        .collect();
+
+    let found = top_k(
+        &embedder.vector("how do I revoke an old API key?"),
+        chunks,
+        1,
+        0.0,
+    );
+    assert_eq!(found[0].1.body, passages[0]);
+}
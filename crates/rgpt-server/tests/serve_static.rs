@@ -0,0 +1,259 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use hyper::{
+    HeaderMap,
+    header::{ACCEPT_ENCODING, HeaderValue, IF_MODIFIED_SINCE, IF_NONE_MATCH},
+};
+use rgpt_server::serve_static::{
+    encoding::{Encoding, accepted_encodings},
+    is_not_modified, parse_path,
+    range::{ByteRange, RangeRequest, parse_range},
+    resolve_path,
+};
+
+fn headers(pairs: &[(hyper::header::HeaderName, &str)]) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    for (name, value) in pairs {
+        headers.insert(name, HeaderValue::from_str(value).unwrap());
+    }
+    headers
+}
+
+fn http_date(secs: u64) -> String {
+    httpdate::fmt_http_date(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+#[test]
+fn paths_stay_in_the_static_dir() {
+    assert_eq!(parse_path("/"), Some(PathBuf::new()));
+    assert_eq!(parse_path("/index.html"), Some("index.html".into()));
+    assert_eq!(parse_path("/assets/./app.js"), Some("assets/app.js".into()));
+    assert_eq!(parse_path("/my%20file.txt"), Some("my file.txt".into()));
+    // Absolute paths are taken relative to the static dir
+    assert_eq!(parse_path("//etc/passwd"), Some("etc/passwd".into()));
+
+    for path in [
+        "/..",
+        "/../etc/passwd",
+        "/assets/../../etc/passwd",
+        "/%2e%2e/etc/passwd",
+        "/%2E%2E/%2e%2E/etc/passwd",
+        "/assets/%2e%2e%2f%2e%2e%2fetc/passwd",
+        "/assets%5c..%5c..%5cetc%5cpasswd",
+        "/index.html%00.png",
+        "/%ff",
+    ] {
+        assert_eq!(parse_path(path), None, "{path}");
+    }
+}
+
+/// A fresh static dir holding `index.html` and `docs/index.html`,
+/// returned canonical as the server uses it
+fn static_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rgpt-static-{}-{test}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("docs")).unwrap();
+    std::fs::write(dir.join("index.html"), "shell").unwrap();
+    std::fs::write(dir.join("docs/index.html"), "docs").unwrap();
+    dir.canonicalize().unwrap()
+}
+
+#[tokio::test]
+async fn resolving_stays_in_the_static_dir() {
+    let base = static_dir("resolve");
+
+    let index = resolve_path(&base, Path::new("index.html")).await.unwrap();
+    assert_eq!(index, base.join("index.html"));
+    let docs = resolve_path(&base, Path::new("docs")).await.unwrap();
+    assert_eq!(docs, base.join("docs/index.html"));
+
+    let missing = resolve_path(&base, Path::new("missing.js")).await;
+    assert_eq!(missing.unwrap_err().kind(), std::io::ErrorKind::NotFound);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn symlinks_out_of_the_static_dir_are_missing() {
+    let base = static_dir("symlink");
+    let outside = base.with_extension("secret");
+    std::fs::write(&outside, "secret").unwrap();
+    std::os::unix::fs::symlink(&outside, base.join("secret.txt")).unwrap();
+    std::os::unix::fs::symlink(base.join("index.html"), base.join("home.html")).unwrap();
+
+    let escaped = resolve_path(&base, Path::new("secret.txt")).await;
+    assert_eq!(escaped.unwrap_err().kind(), std::io::ErrorKind::NotFound);
+
+    let inside = resolve_path(&base, Path::new("home.html")).await.unwrap();
+    assert_eq!(inside, base.join("index.html"));
+}
+
+#[test]
+fn etags_are_compared_weakly() {
+    let etag = "\"abc\"";
+    let modified = Some(UNIX_EPOCH + Duration::from_secs(1_000_000));
+
+    assert!(is_not_modified(
+        &headers(&[(IF_NONE_MATCH, "\"abc\"")]),
+        etag,
+        modified
+    ));
+    assert!(is_not_modified(
+        &headers(&[(IF_NONE_MATCH, "W/\"abc\"")]),
+        etag,
+        modified
+    ));
+    assert!(is_not_modified(
+        &headers(&[(IF_NONE_MATCH, "\"x\", \"abc\"")]),
+        etag,
+        modified
+    ));
+    assert!(is_not_modified(
+        &headers(&[(IF_NONE_MATCH, "*")]),
+        etag,
+        modified
+    ));
+    assert!(!is_not_modified(
+        &headers(&[(IF_NONE_MATCH, "\"x\"")]),
+        etag,
+        modified
+    ));
+    assert!(!is_not_modified(&HeaderMap::new(), etag, modified));
+}
+
+#[test]
+fn modification_dates() {
+    let etag = "\"abc\"";
+    let modified = Some(UNIX_EPOCH + Duration::from_secs(1_000_000));
+    let since = |secs| headers(&[(IF_MODIFIED_SINCE, &http_date(secs))]);
+
+    assert!(is_not_modified(&since(1_000_000), etag, modified));
+    assert!(is_not_modified(&since(2_000_000), etag, modified));
+    assert!(!is_not_modified(&since(999_999), etag, modified));
+    assert!(!is_not_modified(&since(2_000_000), etag, None));
+
+    let invalid = headers(&[(IF_MODIFIED_SINCE, "yesterday")]);
+    assert!(!is_not_modified(&invalid, etag, modified));
+}
+
+#[test]
+fn etags_take_precedence_over_dates() {
+    let etag = "\"abc\"";
+    let modified = Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000));
+
+    // Unchanged by date, but the tag doesn't match
+    let stale_tag = headers(&[
+        (IF_NONE_MATCH, "\"old\""),
+        (IF_MODIFIED_SINCE, &http_date(2_000_000)),
+    ]);
+    assert!(!is_not_modified(&stale_tag, etag, modified));
+
+    // Changed by date, but the tag matches
+    let fresh_tag = headers(&[
+        (IF_NONE_MATCH, "\"abc\""),
+        (IF_MODIFIED_SINCE, &http_date(0)),
+    ]);
+    assert!(is_not_modified(&fresh_tag, etag, modified));
+}
+
+fn ranges(ranges: &[(u64, u64)]) -> RangeRequest {
+    RangeRequest::Partial(
+        ranges
+            .iter()
+            .map(|&(start, end)| ByteRange { start, end })
+            .collect(),
+    )
+}
+
+#[test]
+fn byte_ranges() {
+    assert_eq!(parse_range("bytes=0-9", 100), ranges(&[(0, 9)]));
+    assert_eq!(parse_range("bytes=90-", 100), ranges(&[(90, 99)]));
+    assert_eq!(parse_range("bytes=50-1000", 100), ranges(&[(50, 99)]));
+}
+
+#[test]
+fn suffix_ranges() {
+    assert_eq!(parse_range("bytes=-10", 100), ranges(&[(90, 99)]));
+    assert_eq!(parse_range("bytes=-200", 100), ranges(&[(0, 99)]));
+    assert_eq!(parse_range("bytes=-0", 100), RangeRequest::Unsatisfiable);
+    assert_eq!(parse_range("bytes=-10", 0), RangeRequest::Unsatisfiable);
+}
+
+#[test]
+fn unsatisfiable_ranges() {
+    assert_eq!(parse_range("bytes=100-", 100), RangeRequest::Unsatisfiable);
+    assert_eq!(
+        parse_range("bytes=200-300", 100),
+        RangeRequest::Unsatisfiable
+    );
+    assert_eq!(parse_range("bytes=0-", 0), RangeRequest::Unsatisfiable);
+    // Ranges past the end are dropped if others can be served
+    assert_eq!(parse_range("bytes=0-9, 200-300", 100), ranges(&[(0, 9)]));
+}
+
+#[test]
+fn multiple_ranges_are_sorted_and_merged() {
+    assert_eq!(
+        parse_range("bytes=50-59, 0-9", 100),
+        ranges(&[(0, 9), (50, 59)])
+    );
+    assert_eq!(parse_range("bytes=0-10, 5-20", 100), ranges(&[(0, 20)]));
+    assert_eq!(parse_range("bytes=0-9, 10-19", 100), ranges(&[(0, 19)]));
+    assert_eq!(parse_range("bytes=0-50, -60", 100), ranges(&[(0, 99)]));
+
+    // Too many ranges are served as the whole file
+    let many = (0..40)
+        .map(|i| format!("{}-{}", i * 2, i * 2))
+        .collect::<Vec<_>>()
+        .join(",");
+    assert_eq!(
+        parse_range(&format!("bytes={many}"), 100),
+        RangeRequest::Full
+    );
+}
+
+#[test]
+fn invalid_ranges_are_ignored() {
+    for header in [
+        "items=0-9",
+        "bytes=",
+        "bytes=9-0",
+        "bytes=a-b",
+        "bytes=-",
+        "bytes=0-9,x",
+    ] {
+        assert_eq!(parse_range(header, 100), RangeRequest::Full, "{header}");
+    }
+}
+
+fn encodings(accept: &str) -> Vec<Encoding> {
+    accepted_encodings(&headers(&[(ACCEPT_ENCODING, accept)]))
+}
+
+#[test]
+fn encoding_preferences() {
+    use Encoding::{Brotli, Gzip};
+
+    assert!(accepted_encodings(&HeaderMap::new()).is_empty());
+    assert!(encodings("identity").is_empty());
+    // Equal weights keep the server's preference
+    assert_eq!(encodings("gzip, br"), [Brotli, Gzip]);
+    assert_eq!(encodings("GZIP"), [Gzip]);
+    assert_eq!(encodings("gzip;q=1.0, br;q=0.5"), [Gzip, Brotli]);
+    assert_eq!(encodings("*"), [Brotli, Gzip]);
+    assert!(encodings("gzip;q=abc").is_empty());
+}
+
+#[test]
+fn zero_weights_refuse_encodings() {
+    use Encoding::{Brotli, Gzip};
+
+    assert_eq!(encodings("br;q=0, gzip"), [Gzip]);
+    assert!(encodings("br;q=0.0, gzip;q=0").is_empty());
+    assert!(encodings("*;q=0").is_empty());
+    assert_eq!(encodings("*, br;q=0"), [Gzip]);
+    assert_eq!(encodings("*;q=0, br"), [Brotli]);
+}
@@ -0,0 +1,192 @@
+use std::sync::Arc;
+
+use async_openai::types::{
+    ChatCompletionMessageToolCall, ChatCompletionMessageToolCallChunk, ChatCompletionToolType,
+    FunctionCall, FunctionCallStream,
+};
+use rgpt_db::{
+    msg::{Generation, MsgStatus},
+    store::{MemoryStore, Store},
+};
+use rgpt_server::tools::{ToolCallChunks, ToolContext, ToolRegistry, calculator::evaluate};
+
+fn call(name: &str, arguments: &str) -> ChatCompletionMessageToolCall {
+    ChatCompletionMessageToolCall {
+        id: "call_1".into(),
+        r#type: ChatCompletionToolType::Function,
+        function: FunctionCall {
+            name: name.into(),
+            arguments: arguments.into(),
+        },
+    }
+}
+
+fn tool_cx(store: Arc<dyn Store>, user_id: i32) -> ToolContext {
+    ToolContext {
+        store,
+        user_id,
+        chat_id: 1,
+    }
+}
+
+#[test]
+fn calculator_precedence() {
+    assert_eq!(evaluate("1 + 2 * 3").unwrap(), 7.0);
+    assert_eq!(evaluate("(1 + 2) * 3").unwrap(), 9.0);
+    assert_eq!(evaluate("10 - 4 - 3").unwrap(), 3.0);
+    assert_eq!(evaluate("2 ^ 3 ^ 2").unwrap(), 512.0);
+    assert_eq!(evaluate("-2 ^ 2").unwrap(), -4.0);
+    assert_eq!(evaluate("2 ^ -1").unwrap(), 0.5);
+    assert_eq!(evaluate("17 % 5").unwrap(), 2.0);
+    assert_eq!(evaluate("1.5 * 4").unwrap(), 6.0);
+}
+
+#[test]
+fn calculator_rejects_invalid_expressions() {
+    for expression in ["", "2 +", "(1 + 2", "1 + 2)", "two", "1 / 0", "1..2"] {
+        assert!(evaluate(expression).is_err(), "{expression:?} evaluated");
+    }
+    assert!(evaluate(&"1+".repeat(600)).is_err());
+}
+
+#[test]
+fn enabled_tools() {
+    let registry = ToolRegistry::enabled(&["calculator".into(), "no_such_tool".into()]);
+    let names = registry
+        .definitions()
+        .into_iter()
+        .map(|tool| tool.function.name)
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["calculator"]);
+
+    assert!(ToolRegistry::enabled(&[]).is_empty());
+    assert_eq!(ToolRegistry::builtin().definitions().len(), 3);
+}
+
+#[tokio::test]
+async fn tool_errors_are_results() {
+    let registry = ToolRegistry::builtin();
+    let cx = tool_cx(Arc::new(MemoryStore::new()), 2);
+
+    let record = registry
+        .call(&cx, call("calculator", r#"{"expression": "6 * 7"}"#))
+        .await;
+    assert_eq!(record.id, "call_1");
+    assert_eq!(record.result, "42");
+
+    let unknown = registry.call(&cx, call("shell", "{}")).await;
+    assert!(unknown.result.starts_with("error:"));
+
+    let malformed = registry.call(&cx, call("calculator", "{")).await;
+    assert!(malformed.result.starts_with("error:"));
+
+    let invalid = registry
+        .call(&cx, call("calculator", r#"{"expression": "1 / 0"}"#))
+        .await;
+    assert!(invalid.result.starts_with("error:"));
+}
+
+#[tokio::test]
+async fn current_datetime_offset() {
+    let registry = ToolRegistry::builtin();
+    let cx = tool_cx(Arc::new(MemoryStore::new()), 2);
+
+    let utc = registry.call(&cx, call("current_datetime", "{}")).await;
+    assert!(utc.result.ends_with("+00:00"), "{}", utc.result);
+
+    let offset = registry
+        .call(
+            &cx,
+            call("current_datetime", r#"{"utc_offset_minutes": 330}"#),
+        )
+        .await;
+    assert!(offset.result.ends_with("+05:30"), "{}", offset.result);
+
+    let out_of_range = registry
+        .call(
+            &cx,
+            call("current_datetime", r#"{"utc_offset_minutes": 100000}"#),
+        )
+        .await;
+    assert!(out_of_range.result.starts_with("error:"));
+}
+
+#[tokio::test]
+async fn chat_search_is_scoped_to_the_user() {
+    let store: Arc<dyn Store> = Arc::new(MemoryStore::new());
+    let user = store
+        .create_user(
+            "search".into(),
+            "search@example.com".into(),
+            "Search".into(),
+        )
+        .await
+        .unwrap();
+    let other = store
+        .create_user("other".into(), "other@example.com".into(), "Other".into())
+        .await
+        .unwrap();
+    for (user_id, body) in [
+        (user.user_id, "My cat is called Pixel"),
+        (other.user_id, "My cat is called Byte"),
+    ] {
+        store
+            .create_msg(
+                body.into(),
+                "user".into(),
+                user_id,
+                None,
+                MsgStatus::Complete,
+                Generation::default(),
+            )
+            .await
+            .unwrap();
+    }
+
+    let registry = ToolRegistry::builtin();
+    let search = call("search_chats", r#"{"query": "cat"}"#);
+
+    let record = registry
+        .call(&tool_cx(store.clone(), user.user_id), search.clone())
+        .await;
+    let matches: serde_json::Value = serde_json::from_str(&record.result).unwrap();
+    let matches = matches.as_array().unwrap();
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0]["text"], "My cat is called Pixel");
+    assert_eq!(matches[0]["sender"], "user");
+
+    // Guests share a user, so they can't search at all
+    let guest = registry.call(&tool_cx(store, 1), search).await;
+    assert!(!guest.result.contains("Pixel"));
+}
+
+#[test]
+fn streamed_calls_are_assembled() {
+    let chunk = |index, id: Option<&str>, name: Option<&str>, arguments: Option<&str>| {
+        ChatCompletionMessageToolCallChunk {
+            index,
+            id: id.map(Into::into),
+            r#type: None,
+            function: Some(FunctionCallStream {
+                name: name.map(Into::into),
+                arguments: arguments.map(Into::into),
+            }),
+        }
+    };
+
+    let mut chunks = ToolCallChunks::default();
+    chunks.push(chunk(0, Some("a"), Some("calculator"), Some("")));
+    chunks.push(chunk(1, Some("b"), Some("current_datetime"), None));
+    chunks.push(chunk(0, None, None, Some(r#"{"expression""#)));
+    chunks.push(chunk(0, None, None, Some(r#": "1 + 1"}"#)));
+    chunks.push(chunk(1, None, None, Some("{}")));
+    // Out of range indices are dropped rather than allocated
+    chunks.push(chunk(u32::MAX, Some("c"), Some("calculator"), None));
+
+    let calls = chunks.into_calls();
+    assert_eq!(calls.len(), 2);
+    assert_eq!(calls[0].id, "a");
+    assert_eq!(calls[0].function.arguments, r#"{"expression": "1 + 1"}"#);
+    assert_eq!(calls[1].function.name, "current_datetime");
+    assert_eq!(calls[1].function.arguments, "{}");
+}
@@ -2,12 +2,86 @@ use std::collections::HashMap;
 
 use bytes::Bytes;
 use futures::channel::mpsc;
+use rgpt_db::chat::Chat;
+use serde::Serialize;
 use tokio::sync::oneshot;
 use uuid::Uuid;
 
+pub mod protocol;
+
+/// An event pushed to the client attached to a generation
+#[derive(Debug, Clone)]
+pub enum StreamEvent {
+    /// The model serving the generation, sent
+    /// before any of its text
+    Model(String),
+    /// A chunk of generated text
+    Delta(Bytes),
+    /// The model called the named tool, whose result
+    /// is fed back to it before the reply continues
+    ToolCall(String),
+    /// The title generated for a new chat, sent
+    /// once the reply has been saved
+    Title(String),
+    /// The generation failed. Any text sent before this
+    /// event has been saved as a partial reply
+    Error(String),
+    /// The reply was saved. Always the last event
+    /// unless the generation failed or was cancelled
+    Done(Finished),
+    /// The generation was cancelled, and whatever text had
+    /// been generated saved as the message with this id
+    Cancelled(i32),
+}
+
+/// How a saved reply ended
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Finished {
+    pub msg_id: i32,
+    /// Why the model stopped: `stop`, `length` or `content_filter`,
+    /// or `interrupted` if the provider's stream ended early
+    pub finish_reason: String,
+    /// The tokens the reply took, across all of its tool call
+    /// rounds, if the provider reported them
+    pub usage: Option<Usage>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct Usage {
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+    pub total_tokens: u32,
+}
+
+/// An event pushed to every session socket a user has open
+#[derive(Debug, Clone)]
+pub enum UserEvent {
+    /// An event of a generation in one of the user's chats
+    Generation { chat_id: i32, event: StreamEvent },
+    /// One of the user's chats was created or changed,
+    /// including being moved to the trash
+    Chat(Chat),
+}
+
 #[derive(Debug)]
 pub struct StreamRegistry {
     pub handle_map: HashMap<Uuid, AttachHandle>,
+    /// The generations in progress, by attach token
+    pub generations: HashMap<Uuid, ActiveGeneration>,
+    /// The session sockets open, by user id
+    sockets: HashMap<i32, Vec<mpsc::UnboundedSender<UserEvent>>>,
+}
+
+/// A generation that can be cancelled until it finishes
+#[derive(Debug)]
+pub struct ActiveGeneration {
+    pub chat_id: i32,
+    pub user_id: i32,
+    /// The model serving the generation, and the text it has
+    /// generated so far, for sockets opened partway through
+    pub model: Option<String>,
+    pub text: String,
+    cancel: oneshot::Sender<()>,
 }
 
 impl Default for StreamRegistry {
@@ -20,6 +94,8 @@ impl StreamRegistry {
     pub fn new() -> Self {
         StreamRegistry {
             handle_map: HashMap::new(),
+            generations: HashMap::new(),
+            sockets: HashMap::new(),
         }
     }
 
@@ -31,22 +107,114 @@ impl StreamRegistry {
         Some(uuid)
     }
 
-    pub fn try_attach(&mut self, id: Uuid) -> Option<mpsc::UnboundedReceiver<Bytes>> {
+    pub fn try_attach(&mut self, id: Uuid) -> Option<mpsc::UnboundedReceiver<StreamEvent>> {
         self.handle_map.remove(&id).map(|handle| handle.attach())
     }
+
+    /// Records a generation for the user's chat, returning
+    /// the receiver its cancellation is sent on
+    pub fn start_generation(
+        &mut self,
+        id: Uuid,
+        chat_id: i32,
+        user_id: i32,
+    ) -> oneshot::Receiver<()> {
+        let (cancel, cancelled) = oneshot::channel();
+        self.generations.insert(
+            id,
+            ActiveGeneration {
+                chat_id,
+                user_id,
+                model: None,
+                text: String::new(),
+                cancel,
+            },
+        );
+        cancelled
+    }
+
+    pub fn finish_generation(&mut self, id: Uuid) {
+        self.generations.remove(&id);
+    }
+
+    /// Cancels the chat's generations, returning whether
+    /// there were any
+    pub fn cancel_chat(&mut self, chat_id: i32) -> bool {
+        let ids = self
+            .generations
+            .iter()
+            .filter(|(_, generation)| generation.chat_id == chat_id)
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        for id in &ids {
+            if let Some(generation) = self.generations.remove(id) {
+                // A generation that has just finished has dropped its receiver
+                let _ = generation.cancel.send(());
+            }
+        }
+        !ids.is_empty()
+    }
+
+    /// Opens a socket for the user's events, which starts with
+    /// what their generations in progress have sent so far
+    pub fn subscribe(&mut self, user_id: i32) -> mpsc::UnboundedReceiver<UserEvent> {
+        let (tx, rx) = mpsc::unbounded();
+        for generation in self.generations.values() {
+            if generation.user_id != user_id {
+                continue;
+            }
+            let chat_id = generation.chat_id;
+            if let Some(model) = &generation.model {
+                let event = StreamEvent::Model(model.clone());
+                let _ = tx.unbounded_send(UserEvent::Generation { chat_id, event });
+            }
+            if !generation.text.is_empty() {
+                let event = StreamEvent::Delta(generation.text.clone().into());
+                let _ = tx.unbounded_send(UserEvent::Generation { chat_id, event });
+            }
+        }
+        self.sockets.entry(user_id).or_default().push(tx);
+        rx
+    }
+
+    /// Pushes an event of the generation with attach token `id`
+    /// to its user's sockets
+    pub fn publish(&mut self, id: Uuid, user_id: i32, chat_id: i32, event: StreamEvent) {
+        if let Some(generation) = self.generations.get_mut(&id) {
+            match &event {
+                StreamEvent::Model(model) => generation.model = Some(model.clone()),
+                StreamEvent::Delta(bytes) => {
+                    generation.text.push_str(&String::from_utf8_lossy(bytes))
+                }
+                _ => {}
+            }
+        }
+        self.push(user_id, UserEvent::Generation { chat_id, event });
+    }
+
+    /// Pushes an event to the user's sockets, forgetting
+    /// any that have closed
+    pub fn push(&mut self, user_id: i32, event: UserEvent) {
+        if let Some(sockets) = self.sockets.get_mut(&user_id) {
+            sockets.retain(|socket| socket.unbounded_send(event.clone()).is_ok());
+            if sockets.is_empty() {
+                self.sockets.remove(&user_id);
+            }
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct AttachHandle {
-    pub attacher: oneshot::Sender<mpsc::UnboundedSender<Bytes>>,
+    pub attacher: oneshot::Sender<mpsc::UnboundedSender<StreamEvent>>,
 }
 
 impl AttachHandle {
-    pub fn new(attacher: oneshot::Sender<mpsc::UnboundedSender<Bytes>>) -> Self {
+    pub fn new(attacher: oneshot::Sender<mpsc::UnboundedSender<StreamEvent>>) -> Self {
         AttachHandle { attacher }
     }
 
-    pub fn attach(self) -> mpsc::UnboundedReceiver<Bytes> {
+    pub fn attach(self) -> mpsc::UnboundedReceiver<StreamEvent> {
         let (tx, rx) = mpsc::unbounded();
         self.attacher.send(tx).expect("recv end was dropped first");
         rx
@@ -0,0 +1,102 @@
+//! The JSON protocol attached clients can opt into
+//!
+//! Clients offer [`JSON_PROTOCOL`] as a WebSocket subprotocol. Each
+//! event is then sent as a text frame holding one JSON object, whose
+//! `type` names the event. A stream ends with exactly one `done`,
+//! `error` or `cancelled` event before the socket is closed, so a
+//! socket that closes without one was dropped.
+//!
+//! Clients that don't offer it get the raw protocol: the reply's
+//! text and nothing else.
+//!
+//! Session sockets always speak JSON. Their generation events are the
+//! same objects with the `chat_id` they belong to added, and changes to
+//! the user's chats are sent as `chat` events holding the whole chat.
+
+use std::borrow::Cow;
+
+use serde::Serialize;
+
+use rgpt_db::chat::Chat;
+
+use crate::{Finished, StreamEvent, UserEvent};
+
+/// The subprotocol naming this version of the JSON protocol
+pub const JSON_PROTOCOL: &str = "retrogpt.v1";
+
+/// An event as the JSON protocol sends it
+#[derive(Serialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum JsonEvent<'a> {
+    Model { model: &'a str },
+    Delta { text: Cow<'a, str> },
+    ToolCall { name: &'a str },
+    Title { title: &'a str },
+    Error { message: &'a str },
+    Done(&'a Finished),
+    Cancelled { msg_id: i32 },
+}
+
+impl StreamEvent {
+    pub fn as_json_event(&self) -> JsonEvent<'_> {
+        match self {
+            StreamEvent::Model(model) => JsonEvent::Model { model },
+            StreamEvent::Delta(bytes) => JsonEvent::Delta {
+                text: String::from_utf8_lossy(bytes),
+            },
+            StreamEvent::ToolCall(name) => JsonEvent::ToolCall { name },
+            StreamEvent::Title(title) => JsonEvent::Title { title },
+            StreamEvent::Error(message) => JsonEvent::Error { message },
+            StreamEvent::Done(finished) => JsonEvent::Done(finished),
+            StreamEvent::Cancelled(msg_id) => JsonEvent::Cancelled { msg_id: *msg_id },
+        }
+    }
+
+    /// The event as a JSON protocol text frame
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(&self.as_json_event()).expect("events always serialize")
+    }
+
+    /// Whether no events follow this one
+    pub fn ends_stream(&self) -> bool {
+        matches!(
+            self,
+            StreamEvent::Error(_) | StreamEvent::Done(_) | StreamEvent::Cancelled(_)
+        )
+    }
+}
+
+/// A user event as a session socket sends it
+#[derive(Serialize, Debug)]
+#[serde(untagged)]
+pub enum JsonUserEvent<'a> {
+    Generation {
+        chat_id: i32,
+        #[serde(flatten)]
+        event: JsonEvent<'a>,
+    },
+    Chat(JsonChatEvent<'a>),
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum JsonChatEvent<'a> {
+    Chat { chat: &'a Chat },
+}
+
+impl UserEvent {
+    pub fn as_json_event(&self) -> JsonUserEvent<'_> {
+        match self {
+            UserEvent::Generation { chat_id, event } => JsonUserEvent::Generation {
+                chat_id: *chat_id,
+                event: event.as_json_event(),
+            },
+            UserEvent::Chat(chat) => JsonUserEvent::Chat(JsonChatEvent::Chat { chat }),
+        }
+    }
+
+    /// The event as a session socket text frame
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(&self.as_json_event()).expect("events always serialize")
+    }
+}
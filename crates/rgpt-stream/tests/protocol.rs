@@ -0,0 +1,156 @@
+use chrono::NaiveDateTime;
+use rgpt_db::chat::Chat;
+use rgpt_stream::{Finished, StreamEvent, StreamRegistry, Usage, UserEvent};
+use serde_json::{Value, json};
+use uuid::Uuid;
+
+fn json_of(event: StreamEvent) -> Value {
+    serde_json::from_str(&event.to_json()).unwrap()
+}
+
+#[test]
+fn events_are_tagged_objects() {
+    assert_eq!(
+        json_of(StreamEvent::Delta("héllo".into())),
+        json!({"type": "delta", "text": "héllo"})
+    );
+    assert_eq!(
+        json_of(StreamEvent::Model("gpt-4o-mini".into())),
+        json!({"type": "model", "model": "gpt-4o-mini"})
+    );
+    assert_eq!(
+        json_of(StreamEvent::ToolCall("calculator".into())),
+        json!({"type": "tool_call", "name": "calculator"})
+    );
+    assert_eq!(
+        json_of(StreamEvent::Title("Greetings".into())),
+        json!({"type": "title", "title": "Greetings"})
+    );
+    assert_eq!(
+        json_of(StreamEvent::Error("Model Timeout".into())),
+        json!({"type": "error", "message": "Model Timeout"})
+    );
+    assert_eq!(
+        json_of(StreamEvent::Cancelled(7)),
+        json!({"type": "cancelled", "msg_id": 7})
+    );
+}
+
+#[test]
+fn done_carries_the_reply() {
+    let done = StreamEvent::Done(Finished {
+        msg_id: 12,
+        finish_reason: "stop".into(),
+        usage: Some(Usage {
+            prompt_tokens: 30,
+            completion_tokens: 5,
+            total_tokens: 35,
+        }),
+    });
+    assert!(done.ends_stream());
+    assert_eq!(
+        json_of(done),
+        json!({
+            "type": "done",
+            "msg_id": 12,
+            "finish_reason": "stop",
+            "usage": {"prompt_tokens": 30, "completion_tokens": 5, "total_tokens": 35},
+        })
+    );
+
+    assert!(!StreamEvent::Delta("more".into()).ends_stream());
+    assert!(!StreamEvent::Title("more".into()).ends_stream());
+}
+
+#[test]
+fn cancelling_a_chat() {
+    let mut registry = StreamRegistry::new();
+    let (first, second, other) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+    let mut first_rx = registry.start_generation(first, 1, 5);
+    let mut second_rx = registry.start_generation(second, 1, 5);
+    let mut other_rx = registry.start_generation(other, 2, 5);
+
+    assert!(registry.cancel_chat(1));
+    assert_eq!(first_rx.try_recv(), Ok(()));
+    assert_eq!(second_rx.try_recv(), Ok(()));
+    assert!(other_rx.try_recv().is_err());
+
+    // Finished generations can't be cancelled
+    registry.finish_generation(other);
+    assert!(!registry.cancel_chat(1));
+    assert!(!registry.cancel_chat(2));
+}
+
+fn json_of_user_event(event: &UserEvent) -> Value {
+    serde_json::from_str(&event.to_json()).unwrap()
+}
+
+#[test]
+fn user_events_name_their_chat() {
+    let delta = UserEvent::Generation {
+        chat_id: 3,
+        event: StreamEvent::Delta("hi".into()),
+    };
+    assert_eq!(
+        json_of_user_event(&delta),
+        json!({"type": "delta", "chat_id": 3, "text": "hi"})
+    );
+
+    let chat = Chat {
+        id: 3,
+        head_msg: Some(9),
+        user_id: 5,
+        created_at: NaiveDateTime::default(),
+        updated_at: NaiveDateTime::default(),
+        name: Some("Greetings".into()),
+        deleted: false,
+        tools_enabled: false,
+        output_filters: None,
+        instructions_enabled: true,
+        persona: "terminal_robot".into(),
+    };
+    let event = json_of_user_event(&UserEvent::Chat(chat));
+    assert_eq!(event["type"], "chat");
+    assert_eq!(event["chat"]["id"], 3);
+    assert_eq!(event["chat"]["name"], "Greetings");
+}
+
+#[test]
+fn sockets_catch_up_on_generations() {
+    let mut registry = StreamRegistry::new();
+    let (mine, theirs) = (Uuid::new_v4(), Uuid::new_v4());
+    let _mine_rx = registry.start_generation(mine, 1, 5);
+    let _theirs_rx = registry.start_generation(theirs, 2, 6);
+    registry.publish(mine, 5, 1, StreamEvent::Model("gpt-4o-mini".into()));
+    registry.publish(mine, 5, 1, StreamEvent::Delta("Hel".into()));
+    registry.publish(mine, 5, 1, StreamEvent::Delta("lo".into()));
+    registry.publish(theirs, 6, 2, StreamEvent::Delta("Not yours".into()));
+
+    // A socket opened partway through gets the text so far in one delta
+    let mut socket = registry.subscribe(5);
+    let caught_up = std::iter::from_fn(|| socket.try_next().ok().flatten())
+        .map(|event| json_of_user_event(&event))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        caught_up,
+        [
+            json!({"type": "model", "chat_id": 1, "model": "gpt-4o-mini"}),
+            json!({"type": "delta", "chat_id": 1, "text": "Hello"}),
+        ]
+    );
+
+    // and everything after, but nothing of other users' chats
+    registry.publish(mine, 5, 1, StreamEvent::Delta("!".into()));
+    registry.publish(theirs, 6, 2, StreamEvent::Delta("Still not".into()));
+    let next = socket.try_next().unwrap().unwrap();
+    assert_eq!(
+        json_of_user_event(&next),
+        json!({"type": "delta", "chat_id": 1, "text": "!"})
+    );
+    assert!(socket.try_next().is_err());
+
+    // Closed sockets are forgotten
+    drop(socket);
+    registry.publish(mine, 5, 1, StreamEvent::Delta("?".into()));
+    assert_eq!(registry.generations[&mine].text, "Hello!?");
+}
@@ -4,6 +4,25 @@ import { HttpBody, HttpClient } from "@effect/platform";
 import { BrowserHttpClient } from "@effect/platform-browser";
 import * as BaseUrl from "./BaseUrl";
 import * as WindowLocation from "./WindowLocation";
+import type * as Server from "./generated/api";
+
+// The server's request and response types are generated from its
+// OpenAPI document (`npm run gen:api`). Wrapping each schema below in
+// `sends` or `receives` makes `tsc` fail when the two drift apart.
+
+/** The body the schema encodes must be a valid server input */
+const sends =
+  <T>() =>
+  <A, I extends T>(schema: Schema.Schema<A, I>) =>
+    schema;
+
+/** Whatever the server responds with must decode with the schema */
+const receives =
+  <T>() =>
+  <A, I>(
+    schema: Schema.Schema<A, I> & ([T] extends [I] ? unknown : never),
+  ) =>
+    schema;
 
 const makePostEndpoint =
   <DI, EI, RI, DO, EO, RO>(
@@ -28,52 +47,71 @@ const makePostEndpoint =
     );
 
 export const authApi = makePostEndpoint(
-  Schema.Struct({ user_access_token: Schema.String }),
-  Schema.Struct({ session_token: Schema.String, user_id: Schema.Number }),
+  sends<Server.AuthServiceInput>()(
+    Schema.Struct({ user_access_token: Schema.String }),
+  ),
+  receives<Server.AuthServiceReturn>()(
+    Schema.Struct({ session_token: Schema.String, user_id: Schema.Number }),
+  ),
   "/api/v0.0.1/auth",
 );
 
 export const userChatsApi = makePostEndpoint(
-  Schema.Struct({ user_id: Schema.Union(Schema.Number, Schema.Null) }),
-  Schema.Struct({
-    chats: Schema.Array(
-      Schema.Struct({
-        id: Schema.Number,
-        name: Schema.String,
-      }),
-    ),
-    user_id: Schema.Number,
-  }),
+  sends<Server.UserChatsServiceInput>()(
+    Schema.Struct({ user_id: Schema.Union(Schema.Number, Schema.Null) }),
+  ),
+  receives<Server.UserChatsServiceReturn>()(
+    Schema.Struct({
+      chats: Schema.Array(
+        Schema.Struct({
+          id: Schema.Number,
+          name: Schema.String,
+        }),
+      ),
+      user_id: Schema.Number,
+    }),
+  ),
   "/api/v0.0.1/user_chats",
 );
 
 export const chatMsgsApi = makePostEndpoint(
-  Schema.Struct({ chat_id: Schema.Number }),
-  Schema.Array(
-    Schema.Struct({
-      text: Schema.String,
-      sender: Schema.Union(Schema.Literal("ai"), Schema.Literal("user")),
-    }),
+  sends<Server.ChatMsgServiceInput>()(
+    Schema.Struct({ chat_id: Schema.Number }),
+  ),
+  receives<Server.ChatMsg[]>()(
+    Schema.Array(
+      Schema.Struct({
+        text: Schema.String,
+        sender: Schema.Union(Schema.Literal("ai"), Schema.Literal("user")),
+      }),
+    ),
   ),
   "/api/v0.0.1/chat_msgs",
 );
 
 export const promptApi = makePostEndpoint(
-  Schema.Struct({
-    text: Schema.String,
-    chat_id: Schema.Union(Schema.Number, Schema.Null),
-  }),
-  Schema.Struct({
-    chat_id: Schema.Number,
-    attach_token: Schema.String,
-  }),
+  sends<Server.PromptServiceInput>()(
+    Schema.Struct({
+      text: Schema.String,
+      chat_id: Schema.Union(Schema.Number, Schema.Null),
+    }),
+  ),
+  receives<Server.PromptServiceResponse>()(
+    Schema.Struct({
+      chat_id: Schema.Number,
+      chat_title: Schema.NullOr(Schema.String),
+      attach_token: Schema.String,
+    }),
+  ),
   "/api/v0.0.1/prompt",
 );
 
 export const deleteChatApi = makePostEndpoint(
-  Schema.Struct({
-    chat_id: Schema.Number,
-  }),
+  sends<Server.DeleteChatInput>()(
+    Schema.Struct({
+      chat_id: Schema.Number,
+    }),
+  ),
   Schema.Void,
   "/api/v0.0.1/delete_chat",
 );
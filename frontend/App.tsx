@@ -108,7 +108,7 @@ function App() {
       { text: "...", sender: "ai" as const },
     ]);
 
-    const { chat_id, attach_token } = await Effect.runPromise(
+    const { chat_id, chat_title, attach_token } = await Effect.runPromise(
       Api.promptApi(
         {
           text: msg.text,
@@ -118,6 +118,15 @@ function App() {
       ),
     );
 
+    // A new chat's title is known before the reply
+    // finishes, so list the chat straight away
+    if (
+      chat_title !== null &&
+      getSessionTokenCookieWrapper() !== "__default__"
+    ) {
+      setUserOwnedChats((prev) => [...prev, { id: chat_id, name: chat_title }]);
+    }
+
     // Build WebSocket URL with protocol, host, endpoint, and session token
     const wsProtocol = window.location.protocol === "https:" ? "wss:" : "ws:";
     const wsHost = get_api_host();
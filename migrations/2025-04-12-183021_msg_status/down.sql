@@ -0,0 +1 @@
+ALTER TABLE msgs DROP COLUMN status;
@@ -0,0 +1 @@
+ALTER TABLE msgs ADD COLUMN status VARCHAR NOT NULL DEFAULT 'complete';
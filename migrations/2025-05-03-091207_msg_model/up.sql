@@ -0,0 +1 @@
+ALTER TABLE msgs ADD COLUMN model VARCHAR;
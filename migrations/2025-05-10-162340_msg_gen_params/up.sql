@@ -0,0 +1 @@
+ALTER TABLE msgs ADD COLUMN gen_params JSONB;
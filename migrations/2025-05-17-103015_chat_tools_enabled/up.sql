@@ -0,0 +1 @@
+ALTER TABLE chats ADD COLUMN tools_enabled BOOLEAN NOT NULL DEFAULT TRUE;
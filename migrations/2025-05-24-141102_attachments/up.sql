@@ -0,0 +1,13 @@
+CREATE TABLE attachments (
+    id SERIAL PRIMARY KEY,
+    user_id INT NOT NULL REFERENCES users(user_id) ON DELETE CASCADE,
+    msg_id INT REFERENCES msgs(id) ON DELETE CASCADE,
+    name VARCHAR NOT NULL,
+    media_type VARCHAR NOT NULL,
+    size_bytes INT NOT NULL,
+    content TEXT,
+    storage_key VARCHAR,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX attachments_msg_id_idx ON attachments(msg_id);
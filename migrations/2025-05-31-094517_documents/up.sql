@@ -0,0 +1,22 @@
+CREATE TABLE documents (
+    id SERIAL PRIMARY KEY,
+    user_id INT NOT NULL REFERENCES users(user_id) ON DELETE CASCADE,
+    name VARCHAR NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+-- Embeddings are little-endian f32s, compared in the server
+-- so that no vector extension is needed
+CREATE TABLE document_chunks (
+    id SERIAL PRIMARY KEY,
+    document_id INT NOT NULL REFERENCES documents(id) ON DELETE CASCADE,
+    user_id INT NOT NULL REFERENCES users(user_id) ON DELETE CASCADE,
+    chunk_index INT NOT NULL,
+    body TEXT NOT NULL,
+    embedding_model VARCHAR NOT NULL,
+    embedding BYTEA NOT NULL
+);
+
+CREATE INDEX document_chunks_user_id_idx ON document_chunks(user_id, embedding_model);
+
+ALTER TABLE msgs ADD COLUMN citations JSONB;
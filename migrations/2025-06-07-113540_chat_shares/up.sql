@@ -0,0 +1,15 @@
+-- A share points at the chat's head when it was shared. Messages
+-- are never changed once saved, so the branch ending there is a
+-- snapshot that later messages don't show up in
+CREATE TABLE chat_shares (
+    id SERIAL PRIMARY KEY,
+    token VARCHAR UNIQUE NOT NULL,
+    chat_id INT NOT NULL REFERENCES chats(id) ON DELETE CASCADE,
+    user_id INT NOT NULL REFERENCES users(user_id) ON DELETE CASCADE,
+    head_msg INT NOT NULL REFERENCES msgs(id) ON DELETE CASCADE,
+    name VARCHAR,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMP
+);
+
+CREATE INDEX chat_shares_chat_id_idx ON chat_shares(chat_id);
@@ -0,0 +1 @@
+ALTER TABLE chats ADD COLUMN output_filters JSONB;
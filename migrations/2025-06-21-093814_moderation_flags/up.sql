@@ -0,0 +1,17 @@
+-- Prompts and replies a moderator flagged or blocked, kept for
+-- operators to review. Blocked prompts are never saved as messages,
+-- so their text is kept here and `msg_id` is left null
+CREATE TABLE moderation_flags (
+    id SERIAL PRIMARY KEY,
+    user_id INT NOT NULL REFERENCES users(user_id) ON DELETE CASCADE,
+    chat_id INT REFERENCES chats(id) ON DELETE CASCADE,
+    msg_id INT REFERENCES msgs(id) ON DELETE CASCADE,
+    source VARCHAR NOT NULL,
+    action VARCHAR NOT NULL,
+    reasons TEXT NOT NULL,
+    content TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    reviewed_at TIMESTAMP
+);
+
+CREATE INDEX moderation_flags_unreviewed_idx ON moderation_flags(id) WHERE reviewed_at IS NULL;
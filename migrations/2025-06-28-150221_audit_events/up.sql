@@ -0,0 +1,24 @@
+-- Security-relevant events, kept after the accounts they concern
+-- are deleted, so `user_id` doesn't reference users. Rows are never
+-- changed or removed once recorded
+CREATE TABLE audit_events (
+    id SERIAL PRIMARY KEY,
+    user_id INT,
+    kind VARCHAR NOT NULL,
+    detail TEXT NOT NULL DEFAULT '',
+    ip VARCHAR,
+    user_agent TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX audit_events_user_id_idx ON audit_events(user_id, id);
+
+CREATE FUNCTION audit_events_append_only() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'audit_events is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_events_append_only
+    BEFORE UPDATE OR DELETE ON audit_events
+    FOR EACH ROW EXECUTE FUNCTION audit_events_append_only();
@@ -0,0 +1,3 @@
+-- Set while the user's account is waiting out its
+-- grace period, after which it's deleted for good
+ALTER TABLE users ADD COLUMN delete_after TIMESTAMP;
@@ -0,0 +1,2 @@
+ALTER TABLE chats DROP COLUMN instructions_enabled;
+DROP TABLE custom_instructions;
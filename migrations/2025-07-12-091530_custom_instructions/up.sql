@@ -0,0 +1,12 @@
+-- What each user has told the model about themselves and how
+-- they'd like it to reply, merged into the system prompt of
+-- chats that haven't opted out
+CREATE TABLE custom_instructions (
+    user_id INT PRIMARY KEY REFERENCES users(user_id) ON DELETE CASCADE,
+    about_me TEXT NOT NULL DEFAULT '',
+    response_style TEXT NOT NULL DEFAULT '',
+    language VARCHAR NOT NULL DEFAULT '',
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+ALTER TABLE chats ADD COLUMN instructions_enabled BOOLEAN NOT NULL DEFAULT TRUE;
@@ -0,0 +1,2 @@
+-- Chats from before personas were answered as the terminal robot
+ALTER TABLE chats ADD COLUMN persona VARCHAR NOT NULL DEFAULT 'terminal_robot';